@@ -0,0 +1,109 @@
+//! Checks that calls to functions pass an argument for every parameter, and no more.
+use crate::diagnostics::Diagnostic;
+use crate::resolved_ast::*;
+use crate::visit::{walk_expression, walk_program, Visitor};
+
+/// Check the calls of a linked program, whose omitted arguments with defaults are filled in.
+pub fn check(program: &Program) -> Vec<Diagnostic> {
+    let mut checker = Checker {
+        program,
+        diagnostics: Vec::new(),
+    };
+    walk_program(&mut checker, program);
+    checker.diagnostics
+}
+
+struct Checker<'a, 'input> {
+    program: &'a Program<'input>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+/// `n argument(s)`
+fn arguments(n: usize) -> String {
+    format!("{} argument{}", n, if n == 1 { "" } else { "s" })
+}
+
+impl<'input> Visitor<'input> for Checker<'_, 'input> {
+    fn visit_expression(&mut self, expr: &Expression<'input>) {
+        if let Expression::FunctionCall(
+            call @ FunctionCall::Direct {
+                function,
+                name,
+                args,
+                ..
+            },
+        ) = expr
+        {
+            let params = &self.program.functions[*function].definition.params;
+            let message = if args.len() > params.len() {
+                Some(format!(
+                    "`{}` takes {}, but {} given",
+                    name,
+                    arguments(params.len()),
+                    if args.len() == 1 {
+                        "1 was".to_string()
+                    } else {
+                        format!("{} were", args.len())
+                    }
+                ))
+            } else {
+                // the defaults are filled in already, so none of these have one
+                let missing = params[args.len()..]
+                    .iter()
+                    .map(|param| format!("`{}`", param.name))
+                    .collect::<Vec<_>>();
+                (!missing.is_empty()).then(|| {
+                    format!(
+                        "missing {} for {} in the call to `{}`",
+                        if missing.len() == 1 {
+                            "an argument"
+                        } else {
+                            "arguments"
+                        },
+                        missing.join(", "),
+                        name
+                    )
+                })
+            };
+            if let Some(message) = message {
+                let mut diagnostic =
+                    Diagnostic::error(message, call.span().clone()).with_code("E020");
+                // the spans of an imported function point into another file
+                if !self.program.is_imported(*function) {
+                    let definition = &self.program.functions[*function].definition;
+                    diagnostic = diagnostic.with_note(
+                        format!("`{}` is defined here", name),
+                        definition.span.clone(),
+                    );
+                }
+                self.diagnostics.push(diagnostic);
+            }
+        }
+        if let Expression::FunctionCall(
+            call @ FunctionCall::Intrinsic {
+                intrinsic, args, ..
+            },
+        ) = expr
+        {
+            if args.len() != intrinsic.arity() {
+                self.diagnostics.push(
+                    Diagnostic::error(
+                        format!(
+                            "`{}` takes {}, but {} given",
+                            intrinsic.name(),
+                            arguments(intrinsic.arity()),
+                            if args.len() == 1 {
+                                "1 was".to_string()
+                            } else {
+                                format!("{} were", args.len())
+                            }
+                        ),
+                        call.span().clone(),
+                    )
+                    .with_code("E021"),
+                );
+            }
+        }
+        walk_expression(self, expr);
+    }
+}
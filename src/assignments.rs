@@ -0,0 +1,69 @@
+//! Checks that assignments only target mutable variables that are in scope.
+use std::collections::HashMap;
+
+use crate::diagnostics::Diagnostic;
+use crate::resolved_ast::*;
+use crate::visit::{walk_function, walk_program, walk_statement, Visitor};
+use crate::Span;
+
+pub fn check(program: &Program) -> Vec<Diagnostic> {
+    let mut checker = Checker {
+        program,
+        declared: HashMap::new(),
+        diagnostics: Vec::new(),
+    };
+    walk_program(&mut checker, program);
+    checker.diagnostics
+}
+
+struct Checker<'a, 'input> {
+    program: &'a Program<'input>,
+    /// The parameters and `let`s seen so far, which come before the assignments to them.
+    declared: HashMap<VariableId, Span>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'input> Visitor<'input> for Checker<'_, 'input> {
+    fn visit_function(&mut self, func: &Function<'input>) {
+        for param in &func.definition.params {
+            if let Some(id) = param.id {
+                self.declared.insert(id, param.span.clone());
+            }
+        }
+        walk_function(self, func);
+    }
+
+    fn visit_statement(&mut self, stmt: &Statement<'input>) {
+        if let Statement::Let(let_) = stmt {
+            self.declared.insert(let_.id, let_.span.clone());
+        }
+        walk_statement(self, stmt);
+        if let Statement::Assign(assign) = stmt {
+            let (code, message) = match assign.variable.map(|id| &self.program.variables[id]) {
+                Some(binding) if binding.mutable => return,
+                Some(_) => (
+                    "E012",
+                    format!(
+                        "cannot assign to `{}`, it is not declared as `let mut`",
+                        assign.target
+                    ),
+                ),
+                None => (
+                    "E013",
+                    format!(
+                        "cannot assign to `{}`, it is not declared in this scope",
+                        assign.target
+                    ),
+                ),
+            };
+            let mut diagnostic = Diagnostic::error(message, assign.span.clone()).with_code(code);
+            if let Some(span) = assign.variable.and_then(|id| self.declared.get(&id)) {
+                diagnostic = diagnostic.with_note(
+                    format!("`{}` is declared here", assign.target),
+                    span.clone(),
+                );
+            }
+            self.diagnostics.push(diagnostic);
+        }
+    }
+}
@@ -1,53 +1,100 @@
 use colored::Colorize;
+use serde::Serialize;
 use std::fmt::{Debug, Display, Error};
+use std::num::ParseIntError;
 
 use crate::formatter::{Format, Formatter};
+use crate::keywords::{Keyword, Spelling};
+use crate::Span;
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct Program<'input> {
     pub things: Vec<TopLevel<'input>>,
 }
 
 impl Format for Program<'_> {
     fn format(&self, fmt: &mut Formatter) {
-        for func in &self.things {
+        for (i, func) in self.things.iter().enumerate() {
             func.format(fmt);
+            // separate the imports and the top level statements from the rest of the file
+            let next = self.things.get(i + 1);
+            let separate = match func {
+                TopLevel::Import(_) => !matches!(next, Some(TopLevel::Import(_)) | None),
+                TopLevel::Statement(..) => !matches!(next, Some(TopLevel::Statement(..)) | None),
+                _ => false,
+            };
+            if separate {
+                fmt.push_str("\n");
+            }
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub enum TopLevel<'input> {
     Function(Function<'input>),
+    Enum(Enum<'input>),
     Comment(Comment<'input>),
+    Import(Import<'input>),
+    /// A statement outside of any function, with its span.
+    Statement(Statement<'input>, Span),
 }
 
 impl Format for TopLevel<'_> {
     fn format(&self, fmt: &mut Formatter) {
         match self {
             TopLevel::Function(func) => func.format(fmt),
+            TopLevel::Enum(enum_) => enum_.format(fmt),
             TopLevel::Comment(comment) => comment.format(fmt),
+            TopLevel::Import(import) => import.format(fmt),
+            TopLevel::Statement(stmt, _) => stmt.format(fmt),
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
+pub struct Import<'input> {
+    pub keyword: Spelling,
+    /// The imported file, relative to the importing one.
+    pub path: &'input str,
+    pub span: Span,
+}
+
+impl Format for Import<'_> {
+    fn format(&self, fmt: &mut Formatter) {
+        fmt.push_keyword(Keyword::Import, self.keyword);
+        fmt.push_string(format!(" \"{}\";\n", self.path));
+    }
+}
+
+#[derive(Debug, Serialize)]
 pub struct Comment<'input> {
     pub text: &'input str,
+    pub span: Span,
+    /// Whether the comment follows code on its line, where the formatter keeps it.
+    pub trailing: bool,
 }
 
 impl<'input> Comment<'input> {
-    pub fn new(text: &'input str) -> Self {
+    pub fn new(text: &'input str, span: Span, trailing: bool) -> Self {
         // remove the // from the start of the comment
         let text = &text[2..];
         // remove any whitespace from the start of the comment
         let text = text.trim_start();
-        Self { text }
+        Self {
+            text,
+            span,
+            trailing,
+        }
     }
 }
 
 impl Format for Comment<'_> {
     fn format(&self, fmt: &mut Formatter) {
+        if self.trailing {
+            fmt.push_trailing_comment(self.text);
+            return;
+        }
         fmt.push_str_indented("// ");
         fmt.push_str(self.text);
         fmt.push_str("\n");
@@ -56,22 +103,28 @@ impl Format for Comment<'_> {
 
 // function -------------------------------------------------------------------
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct Function<'input> {
     pub definition: FunctionDefinition<'input>,
     pub body: Box<Expression<'input>>,
     pub tests: Vec<Test<'input>>,
+    pub benchmarks: Vec<Benchmark<'input>>,
 }
 
 impl<'input> Function<'input> {
     pub fn comment(content: &'input str) -> Self {
         Self {
             definition: FunctionDefinition {
+                keyword: Spelling::default(),
                 name: content,
+                name_keyword: Spelling::default(),
                 params: Vec::new(),
+                return_type: None,
+                span: 0..0,
             },
-            body: Box::new(Expression::Error),
+            body: Box::new(Expression::Error(0..0)),
             tests: Vec::new(),
+            benchmarks: Vec::new(),
         }
     }
 }
@@ -83,110 +136,307 @@ impl Format for Function<'_> {
         if !self.tests.is_empty() {
             fmt.push_str_indented("[\n");
             fmt.indent();
-            for test in &self.tests {
+            for (i, test) in self.tests.iter().enumerate() {
                 fmt.push_str_indented("");
                 test.format(fmt);
-                fmt.push_str(",\n");
+                fmt.push_list_separator(i, self.tests.len(), true);
+                fmt.push_str("\n");
             }
             fmt.unindent();
             fmt.push_str_indented("]");
         }
-        fmt.push_str("\n\n");
+        if !self.benchmarks.is_empty() {
+            fmt.push_str_indented("<[\n");
+            fmt.indent();
+            for (i, benchmark) in self.benchmarks.iter().enumerate() {
+                fmt.push_str_indented("");
+                benchmark.input.format(fmt);
+                fmt.push_list_separator(i, self.benchmarks.len(), true);
+                fmt.push_str("\n");
+            }
+            fmt.unindent();
+            fmt.push_str_indented("]>");
+        }
+        fmt.push_blank_line();
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct FunctionDefinition<'input> {
+    pub keyword: Spelling,
     pub name: &'input str,
+    /// How the name was spelled, if it is `hatch`, which has an emoji spelling like a keyword.
+    pub name_keyword: Spelling,
     pub params: Vec<Parameter<'input>>,
+    pub return_type: Option<Type<'input>>,
+    /// From the keyword to the return type.
+    pub span: Span,
 }
 
 impl Format for FunctionDefinition<'_> {
     fn format(&self, fmt: &mut Formatter) {
+        fmt.push_indent();
+        fmt.push_keyword(Keyword::Function, self.keyword);
+        fmt.push_str(" ");
         if self.name == "hatch" {
-            fmt.push_str_indented("🥚 🐣(");
+            fmt.push_keyword(Keyword::Hatch, self.name_keyword);
         } else {
-            fmt.push_str_indented("🥚 ");
             fmt.push_str(self.name);
-            fmt.push_str("(");
         }
-        fmt.push_string(
-            self.params
-                .iter()
-                .map(|param| format!("{}", param))
-                .collect::<Vec<_>>()
-                .join(", "),
-        );
+        fmt.push_str("(");
+        for (i, param) in self.params.iter().enumerate() {
+            fmt.push_string(param.to_string());
+            if let Some(default) = &param.default {
+                fmt.push_str(" = ");
+                default.format(fmt);
+            }
+            fmt.push_list_separator(i, self.params.len(), false);
+        }
 
         fmt.push_str(") ");
+        if let Some(return_type) = &self.return_type {
+            fmt.push_string(format!("-> {} ", return_type));
+        }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct Parameter<'input> {
     pub name: &'input str,
+    pub type_: Option<Type<'input>>,
+    /// The value of the parameter when a call leaves it out, only functions have these.
+    pub default: Option<Box<Expression<'input>>>,
+    pub span: Span,
 }
 
 impl Display for Parameter<'_> {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> Result<(), Error> {
+        write!(fmt, "{}", self.name)?;
+        if let Some(type_) = &self.type_ {
+            write!(fmt, ": {}", type_)?;
+        }
+        Ok(())
+    }
+}
+
+/// A type annotation. Types are not checked yet, only parsed and formatted.
+#[derive(Debug, Serialize)]
+pub struct Type<'input> {
+    pub name: &'input str,
+    pub span: Span,
+}
+
+impl Display for Type<'_> {
     fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> Result<(), Error> {
         write!(fmt, "{}", self.name)
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct Test<'input> {
+    pub name: Option<ASTString<'input>>,
+    pub forall: Option<Forall<'input>>,
     pub input: Box<Expression<'input>>,
     pub output: Box<Expression<'input>>,
+    pub span: Span,
 }
 
 impl Format for Test<'_> {
     fn format(&self, fmt: &mut Formatter) {
+        if let Some(name) = &self.name {
+            fmt.push_string(name.to_string());
+            fmt.push_str(": ");
+        }
+        if let Some(forall) = &self.forall {
+            forall.format(fmt);
+        }
         self.input.format(fmt);
         fmt.push_str(" = ");
         self.output.format(fmt);
     }
 }
 
+/// A benchmark after a function, `<[10000]>`, the first argument of the calls that are timed.
+#[derive(Debug, Serialize)]
+pub struct Benchmark<'input> {
+    pub input: Box<Expression<'input>>,
+    pub span: Span,
+}
+
+/// `forall n in 0..10:` before a test, which makes it a test for the values of `n`.
+#[derive(Debug, Serialize)]
+pub struct Forall<'input> {
+    pub keyword: Spelling,
+    pub generators: Vec<Generator<'input>>,
+    /// Without the colon.
+    pub span: Span,
+}
+
+impl Format for Forall<'_> {
+    fn format(&self, fmt: &mut Formatter) {
+        fmt.push_keyword(Keyword::Forall, self.keyword);
+        fmt.push_str(" ");
+        for (i, generator) in self.generators.iter().enumerate() {
+            if i > 0 {
+                fmt.push_str(", ");
+            }
+            generator.format(fmt);
+        }
+        fmt.push_str(": ");
+    }
+}
+
+/// `n in 0..10`, a variable of a [`Forall`] and the range it takes its values from.
+#[derive(Debug, Serialize)]
+pub struct Generator<'input> {
+    pub name: &'input str,
+    /// The spelling of `in`.
+    pub keyword: Spelling,
+    pub range: Box<Expression<'input>>,
+    pub span: Span,
+}
+
+impl Format for Generator<'_> {
+    fn format(&self, fmt: &mut Formatter) {
+        fmt.push_str(self.name);
+        fmt.push_str(" ");
+        fmt.push_keyword(Keyword::In, self.keyword);
+        fmt.push_str(" ");
+        self.range.format(fmt);
+    }
+}
+
+// enums ----------------------------------------------------------------------
+
+#[derive(Debug, Serialize)]
+pub struct Enum<'input> {
+    pub keyword: Spelling,
+    pub name: &'input str,
+    pub variants: Vec<Variant<'input>>,
+    pub span: Span,
+}
+
+impl Format for Enum<'_> {
+    fn format(&self, fmt: &mut Formatter) {
+        fmt.push_indent();
+        fmt.push_keyword(Keyword::Enum, self.keyword);
+        fmt.push_str(" ");
+        fmt.push_str(self.name);
+        fmt.push_str(" {\n");
+        fmt.indent();
+        for (i, variant) in self.variants.iter().enumerate() {
+            fmt.push_str_indented("");
+            variant.format(fmt);
+            fmt.push_list_separator(i, self.variants.len(), true);
+            fmt.push_str("\n");
+        }
+        fmt.unindent();
+        fmt.push_str_indented("}");
+        fmt.push_blank_line();
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Variant<'input> {
+    pub name: &'input str,
+    pub fields: Vec<Parameter<'input>>,
+    pub span: Span,
+}
+
+impl Format for Variant<'_> {
+    fn format(&self, fmt: &mut Formatter) {
+        fmt.push_str(self.name);
+        if !self.fields.is_empty() {
+            fmt.push_str("(");
+            for (i, field) in self.fields.iter().enumerate() {
+                fmt.push_string(field.to_string());
+                fmt.push_list_separator(i, self.fields.len(), false);
+            }
+            fmt.push_str(")");
+        }
+    }
+}
+
 // statements -----------------------------------------------------------------
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub enum Statement<'input> {
     Let(Let<'input>),
+    Assign(Assign<'input>),
     Expression(Box<Expression<'input>>),
-    Return(Box<Expression<'input>>),
+    /// The span covers the whole statement, without the semicolon.
+    Return(Box<Expression<'input>>, Spelling, Span),
+    Assert(Assert<'input>),
     Comment(Comment<'input>),
-    Error,
+    Function(NestedFunction<'input>),
 }
 
 impl Format for Statement<'_> {
     fn format(&self, fmt: &mut Formatter) {
         match self {
             Statement::Let(let_) => let_.format(fmt),
+            Statement::Assign(assign) => assign.format(fmt),
+            Statement::Assert(assert) => assert.format(fmt),
             Statement::Expression(expr) => {
                 fmt.push_str_indented("");
                 expr.format(fmt);
                 fmt.push_str(";\n");
             }
-            Statement::Return(expr) => {
-                fmt.push_str_indented("🐔 ");
+            Statement::Return(expr, keyword, _) => {
+                fmt.push_indent();
+                fmt.push_keyword(Keyword::Return, *keyword);
+                fmt.push_str(" ");
                 expr.format(fmt);
                 fmt.push_str(";\n");
             }
             Statement::Comment(comment) => comment.format(fmt),
-            Statement::Error => fmt.push_string_indented("error!\n".red().to_string()),
+            Statement::Function(nested) => nested.format(fmt),
         }
     }
 }
 
-#[derive(Debug)]
+/// A function defined inside another function.
+#[derive(Debug, Serialize)]
+pub enum NestedFunction<'input> {
+    Definition(Box<Function<'input>>),
+    /// Moved out of its parent by [`crate::resolved_ast::Program::link`], this is the index of
+    /// its `FunctionId`.
+    Lifted(usize),
+}
+
+impl Format for NestedFunction<'_> {
+    fn format(&self, fmt: &mut Formatter) {
+        match self {
+            NestedFunction::Definition(func) => func.format(fmt),
+            NestedFunction::Lifted(index) => {
+                fmt.push_indent();
+                fmt.push_error(&format!("lifted function #{}", index));
+                fmt.push_str("\n");
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
 pub struct Let<'input> {
+    pub keyword: Spelling,
     pub name: &'input str,
+    /// The spelling of `mut`, if the binding is mutable.
+    pub mutable: Option<Spelling>,
     pub value: Box<Expression<'input>>,
+    /// The whole binding, without the semicolon.
+    pub span: Span,
 }
 
 impl Format for Let<'_> {
     fn format(&self, fmt: &mut Formatter) {
-        fmt.push_str_indented("let ");
+        fmt.push_indent();
+        fmt.push_keyword(Keyword::Let, self.keyword);
+        fmt.push_str(" ");
+        if let Some(mutable) = self.mutable {
+            fmt.push_keyword(Keyword::Mut, mutable);
+            fmt.push_str(" ");
+        }
         fmt.push_str(self.name);
         fmt.push_str(" = ");
         self.value.format(fmt);
@@ -194,80 +444,254 @@ impl Format for Let<'_> {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
+pub struct Assert<'input> {
+    pub keyword: Spelling,
+    pub condition: Box<Expression<'input>>,
+    /// The condition as written, to report a failing assertion.
+    pub text: &'input str,
+    /// The whole assertion, without the semicolon.
+    pub span: Span,
+}
+
+impl Format for Assert<'_> {
+    fn format(&self, fmt: &mut Formatter) {
+        fmt.push_indent();
+        fmt.push_keyword(Keyword::Assert, self.keyword);
+        fmt.push_str(" ");
+        self.condition.format(fmt);
+        fmt.push_str(";\n");
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Assign<'input> {
+    pub target: &'input str,
+    /// The operator of a compound assignment like `x += 1`.
+    pub op: Option<Opcode>,
+    pub value: Box<Expression<'input>>,
+    pub span: Span,
+}
+
+impl Format for Assign<'_> {
+    fn format(&self, fmt: &mut Formatter) {
+        fmt.push_str_indented(self.target);
+        fmt.push_str(" ");
+        if let Some(op) = self.op {
+            fmt.push_string(op.to_string());
+        }
+        fmt.push_str("= ");
+        self.value.format(fmt);
+        fmt.push_str(";\n");
+    }
+}
+
+#[derive(Debug, Serialize)]
 pub struct FunctionCall<'input> {
     pub name: &'input str,
     pub args: Vec<Box<Expression<'input>>>,
+    pub span: Span,
 }
 
 impl Format for FunctionCall<'_> {
     fn format(&self, fmt: &mut Formatter) {
         fmt.push_str(&format!("{}(", self.name));
         for (i, arg) in self.args.iter().enumerate() {
-            if i > 0 {
-                fmt.push_str(", ");
-            }
             arg.format(fmt);
+            fmt.push_list_separator(i, self.args.len(), false);
         }
         fmt.push_str(")");
     }
 }
 
+#[derive(Debug, Serialize)]
+pub struct EnumConstructor<'input> {
+    pub enum_name: &'input str,
+    pub variant: &'input str,
+    pub args: Vec<Box<Expression<'input>>>,
+    pub span: Span,
+}
+
+impl Format for EnumConstructor<'_> {
+    fn format(&self, fmt: &mut Formatter) {
+        fmt.push_str(&format!("{}::{}", self.enum_name, self.variant));
+        if !self.args.is_empty() {
+            fmt.push_str("(");
+            for (i, arg) in self.args.iter().enumerate() {
+                arg.format(fmt);
+                fmt.push_list_separator(i, self.args.len(), false);
+            }
+            fmt.push_str(")");
+        }
+    }
+}
+
 // expressions ----------------------------------------------------------------
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub enum Expression<'input> {
     Expression(Box<Expression<'input>>),
-    Block(Vec<Statement<'input>>),
+    /// An expression in parentheses, with the span of them. Only the formatter tells it apart
+    /// from the expression, to keep the parentheses that were written.
+    Paren(Box<Expression<'input>>, Span),
+    /// Statements, optionally followed by an expression without a semicolon that is the value of
+    /// the block, with the span of the braces and everything between them.
+    Block(
+        Vec<Statement<'input>>,
+        Option<Box<Expression<'input>>>,
+        Span,
+    ),
     FunctionCall(FunctionCall<'input>),
+    /// `lhs |> call`, calling `call` with `lhs` as its first argument, with the span of the whole
+    /// pipe.
+    Pipe(Box<Expression<'input>>, FunctionCall<'input>, Span),
+    EnumConstructor(EnumConstructor<'input>),
     Variable(Variable<'input>),
-    Number(i32),
+    Number(Number<'input>),
     String(ASTString<'input>),
+    /// `()`, the value of expressions that have no meaningful value.
+    Unit(Spelling, Span),
+    /// `true` or `false`.
+    Bool(bool, Spelling, Span),
+    /// `none`, a missing value.
+    None(Spelling, Span),
+    /// `some(value)`, a value that is present.
+    Some(Box<Expression<'input>>, Spelling, Span),
+    /// `expr?`, which returns none from the function if `expr` is none and is the value inside
+    /// it otherwise, with the span of the whole expression.
+    Propagate(Box<Expression<'input>>, Span),
     If(If<'input>),
-    Op(Box<Expression<'input>>, Opcode, Box<Expression<'input>>),
+    Lambda(Lambda<'input>),
+    /// `object.name`
+    Field(Field<'input>),
+    /// `object.method(args)`
+    MethodCall(MethodCall<'input>),
+    /// `try { ... } rescue error { ... }`
+    Try(Try<'input>),
+    Range(Range<'input>),
+    /// `lhs op rhs`, with the span of the whole operation.
+    Op(
+        Box<Expression<'input>>,
+        Opcode,
+        Box<Expression<'input>>,
+        Span,
+    ),
     ExpressionComment((Box<Expression<'input>>, Comment<'input>)),
-    Error,
+    /// Where the parser recovered from a syntax error.
+    Error(Span),
+}
+
+impl Expression<'_> {
+    /// The span of the whole expression.
+    pub fn span(&self) -> Span {
+        match self {
+            Expression::Expression(expr) | Expression::ExpressionComment((expr, _)) => expr.span(),
+            Expression::Block(_, _, span)
+            | Expression::Paren(_, span)
+            | Expression::Pipe(_, _, span)
+            | Expression::Unit(_, span)
+            | Expression::Bool(_, _, span)
+            | Expression::None(_, span)
+            | Expression::Some(_, _, span)
+            | Expression::Propagate(_, span)
+            | Expression::Op(_, _, _, span)
+            | Expression::Error(span) => span.clone(),
+            Expression::FunctionCall(call) => call.span.clone(),
+            Expression::EnumConstructor(constructor) => constructor.span.clone(),
+            Expression::Variable(var) => var.span.clone(),
+            Expression::Number(number) => number.span.clone(),
+            Expression::String(string) => string.span.clone(),
+            Expression::If(if_) => if_.span.clone(),
+            Expression::Lambda(lambda) => lambda.span.clone(),
+            Expression::Field(field) => field.span.clone(),
+            Expression::MethodCall(call) => call.span.clone(),
+            Expression::Try(try_) => try_.span.clone(),
+            Expression::Range(range) => range.span.clone(),
+        }
+    }
 }
 
 impl Format for Expression<'_> {
     fn format(&self, fmt: &mut Formatter) {
         match self {
-            Expression::Expression(expr) => expr.format(fmt),
+            Expression::Expression(expr) | Expression::Paren(expr, _) => expr.format(fmt),
             Expression::FunctionCall(func) => func.format(fmt),
-            Expression::Block(block) => {
+            Expression::Pipe(lhs, call, _) => {
+                if matches!(**lhs, Expression::Lambda(_)) || is_conditional(lhs) {
+                    fmt.push_str("(");
+                    lhs.format(fmt);
+                    fmt.push_str(")");
+                } else {
+                    lhs.format(fmt);
+                }
+                fmt.push_str(" |> ");
+                if call.args.is_empty() {
+                    fmt.push_str(call.name);
+                } else {
+                    call.format(fmt);
+                }
+            }
+            Expression::EnumConstructor(constructor) => constructor.format(fmt),
+            Expression::Block(block, None, _) if block.is_empty() => fmt.push_str("{}"),
+            Expression::Block(block, tail, _) => {
                 fmt.push_str("{\n");
                 fmt.indent();
                 for stmt in block {
                     stmt.format(fmt);
                 }
+                if let Some(tail) = tail {
+                    fmt.push_indent();
+                    tail.format(fmt);
+                    fmt.push_str("\n");
+                }
                 fmt.unindent();
                 fmt.push_str_indented("}");
             }
             Expression::Variable(var) => fmt.push_string(var.to_string()),
             Expression::Number(num) => fmt.push_string(num.to_string()),
             Expression::String(str) => fmt.push_string(str.to_string()),
-            Expression::Op(lhs, op, rhs) => {
+            Expression::Unit(keyword, _) => fmt.push_keyword(Keyword::Unit, *keyword),
+            Expression::Bool(true, keyword, _) => fmt.push_keyword(Keyword::True, *keyword),
+            Expression::Bool(false, keyword, _) => fmt.push_keyword(Keyword::False, *keyword),
+            Expression::None(keyword, _) => fmt.push_keyword(Keyword::None, *keyword),
+            Expression::Some(value, keyword, _) => {
+                fmt.push_keyword(Keyword::Some, *keyword);
+                fmt.push_str("(");
+                value.format(fmt);
+                fmt.push_str(")");
+            }
+            Expression::Propagate(expr, _) => {
+                format_operand(expr, fmt);
+                fmt.push_str("?");
+            }
+            Expression::Op(lhs, op, rhs, _) => {
                 fmt.push_str("(");
-                lhs.format(fmt);
+                format_operand(lhs, fmt);
                 fmt.push_str(" ");
                 fmt.push_string(op.to_string());
                 fmt.push_str(" ");
-                rhs.format(fmt);
+                format_operand(rhs, fmt);
                 fmt.push_str(")");
             }
+            Expression::Range(range) => range.format(fmt),
             Expression::If(if_) => if_.format(fmt),
+            Expression::Lambda(lambda) => lambda.format(fmt),
+            Expression::Field(field) => field.format(fmt),
+            Expression::MethodCall(call) => call.format(fmt),
+            Expression::Try(try_) => try_.format(fmt),
             Expression::ExpressionComment((expr, comment)) => {
                 expr.format(fmt);
                 comment.format(fmt);
             }
-            Expression::Error => fmt.push_string("error".red().to_string()),
+            Expression::Error(_) => fmt.push_error("error"),
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct Variable<'input> {
     pub name: &'input str,
+    pub span: Span,
 }
 
 impl Display for Variable<'_> {
@@ -276,50 +700,326 @@ impl Display for Variable<'_> {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
+pub struct Number<'input> {
+    pub value: i64,
+    pub radix: Radix,
+    /// The literal as written, including its prefix and digit separators.
+    pub text: &'input str,
+    pub span: Span,
+}
+
+impl<'input> Number<'input> {
+    /// Parse a literal of the given radix, e.g. `0xFF` or `1_000_000`.
+    pub fn new(text: &'input str, radix: Radix, span: Span) -> Result<Self, ParseIntError> {
+        let digits = text[radix.prefix().len()..].replace('_', "");
+        let value = i64::from_str_radix(&digits, radix.base())?;
+        Ok(Self {
+            value,
+            radix,
+            text,
+            span,
+        })
+    }
+}
+
+impl Display for Number<'_> {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> Result<(), Error> {
+        write!(fmt, "{}", self.text)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+pub enum Radix {
+    Binary,
+    Octal,
+    Decimal,
+    Hexadecimal,
+}
+
+impl Radix {
+    pub fn base(&self) -> u32 {
+        match self {
+            Radix::Binary => 2,
+            Radix::Octal => 8,
+            Radix::Decimal => 10,
+            Radix::Hexadecimal => 16,
+        }
+    }
+
+    pub fn prefix(&self) -> &'static str {
+        match self {
+            Radix::Binary => "0b",
+            Radix::Octal => "0o",
+            Radix::Decimal => "",
+            Radix::Hexadecimal => "0x",
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
 pub struct ASTString<'input> {
+    /// The contents between the delimiters, exactly as written.
     pub value: &'input str,
+    pub kind: StringKind,
+    /// The whole literal, including the delimiters.
+    pub span: Span,
 }
 
 impl<'input> Display for ASTString<'input> {
     fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> Result<(), Error> {
-        write!(fmt, "{}", self.value)
+        let (open, close) = self.kind.delimiters();
+        write!(fmt, "{}{}{}", open, self.value, close)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum StringKind {
+    /// `"..."`
+    Plain,
+    /// `"""..."""`, which can contain single quotes.
+    Multiline,
+    /// `r"..."`, or `r#"..."#` with one hash, which can contain quotes.
+    Raw { hashes: usize },
+}
+
+impl StringKind {
+    pub fn delimiters(&self) -> (String, String) {
+        match self {
+            StringKind::Plain => ("\"".to_string(), "\"".to_string()),
+            StringKind::Multiline => ("\"\"\"".to_string(), "\"\"\"".to_string()),
+            StringKind::Raw { hashes } => (
+                format!("r{}\"", "#".repeat(*hashes)),
+                format!("\"{}", "#".repeat(*hashes)),
+            ),
+        }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct If<'input> {
+    pub keyword: Spelling,
     pub condition: Box<Expression<'input>>,
     pub body: Box<Expression<'input>>,
-    pub else_body: Option<Box<Expression<'input>>>,
+    /// The spelling of `else`, if there is an else branch.
+    pub else_keyword: Spelling,
+    pub else_body: Option<Else<'input>>,
+    /// The whole `if`, including the else branches.
+    pub span: Span,
+}
+
+impl<'input> If<'input> {
+    /// The values of both branches, if they are simple enough to write as `condition ? a : b`.
+    pub fn conditional_branches(&self) -> Option<(&Expression<'input>, &Expression<'input>)> {
+        let then = block_value(&self.body)?;
+        let else_ = match &self.else_body {
+            Some(Else::Block(block)) => block_value(block)?,
+            _ => return None,
+        };
+        let simple = |expr: &Expression| {
+            !matches!(
+                expr,
+                Expression::Block(..)
+                    | Expression::If(_)
+                    | Expression::Lambda(_)
+                    | Expression::ExpressionComment(_)
+            )
+        };
+        // `a ? b : c ? d : e` nests to the right
+        (simple(then) && (simple(else_) || is_conditional(else_))).then_some((then, else_))
+    }
+}
+
+/// The value of a block that consists of nothing but its final expression.
+fn block_value<'a, 'input>(expr: &'a Expression<'input>) -> Option<&'a Expression<'input>> {
+    match expr {
+        Expression::Block(block, Some(tail), _) if block.is_empty() => Some(tail),
+        _ => None,
+    }
+}
+
+/// Whether the expression is formatted as `condition ? a : b`.
+fn is_conditional(expr: &Expression) -> bool {
+    matches!(expr, Expression::If(if_) if if_.conditional_branches().is_some())
 }
 
 impl Format for If<'_> {
     fn format(&self, fmt: &mut Formatter) {
-        fmt.push_str("if ");
+        if let Some((then, else_)) = self.conditional_branches() {
+            if is_conditional(&self.condition) || matches!(*self.condition, Expression::Lambda(_)) {
+                fmt.push_str("(");
+                self.condition.format(fmt);
+                fmt.push_str(")");
+            } else {
+                self.condition.format(fmt);
+            }
+            fmt.push_str(" ? ");
+            then.format(fmt);
+            fmt.push_str(" : ");
+            else_.format(fmt);
+            return;
+        }
+        fmt.push_keyword(Keyword::If, self.keyword);
+        fmt.push_str(" ");
         self.condition.format(fmt);
         fmt.push_str(" ");
         self.body.format(fmt);
         if let Some(else_) = &self.else_body {
+            fmt.push_str(" ");
+            fmt.push_keyword(Keyword::Else, self.else_keyword);
+            fmt.push_str(" ");
             else_.format(fmt);
         }
     }
 }
 
+#[derive(Debug, Serialize)]
+pub enum Else<'input> {
+    Block(Box<Expression<'input>>),
+    If(Box<If<'input>>),
+}
+
+impl Format for Else<'_> {
+    fn format(&self, fmt: &mut Formatter) {
+        match self {
+            Else::Block(block) => block.format(fmt),
+            Else::If(if_) => if_.format(fmt),
+        }
+    }
+}
+
+/// `start..end`, or `start..=end` if the end is included.
+#[derive(Debug, Serialize)]
+pub struct Range<'input> {
+    pub start: Box<Expression<'input>>,
+    pub end: Box<Expression<'input>>,
+    pub inclusive: bool,
+    pub span: Span,
+}
+
+impl Format for Range<'_> {
+    fn format(&self, fmt: &mut Formatter) {
+        format_operand(&self.start, fmt);
+        fmt.push_str(if self.inclusive { "..=" } else { ".." });
+        format_operand(&self.end, fmt);
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Field<'input> {
+    pub object: Box<Expression<'input>>,
+    pub name: &'input str,
+    pub span: Span,
+}
+
+impl Format for Field<'_> {
+    fn format(&self, fmt: &mut Formatter) {
+        format_operand(&self.object, fmt);
+        fmt.push_str(".");
+        fmt.push_str(self.name);
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct MethodCall<'input> {
+    pub object: Box<Expression<'input>>,
+    pub method: &'input str,
+    pub args: Vec<Box<Expression<'input>>>,
+    pub span: Span,
+}
+
+impl Format for MethodCall<'_> {
+    fn format(&self, fmt: &mut Formatter) {
+        format_operand(&self.object, fmt);
+        fmt.push_str(".");
+        fmt.push_str(self.method);
+        fmt.push_str("(");
+        for (i, arg) in self.args.iter().enumerate() {
+            arg.format(fmt);
+            fmt.push_list_separator(i, self.args.len(), false);
+        }
+        fmt.push_str(")");
+    }
+}
+
+/// Format an operand of an operator or the object of a field access or method call, adding
+/// parentheses around the expressions that bind weaker than those.
+fn format_operand(expr: &Expression, fmt: &mut Formatter) {
+    let weaker = matches!(
+        expr,
+        Expression::Range(_) | Expression::Lambda(_) | Expression::Pipe(..)
+    );
+    if weaker || is_conditional(expr) {
+        fmt.push_str("(");
+        expr.format(fmt);
+        fmt.push_str(")");
+    } else {
+        expr.format(fmt);
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Lambda<'input> {
+    pub params: Vec<Parameter<'input>>,
+    pub body: Box<Expression<'input>>,
+    pub span: Span,
+}
+
+impl Format for Lambda<'_> {
+    fn format(&self, fmt: &mut Formatter) {
+        fmt.push_str("|");
+        for (i, param) in self.params.iter().enumerate() {
+            fmt.push_string(param.to_string());
+            fmt.push_list_separator(i, self.params.len(), false);
+        }
+        fmt.push_str("| ");
+        self.body.format(fmt);
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Try<'input> {
+    pub keyword: Spelling,
+    pub body: Box<Expression<'input>>,
+    /// The name the rescue block binds the error to.
+    pub error: &'input str,
+    pub rescue_keyword: Spelling,
+    /// Evaluated instead of the rest of the body when the body fails.
+    pub rescue: Box<Expression<'input>>,
+    pub span: Span,
+}
+
+impl Format for Try<'_> {
+    fn format(&self, fmt: &mut Formatter) {
+        fmt.push_keyword(Keyword::Try, self.keyword);
+        fmt.push_str(" ");
+        self.body.format(fmt);
+        fmt.push_str(" ");
+        fmt.push_keyword(Keyword::Rescue, self.rescue_keyword);
+        fmt.push_str(" ");
+        fmt.push_str(self.error);
+        fmt.push_str(" ");
+        self.rescue.format(fmt);
+    }
+}
+
 // math -----------------------------------------------------------------------
 
+#[derive(Serialize)]
 pub enum ExprSymbol<'input> {
     NumSymbol(&'input str),
     Op(Box<ExprSymbol<'input>>, Opcode, Box<ExprSymbol<'input>>),
     Error,
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Serialize)]
 pub enum Opcode {
     Mul,
     Div,
     Add,
     Sub,
+    Eq,
+    NotEq,
 }
 
 impl<'input> Debug for ExprSymbol<'input> {
@@ -341,6 +1041,8 @@ impl Debug for Opcode {
             Div => write!(fmt, "/"),
             Add => write!(fmt, "+"),
             Sub => write!(fmt, "-"),
+            Eq => write!(fmt, "=="),
+            NotEq => write!(fmt, "!="),
         }
     }
 }
@@ -353,6 +1055,8 @@ impl Display for Opcode {
             Div => write!(fmt, "/"),
             Add => write!(fmt, "+"),
             Sub => write!(fmt, "-"),
+            Eq => write!(fmt, "=="),
+            NotEq => write!(fmt, "!="),
         }
     }
 }
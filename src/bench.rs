@@ -0,0 +1,132 @@
+//! Runs the benchmarks attached to functions, like `<[10000]>` after a function that counts. Like
+//! the input of a test, a benchmark is the first argument of a call of its function, and that
+//! call runs again and again to time it.
+use std::time::{Duration, Instant};
+
+use crate::bytecode;
+use crate::interpreter::{self, Interpreter};
+use crate::resolved_ast::*;
+use crate::runtime::{self, Backend, Failure};
+use crate::vm::{self, Vm};
+use crate::Span;
+
+/// How long the calls of a benchmark took.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Timings {
+    pub runs: usize,
+    pub mean: Duration,
+    pub median: Duration,
+}
+
+impl Timings {
+    fn new(mut durations: Vec<Duration>) -> Self {
+        durations.sort();
+        let runs = durations.len();
+        let median = match runs % 2 {
+            0 => (durations[runs / 2 - 1] + durations[runs / 2]) / 2,
+            _ => durations[runs / 2],
+        };
+        Self {
+            runs,
+            mean: durations.iter().sum::<Duration>() / runs as u32,
+            median,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BenchmarkResult {
+    /// The benchmark, its input.
+    pub span: Span,
+    /// The timings, unless evaluating the input or a call failed.
+    pub timings: Result<Timings, Failure>,
+}
+
+#[derive(Debug, Clone)]
+pub struct FunctionBenchmarks {
+    pub function: FunctionId,
+    /// In the order of the benchmarks.
+    pub benchmarks: Vec<BenchmarkResult>,
+}
+
+/// Run the benchmarks of every function that has some, except the imported ones, `runs` times
+/// each after a first call that isn't timed. The input is evaluated by the interpreter whatever
+/// the backend, and isn't timed either. No benchmark may do I/O.
+pub fn run(program: &Program, backend: Backend, runs: usize) -> Vec<FunctionBenchmarks> {
+    let _span = tracing::info_span!("execute").entered();
+    interpreter::with_large_stack(|| {
+        let compiled = match backend {
+            Backend::Interpreter => None,
+            Backend::Vm => Some(bytecode::compile(program)),
+        };
+        program
+            .functions
+            .iter()
+            .filter(|(id, func)| !program.is_imported(*id) && !func.benchmarks.is_empty())
+            .map(|(id, func)| FunctionBenchmarks {
+                function: id,
+                benchmarks: func
+                    .benchmarks
+                    .iter()
+                    .map(|benchmark| BenchmarkResult {
+                        span: benchmark.span.clone(),
+                        timings: time(program, compiled.as_ref(), id, benchmark, runs.max(1)),
+                    })
+                    .collect(),
+            })
+            .collect()
+    })
+}
+
+fn time<'a>(
+    program: &'a Program<'a>,
+    compiled: Option<&bytecode::Compiled<'a>>,
+    function: FunctionId,
+    benchmark: &'a Benchmark<'a>,
+    runs: usize,
+) -> Result<Timings, Failure> {
+    let input =
+        Interpreter::new(program).evaluate(function, Default::default(), &benchmark.input)?;
+    // `<[()]>` benchmarks functions without parameters
+    let args = match input {
+        interpreter::Value::Unit if program.functions[function].definition.params.is_empty() => {
+            vec![]
+        }
+        input => vec![input],
+    };
+    let mut durations = Vec::with_capacity(runs);
+    match compiled {
+        None => {
+            for run in 0..=runs {
+                let mut interpreter = Interpreter::new(program);
+                let start = Instant::now();
+                interpreter.call(function, args.clone(), &benchmark.span)?;
+                // the first call only warms up
+                if run > 0 {
+                    durations.push(start.elapsed());
+                }
+            }
+        }
+        Some(compiled) => {
+            let args = args
+                .iter()
+                .map(runtime::Value::for_backend)
+                .collect::<Option<Vec<vm::Value>>>()
+                .ok_or_else(|| {
+                    Failure::new(
+                        "the vm can't call a function with a lambda the interpreter made",
+                        &benchmark.span,
+                    )
+                })?;
+            for run in 0..=runs {
+                let mut vm = Vm::new(compiled);
+                let start = Instant::now();
+                vm.call(function, args.clone(), &benchmark.span)?;
+                if run > 0 {
+                    durations.push(start.elapsed());
+                }
+            }
+        }
+    }
+    Ok(Timings::new(durations))
+}
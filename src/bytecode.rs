@@ -0,0 +1,510 @@
+//! Compiles linked programs into instructions for the stack machine of [`crate::vm`]. Every
+//! function and every lambda becomes a [`Chunk`], whose instructions push and pop values and read
+//! and write the slots of the variables local to the function.
+use std::collections::HashMap;
+
+use id_collections::IdVec;
+use serde::{Deserialize, Serialize};
+
+use crate::resolved_ast::*;
+use crate::Span;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound(deserialize = "'de: 'input"))]
+pub enum Instruction<'input> {
+    Int(i64),
+    String(&'input str),
+    Unit,
+    Bool(bool),
+    None,
+    /// Wrap the value on top of the stack in a `some`.
+    Some,
+    Function(FunctionId),
+    /// Create a closure for a lambda of [`Compiled::lambdas`], with a copy of the current slots.
+    Closure(usize),
+    Load(usize),
+    Store(usize),
+    /// Jump if the slot of a parameter is set, which means the call passed an argument for it and
+    /// its default isn't needed.
+    SkipDefault {
+        slot: usize,
+        to: usize,
+    },
+    Pop,
+    Op(Opcode),
+    Range {
+        inclusive: bool,
+    },
+    /// Build an enum value from as many values as it has fields.
+    Enum {
+        name: &'input str,
+        variant: &'input str,
+        fields: Vec<&'input str>,
+    },
+    Field(&'input str),
+    /// Call a method, which no value has yet.
+    Method {
+        name: &'input str,
+        args: usize,
+    },
+    /// Call a function with the given number of arguments on top of the stack.
+    Call {
+        function: FunctionId,
+        args: usize,
+    },
+    /// Call an intrinsic with the given number of arguments on top of the stack.
+    Intrinsic {
+        intrinsic: Intrinsic,
+        args: usize,
+    },
+    /// Call the value below the given number of arguments on top of the stack.
+    CallValue {
+        args: usize,
+    },
+    /// Return the value on top of the stack to the caller.
+    Return,
+    Jump(usize),
+    /// Jump if the comparison on top of the stack is true, and pop it.
+    JumpIf(usize),
+    /// Jump if the comparison on top of the stack is false, and pop it.
+    JumpUnless(usize),
+    /// Until the matching [`Instruction::EndTry`], jump to `rescue` on failure, with the message
+    /// in `slot`.
+    Try {
+        rescue: usize,
+        slot: usize,
+    },
+    EndTry,
+    Fail(String),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound(deserialize = "'de: 'input"))]
+pub struct Param<'input> {
+    pub name: &'input str,
+    /// The slot the argument goes into, the fields of enum variants have none.
+    pub slot: Option<usize>,
+    /// Whether the chunk computes the value if a call leaves the argument out.
+    pub default: bool,
+}
+
+/// The code of a function or a lambda.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound(deserialize = "'de: 'input"))]
+pub struct Chunk<'input> {
+    /// The name of the function, lambdas have none.
+    pub name: Option<&'input str>,
+    pub params: Vec<Param<'input>>,
+    /// The names of the variables local to the function, by slot. Lambdas share the slots of the
+    /// function they are written in.
+    pub slots: Vec<&'input str>,
+    pub code: Vec<Instruction<'input>>,
+    /// Where each instruction comes from, to report failures.
+    pub spans: Vec<Span>,
+    /// The body.
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound(deserialize = "'de: 'input"))]
+pub struct Compiled<'input> {
+    pub functions: IdVec<FunctionId, Chunk<'input>>,
+    pub lambdas: Vec<Chunk<'input>>,
+}
+
+/// Compile every function of a linked program.
+pub fn compile<'input>(program: &Program<'input>) -> Compiled<'input> {
+    compile_functions(program, None)
+}
+
+/// Compile only the given functions of a linked program, which have to include the functions
+/// they call, like [`Program::callees`] does. The others fail if they are called.
+pub fn compile_only<'input>(
+    program: &Program<'input>,
+    functions: &[FunctionId],
+) -> Compiled<'input> {
+    compile_functions(program, Some(functions))
+}
+
+fn compile_functions<'input>(
+    program: &Program<'input>,
+    only: Option<&[FunctionId]>,
+) -> Compiled<'input> {
+    let mut locals = program.functions.map_refs(|_, _| Vec::new());
+    for (id, binding) in &program.variables {
+        locals[binding.function].push(id);
+    }
+    let mut lambdas = Vec::new();
+    let functions = program.functions.map_refs(|id, func| {
+        if only.is_some_and(|only| !only.contains(&id)) {
+            let span = func.definition.span.clone();
+            return Chunk {
+                name: Some(func.definition.name),
+                params: Vec::new(),
+                slots: Vec::new(),
+                code: vec![Instruction::Fail(format!(
+                    "`{}` was left out of the program",
+                    func.definition.name
+                ))],
+                spans: vec![span.clone()],
+                span,
+            };
+        }
+        // the parameters come first, so the arguments of a call go into the first slots
+        let params = &func.definition.params;
+        let mut slots = HashMap::new();
+        for var in params
+            .iter()
+            .filter_map(|param| param.id)
+            .chain(locals[id].clone())
+        {
+            let next = slots.len();
+            slots.entry(var).or_insert(next);
+        }
+        let mut compiler = Compiler {
+            program,
+            function: id,
+            slots: &slots,
+            lambdas: &mut lambdas,
+            code: Vec::new(),
+            spans: Vec::new(),
+        };
+        for param in params {
+            if let (Some(default), Some(var)) = (&param.default, param.id) {
+                let skip = compiler.emit(
+                    Instruction::SkipDefault {
+                        slot: slots[&var],
+                        to: 0,
+                    },
+                    &param.span,
+                );
+                compiler.expression(default);
+                compiler.emit(Instruction::Store(slots[&var]), &param.span);
+                compiler.patch(skip);
+            }
+        }
+        compiler.chunk(Some(func.definition.name), params, &func.body)
+    });
+    Compiled { functions, lambdas }
+}
+
+struct Compiler<'a, 'input> {
+    program: &'a Program<'input>,
+    /// The function being compiled, or the one the lambda being compiled is written in.
+    function: FunctionId,
+    slots: &'a HashMap<VariableId, usize>,
+    lambdas: &'a mut Vec<Chunk<'input>>,
+    code: Vec<Instruction<'input>>,
+    spans: Vec<Span>,
+}
+
+impl<'a, 'input> Compiler<'a, 'input> {
+    /// Add an instruction, returning where it is.
+    fn emit(&mut self, instruction: Instruction<'input>, span: &Span) -> usize {
+        self.code.push(instruction);
+        self.spans.push(span.clone());
+        self.code.len() - 1
+    }
+
+    /// Point the jump at the given position to the next instruction.
+    fn patch(&mut self, at: usize) {
+        let next = self.code.len();
+        match &mut self.code[at] {
+            Instruction::Jump(to)
+            | Instruction::JumpIf(to)
+            | Instruction::JumpUnless(to)
+            | Instruction::SkipDefault { to, .. }
+            | Instruction::Try { rescue: to, .. } => *to = next,
+            instruction => unreachable!("{:?} doesn't jump", instruction),
+        }
+    }
+
+    /// Finish the chunk with a body that returns its value.
+    fn chunk(
+        mut self,
+        name: Option<&'input str>,
+        params: &[Parameter<'input>],
+        body: &Expression<'input>,
+    ) -> Chunk<'input> {
+        self.expression(body);
+        self.emit(Instruction::Return, &body.span());
+        let mut slots = vec![""; self.slots.len()];
+        for (var, slot) in self.slots {
+            slots[*slot] = self.program.variables[var].name;
+        }
+        Chunk {
+            name,
+            params: params
+                .iter()
+                .map(|param| Param {
+                    name: param.name,
+                    slot: param.id.map(|var| self.slots[&var]),
+                    default: param.default.is_some(),
+                })
+                .collect(),
+            slots,
+            code: self.code,
+            spans: self.spans,
+            span: body.span(),
+        }
+    }
+
+    fn expressions(&mut self, exprs: &[Box<Expression<'input>>]) {
+        for expr in exprs {
+            self.expression(expr);
+        }
+    }
+
+    fn expression(&mut self, expr: &Expression<'input>) {
+        match expr {
+            Expression::Expression(expr)
+            | Expression::Paren(expr, _)
+            | Expression::ExpressionComment((expr, _)) => self.expression(expr),
+            Expression::Block(block, tail, span) => {
+                for stmt in block {
+                    self.statement(stmt);
+                }
+                match tail {
+                    Some(tail) => self.expression(tail),
+                    None => {
+                        self.emit(Instruction::Unit, span);
+                    }
+                }
+            }
+            Expression::FunctionCall(FunctionCall::Direct {
+                function,
+                args,
+                defaulted,
+                span,
+                ..
+            }) => {
+                // the defaults are computed by the callee, where they can see its parameters
+                let args = &args[..args.len() - defaulted];
+                self.expressions(args);
+                self.emit(
+                    Instruction::Call {
+                        function: *function,
+                        args: args.len(),
+                    },
+                    span,
+                );
+            }
+            Expression::FunctionCall(FunctionCall::Intrinsic {
+                intrinsic,
+                args,
+                span,
+                ..
+            }) => {
+                self.expressions(args);
+                self.emit(
+                    Instruction::Intrinsic {
+                        intrinsic: *intrinsic,
+                        args: args.len(),
+                    },
+                    span,
+                );
+            }
+            Expression::FunctionCall(FunctionCall::Indirect {
+                callee, args, span, ..
+            }) => {
+                self.expression(callee);
+                self.expressions(args);
+                self.emit(Instruction::CallValue { args: args.len() }, span);
+            }
+            Expression::EnumConstructor(constructor) => {
+                let variant = self
+                    .program
+                    .enums
+                    .values()
+                    .filter(|enum_| enum_.name == constructor.enum_name)
+                    .flat_map(|enum_| &enum_.variants)
+                    .find(|variant| variant.name == constructor.variant);
+                let message = match variant {
+                    None => format!(
+                        "`{}::{}` is not defined",
+                        constructor.enum_name, constructor.variant
+                    ),
+                    Some(variant) if variant.fields.len() != constructor.args.len() => format!(
+                        "`{}::{}` has {} fields, but got {}",
+                        constructor.enum_name,
+                        constructor.variant,
+                        variant.fields.len(),
+                        constructor.args.len()
+                    ),
+                    Some(variant) => {
+                        self.expressions(&constructor.args);
+                        self.emit(
+                            Instruction::Enum {
+                                name: constructor.enum_name,
+                                variant: constructor.variant,
+                                fields: variant.fields.iter().map(|field| field.name).collect(),
+                            },
+                            &constructor.span,
+                        );
+                        return;
+                    }
+                };
+                self.emit(Instruction::Fail(message), &constructor.span);
+            }
+            Expression::Variable(var) => {
+                let instruction = match var.id {
+                    Some(id) => Instruction::Load(self.slots[&id]),
+                    None => match self.program.function_named(self.function, var.name) {
+                        Some(function) => Instruction::Function(function),
+                        None => Instruction::Fail(format!("`{}` is not defined", var.name)),
+                    },
+                };
+                self.emit(instruction, &var.span);
+            }
+            Expression::Number(number) => {
+                self.emit(Instruction::Int(number.value), &number.span);
+            }
+            Expression::String(string) => {
+                self.emit(Instruction::String(string.value), &string.span);
+            }
+            Expression::Unit(_, span) => {
+                self.emit(Instruction::Unit, span);
+            }
+            Expression::Bool(value, _, span) => {
+                self.emit(Instruction::Bool(*value), span);
+            }
+            Expression::None(_, span) => {
+                self.emit(Instruction::None, span);
+            }
+            Expression::Some(value, _, span) => {
+                self.expression(value);
+                self.emit(Instruction::Some, span);
+            }
+            Expression::If(if_) => self.if_(if_),
+            Expression::Lambda(lambda) => {
+                let compiler = Compiler {
+                    program: self.program,
+                    function: self.function,
+                    slots: self.slots,
+                    lambdas: &mut *self.lambdas,
+                    code: Vec::new(),
+                    spans: Vec::new(),
+                };
+                let chunk = compiler.chunk(None, &lambda.params, &lambda.body);
+                self.lambdas.push(chunk);
+                self.emit(Instruction::Closure(self.lambdas.len() - 1), &lambda.span);
+            }
+            Expression::Field(field) => {
+                self.expression(&field.object);
+                self.emit(Instruction::Field(field.name), &field.span);
+            }
+            Expression::MethodCall(call) => {
+                self.expression(&call.object);
+                self.expressions(&call.args);
+                self.emit(
+                    Instruction::Method {
+                        name: call.method,
+                        args: call.args.len(),
+                    },
+                    &call.span,
+                );
+            }
+            Expression::Try(try_) => {
+                let try_at = self.emit(
+                    Instruction::Try {
+                        rescue: 0,
+                        slot: self.slots[&try_.error_id],
+                    },
+                    &try_.span,
+                );
+                self.expression(&try_.body);
+                self.emit(Instruction::EndTry, &try_.span);
+                let jump = self.emit(Instruction::Jump(0), &try_.span);
+                self.patch(try_at);
+                self.expression(&try_.rescue);
+                self.patch(jump);
+            }
+            Expression::Range(range) => {
+                self.expression(&range.start);
+                self.expression(&range.end);
+                self.emit(
+                    Instruction::Range {
+                        inclusive: range.inclusive,
+                    },
+                    &range.span,
+                );
+            }
+            Expression::Op(lhs, op, rhs, span) => {
+                self.expression(lhs);
+                self.expression(rhs);
+                self.emit(Instruction::Op(*op), span);
+            }
+            Expression::Error(span) => {
+                self.emit(
+                    Instruction::Fail("the program has a syntax error here".to_string()),
+                    span,
+                );
+            }
+        }
+    }
+
+    fn if_(&mut self, if_: &If<'input>) {
+        self.expression(&if_.condition);
+        let otherwise = self.emit(Instruction::JumpUnless(0), &if_.condition.span());
+        self.expression(&if_.body);
+        let jump = self.emit(Instruction::Jump(0), &if_.span);
+        self.patch(otherwise);
+        match &if_.else_body {
+            Some(Else::Block(block)) => self.expression(block),
+            Some(Else::If(else_if)) => self.if_(else_if),
+            None => {
+                self.emit(Instruction::Unit, &if_.span);
+            }
+        }
+        self.patch(jump);
+    }
+
+    fn statement(&mut self, stmt: &Statement<'input>) {
+        match stmt {
+            Statement::Let(let_) => {
+                self.expression(&let_.value);
+                self.emit(Instruction::Store(self.slots[&let_.id]), &let_.span);
+            }
+            Statement::Assign(assign) => {
+                let var = match assign.variable {
+                    Some(var) => var,
+                    None => {
+                        self.emit(
+                            Instruction::Fail(format!("`{}` is not defined", assign.target)),
+                            &assign.span,
+                        );
+                        return;
+                    }
+                };
+                let slot = self.slots[&var];
+                match assign.op {
+                    Some(op) => {
+                        self.emit(Instruction::Load(slot), &assign.span);
+                        self.expression(&assign.value);
+                        self.emit(Instruction::Op(op), &assign.span);
+                    }
+                    None => self.expression(&assign.value),
+                }
+                self.emit(Instruction::Store(slot), &assign.span);
+            }
+            Statement::Expression(expr) => {
+                self.expression(expr);
+                self.emit(Instruction::Pop, &expr.span());
+            }
+            Statement::Return(value, _, span) => {
+                self.expression(value);
+                self.emit(Instruction::Return, span);
+            }
+            Statement::Assert(assert) => {
+                self.expression(&assert.condition);
+                let passed = self.emit(Instruction::JumpIf(0), &assert.condition.span());
+                self.emit(
+                    Instruction::Fail(format!("assertion `{}` failed", assert.text)),
+                    &assert.span,
+                );
+                self.patch(passed);
+            }
+            Statement::Comment(_) | Statement::Function(_) => {}
+        }
+    }
+}
@@ -0,0 +1,29 @@
+//! Export of [`Program::calls`] for visualisation.
+use id_collections::Id;
+
+use crate::resolved_ast::*;
+
+/// The call graph in GraphViz DOT, with a node for every function and an edge from each function
+/// to the ones it calls. Nested functions are labelled with the functions around them.
+pub fn dot(program: &Program) -> String {
+    let mut dot = String::from("digraph calls {\n");
+    for (id, func) in &program.functions {
+        let mut label = func.definition.name.to_string();
+        let mut parent = func.parent;
+        while let Some(outer) = parent {
+            label = format!("{}::{}", program.functions[outer].definition.name, label);
+            parent = program.functions[outer].parent;
+        }
+        if Some(id) == program.script {
+            label += " (top level statements)";
+        }
+        dot += &format!("    {} [label=\"{}\"];\n", id.to_index(), label);
+    }
+    for (id, callees) in &program.calls {
+        for callee in callees {
+            dot += &format!("    {} -> {};\n", id.to_index(), callee.to_index());
+        }
+    }
+    dot += "}\n";
+    dot
+}
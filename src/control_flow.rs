@@ -0,0 +1,138 @@
+//! Checks the control flow of function bodies: functions that return a value on some paths but
+//! reach the end of their body on others, and code after a return that can never run.
+use std::ops;
+
+use crate::diagnostics::Diagnostic;
+use crate::resolved_ast::*;
+use crate::visit::{walk_expression, walk_function, walk_statement, Visitor};
+
+/// Check the functions of a linked program, except the imported ones.
+pub fn check(program: &Program) -> Vec<Diagnostic> {
+    let mut checker = Checker {
+        diagnostics: Vec::new(),
+    };
+    for (id, func) in &program.functions {
+        if program.is_imported(id) {
+            continue;
+        }
+        // returning from the top level statements ends the program, there is no value to miss
+        if Some(id) != program.script {
+            checker.check_returns(func);
+        }
+        walk_function(&mut checker, func);
+    }
+    checker.diagnostics
+}
+
+struct Checker {
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Checker {
+    fn check_returns(&mut self, func: &Function) {
+        // a body with a value returns it on every path that doesn't return earlier
+        if !matches!(*func.body, Expression::Block(_, None, _)) || diverges(&func.body).is_some() {
+            return;
+        }
+        let mut returns = FirstReturn { span: None };
+        returns.visit_expression(&func.body);
+        if let Some(span) = returns.span {
+            self.diagnostics.push(
+                Diagnostic::warning(
+                    format!(
+                        "`{}` returns a value on some paths but not on others",
+                        func.definition.name
+                    ),
+                    func.definition.span.clone(),
+                )
+                .with_code("W001")
+                .with_note("it returns a value here".to_string(), span),
+            );
+        }
+    }
+}
+
+impl<'input> Visitor<'input> for Checker {
+    fn visit_expression(&mut self, expr: &Expression<'input>) {
+        if let Expression::Block(block, tail, _) = expr {
+            let mut statements = block.iter();
+            let returning = statements
+                .by_ref()
+                .find_map(|stmt| Some((stmt, always_returns(stmt)?)));
+            let rest = statements
+                .any(|stmt| !matches!(stmt, Statement::Comment(_) | Statement::Function(_)));
+            if let Some((stmt, span)) = returning.filter(|_| rest || tail.is_some()) {
+                let message = match stmt {
+                    Statement::Return(..) => "the code after this return is unreachable",
+                    _ => "the code after this is unreachable, every path through it returns",
+                };
+                self.diagnostics
+                    .push(Diagnostic::warning(message.to_string(), span).with_code("W002"));
+            }
+        }
+        walk_expression(self, expr);
+    }
+}
+
+/// Finds the first return statement of a function body, leaving out the ones of lambdas and of
+/// `expr?`, which returns `none` on its own.
+struct FirstReturn {
+    span: Option<ops::Range<usize>>,
+}
+
+impl<'input> Visitor<'input> for FirstReturn {
+    fn visit_statement(&mut self, stmt: &Statement<'input>) {
+        match stmt {
+            Statement::Return(_, _, span) if self.span.is_none() => self.span = Some(span.clone()),
+            _ => walk_statement(self, stmt),
+        }
+    }
+
+    fn visit_expression(&mut self, expr: &Expression<'input>) {
+        if let Some(operand) = expr.propagated() {
+            self.visit_expression(operand);
+        } else if !matches!(expr, Expression::Lambda(_)) {
+            walk_expression(self, expr);
+        }
+    }
+}
+
+/// The span of what makes the statement return on every path, if it does.
+fn always_returns(stmt: &Statement) -> Option<ops::Range<usize>> {
+    match stmt {
+        Statement::Return(_, _, span) => Some(span.clone()),
+        Statement::Expression(expr) => diverges(expr),
+        Statement::Let(let_) => diverges(&let_.value),
+        Statement::Assign(assign) => diverges(&assign.value),
+        Statement::Assert(assert) => diverges(&assert.condition),
+        Statement::Comment(_) | Statement::Function(_) => None,
+    }
+}
+
+/// The span of what makes evaluating the expression return on every path, if it does.
+fn diverges(expr: &Expression) -> Option<ops::Range<usize>> {
+    match expr {
+        Expression::Expression(expr)
+        | Expression::Paren(expr, _)
+        | Expression::ExpressionComment((expr, _)) => diverges(expr),
+        Expression::Block(block, tail, _) => block
+            .iter()
+            .find_map(always_returns)
+            .or_else(|| tail.as_deref().and_then(diverges)),
+        Expression::If(if_) => if_diverges(if_),
+        Expression::Try(try_) => diverges(&try_.rescue).and(diverges(&try_.body)),
+        _ => None,
+    }
+}
+
+fn if_diverges(if_: &If) -> Option<ops::Range<usize>> {
+    diverges(&if_.condition).or_else(|| {
+        diverges(&if_.body)?;
+        match &if_.else_body {
+            Some(Else::Block(block)) => diverges(block)?,
+            Some(Else::If(else_if)) => if_diverges(else_if)?,
+            None => return None,
+        };
+        Some(if_.span.clone())
+    })
+}
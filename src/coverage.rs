@@ -0,0 +1,291 @@
+//! Statistics on which language constructs a set of programs uses.
+//!
+//! Used by `wryneck grammar-coverage` and the test suite to make sure every construct the grammar
+//! accepts shows up in the example corpus in `tests/`.
+use std::collections::BTreeMap;
+
+use crate::resolved_ast::*;
+use crate::visit::{walk_expression, walk_function, walk_program, walk_statement, Visitor};
+
+/// Every construct the grammar can produce, apart from error recovery.
+pub const CONSTRUCTS: &[&str] = &[
+    "function",
+    "function parameters",
+    "parameter type",
+    "default parameter value",
+    "return type",
+    "function tests",
+    "named test",
+    "test expecting an error",
+    "property test",
+    "benchmarks",
+    "nested function",
+    "enum",
+    "enum variant",
+    "enum variant fields",
+    "top level comment",
+    "import",
+    "top level statement",
+    "let",
+    "let mut",
+    "assignment",
+    "compound assignment",
+    "expression statement",
+    "return",
+    "assert",
+    "comment",
+    "block",
+    "block value",
+    "function call",
+    "indirect call",
+    "intrinsic call",
+    "pipeline",
+    "enum constructor",
+    "enum constructor arguments",
+    "variable",
+    "decimal number",
+    "hexadecimal number",
+    "octal number",
+    "binary number",
+    "string",
+    "multiline string",
+    "raw string",
+    "unit",
+    "bool",
+    "none",
+    "some",
+    "if",
+    "else",
+    "else if",
+    "conditional",
+    "lambda",
+    "field access",
+    "method call",
+    "try",
+    "propagation",
+    "range",
+    "inclusive range",
+    "addition",
+    "subtraction",
+    "multiplication",
+    "division",
+    "concatenation",
+    "equality",
+    "inequality",
+];
+
+#[derive(Debug, Default)]
+pub struct Coverage {
+    /// How often each construct was seen.
+    pub counts: BTreeMap<&'static str, usize>,
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count the constructs used by the program.
+    pub fn record(&mut self, program: &Program) {
+        walk_program(self, program);
+    }
+
+    /// The constructs that were never seen.
+    pub fn missing(&self) -> Vec<&'static str> {
+        CONSTRUCTS
+            .iter()
+            .copied()
+            .filter(|construct| !self.counts.contains_key(construct))
+            .collect()
+    }
+
+    fn saw(&mut self, construct: &'static str) {
+        debug_assert!(
+            CONSTRUCTS.contains(&construct),
+            "unknown construct {}",
+            construct
+        );
+        *self.counts.entry(construct).or_insert(0) += 1;
+    }
+}
+
+impl<'input> Visitor<'input> for Coverage {
+    fn visit_function(&mut self, func: &Function<'input>) {
+        self.saw("function");
+        if !func.definition.params.is_empty() {
+            self.saw("function parameters");
+        }
+        if func
+            .definition
+            .params
+            .iter()
+            .any(|param| param.type_.is_some())
+        {
+            self.saw("parameter type");
+        }
+        if func
+            .definition
+            .params
+            .iter()
+            .any(|param| param.default.is_some())
+        {
+            self.saw("default parameter value");
+        }
+        if func.definition.return_type.is_some() {
+            self.saw("return type");
+        }
+        if !func.tests.is_empty() {
+            self.saw("function tests");
+        }
+        if func.tests.iter().any(|test| test.name.is_some()) {
+            self.saw("named test");
+        }
+        if func
+            .tests
+            .iter()
+            .any(|test| matches!(test.output, Expected::Failure(_)))
+        {
+            self.saw("test expecting an error");
+        }
+        if func.tests.iter().any(|test| test.forall.is_some()) {
+            self.saw("property test");
+        }
+        if !func.benchmarks.is_empty() {
+            self.saw("benchmarks");
+        }
+        walk_function(self, func);
+    }
+
+    fn visit_script(&mut self, func: &Function<'input>) {
+        // not a `function`, nobody wrote one
+        self.saw("top level statement");
+        walk_function(self, func);
+    }
+
+    fn visit_enum(&mut self, enum_: &Enum<'input>) {
+        self.saw("enum");
+        for variant in &enum_.variants {
+            self.saw("enum variant");
+            if !variant.fields.is_empty() {
+                self.saw("enum variant fields");
+            }
+        }
+    }
+
+    fn visit_import(&mut self, _import: &Import<'input>) {
+        self.saw("import");
+    }
+
+    fn visit_comment(&mut self, _comment: &Comment<'input>) {
+        self.saw("top level comment");
+    }
+
+    fn visit_statement(&mut self, stmt: &Statement<'input>) {
+        match stmt {
+            Statement::Let(let_) => self.saw(if let_.mutable.is_some() {
+                "let mut"
+            } else {
+                "let"
+            }),
+            Statement::Assign(assign) => match assign.op {
+                Some(_) => self.saw("compound assignment"),
+                None => self.saw("assignment"),
+            },
+            Statement::Expression(_) => self.saw("expression statement"),
+            Statement::Return(..) => self.saw("return"),
+            Statement::Assert(_) => self.saw("assert"),
+            // not `top level comment`, so don't recurse into visit_comment
+            Statement::Comment(_) => return self.saw("comment"),
+            Statement::Function(_) => self.saw("nested function"),
+        }
+        walk_statement(self, stmt);
+    }
+
+    fn visit_expression(&mut self, expr: &Expression<'input>) {
+        // only the operand was written, not what it desugars to
+        if let Some(operand) = expr.propagated() {
+            self.saw("propagation");
+            return self.visit_expression(operand);
+        }
+        match expr {
+            Expression::Block(_, tail, _) => {
+                self.saw("block");
+                if tail.is_some() {
+                    self.saw("block value");
+                }
+            }
+            Expression::FunctionCall(call) => {
+                self.saw(match call {
+                    FunctionCall::Direct { .. } => "function call",
+                    FunctionCall::Intrinsic { .. } => "intrinsic call",
+                    FunctionCall::Indirect { .. } => "indirect call",
+                });
+                if call.piped() {
+                    self.saw("pipeline");
+                }
+            }
+            Expression::EnumConstructor(constructor) => {
+                self.saw("enum constructor");
+                if !constructor.args.is_empty() {
+                    self.saw("enum constructor arguments");
+                }
+            }
+            Expression::Variable(_) => self.saw("variable"),
+            Expression::Number(number) => self.saw(match number.radix {
+                Radix::Binary => "binary number",
+                Radix::Octal => "octal number",
+                Radix::Decimal => "decimal number",
+                Radix::Hexadecimal => "hexadecimal number",
+            }),
+            Expression::String(string) => self.saw(match string.kind {
+                StringKind::Plain => "string",
+                StringKind::Multiline => "multiline string",
+                StringKind::Raw { .. } => "raw string",
+            }),
+            Expression::If(if_) if if_.conditional_branches().is_some() => self.saw("conditional"),
+            Expression::If(if_) => {
+                let mut if_ = if_;
+                self.saw("if");
+                while let Some(else_) = &if_.else_body {
+                    match else_ {
+                        Else::Block(_) => {
+                            self.saw("else");
+                            break;
+                        }
+                        Else::If(else_if) => {
+                            self.saw("else if");
+                            if_ = else_if;
+                        }
+                    }
+                }
+            }
+            Expression::Unit(_, _) => self.saw("unit"),
+            Expression::Bool(..) => self.saw("bool"),
+            Expression::None(_, _) => self.saw("none"),
+            Expression::Some(..) => self.saw("some"),
+            Expression::Lambda(_) => self.saw("lambda"),
+            Expression::Field(_) => self.saw("field access"),
+            Expression::MethodCall(_) => self.saw("method call"),
+            Expression::Try(_) => self.saw("try"),
+            Expression::Range(range) => self.saw(if range.inclusive {
+                "inclusive range"
+            } else {
+                "range"
+            }),
+            Expression::Op(_, op, _, _) => self.saw(match op {
+                Opcode::Add => "addition",
+                Opcode::Sub => "subtraction",
+                Opcode::Mul => "multiplication",
+                Opcode::Div => "division",
+                Opcode::Concat => "concatenation",
+                Opcode::Eq => "equality",
+                Opcode::NotEq => "inequality",
+            }),
+            Expression::Expression(_)
+            | Expression::Paren(..)
+            | Expression::ExpressionComment(_)
+            | Expression::Error(_) => {}
+        }
+        walk_expression(self, expr);
+    }
+}
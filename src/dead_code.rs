@@ -0,0 +1,35 @@
+//! Warns about functions that can never be called, because no call from `hatch` leads to them.
+use std::collections::HashSet;
+
+use crate::diagnostics::Diagnostic;
+use crate::resolved_ast::*;
+
+/// Check the functions of a linked program. Programs without a `hatch` are libraries, whose
+/// functions are all meant to be called from elsewhere, and so are imported functions.
+pub fn check(program: &Program) -> Vec<Diagnostic> {
+    let hatch = match program.hatch() {
+        Some(hatch) => hatch,
+        None => return Vec::new(),
+    };
+    let mut reachable = HashSet::from([hatch]);
+    let mut unvisited = vec![hatch];
+    while let Some(func) = unvisited.pop() {
+        for callee in &program.calls[func] {
+            if reachable.insert(*callee) {
+                unvisited.push(*callee);
+            }
+        }
+    }
+    program
+        .functions
+        .iter()
+        .filter(|(id, _)| !reachable.contains(id) && !program.is_imported(*id))
+        .map(|(_, func)| {
+            Diagnostic::warning(
+                format!("`{}` is never called", func.definition.name),
+                func.definition.span.clone(),
+            )
+            .with_code("W011")
+        })
+        .collect()
+}
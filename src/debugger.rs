@@ -0,0 +1,196 @@
+//! Stops a program the interpreter runs at breakpoints or step by step, and shows the values of
+//! its variables, by the commands it reads. It only knows the text of the file it was started
+//! on, so it shows no lines of imported functions and can't break on them by line.
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+
+use crate::interpreter::{Event, Observer, Value};
+use crate::resolved_ast::{FunctionId, Program, VariableId};
+use crate::Span;
+
+const HELP: &str = "\
+break <function or line>   stop when the function is called or the line is reached
+delete <function or line>  remove a breakpoint
+step                       go on until the program goes on anywhere else
+next                       go on, but not into the calls of this function
+continue                   go on until a breakpoint
+print <variable>           show the value of a variable
+locals                     show the values of all variables
+quit                       stop the program";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Breakpoint {
+    Function(String),
+    Line(usize),
+}
+
+impl Breakpoint {
+    fn parse(text: &str) -> Option<Self> {
+        match text.parse() {
+            Ok(line) => Some(Breakpoint::Line(line)),
+            Err(_) if !text.is_empty() => Some(Breakpoint::Function(text.to_string())),
+            Err(_) => None,
+        }
+    }
+}
+
+/// How the program goes on until it stops again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Continue,
+    Step,
+    /// Stop at the same depth or one that is less deep.
+    Next(usize),
+}
+
+pub struct Debugger<'a, R, W> {
+    program: &'a Program<'a>,
+    /// The text of the file the program was started from.
+    text: &'a str,
+    input: R,
+    output: W,
+    breakpoints: Vec<Breakpoint>,
+    mode: Mode,
+}
+
+impl<'a, R: BufRead, W: Write> Debugger<'a, R, W> {
+    /// A debugger that stops before the program does anything, so breakpoints can be set.
+    pub fn new(program: &'a Program<'a>, text: &'a str, input: R, output: W) -> Self {
+        Self {
+            program,
+            text,
+            input,
+            output,
+            breakpoints: Vec::new(),
+            mode: Mode::Step,
+        }
+    }
+
+    /// The line of the start of a span, counting from 1, if it is in the file of the debugger.
+    fn line(&self, function: FunctionId, span: &Span) -> Option<usize> {
+        (!self.program.is_imported(function))
+            .then(|| self.text[..span.start].matches('\n').count() + 1)
+    }
+
+    fn stops(&self, event: Event, function: FunctionId, span: &Span, depth: usize) -> bool {
+        let stepped = match self.mode {
+            Mode::Continue => false,
+            Mode::Step => true,
+            Mode::Next(next) => depth <= next,
+        };
+        let name = self.program.functions[function].definition.name;
+        let line = self.line(function, span);
+        stepped
+            || self.breakpoints.iter().any(|breakpoint| match breakpoint {
+                Breakpoint::Function(breakpoint) => event == Event::Call && breakpoint == name,
+                Breakpoint::Line(breakpoint) => {
+                    event == Event::Evaluate && Some(*breakpoint) == line
+                }
+            })
+    }
+
+    /// Read commands until one makes the program go on, false if it should stop.
+    fn prompt(
+        &mut self,
+        depth: usize,
+        variables: &HashMap<VariableId, Value<'a>>,
+    ) -> std::io::Result<bool> {
+        loop {
+            write!(self.output, "(wryneck) ")?;
+            self.output.flush()?;
+            let mut command = String::new();
+            if self.input.read_line(&mut command)? == 0 {
+                // the input ended, there is no one left to debug the program
+                writeln!(self.output)?;
+                return Ok(false);
+            }
+            let (command, argument) = match command.trim().split_once(' ') {
+                Some((command, argument)) => (command, argument.trim()),
+                None => (command.trim(), ""),
+            };
+            match command {
+                "break" | "b" => match Breakpoint::parse(argument) {
+                    Some(breakpoint) => self.breakpoints.push(breakpoint),
+                    None => writeln!(self.output, "`break` needs a function or a line")?,
+                },
+                "delete" | "d" => match Breakpoint::parse(argument) {
+                    Some(breakpoint) if self.breakpoints.contains(&breakpoint) => {
+                        self.breakpoints.retain(|other| *other != breakpoint)
+                    }
+                    _ => writeln!(self.output, "there is no breakpoint `{}`", argument)?,
+                },
+                "step" | "s" => {
+                    self.mode = Mode::Step;
+                    return Ok(true);
+                }
+                "next" | "n" => {
+                    self.mode = Mode::Next(depth);
+                    return Ok(true);
+                }
+                "continue" | "c" => {
+                    self.mode = Mode::Continue;
+                    return Ok(true);
+                }
+                "print" | "p" => {
+                    // the variable bound last shadows the others of the same name
+                    let value = variables
+                        .iter()
+                        .filter(|(id, _)| self.program.variables[**id].name == argument)
+                        .max_by_key(|(id, _)| **id)
+                        .map(|(_, value)| value);
+                    match value {
+                        Some(value) => writeln!(self.output, "{} = {}", argument, value)?,
+                        None => writeln!(self.output, "`{}` has no value here", argument)?,
+                    }
+                }
+                "locals" | "l" => {
+                    let mut variables = variables.iter().collect::<Vec<_>>();
+                    variables.sort_by_key(|(id, _)| **id);
+                    for (id, value) in variables {
+                        let name = self.program.variables[*id].name;
+                        writeln!(self.output, "{} = {}", name, value)?;
+                    }
+                }
+                "quit" | "q" => return Ok(false),
+                "help" | "h" => writeln!(self.output, "{}", HELP)?,
+                "" => {}
+                command => writeln!(
+                    self.output,
+                    "unknown command `{}`, `help` lists them",
+                    command
+                )?,
+            }
+        }
+    }
+}
+
+impl<'a, R: BufRead, W: Write> Observer<'a> for Debugger<'a, R, W> {
+    fn observe(
+        &mut self,
+        event: Event,
+        function: FunctionId,
+        span: &Span,
+        depth: usize,
+        variables: &HashMap<VariableId, Value<'a>>,
+    ) -> bool {
+        if !self.stops(event, function, span, depth) {
+            return true;
+        }
+        let name = self.program.functions[function].definition.name;
+        let location = match self.line(function, span) {
+            Some(line) => {
+                let text = self.text.lines().nth(line - 1).unwrap_or_default();
+                format!("`{}`, line {}: {}", name, line, text.trim())
+            }
+            None => format!("`{}`, which is imported", name),
+        };
+        let stopped = match event {
+            Event::Call => writeln!(self.output, "called {}", location),
+            Event::Evaluate => writeln!(self.output, "at {}", location),
+        };
+        // a debugger that can't talk to anyone stops the program
+        stopped
+            .and_then(|()| self.prompt(depth, variables))
+            .unwrap_or(false)
+    }
+}
@@ -0,0 +1,1002 @@
+//! Problems found in a program, by the parser or the passes after it, and how they are shown with
+//! the source they point at.
+use std::fmt::{Display, Error};
+use std::ops::Range;
+
+use colored::{ColoredString, Colorize};
+use lalrpop_util::{lexer::Token, ParseError};
+use serde::Serialize;
+
+use crate::line_index::LineIndex;
+use crate::GrammarError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Display for Severity {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> Result<(), Error> {
+        match self {
+            Severity::Error => write!(fmt, "error"),
+            Severity::Warning => write!(fmt, "warning"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// What kind of problem it is, the same for every diagnostic of that kind.
+    pub code: Option<&'static str>,
+    pub message: String,
+    /// The byte range of the input the diagnostic points at.
+    pub span: Range<usize>,
+    /// Other places of the input that explain the diagnostic, with what they are.
+    pub notes: Vec<(String, Range<usize>)>,
+    /// What else there is to say about the diagnostic, that isn't about a place of the input.
+    pub help: Vec<String>,
+    /// How to fix the problem, if it is clear enough to be done for the user.
+    pub fix: Option<Fix>,
+    /// The file the spans and the edits of the fix point into, by its index among the files of a
+    /// linked program: 0 for the main file, then the files it imports in the order they are
+    /// loaded.
+    pub file: usize,
+}
+
+/// A change to the text of a file: what replaces its text in `span`, which is empty to insert.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edit {
+    pub span: Range<usize>,
+    pub replacement: String,
+}
+
+impl Edit {
+    pub fn insert(at: usize, text: impl Into<String>) -> Self {
+        Self {
+            span: at..at,
+            replacement: text.into(),
+        }
+    }
+
+    pub fn replace(span: Range<usize>, text: impl Into<String>) -> Self {
+        Self {
+            span,
+            replacement: text.into(),
+        }
+    }
+
+    /// Whether the edits change the same text, or insert at the same place, so applying one
+    /// changes what the other means.
+    fn overlaps(&self, other: &Edit) -> bool {
+        self.span.start == other.span.start
+            || (self.span.start < other.span.end && other.span.start < self.span.end)
+    }
+}
+
+/// The edits that fix what a diagnostic is about, in the file it points into.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fix {
+    /// What the edits do, like "insert the missing `;`".
+    pub message: String,
+    pub edits: Vec<Edit>,
+}
+
+/// Apply fixes to `text`, skipping the ones with an edit that overlaps an edit of a fix before
+/// them, and return the fixed text with how many fixes it took.
+pub fn apply_fixes<'f>(text: &str, fixes: impl IntoIterator<Item = &'f Fix>) -> (String, usize) {
+    let mut edits: Vec<&Edit> = Vec::new();
+    let mut applied = 0;
+    for fix in fixes {
+        if fix
+            .edits
+            .iter()
+            .any(|edit| edits.iter().any(|other| edit.overlaps(other)))
+        {
+            continue;
+        }
+        edits.extend(&fix.edits);
+        applied += 1;
+    }
+    // from the end, so the spans of the edits still to make stay where they are
+    edits.sort_by_key(|edit| std::cmp::Reverse(edit.span.start));
+    let mut text = text.to_string();
+    for edit in edits {
+        text.replace_range(edit.span.clone(), &edit.replacement);
+    }
+    (text, applied)
+}
+
+impl Diagnostic {
+    pub fn error(message: impl Into<String>, span: Range<usize>) -> Self {
+        Self {
+            severity: Severity::Error,
+            code: None,
+            message: message.into(),
+            span,
+            notes: Vec::new(),
+            help: Vec::new(),
+            fix: None,
+            file: 0,
+        }
+    }
+
+    pub fn warning(message: impl Into<String>, span: Range<usize>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            code: None,
+            message: message.into(),
+            span,
+            notes: Vec::new(),
+            help: Vec::new(),
+            fix: None,
+            file: 0,
+        }
+    }
+
+    pub fn with_note(mut self, message: impl Into<String>, span: Range<usize>) -> Self {
+        self.notes.push((message.into(), span));
+        self
+    }
+
+    pub fn with_help(mut self, message: impl Into<String>) -> Self {
+        self.help.push(message.into());
+        self
+    }
+
+    pub fn with_fix(mut self, message: impl Into<String>, edits: Vec<Edit>) -> Self {
+        self.fix = Some(Fix {
+            message: message.into(),
+            edits,
+        });
+        self
+    }
+
+    /// Point the diagnostic into another file of a linked program than the main file.
+    pub fn with_file(mut self, file: usize) -> Self {
+        self.file = file;
+        self
+    }
+
+    /// Give the diagnostic one of the codes [`explain`] knows.
+    pub fn with_code(mut self, code: &'static str) -> Self {
+        debug_assert!(explain(code).is_some(), "unknown code {}", code);
+        self.code = Some(code);
+        self
+    }
+
+    /// The error the parser stopped at, or recovered from, in `text`.
+    pub fn from_parse_error(error: &ParseError<usize, Token, GrammarError>, text: &str) -> Self {
+        let diagnostic = match error {
+            ParseError::InvalidToken { location } => {
+                Self::error("invalid token", *location..*location + 1).with_code("E001")
+            }
+            ParseError::UnrecognizedEOF { location, expected }
+                if expected.iter().any(|token| token == "\"}\"") =>
+            {
+                match unclosed_block(text) {
+                    Some(brace) => Self::error(
+                        "expected `}` before the end of the file",
+                        *location..*location,
+                    )
+                    .with_code("E009")
+                    .with_note("unclosed block started here", brace..brace + 1),
+                    None => Self::error("unexpected end of file", *location..*location)
+                        .with_code("E002")
+                        .with_help(format!("expected one of {}", expected.join(", "))),
+                }
+            }
+            ParseError::UnrecognizedEOF { location, expected } => {
+                Self::error("unexpected end of file", *location..*location)
+                    .with_code("E002")
+                    .with_help(format!("expected one of {}", expected.join(", ")))
+            }
+            ParseError::UnrecognizedToken {
+                token: (start, token, end),
+                expected,
+            } => Self::error(format!("unrecognized token `{}`", token), *start..*end)
+                .with_code("E003")
+                .with_help(format!("expected one of {}", expected.join(", "))),
+            ParseError::ExtraToken {
+                token: (start, token, end),
+            } => Self::error(format!("extra token `{}`", token), *start..*end).with_code("E004"),
+            ParseError::User { error } => match error {
+                GrammarError::InvalidNumber(span, _) => {
+                    Self::error(error.to_string(), span.clone()).with_code("E005")
+                }
+                GrammarError::MissingSemicolon(at) => {
+                    // what comes next, which the `;` would be before
+                    let next = text.len() - text[*at..].trim_start().len();
+                    let diagnostic = Self::error(error.to_string(), *at..*at).with_code("E008");
+                    match missing_semicolon(text, next) {
+                        Some(at) => diagnostic
+                            .with_fix("insert the missing `;`", vec![Edit::insert(at, ";")]),
+                        None => diagnostic,
+                    }
+                }
+            },
+        };
+        match error {
+            ParseError::UnrecognizedEOF { location, expected }
+            | ParseError::UnrecognizedToken {
+                token: (location, _, _),
+                expected,
+            } if expected.iter().any(|token| token == "\";\"") => {
+                match missing_semicolon(text, *location) {
+                    Some(at) => {
+                        diagnostic.with_fix("insert the missing `;`", vec![Edit::insert(at, ";")])
+                    }
+                    None => diagnostic,
+                }
+            }
+            _ => diagnostic,
+        }
+    }
+}
+
+/// Where a `;` goes when the parser expected one at `location`: after the text before it, if that
+/// ends a line, before the end of the text, a line break or a comment. Within a line, what is
+/// missing is less clear, and after a comment a `;` would be part of it.
+fn missing_semicolon(text: &str, location: usize) -> Option<usize> {
+    let before = text[..location].trim_end();
+    let line = &before[before.rfind('\n').map_or(0, |i| i + 1)..];
+    let ends_line = location == text.len()
+        || text[before.len()..location].contains('\n')
+        || text[location..].starts_with("//");
+    (!before.is_empty() && ends_line && !line.contains("//")).then_some(before.len())
+}
+
+/// Where the `{` is that no `}` closes, if it is the innermost bracket left open, leaving out
+/// comments and strings, which may have brackets of their own.
+///
+/// A `}` that starts a line indented less than the line of the `{` it closes is taken to close a
+/// block around it, so the innermost `{` closed like that is the one missing its `}`, as long as
+/// it is in the innermost block left open.
+fn unclosed_block(text: &str) -> Option<usize> {
+    // how far the line an offset is on is indented, if only whitespace comes before the offset
+    let indent = |at: usize| {
+        let line = &text[text[..at].rfind('\n').map_or(0, |i| i + 1)..at];
+        line.trim_start().is_empty().then_some(line.len())
+    };
+    let line_indent = |at: usize| {
+        let start = text[..at].rfind('\n').map_or(0, |i| i + 1);
+        let line = &text[start..];
+        line.len() - line.trim_start().len()
+    };
+    let mut open = Vec::new();
+    let mut misclosed = None;
+    let mut at = 0;
+    while let Some(c) = text[at..].chars().next() {
+        let rest = &text[at..];
+        // where what starts here ends, if it is a comment or string
+        let end = if rest.starts_with("//") {
+            rest.find('\n')
+        } else if let Some(string) = rest.strip_prefix("\"\"\"") {
+            string.find("\"\"\"").map(|end| end + 6)
+        } else if let Some(string) = rest.strip_prefix("r#\"") {
+            string.find("\"#").map(|end| end + 5)
+        } else if let Some(string) = rest.strip_prefix('"') {
+            string.find('"').map(|end| end + 2)
+        } else {
+            match c {
+                '{' | '(' | '[' => open.push((c, at)),
+                '}' | ')' | ']' => {
+                    if let (Some(('{', brace)), '}', Some(indent)) = (open.pop(), c, indent(at)) {
+                        if indent < line_indent(brace) {
+                            misclosed = Some(brace);
+                        }
+                    }
+                }
+                _ => {}
+            }
+            Some(c.len_utf8())
+        };
+        at += end.unwrap_or(rest.len());
+    }
+    match open.pop() {
+        Some(('{', at)) => Some(misclosed.filter(|&brace| brace > at).unwrap_or(at)),
+        _ => None,
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> Result<(), Error> {
+        write!(fmt, "{}: {}", self.severity, self.message)
+    }
+}
+
+/// A span of the source to underline, with what it is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Label {
+    pub span: Range<usize>,
+    pub message: String,
+    /// Whether this is what the message is about, underlined with `^` rather than `-`.
+    pub primary: bool,
+}
+
+impl Label {
+    pub fn primary(span: Range<usize>, message: impl Into<String>) -> Self {
+        Self {
+            span,
+            message: message.into(),
+            primary: true,
+        }
+    }
+
+    pub fn secondary(span: Range<usize>, message: impl Into<String>) -> Self {
+        Self {
+            span,
+            message: message.into(),
+            primary: false,
+        }
+    }
+}
+
+impl Diagnostic {
+    /// The diagnostic as it is printed for people: its severity and message, the lines of the
+    /// file `name` it points at, and its help.
+    pub fn render(&self, name: &str, lines: &LineIndex, color: bool) -> String {
+        let header = match self.code {
+            Some(code) => format!("{}[{}]: {}", self.severity, code, self.message),
+            None => self.to_string(),
+        };
+        let mut text = match (color, self.severity) {
+            (false, _) => header,
+            (true, Severity::Error) => header.red().to_string(),
+            (true, Severity::Warning) => header.yellow().to_string(),
+        };
+        text += "\n";
+        let labels = self.labels();
+        // there are no lines to show of a file that couldn't be read, only its name
+        if lines.text().is_empty() && self.span == (0..0) {
+            text += &format!(" --> {}\n", name);
+        } else {
+            text += &render(name, lines, &labels, color);
+        }
+        let gutter = labels
+            .iter()
+            .map(|label| line_range(lines, &label.span).1)
+            .max()
+            .map_or(1, |line| line.to_string().len());
+        for help in &self.help {
+            text += &format!("{} = help: {}\n", " ".repeat(gutter), help);
+        }
+        if let Some(fix) = &self.fix {
+            text += &format!("{} = fix: {}\n", " ".repeat(gutter), fix.message);
+        }
+        text
+    }
+
+    /// The diagnostic as one line of JSON for editors: the file `name` it is in, its severity,
+    /// code and message, every span it points at with its line and column, its help, and the
+    /// edits of its fix.
+    pub fn json(&self, name: &str, lines: &LineIndex) -> String {
+        #[derive(Serialize)]
+        struct Position {
+            start: usize,
+            end: usize,
+            /// Counted from 1, like the columns.
+            line: usize,
+            column: usize,
+            end_line: usize,
+            end_column: usize,
+        }
+        #[derive(Serialize)]
+        struct Span<'a> {
+            #[serde(flatten)]
+            position: Position,
+            label: &'a str,
+            primary: bool,
+        }
+        #[derive(Serialize)]
+        struct Edit<'a> {
+            #[serde(flatten)]
+            position: Position,
+            replacement: &'a str,
+        }
+        #[derive(Serialize)]
+        struct Fix<'a> {
+            message: &'a str,
+            edits: Vec<Edit<'a>>,
+        }
+        #[derive(Serialize)]
+        struct Json<'a> {
+            file: &'a str,
+            severity: String,
+            code: Option<&'a str>,
+            message: &'a str,
+            spans: Vec<Span<'a>>,
+            help: &'a [String],
+            fix: Option<Fix<'a>>,
+        }
+        let position = |span: &Range<usize>| {
+            let (line, column) = lines.position(span.start);
+            let (end_line, end_column) = lines.position(span.end);
+            Position {
+                start: span.start,
+                end: span.end,
+                line,
+                column,
+                end_line,
+                end_column,
+            }
+        };
+        let spans = std::iter::once(("", &self.span, true))
+            .chain(
+                self.notes
+                    .iter()
+                    .map(|(message, span)| (message.as_str(), span, false)),
+            )
+            .map(|(label, span, primary)| Span {
+                position: position(span),
+                label,
+                primary,
+            })
+            .collect();
+        let fix = self.fix.as_ref().map(|fix| Fix {
+            message: &fix.message,
+            edits: fix
+                .edits
+                .iter()
+                .map(|edit| Edit {
+                    position: position(&edit.span),
+                    replacement: &edit.replacement,
+                })
+                .collect(),
+        });
+        let json = Json {
+            file: name,
+            severity: self.severity.to_string(),
+            code: self.code,
+            message: &self.message,
+            spans,
+            help: &self.help,
+            fix,
+        };
+        serde_json::to_string(&json).unwrap()
+    }
+
+    /// What the diagnostic points at, and its notes.
+    pub fn labels(&self) -> Vec<Label> {
+        std::iter::once(Label::primary(self.span.clone(), ""))
+            .chain(
+                self.notes
+                    .iter()
+                    .map(|(message, span)| Label::secondary(span.clone(), message.clone())),
+            )
+            .collect()
+    }
+}
+
+/// How many columns the start of a line takes up, with tabs as 4.
+fn width(text: &str) -> usize {
+    text.chars().map(|c| if c == '\t' { 4 } else { 1 }).sum()
+}
+
+/// The lines a span starts and ends on, the end included.
+fn line_range(lines: &LineIndex, span: &Range<usize>) -> (usize, usize) {
+    let (start, _) = lines.position(span.start);
+    let (end, _) = lines.position(span.end.max(span.start + 1) - 1);
+    (start, end.max(start))
+}
+
+/// The lines of the file `name` the labels are on, with the labels underlined below them and
+/// their messages after the underlines, like
+///
+/// ```text
+///  --> nest.wy:2:5
+///   |
+/// 1 | let eggs = 1;
+///   |     ---- declared here
+/// 2 | eggs + hens
+///   |        ^^^^
+/// ```
+///
+/// A label over several lines underlines all of them, and lines far apart are separated by
+/// `...`. Without `color`, there are no escape sequences in it.
+pub fn render(name: &str, lines: &LineIndex, labels: &[Label], color: bool) -> String {
+    let paint = |text: String, primary: bool| -> ColoredString {
+        match (color, primary) {
+            (false, _) => text.normal(),
+            (true, true) => text.red().bold(),
+            (true, false) => text.blue().bold(),
+        }
+    };
+    // the lines every label starts and ends on, the end included
+    let ranges = labels
+        .iter()
+        .map(|label| line_range(lines, &label.span))
+        .collect::<Vec<_>>();
+    let mut shown = ranges
+        .iter()
+        .flat_map(|&(start, end)| start..=end)
+        .collect::<Vec<_>>();
+    shown.sort_unstable();
+    shown.dedup();
+    let gutter = shown.last().map_or(1, |line| line.to_string().len());
+    let margin = paint(format!("{:gutter$} |", ""), false);
+    let mut text = String::new();
+    if let Some(label) = labels.iter().find(|label| label.primary).or(labels.first()) {
+        let (line, column) = lines.position(label.span.start);
+        text += &format!(
+            "{}{} {}:{}:{}\n",
+            " ".repeat(gutter),
+            paint("-->".into(), false),
+            name,
+            line,
+            column
+        );
+    }
+    text += &format!("{}\n", margin);
+    let mut previous = None;
+    for &number in &shown {
+        if previous.is_some_and(|previous| number > previous + 1) {
+            text += &format!("{}\n", paint("...".into(), false));
+        }
+        previous = Some(number);
+        let span = lines.line_span(number);
+        let line = lines.line(number);
+        text += &format!(
+            "{} {}\n",
+            paint(format!("{:>gutter$} |", number), false),
+            line.replace('\t', "    ")
+        );
+        // which column of the line each label underlines, and the messages that end here
+        let mut marks = vec![None; width(line) + 1];
+        let mut messages = Vec::new();
+        for (label, &(start, end)) in labels.iter().zip(&ranges) {
+            if !(start..=end).contains(&number) {
+                continue;
+            }
+            let column =
+                |offset: usize| width(&line[..offset.clamp(span.start, span.end) - span.start]);
+            let from = if start == number {
+                column(label.span.start)
+            } else {
+                0
+            };
+            let to = if end == number {
+                column(label.span.end).max(from + 1)
+            } else {
+                width(line)
+            };
+            for mark in &mut marks[from..to] {
+                // the primary label shows over the others
+                if *mark != Some(true) {
+                    *mark = Some(label.primary);
+                }
+            }
+            if end == number && !label.message.is_empty() {
+                messages.push((from, to, label));
+            }
+        }
+        let underline_end = marks.iter().rposition(Option::is_some).map_or(0, |i| i + 1);
+        let mut underline = String::new();
+        for mark in &marks[..underline_end] {
+            underline += &match mark {
+                None => " ".normal(),
+                Some(true) => paint("^".into(), true),
+                Some(false) => paint("-".into(), false),
+            }
+            .to_string();
+        }
+        // the message of the last underline goes after it, the others below the underlines
+        messages.sort_by_key(|&(from, to, _)| (to, from));
+        let mut row = format!("{} {}", margin, underline);
+        if let Some(&(_, to, label)) = messages.last() {
+            if to == underline_end {
+                row += &format!(" {}", paint(label.message.clone(), label.primary));
+                messages.pop();
+            }
+        }
+        text += row.trim_end();
+        text += "\n";
+        for (from, _, label) in messages.into_iter().rev() {
+            text += &format!(
+                "{} {}{}\n",
+                margin,
+                " ".repeat(from),
+                paint(label.message.clone(), label.primary)
+            );
+        }
+    }
+    text
+}
+
+/// What the diagnostics with a code are about, for `wryneck explain`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Explanation {
+    pub code: &'static str,
+    /// In a few words.
+    pub title: &'static str,
+    /// Why it is a problem and how to fix it, with examples.
+    pub description: &'static str,
+}
+
+/// The explanation of a code, like `E010`, in upper or lower case.
+pub fn explain(code: &str) -> Option<&'static Explanation> {
+    EXPLANATIONS
+        .iter()
+        .find(|explanation| explanation.code.eq_ignore_ascii_case(code))
+}
+
+/// Every code a diagnostic can have, in order. Codes starting with `E` are errors, those starting
+/// with `W` warnings unless a lint is denied. A code is never reused for something else.
+pub const EXPLANATIONS: &[Explanation] = &[
+    Explanation {
+        code: "E001",
+        title: "invalid token",
+        description: "\
+The parser came across characters that aren't part of any token, like a `$` outside of a string:
+
+    let price = $5;
+
+Remove them, or put them in a string.",
+    },
+    Explanation {
+        code: "E002",
+        title: "unexpected end of file",
+        description: "\
+The file ended in the middle of something, like a function call whose `)` is missing:
+
+    egg lay(eggs) {
+        eggs + max(1, 2
+
+Close what is still open. The help lists what the parser expected to come next. A block whose
+`}` is missing is E009.",
+    },
+    Explanation {
+        code: "E003",
+        title: "unrecognized token",
+        description: "\
+A token came where the grammar doesn't allow it, like an operator without its right operand:
+
+    egg lay(eggs) {
+        eggs +
+    }
+
+The help lists what the parser expected instead. The parser carries on after the error, so one
+mistake can cause more errors after it.",
+    },
+    Explanation {
+        code: "E004",
+        title: "extra token",
+        description: "\
+The parser was done, but there was more input after it. Remove what comes after the end of the
+program, or whatever makes it end early.",
+    },
+    Explanation {
+        code: "E005",
+        title: "invalid number literal",
+        description: "\
+A number literal has no digits, or doesn't fit in an `int`, a 64 bit signed integer:
+
+    let many = 99999999999999999999;
+    let none = 0x_;
+
+Ints go from -9223372036854775808 to 9223372036854775807.",
+    },
+    Explanation {
+        code: "E006",
+        title: "cannot import a file",
+        description: "\
+An imported file doesn't exist or can't be read:
+
+    import \"bird.wy\";
+
+Paths are relative to the directory of the file that imports them.",
+    },
+    Explanation {
+        code: "E007",
+        title: "import cycle",
+        description: "\
+A file imports itself, directly or through the files it imports, which the error lists in order.
+Move what the files share into a file of its own that both import.",
+    },
+    Explanation {
+        code: "E008",
+        title: "expected `;` after statement",
+        description: "\
+A statement doesn't end with a `;`:
+
+    let eggs = 3
+    let nests = 2;
+
+Only the last expression of a block, its value, goes without one. The parser carries on as if
+the `;` was there, and `wryneck check --fix` inserts it when the statement ends its line.",
+    },
+    Explanation {
+        code: "E009",
+        title: "unclosed block",
+        description: "\
+The file ended before the `}` of a block:
+
+    egg lay(eggs) {
+        if eggs == 0 {
+            eggs
+
+    egg hatch() {
+        lay(2)
+    }
+
+The note points at the `{` that nothing closes, leaving out the blocks that are closed. A `}` on
+a line of its own that is indented less than the `{` it closes is taken to close the block
+around it instead, so the note points at that `{`. As a function can be in a block, the file can
+go on for a while before the parser notices. The errors before it, like missing `;`s, are
+reported as well.",
+    },
+    Explanation {
+        code: "E010",
+        title: "not declared in this scope",
+        description: "\
+A variable is used that no `let`, parameter, `forall` or lambda declares where it is used:
+
+    egg lay(eggs) {
+        eggs + chicks
+    }
+
+Declare it first, or pass it as a parameter. A `let` in a block is only in scope until the end
+of the block.",
+    },
+    Explanation {
+        code: "E011",
+        title: "no function of that name",
+        description: "\
+A function is called that isn't defined, imported or intrinsic, often because of a typo:
+
+    egg lay(eggs) { eggs }
+    egg hatch() { lya(1) }
+
+The error suggests the function with the closest name, if there is one.",
+    },
+    Explanation {
+        code: "E012",
+        title: "assignment to an immutable variable",
+        description: "\
+A variable is assigned to that is declared with `let` rather than `let mut`:
+
+    let eggs = 1;
+    eggs = 2;
+
+Declare it with `let mut eggs = 1;`, or declare a new variable with `let`.",
+    },
+    Explanation {
+        code: "E013",
+        title: "assignment to an undeclared variable",
+        description: "\
+A variable is assigned to that isn't declared in this scope:
+
+    eggs = 2;
+
+Declare it with `let mut eggs = 2;` instead.",
+    },
+    Explanation {
+        code: "E014",
+        title: "defined more than once",
+        description: "\
+Two functions in the same scope, or two parameters of a function, have the same name:
+
+    egg lay(eggs, eggs) {
+        eggs
+    }
+    egg lay() {
+        1
+    }
+
+Only one of them can be used, so rename or remove the other. The note points at the first
+definition.",
+    },
+    Explanation {
+        code: "E015",
+        title: "cannot read a file",
+        description: "\
+One of the files given to a command doesn't exist or can't be read, like a file that was moved:
+
+    wryneck check nest.wy missing.wy coop.wy
+
+The other files are still checked, and `wryneck` exits with 2 at the end, as it couldn't do all
+it was asked. A file that is imported but can't be read is E006.",
+    },
+    Explanation {
+        code: "E020",
+        title: "wrong number of arguments",
+        description: "\
+A function is called with more arguments than it has parameters, or without an argument for a
+parameter that has no default:
+
+    egg lay(eggs, nest) { eggs }
+    egg hatch() { lay(1) }
+
+Pass an argument for every parameter without a default, like `lay(1, 2)`, or give the parameter
+a default, like `egg lay(eggs, nest = 0)`.",
+    },
+    Explanation {
+        code: "E021",
+        title: "wrong number of arguments to an intrinsic",
+        description: "\
+An intrinsic function, like `max` or `slice`, is called with a different number of arguments than
+it takes:
+
+    max(1, 2, 3)
+
+Intrinsics have no defaults, so they always take all their arguments. Nest the calls instead, like
+`max(1, max(2, 3))`.",
+    },
+    Explanation {
+        code: "E030",
+        title: "condition is not a bool",
+        description: "\
+The condition of an `if`, a ternary or an `assert` has a type other than `bool`:
+
+    let eggs = 3;
+    if eggs { \"laid\" } else { \"none\" }
+
+There are no truthy values, compare instead, like `if eggs != 0`.",
+    },
+    Explanation {
+        code: "E031",
+        title: "comparison of different types",
+        description: "\
+`==` or `!=` compares values of different types, which are never equal:
+
+    1 == \"1\"
+
+Convert one of them first, or compare with a value of the same type.",
+    },
+    Explanation {
+        code: "E032",
+        title: "operator used with the wrong type",
+        description: "\
+An arithmetic operator is used with something other than ints, or `+` with a string and an int:
+
+    \"eggs: \" + 3
+
+Strings can only be added to strings, and `-`, `*` and `/` only work on ints.",
+    },
+    Explanation {
+        code: "E033",
+        title: "operator used with a unit value",
+        description: "\
+An operator is used with `()`, the value of a block without a tail expression or a function that
+doesn't return anything:
+
+    let laid = { lay(1); };
+    laid + 1
+
+Leave out the `;` after the last expression of the block to give it that value.",
+    },
+    Explanation {
+        code: "E034",
+        title: "assignment of a different type",
+        description: "\
+A value is assigned to a variable that holds a value of another type:
+
+    let mut eggs = 1;
+    eggs = \"many\";
+
+A variable keeps the type of the value it is declared with. Declare a new variable instead.",
+    },
+    Explanation {
+        code: "E035",
+        title: "argument of the wrong type",
+        description: "\
+A function is called with an argument of a type other than the one its parameter takes, whether
+that is written down, like `eggs: int`, inferred from what the function does with it, or the type
+an intrinsic takes:
+
+    egg lay(eggs: int) { eggs }
+    egg hatch() { lay(\"two\") }",
+    },
+    Explanation {
+        code: "E040",
+        title: "constant arithmetic fails",
+        description: "\
+An operation on literals divides by zero or overflows, so it would fail every time it runs:
+
+    let eggs = 1 / 0;
+    let many = 9223372036854775807 + 1;",
+    },
+    Explanation {
+        code: "E050",
+        title: "`hatch` next to top level statements",
+        description: "\
+A program with statements outside of functions runs those statements, so a `hatch` function next
+to them would never be called. Move the statements into `hatch`, or `hatch` into the statements.",
+    },
+    Explanation {
+        code: "E100",
+        title: "the program failed",
+        description: "\
+The program failed while running, like an `assert` that didn't hold, a `?` on `none`, or a
+division by zero. A `try` around it rescues the failure:
+
+    let eggs = try { lay(0) } rescue error { 0 };",
+    },
+    Explanation {
+        code: "W001",
+        title: "value returned on some paths only",
+        description: "\
+A function returns a value with `*)>` on some paths, but ends without a value on others:
+
+    egg lay(eggs) {
+        if eggs != 0 { *)> eggs; };
+    }
+
+Give it a tail expression, or return on every path.",
+    },
+    Explanation {
+        code: "W002",
+        title: "unreachable code",
+        description: "\
+Code comes after a `*)>` return, or after a statement every path through which returns, so it
+never runs:
+
+    egg lay(eggs) {
+        *)> eggs;
+        eggs + 1
+    }
+
+Remove it, or move the return after it.",
+    },
+    Explanation {
+        code: "W010",
+        title: "unused variable",
+        description: "\
+The value of a `let` binding is never read. Remove the binding, or, if its value has to be
+computed for what it does, use it as a statement instead. This is the `unused-variable` lint.",
+    },
+    Explanation {
+        code: "W011",
+        title: "dead function",
+        description: "\
+No call from `hatch`, or from the top level statements, leads to a function, so it can be
+removed. Programs without either are libraries, whose functions are all meant to be called from
+elsewhere. This is the `dead-function` lint.",
+    },
+    Explanation {
+        code: "W012",
+        title: "shadowing",
+        description: "\
+A `let` declares a variable with the name of a parameter or an earlier variable, which can no
+longer be used after it:
+
+    egg lay(eggs) {
+        let eggs = eggs + 1;
+        eggs
+    }
+
+Give it a name of its own. This is the `shadowing` lint.",
+    },
+    Explanation {
+        code: "W013",
+        title: "discarded value of a pure call",
+        description: "\
+A function that does nothing but compute its value is called as a statement, so the call has no
+effect:
+
+    max(eggs, 1);
+
+Use its value, or remove the call. This is the `discarded-pure-call` lint.",
+    },
+    Explanation {
+        code: "W014",
+        title: "recursion outside of a tail position",
+        description: "\
+A function calls itself, directly or through others, and does something with the result, so deep
+recursion takes up stack:
+
+    egg count(eggs) { eggs == 0 ? 0 : 1 + count(eggs - 1) }
+
+Pass what is left to do along instead, like
+`egg count(eggs, counted = 0) { eggs == 0 ? counted : count(eggs - 1, counted + 1) }`. This is
+the `non-tail-recursion` lint, which is only reported with `--warn non-tail-recursion`.",
+    },
+    Explanation {
+        code: "W020",
+        title: "possible misspelling",
+        description: "\
+A word in a comment or string isn't in the dictionary. Fix it, or add it to a `wryneck.dic` file
+next to the checked file or in a directory above it, one word a line.",
+    },
+];
@@ -0,0 +1,108 @@
+//! Unified diffs between two versions of a text, like `diff -u` prints them, for `wryneck fmt
+//! --diff` to show what formatting would change.
+
+/// How many unchanged lines are shown around every change.
+const CONTEXT: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Line<'t> {
+    Same(&'t str),
+    Removed(&'t str),
+    Added(&'t str),
+}
+
+/// The lines of `old` and `new`, each marked as kept, removed or added, keeping as many lines as
+/// possible: the longest common subsequence.
+fn lines<'t>(old: &[&'t str], new: &[&'t str]) -> Vec<Line<'t>> {
+    // how many lines `old[i..]` and `new[j..]` have in common
+    let mut common = vec![vec![0u32; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    let mut lines = Vec::new();
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push(Line::Same(old[i]));
+            (i, j) = (i + 1, j + 1);
+        } else if i < old.len() && (j == new.len() || common[i + 1][j] >= common[i][j + 1]) {
+            lines.push(Line::Removed(old[i]));
+            i += 1;
+        } else {
+            lines.push(Line::Added(new[j]));
+            j += 1;
+        }
+    }
+    lines
+}
+
+/// The changes from `old` to `new` as a unified diff with `old_name` and `new_name` in its header,
+/// or nothing if they are the same.
+pub fn unified(old: &str, new: &str, old_name: &str, new_name: &str) -> String {
+    let old_lines = old.lines().collect::<Vec<_>>();
+    let new_lines = new.lines().collect::<Vec<_>>();
+    let lines = lines(&old_lines, &new_lines);
+    let changed = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| !matches!(line, Line::Same(_)))
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+    if changed.is_empty() {
+        return String::new();
+    }
+    // changes closer than twice the context share a hunk
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for i in changed {
+        let start = i.saturating_sub(CONTEXT);
+        let end = (i + CONTEXT + 1).min(lines.len());
+        match hunks.last_mut() {
+            Some((_, last)) if start <= *last => *last = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+    let mut diff = format!("--- {}\n+++ {}\n", old_name, new_name);
+    // the line numbers the lines before the hunk take up in the old and new text
+    let (mut old_line, mut new_line, mut at) = (0, 0, 0);
+    for (start, end) in hunks {
+        for line in &lines[at..start] {
+            old_line += !matches!(line, Line::Added(_)) as usize;
+            new_line += !matches!(line, Line::Removed(_)) as usize;
+        }
+        let hunk = &lines[start..end];
+        let old_count = hunk
+            .iter()
+            .filter(|line| !matches!(line, Line::Added(_)))
+            .count();
+        let new_count = hunk
+            .iter()
+            .filter(|line| !matches!(line, Line::Removed(_)))
+            .count();
+        // an empty range starts at the line before it
+        let first = |line: usize, count: usize| if count == 0 { line } else { line + 1 };
+        diff += &format!(
+            "@@ -{},{} +{},{} @@\n",
+            first(old_line, old_count),
+            old_count,
+            first(new_line, new_count),
+            new_count
+        );
+        for line in hunk {
+            diff += &match line {
+                Line::Same(text) => format!(" {}\n", text),
+                Line::Removed(text) => format!("-{}\n", text),
+                Line::Added(text) => format!("+{}\n", text),
+            };
+        }
+        old_line += old_count;
+        new_line += new_count;
+        at = end;
+    }
+    diff
+}
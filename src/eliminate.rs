@@ -0,0 +1,171 @@
+//! Dead code elimination: branches whose condition is constant and `let` bindings nobody uses
+//! are removed. Best run after [`crate::fold`], which turns more conditions into constants.
+use std::collections::HashSet;
+use std::mem;
+
+use crate::resolved_ast::*;
+use crate::visit::{
+    walk_expression, walk_expression_mut, walk_if_mut, walk_program, walk_program_mut,
+    walk_statement, Visitor, VisitorMut,
+};
+
+/// Remove the dead code of every function of a linked program.
+pub fn eliminate(program: &mut Program) {
+    // removing a binding can leave the bindings its value used unused as well
+    loop {
+        let mut uses = Uses {
+            used: HashSet::new(),
+        };
+        walk_program(&mut uses, program);
+        let mut eliminator = Eliminator {
+            used: uses.used,
+            changed: false,
+        };
+        walk_program_mut(&mut eliminator, program);
+        if !eliminator.changed {
+            break;
+        }
+    }
+}
+
+/// Collects the variables that are read or assigned to anywhere.
+struct Uses {
+    used: HashSet<VariableId>,
+}
+
+impl<'input> Visitor<'input> for Uses {
+    fn visit_statement(&mut self, stmt: &Statement<'input>) {
+        if let Statement::Assign(Assign {
+            variable: Some(id), ..
+        }) = stmt
+        {
+            self.used.insert(*id);
+        }
+        walk_statement(self, stmt);
+    }
+
+    fn visit_expression(&mut self, expr: &Expression<'input>) {
+        if let Expression::Variable(Variable { id: Some(id), .. }) = expr {
+            self.used.insert(*id);
+        }
+        walk_expression(self, expr);
+    }
+}
+
+struct Eliminator {
+    used: HashSet<VariableId>,
+    changed: bool,
+}
+
+impl Eliminator {
+    /// Whether the statement can go without changing what the program does.
+    fn is_dead(&self, stmt: &Statement) -> bool {
+        match stmt {
+            Statement::Let(let_) => !self.used.contains(&let_.id) && is_pure(&let_.value),
+            Statement::Expression(expr) => is_pure(expr),
+            _ => false,
+        }
+    }
+}
+
+impl<'input> VisitorMut<'input> for Eliminator {
+    fn visit_expression_mut(&mut self, expr: &mut Expression<'input>) {
+        walk_expression_mut(self, expr);
+        // the branch taken replaces the if, which may be an if again
+        while let Expression::If(if_) = expr {
+            let taken = match constant(&if_.condition) {
+                Some(true) => mem::replace(&mut *if_.body, Expression::Error(0..0)),
+                Some(false) => match if_.else_body.take() {
+                    Some(Else::Block(block)) => *block,
+                    Some(Else::If(else_if)) => Expression::If(*else_if),
+                    None => Expression::Block(Vec::new(), None, if_.span.clone()),
+                },
+                None => break,
+            };
+            *expr = match taken {
+                // `c ? a : b` branches are blocks with just a value
+                Expression::Block(block, Some(tail), _) if block.is_empty() => *tail,
+                taken => taken,
+            };
+            self.changed = true;
+        }
+        if let Expression::Block(block, _, _) = expr {
+            let count = block.len();
+            let mut statements = Vec::with_capacity(count);
+            for stmt in mem::take(block) {
+                match stmt {
+                    // the statements of a block without bindings can go where the block was
+                    Statement::Expression(inner)
+                        if matches!(&*inner, Expression::Block(inner, None, _)
+                            if !inner.iter().any(|stmt| matches!(stmt, Statement::Let(_)))) =>
+                    {
+                        if let Expression::Block(inner, _, _) = *inner {
+                            statements.extend(inner);
+                        }
+                    }
+                    stmt => statements.push(stmt),
+                }
+            }
+            statements.retain(|stmt| !self.is_dead(stmt));
+            self.changed |= statements.len() != count;
+            *block = statements;
+        }
+    }
+
+    fn visit_if_mut(&mut self, if_: &mut If<'input>) {
+        walk_if_mut(self, if_);
+        while let Some(Else::If(else_if)) = &mut if_.else_body {
+            if_.else_body = match constant(&else_if.condition) {
+                Some(true) => Some(Else::Block(mem::replace(
+                    &mut else_if.body,
+                    Box::new(Expression::Error(0..0)),
+                ))),
+                Some(false) => else_if.else_body.take(),
+                None => break,
+            };
+            self.changed = true;
+        }
+    }
+}
+
+/// The value of a condition that is the same every time, like `1 == 1`.
+fn constant(condition: &Expression) -> Option<bool> {
+    match condition {
+        Expression::Expression(expr)
+        | Expression::Paren(expr, _)
+        | Expression::ExpressionComment((expr, _)) => constant(expr),
+        Expression::Op(lhs, op @ (Opcode::Eq | Opcode::NotEq), rhs, _) => match (&**lhs, &**rhs) {
+            (Expression::Number(lhs), Expression::Number(rhs)) => {
+                Some((lhs.value == rhs.value) == matches!(op, Opcode::Eq))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Whether evaluating the expression can neither fail nor have an effect.
+fn is_pure(expr: &Expression) -> bool {
+    match expr {
+        Expression::Number(_)
+        | Expression::String(_)
+        | Expression::Unit(_, _)
+        | Expression::Bool(_, _, _)
+        | Expression::None(_, _)
+        | Expression::Variable(_)
+        | Expression::Lambda(_) => true,
+        Expression::Expression(expr)
+        | Expression::Paren(expr, _)
+        | Expression::ExpressionComment((expr, _)) => is_pure(expr),
+        Expression::Some(value, _, _) => is_pure(value),
+        Expression::EnumConstructor(constructor) => constructor.args.iter().all(|arg| is_pure(arg)),
+        // division can divide by zero
+        Expression::Op(lhs, op, rhs, _) => {
+            !matches!(op, Opcode::Div) && is_pure(lhs) && is_pure(rhs)
+        }
+        Expression::Block(block, tail, _) => {
+            block.is_empty() && tail.as_deref().is_none_or(is_pure)
+        }
+        _ => false,
+    }
+}
@@ -0,0 +1,139 @@
+//! Constant folding: arithmetic on number literals is done once, ahead of time, so
+//! `(69 + 31) * 3` becomes `300`. Constant arithmetic that can't be folded, because it overflows
+//! or divides by zero, is reported by `check` whether the program is folded or not, except in the
+//! input of a test that expects it to fail.
+use crate::diagnostics::Diagnostic;
+use crate::resolved_ast::*;
+use crate::visit::{
+    walk_expression, walk_expression_mut, walk_program, walk_program_mut, walk_test, walk_test_mut,
+    Visitor, VisitorMut,
+};
+
+/// Fold the constant arithmetic of every function of a linked program. Arithmetic that overflows
+/// or divides by zero is left as it is and reported.
+pub fn fold(program: &mut Program) -> Vec<Diagnostic> {
+    let mut folder = Folder {
+        diagnostics: Vec::new(),
+    };
+    walk_program_mut(&mut folder, program);
+    folder.diagnostics
+}
+
+/// Report the constant arithmetic that overflows or divides by zero, without folding anything.
+pub fn check(program: &Program) -> Vec<Diagnostic> {
+    let mut checker = Checker {
+        diagnostics: Vec::new(),
+    };
+    walk_program(&mut checker, program);
+    checker.diagnostics
+}
+
+/// `lhs op rhs` for two ints, `None` if the operator isn't arithmetic. The interpreter does its
+/// arithmetic with this as well, so folding can't change what a program does.
+pub(crate) fn evaluate(lhs: i64, op: Opcode, rhs: i64) -> Result<Option<i64>, String> {
+    let value = match op {
+        Opcode::Add => lhs.checked_add(rhs),
+        Opcode::Sub => lhs.checked_sub(rhs),
+        Opcode::Mul => lhs.checked_mul(rhs),
+        Opcode::Div if rhs == 0 => return Err(format!("`{} / {}` divides by zero", lhs, rhs)),
+        Opcode::Div => lhs.checked_div(rhs),
+        Opcode::Concat | Opcode::Eq | Opcode::NotEq => return Ok(None),
+    };
+    value.map(Some).ok_or_else(|| {
+        format!(
+            "`{} {} {}` overflows, the result doesn't fit in an `int`",
+            lhs, op, rhs
+        )
+    })
+}
+
+struct Folder {
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'input> VisitorMut<'input> for Folder {
+    fn visit_test_mut(&mut self, test: &mut Test<'input>) {
+        let reported = self.diagnostics.len();
+        walk_test_mut(self, test);
+        // failing is what the test is about
+        if let Expected::Failure(_) = test.output {
+            self.diagnostics.truncate(reported);
+        }
+    }
+
+    fn visit_expression_mut(&mut self, expr: &mut Expression<'input>) {
+        walk_expression_mut(self, expr);
+        let value = match expr {
+            Expression::Expression(inner) | Expression::Paren(inner, _) => match **inner {
+                Expression::Number(Number { value, .. }) => value,
+                _ => return,
+            },
+            Expression::Op(lhs, op, rhs, span) => match (&**lhs, &**rhs) {
+                (Expression::Number(lhs), Expression::Number(rhs)) => {
+                    match evaluate(lhs.value, *op, rhs.value) {
+                        Ok(Some(value)) => value,
+                        Ok(None) => return,
+                        Err(message) => {
+                            self.diagnostics
+                                .push(Diagnostic::error(message, span.clone()).with_code("E040"));
+                            return;
+                        }
+                    }
+                }
+                _ => return,
+            },
+            _ => return,
+        };
+        *expr = Expression::Number(Number {
+            value,
+            radix: Radix::Decimal,
+            text: None,
+            span: expr.span(),
+        });
+    }
+}
+
+struct Checker {
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Checker {
+    /// The value of a constant expression, checking everything in it along the way.
+    fn value(&mut self, expr: &Expression) -> Option<i64> {
+        match expr {
+            Expression::Number(number) => Some(number.value),
+            Expression::Expression(inner) | Expression::Paren(inner, _) => self.value(inner),
+            Expression::Op(lhs, op, rhs, span) => {
+                let lhs = self.value(lhs);
+                let rhs = self.value(rhs);
+                match evaluate(lhs?, *op, rhs?) {
+                    Ok(value) => value,
+                    Err(message) => {
+                        self.diagnostics
+                            .push(Diagnostic::error(message, span.clone()).with_code("E040"));
+                        None
+                    }
+                }
+            }
+            _ => {
+                walk_expression(self, expr);
+                None
+            }
+        }
+    }
+}
+
+impl<'input> Visitor<'input> for Checker {
+    fn visit_test(&mut self, test: &Test<'input>) {
+        let reported = self.diagnostics.len();
+        walk_test(self, test);
+        // failing is what the test is about
+        if let Expected::Failure(_) = test.output {
+            self.diagnostics.truncate(reported);
+        }
+    }
+
+    fn visit_expression(&mut self, expr: &Expression<'input>) {
+        self.value(expr);
+    }
+}
@@ -1,15 +1,431 @@
-use crate::base_ast::*;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
-pub struct Formatter {
+use colored::Colorize;
+use serde::Deserialize;
+
+use crate::keywords::{Keyword, Spelling};
+use crate::resolved_ast::Program;
+
+/// The comment after which the formatter keeps the source as it is written, up to the comment
+/// [`FORMAT_ON`] or the end of the block or file. It needs the source for that, see
+/// [`format_source`].
+pub const FORMAT_OFF: &str = "wryneck-fmt: off";
+pub const FORMAT_ON: &str = "wryneck-fmt: on";
+
+/// The name of the file with the formatter options of a project, see [`FormatConfig::find`].
+pub const CONFIG_FILE: &str = "wryneck.toml";
+
+/// Where the formatter puts a comma after the last item of a list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TrailingCommas {
+    /// Only in lists with one item per line, like enum variants and tests.
+    #[default]
+    Vertical,
+    Always,
+    Never,
+}
+
+impl FromStr for TrailingCommas {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "vertical" => Ok(Self::Vertical),
+            "always" => Ok(Self::Always),
+            "never" => Ok(Self::Never),
+            _ => Err(format!(
+                "unknown trailing comma style `{}`, expected vertical, always or never",
+                s
+            )),
+        }
+    }
+}
+
+/// How the formatter spells keywords.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KeywordStyle {
+    /// `🥚`, `🐔` and `🐣`, the way wryneck has always printed functions, returns and the entry
+    /// point, and ASCII for the others.
+    #[default]
+    Classic,
+    /// The way they were written.
+    Preserve,
+    Ascii,
+    Emoji,
+}
+
+impl FromStr for KeywordStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "classic" => Ok(Self::Classic),
+            "preserve" => Ok(Self::Preserve),
+            "ascii" => Ok(Self::Ascii),
+            "emoji" => Ok(Self::Emoji),
+            _ => Err(format!(
+                "unknown keyword style `{}`, expected classic, preserve, ascii or emoji",
+                s
+            )),
+        }
+    }
+}
+
+/// Where the formatter puts parentheses around operations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Parens {
+    /// Around every one, so reading them takes no knowing which operators bind tighter.
+    #[default]
+    Full,
+    /// Only where precedence and associativity need them.
+    Minimal,
+    /// Where they were written.
+    Preserve,
+}
+
+impl FromStr for Parens {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "full" => Ok(Self::Full),
+            "minimal" => Ok(Self::Minimal),
+            "preserve" => Ok(Self::Preserve),
+            _ => Err(format!(
+                "unknown parentheses style `{}`, expected full, minimal or preserve",
+                s
+            )),
+        }
+    }
+}
+
+/// What the formatter indents with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IndentStyle {
+    /// [`FormatConfig::indent_width`] of them for every level.
+    #[default]
+    Spaces,
+    /// One for every level.
+    Tabs,
+}
+
+impl FromStr for IndentStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "spaces" | "space" => Ok(Self::Spaces),
+            "tabs" | "tab" => Ok(Self::Tabs),
+            _ => Err(format!(
+                "unknown indent style `{}`, expected spaces or tabs",
+                s
+            )),
+        }
+    }
+}
+
+/// What the formatter ends lines with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NewlineStyle {
+    #[default]
+    Lf,
+    Crlf,
+    /// Crlf on Windows, lf everywhere else.
+    Native,
+}
+
+impl NewlineStyle {
+    pub fn newline(self) -> &'static str {
+        match self {
+            Self::Lf => "\n",
+            Self::Crlf => "\r\n",
+            Self::Native if cfg!(windows) => "\r\n",
+            Self::Native => "\n",
+        }
+    }
+}
+
+impl FromStr for NewlineStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lf" => Ok(Self::Lf),
+            "crlf" => Ok(Self::Crlf),
+            "native" => Ok(Self::Native),
+            _ => Err(format!(
+                "unknown newline style `{}`, expected lf, crlf or native",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FormatConfig {
+    pub trailing_commas: TrailingCommas,
+    pub keywords: KeywordStyle,
+    pub parens: Parens,
+    pub indent_style: IndentStyle,
+    pub indent_width: usize,
+    /// How many blank lines may separate top level items. The formatter puts one between
+    /// functions and keeps up to this many of the ones the source has between any items.
+    pub max_blank_lines: usize,
+    /// The column comments after code on their line start at, or a space after the code if it is
+    /// longer.
+    pub comment_column: usize,
+    /// How long lines may get before calls, operators and pipelines are broken up over several.
+    pub max_width: usize,
+    /// Whether the tests of a function are each put on one line, with their `=` lined up.
+    pub align_tests: bool,
+    /// Whether the top level functions are sorted by name, `hatch` first.
+    pub sort_functions: bool,
+    /// Whether comments are re-flowed to fit in [`FormatConfig::max_width`], see
+    /// [`wrap_comments`].
+    pub wrap_comments: bool,
+    pub newline: NewlineStyle,
+    /// Whether the last line ends with a newline. Without one, the blank line after the last
+    /// item goes too.
+    pub final_newline: bool,
+    /// Whether what couldn't be parsed is shown in color, for a terminal. Off by default, so
+    /// files never get escape sequences written to them, and never set by a [`CONFIG_FILE`].
+    pub color: bool,
+}
+
+impl Default for FormatConfig {
+    fn default() -> Self {
+        Self {
+            trailing_commas: TrailingCommas::default(),
+            keywords: KeywordStyle::default(),
+            parens: Parens::default(),
+            indent_style: IndentStyle::default(),
+            indent_width: 4,
+            max_blank_lines: 1,
+            max_width: 100,
+            align_tests: false,
+            sort_functions: false,
+            wrap_comments: false,
+            newline: NewlineStyle::default(),
+            final_newline: true,
+            comment_column: 0,
+            color: false,
+        }
+    }
+}
+
+/// The `[format]` table of a [`CONFIG_FILE`], every option of which may be left out.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FormatOptions {
+    trailing_commas: Option<String>,
+    #[serde(alias = "keyword_style")]
+    keywords: Option<String>,
+    parens: Option<String>,
+    indent_style: Option<String>,
+    indent_width: Option<usize>,
+    /// `indent = { style = "tab", width = 4 }`, the same as `indent_style` and `indent_width`.
+    #[serde(default)]
+    indent: IndentOptions,
+    max_blank_lines: Option<usize>,
+    max_width: Option<usize>,
+    align_tests: Option<bool>,
+    sort_functions: Option<bool>,
+    wrap_comments: Option<bool>,
+    newline: Option<String>,
+    final_newline: Option<bool>,
+    comment_column: Option<usize>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct IndentOptions {
+    style: Option<String>,
+    width: Option<usize>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    format: FormatOptions,
+}
+
+/// The option of the `indent` table or the `indent_` key for the same, which can't both be set.
+fn indent_option<T>(option: &str, table: Option<T>, key: Option<T>) -> Result<Option<T>, String> {
+    match (table, key) {
+        (Some(_), Some(_)) => Err(format!(
+            "`indent.{0}` and `indent_{0}` are both set",
+            option
+        )),
+        (table, key) => Ok(table.or(key)),
+    }
+}
+
+impl FormatConfig {
+    /// The closest [`CONFIG_FILE`] to a file or directory, in it or the nearest directory above
+    /// it that has one.
+    pub fn find(path: &Path) -> Option<PathBuf> {
+        path.canonicalize()
+            .unwrap_or_else(|_| path.to_path_buf())
+            .ancestors()
+            .map(|dir| dir.join(CONFIG_FILE))
+            .find(|file| file.is_file())
+    }
+
+    /// The config with the options of the `[format]` table of a [`CONFIG_FILE`], which keeps the
+    /// others as they are.
+    pub fn with_toml(self, toml: &str) -> Result<Self, String> {
+        let options = toml::from_str::<ConfigFile>(toml)
+            .map_err(|error| error.message().to_string())?
+            .format;
+        let indent_style = indent_option("style", options.indent.style, options.indent_style)?;
+        let indent_width = indent_option("width", options.indent.width, options.indent_width)?;
+        Ok(Self {
+            trailing_commas: match options.trailing_commas {
+                Some(style) => style.parse()?,
+                None => self.trailing_commas,
+            },
+            keywords: match options.keywords {
+                Some(style) => style.parse()?,
+                None => self.keywords,
+            },
+            parens: match options.parens {
+                Some(style) => style.parse()?,
+                None => self.parens,
+            },
+            indent_style: match indent_style {
+                Some(style) => style.parse()?,
+                None => self.indent_style,
+            },
+            indent_width: indent_width.unwrap_or(self.indent_width),
+            max_blank_lines: options.max_blank_lines.unwrap_or(self.max_blank_lines),
+            max_width: options.max_width.unwrap_or(self.max_width),
+            align_tests: options.align_tests.unwrap_or(self.align_tests),
+            sort_functions: options.sort_functions.unwrap_or(self.sort_functions),
+            wrap_comments: options.wrap_comments.unwrap_or(self.wrap_comments),
+            newline: match options.newline {
+                Some(style) => style.parse()?,
+                None => self.newline,
+            },
+            final_newline: options.final_newline.unwrap_or(self.final_newline),
+            comment_column: options.comment_column.unwrap_or(self.comment_column),
+            color: self.color,
+        })
+    }
+}
+
+pub struct Formatter<'a> {
     pub indent_level: usize,
     pub string: String,
+    /// The program being formatted, to look up nested functions, which are referred to by id.
+    pub program: Option<&'a Program<'a>>,
+    /// The source the program was parsed from, for what [`FORMAT_OFF`] keeps as it is written.
+    pub source: Option<&'a str>,
+    pub config: FormatConfig,
+    /// Whether the innermost [`Formatter::group`] is broken up over several lines.
+    broken: bool,
+    /// Whether a group is being tried on one line, so the groups in it stay on one line too.
+    flat: bool,
 }
 
-impl Formatter {
-    pub fn new() -> Self {
+impl<'a> Formatter<'a> {
+    pub fn new(config: FormatConfig) -> Self {
         Self {
             indent_level: 0,
             string: String::new(),
+            program: None,
+            source: None,
+            config,
+            broken: false,
+            flat: false,
+        }
+    }
+
+    /// Format something on one line by itself, the groups in it included, to line it up with
+    /// others. Blocks in it still take several lines.
+    pub fn one_line(&self, format: impl FnOnce(&mut Formatter<'a>)) -> String {
+        let mut formatter = Formatter::new(self.config);
+        formatter.indent_level = self.indent_level;
+        formatter.program = self.program;
+        formatter.source = self.source;
+        formatter.flat = true;
+        format(&mut formatter);
+        formatter.string
+    }
+
+    /// Format something that can be broken up at its [`Formatter::push_line`]s. It stays on one
+    /// line, groups in it included, if its lines then fit in [`FormatConfig::max_width`].
+    /// Otherwise it is formatted again with every one of its own lines broken, and the groups in
+    /// it get to try one line each.
+    pub fn group(&mut self, format: impl Fn(&mut Self)) {
+        let broken = self.broken;
+        self.broken = false;
+        if self.flat {
+            format(self);
+        } else {
+            let start = self.string.len();
+            self.flat = true;
+            format(self);
+            self.flat = false;
+            if !self.fits(start) {
+                self.string.truncate(start);
+                self.broken = true;
+                format(self);
+            }
+        }
+        self.broken = broken;
+    }
+
+    /// Format the lines of a block, which are broken up on their own whether the group around the
+    /// block is or not.
+    pub fn lines(&mut self, format: impl FnOnce(&mut Self)) {
+        let (broken, flat) = (self.broken, self.flat);
+        self.broken = false;
+        self.flat = false;
+        format(self);
+        (self.broken, self.flat) = (broken, flat);
+    }
+
+    /// Format the part of a group whose lines are one level deeper when it is broken.
+    pub fn nest(&mut self, format: impl FnOnce(&mut Self)) {
+        let broken = self.broken;
+        if broken {
+            self.indent();
+        }
+        format(self);
+        if broken {
+            self.unindent();
+        }
+    }
+
+    /// Whether the lines from the one `start` is on to the end fit in the width.
+    fn fits(&self, start: usize) -> bool {
+        let line = self.string[..start].rfind('\n').map_or(0, |i| i + 1);
+        self.string[line..]
+            .lines()
+            .all(|line| line.chars().count() <= self.config.max_width)
+    }
+
+    pub fn is_broken(&self) -> bool {
+        self.broken
+    }
+
+    /// A space, or a new line at the current indentation, if the group is broken.
+    pub fn push_line(&mut self) {
+        if self.broken {
+            self.push_str("\n");
+            self.push_indent();
+        } else {
+            self.push_str(" ");
+        }
+    }
+
+    /// Nothing, or a new line, if the group is broken.
+    pub fn push_soft_line(&mut self) {
+        if self.broken {
+            self.push_str("\n");
+            self.push_indent();
         }
     }
 
@@ -22,7 +438,20 @@ impl Formatter {
     }
 
     pub fn push_indent(&mut self) {
-        self.string.push_str(&" ".repeat(self.indent_level * 4));
+        let indent = match self.config.indent_style {
+            IndentStyle::Spaces => " ".repeat(self.config.indent_width),
+            IndentStyle::Tabs => "\t".to_string(),
+        };
+        self.string.push_str(&indent.repeat(self.indent_level));
+    }
+
+    /// End a line and separate what comes next by a blank line, if the config allows any.
+    pub fn push_blank_line(&mut self) {
+        self.push_str(if self.config.max_blank_lines > 0 {
+            "\n\n"
+        } else {
+            "\n"
+        });
     }
 
     pub fn push_str_indented(&mut self, s: &str) {
@@ -41,17 +470,230 @@ impl Formatter {
     pub fn push_string(&mut self, s: String) {
         self.string.push_str(&s);
     }
+
+    /// Push what stands in for something that couldn't be parsed, in red if the config asks for
+    /// [`FormatConfig::color`].
+    pub fn push_error(&mut self, s: &str) {
+        if self.config.color {
+            self.push_string(s.red().to_string());
+        } else {
+            self.push_str(s);
+        }
+    }
+
+    /// Push what comes after the comment that ends at `off` up to `end` as it is written in the
+    /// source, whole lines of it.
+    pub fn push_unformatted(&mut self, source: &str, off: usize, end: usize) {
+        let start = source[off..]
+            .find('\n')
+            .map_or(source.len(), |i| off + i + 1);
+        let text = &source[start.min(end)..end];
+        // the region can end with code before a closing brace
+        if text.ends_with('\n') {
+            self.push_str(text);
+        } else if !text.trim().is_empty() {
+            self.push_str(text.trim_end());
+            self.push_str("\n");
+        }
+    }
+
+    /// Write what is formatted so far to `out`, except the last line, which a comment after it
+    /// can still be put at the end of.
+    pub fn flush(&mut self, out: &mut impl io::Write) -> io::Result<()> {
+        let end = self.string.trim_end_matches('\n');
+        let last_line = end.rfind('\n').map_or(0, |i| i + 1);
+        let text = self.string.drain(..last_line).collect::<String>();
+        self.write(&text, out)
+    }
+
+    /// Write what is left to `out`, with or without a [`FormatConfig::final_newline`].
+    pub fn finish(mut self, out: &mut impl io::Write) -> io::Result<()> {
+        if !self.config.final_newline {
+            let end = self.string.trim_end_matches(['\n', '\r']).len();
+            self.string.truncate(end);
+        }
+        let text = std::mem::take(&mut self.string);
+        self.write(&text, out)
+    }
+
+    /// Write formatted text with the [`FormatConfig::newline`]s, whatever the lines kept as they
+    /// are written in the source end with.
+    fn write(&self, text: &str, out: &mut impl io::Write) -> io::Result<()> {
+        let newline = self.config.newline.newline();
+        if newline == "\n" && !text.contains('\r') {
+            return out.write_all(text.as_bytes());
+        }
+        let text = text.replace("\r\n", "\n").replace('\n', newline);
+        out.write_all(text.as_bytes())
+    }
+
+    /// Put a comment at the end of the last line, which the formatter already ended, at
+    /// [`FormatConfig::comment_column`].
+    pub fn push_trailing_comment(&mut self, text: &str) {
+        let end = self.string.trim_end_matches('\n').len();
+        let newlines = self.string.split_off(end);
+        let line = self.string[self.string.rfind('\n').map_or(0, |i| i + 1)..]
+            .chars()
+            .count();
+        let padding = self.config.comment_column.saturating_sub(line).max(1);
+        self.push_string(" ".repeat(padding));
+        self.push_str("// ");
+        self.push_str(text);
+        self.push_str(if newlines.is_empty() { "\n" } else { &newlines });
+    }
+
+    /// Push what follows item `i` of a list of `len` items. That is a separator, or a trailing
+    /// comma if the config asks for one. Vertical lists put the newline after it themselves.
+    pub fn push_list_separator(&mut self, i: usize, len: usize, vertical: bool) {
+        if i + 1 < len {
+            self.push_str(if vertical { "," } else { ", " });
+            return;
+        }
+        let trailing = match self.config.trailing_commas {
+            TrailingCommas::Vertical => vertical,
+            TrailingCommas::Always => true,
+            TrailingCommas::Never => false,
+        };
+        if trailing {
+            self.push_str(",");
+        }
+    }
+
+    /// Push the items of a list in parentheses as a group, which puts them on lines of their own
+    /// when it is broken.
+    pub fn push_arguments<T>(&mut self, items: &[T], format: impl Fn(&T, &mut Self)) {
+        self.push_str("(");
+        self.group(|fmt| {
+            fmt.nest(|fmt| {
+                fmt.push_soft_line();
+                let vertical = fmt.is_broken();
+                for (i, item) in items.iter().enumerate() {
+                    format(item, fmt);
+                    fmt.push_list_separator(i, items.len(), vertical);
+                    if vertical && i + 1 < items.len() {
+                        fmt.push_line();
+                    }
+                }
+            });
+            fmt.push_soft_line();
+        });
+        self.push_str(")");
+    }
+
+    /// The spelling the config asks for, given the one a keyword was written with.
+    pub fn spelling(&self, keyword: Keyword, written: Spelling) -> Spelling {
+        match self.config.keywords {
+            KeywordStyle::Classic => match keyword {
+                Keyword::Function | Keyword::Return | Keyword::Hatch => Spelling::Emoji,
+                _ => Spelling::Ascii,
+            },
+            KeywordStyle::Preserve => written,
+            KeywordStyle::Ascii => Spelling::Ascii,
+            KeywordStyle::Emoji => Spelling::Emoji,
+        }
+    }
+
+    pub fn push_keyword(&mut self, keyword: Keyword, written: Spelling) {
+        self.push_str(keyword.spell(self.spelling(keyword, written)));
+    }
+}
+
+impl Default for Formatter<'_> {
+    fn default() -> Self {
+        Self::new(FormatConfig::default())
+    }
 }
 
 pub trait Format {
     fn format(&self, fmt: &mut Formatter);
 }
 
-pub fn format<P: Format>(input: &P) -> String {
-    let mut formatter = Formatter {
-        indent_level: 0,
-        string: String::new(),
+/// Whether a comment looks like code that is commented out, like `let eggs = 1;`, rather than
+/// prose.
+fn looks_like_code(line: &str) -> bool {
+    line.ends_with([';', '{', '}', '(', '[', ']'])
+        || ["|>", "=>", "==", " = "]
+            .iter()
+            .any(|code| line.contains(code))
+}
+
+/// Whether a comment starts an item of a list, like `- eggs` or `1. eggs`.
+fn is_list_item(line: &str) -> bool {
+    let number = line.trim_start_matches(|c: char| c.is_ascii_digit());
+    ["- ", "* "].iter().any(|bullet| line.starts_with(bullet))
+        || number.len() < line.len() && number.starts_with(". ")
+}
+
+/// The text of comments on lines of their own in a row, re-flowed to lines of at most `width`
+/// characters: the lines of a paragraph are joined and filled with as many words as fit. Empty
+/// lines and the ones that look like code stay as they are and separate paragraphs, and a list
+/// item starts a new one. A word longer than `width` gets a line of its own.
+pub fn wrap_comments(lines: &[&str], width: usize) -> Vec<String> {
+    let mut wrapped = Vec::new();
+    let mut words = Vec::new();
+    let fill = |wrapped: &mut Vec<String>, words: &mut Vec<&str>| {
+        let mut line = String::new();
+        for word in words.drain(..) {
+            if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > width {
+                wrapped.push(std::mem::take(&mut line));
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(word);
+        }
+        if !line.is_empty() {
+            wrapped.push(line);
+        }
     };
+    for line in lines {
+        let kept = line.trim().is_empty() || looks_like_code(line);
+        if kept || is_list_item(line) {
+            fill(&mut wrapped, &mut words);
+        }
+        if kept {
+            wrapped.push(line.to_string());
+        } else {
+            words.extend(line.split_whitespace());
+        }
+    }
+    fill(&mut wrapped, &mut words);
+    wrapped
+}
+
+pub fn format<P: Format>(input: &P) -> String {
+    format_with(input, FormatConfig::default())
+}
+
+pub fn format_with<P: Format>(input: &P, config: FormatConfig) -> String {
+    let mut formatter = Formatter::new(config);
     input.format(&mut formatter);
     formatter.string
 }
+
+/// Format a program parsed from `source`, keeping what is between [`FORMAT_OFF`] and
+/// [`FORMAT_ON`] as it is written there.
+pub fn format_source(program: &Program, source: &str, config: FormatConfig) -> String {
+    let mut out = Vec::new();
+    format_source_to(program, source, &mut out, &config).expect("writing to a Vec doesn't fail");
+    String::from_utf8(out).expect("the formatter only writes strings")
+}
+
+/// Format a program straight to a file or stdout, without building all of it in memory first.
+pub fn format_to(
+    program: &Program,
+    out: &mut impl io::Write,
+    config: &FormatConfig,
+) -> io::Result<()> {
+    program.format_to(out, *config, None)
+}
+
+/// [`format_to`] for a program parsed from `source`, like [`format_source`].
+pub fn format_source_to(
+    program: &Program,
+    source: &str,
+    out: &mut impl io::Write,
+    config: &FormatConfig,
+) -> io::Result<()> {
+    program.format_to(out, *config, Some(source))
+}
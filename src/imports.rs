@@ -0,0 +1,99 @@
+//! Loading a file together with all the files it imports.
+use std::path::{Path, PathBuf};
+
+use crate::base_ast::TopLevel;
+use crate::diagnostics::Diagnostic;
+
+pub struct SourceFile {
+    pub path: PathBuf,
+    pub text: String,
+}
+
+/// Read the file at `path` and everything it imports, directly or indirectly.
+///
+/// The file at `path` comes first, followed by the imported files in the order they were found.
+/// Every file is loaded once, no matter how often it is imported. Only failing to read `path`
+/// itself is an error, problems with imports are reported as diagnostics pointing at the import,
+/// with [`Diagnostic::file`] the index of the importing file in the loaded files.
+pub fn load(path: &Path) -> std::io::Result<(Vec<SourceFile>, Vec<Diagnostic>)> {
+    let mut loader = Loader {
+        files: Vec::new(),
+        canonical_paths: Vec::new(),
+        stack: Vec::new(),
+        errors: Vec::new(),
+    };
+    loader.load(path)?;
+    Ok((loader.files, loader.errors))
+}
+
+struct Loader {
+    files: Vec<SourceFile>,
+    /// The canonical path of each file, to recognize files imported through different paths.
+    canonical_paths: Vec<PathBuf>,
+    /// The files currently being loaded, each one imported by the previous one.
+    stack: Vec<usize>,
+    errors: Vec<Diagnostic>,
+}
+
+impl Loader {
+    fn load(&mut self, path: &Path) -> std::io::Result<()> {
+        tracing::info!("reading {}", path.display());
+        let text = std::fs::read_to_string(path)?;
+        // files that don't parse are reported when they are parsed for real
+        let imports = match crate::parse(&text) {
+            Ok((program, _)) => program
+                .things
+                .into_iter()
+                .filter_map(|thing| match thing {
+                    TopLevel::Import(import) => Some((import.path.to_string(), import.span)),
+                    _ => None,
+                })
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+
+        let file = self.files.len();
+        self.canonical_paths.push(path.canonicalize()?);
+        self.files.push(SourceFile {
+            path: path.to_path_buf(),
+            text,
+        });
+        self.stack.push(file);
+
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        for (import, span) in imports {
+            let imported = dir.join(&import);
+            let (code, message) = match imported.canonicalize() {
+                Err(err) => ("E006", format!("cannot import `{}`: {}", import, err)),
+                Ok(canonical) => {
+                    if let Some(start) = self
+                        .stack
+                        .iter()
+                        .position(|&i| self.canonical_paths[i] == canonical)
+                    {
+                        let cycle = self.stack[start..]
+                            .iter()
+                            .map(|&i| self.files[i].path.display().to_string())
+                            .chain(std::iter::once(imported.display().to_string()))
+                            .collect::<Vec<_>>();
+                        ("E007", format!("import cycle: {}", cycle.join(" -> ")))
+                    } else if self.canonical_paths.contains(&canonical) {
+                        continue;
+                    } else if let Err(err) = self.load(&imported) {
+                        ("E006", format!("cannot import `{}`: {}", import, err))
+                    } else {
+                        continue;
+                    }
+                }
+            };
+            self.errors.push(
+                Diagnostic::error(message, span)
+                    .with_code(code)
+                    .with_file(file),
+            );
+        }
+
+        self.stack.pop();
+        Ok(())
+    }
+}
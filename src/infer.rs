@@ -0,0 +1,552 @@
+//! Inference of the parameter and return types of functions, in the style of Hindley–Milner.
+//!
+//! Functions are inferred one strongly connected component of the call graph at a time, callees
+//! first, so recursive and mutually recursive functions share their types while inferring them.
+//! Afterwards the types that are still open are generalised: `egg same(x) { *)> x; }` takes a
+//! value of any type and returns one of the same type.
+//!
+//! Conflicting types are not reported here, that is up to [`crate::typecheck`], which uses the
+//! inferred signatures.
+use std::collections::HashMap;
+use std::fmt::{Display, Error};
+
+use id_collections::{Id, IdVec};
+
+use crate::intrinsics;
+use crate::resolved_ast::*;
+use crate::typecheck::Type;
+
+/// A type that may still contain type variables.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Term {
+    /// Never [`Type::Unknown`], an unknown type is a variable instead.
+    Known(Type),
+    Var(usize),
+    /// A function value, like a lambda or a function used as a value.
+    Function(Vec<Term>, Box<Term>),
+}
+
+impl Term {
+    /// The type as far as [`crate::typecheck`] knows types.
+    pub fn known(&self) -> Type {
+        match self {
+            Term::Known(type_) => *type_,
+            Term::Var(_) | Term::Function(..) => Type::Unknown,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Signature {
+    /// The variables of a signature are generic, they are numbered in order of appearance.
+    pub params: Vec<Term>,
+    pub ret: Term,
+}
+
+impl Signature {
+    /// The signature as the definition of the function would be written with every type spelled
+    /// out, like `egg same(x: a) -> a`.
+    pub fn describe(&self, definition: &FunctionDefinition) -> String {
+        let params = definition
+            .params
+            .iter()
+            .zip(&self.params)
+            .map(|(param, type_)| format!("{}: {}", param.name, type_))
+            .collect::<Vec<_>>();
+        format!(
+            "egg {}({}) -> {}",
+            definition.name,
+            params.join(", "),
+            self.ret
+        )
+    }
+}
+
+impl Display for Term {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> Result<(), Error> {
+        match self {
+            Term::Known(Type::Int) => write!(fmt, "int"),
+            Term::Known(Type::String) => write!(fmt, "string"),
+            Term::Known(Type::Bool) => write!(fmt, "bool"),
+            Term::Known(Type::Unit) => write!(fmt, "unit"),
+            Term::Known(Type::Unknown) => write!(fmt, "_"),
+            // a, b, ..., z, a1, b1, ...
+            Term::Var(var) => {
+                write!(fmt, "{}", (b'a' + (var % 26) as u8) as char)?;
+                if *var >= 26 {
+                    write!(fmt, "{}", var / 26)?;
+                }
+                Ok(())
+            }
+            Term::Function(params, ret) => {
+                let params = params.iter().map(Term::to_string).collect::<Vec<_>>();
+                write!(fmt, "|{}| -> {}", params.join(", "), ret)
+            }
+        }
+    }
+}
+
+/// The signature of every function of a linked program.
+pub fn signatures(program: &Program) -> IdVec<FunctionId, Signature> {
+    let mut inference = Inference {
+        program,
+        bindings: Vec::new(),
+        variables: HashMap::new(),
+        signatures: HashMap::new(),
+        inferring: HashMap::new(),
+        function: FunctionId::from_index(0),
+        ret: Term::Var(0),
+        returned: false,
+    };
+    for component in components(program) {
+        inference.infer_component(&component);
+    }
+    program
+        .functions
+        .map_refs(|id, _| inference.signatures.remove(&id).unwrap())
+}
+
+/// The strongly connected components of the call graph, callees before their callers.
+fn components(program: &Program) -> Vec<Vec<FunctionId>> {
+    // Tarjan's algorithm
+    struct Tarjan<'a> {
+        calls: &'a IdVec<FunctionId, Vec<FunctionId>>,
+        /// The order each function was first visited in, and the lowest one reachable from it.
+        index: HashMap<FunctionId, (usize, usize)>,
+        stack: Vec<FunctionId>,
+        components: Vec<Vec<FunctionId>>,
+    }
+
+    impl Tarjan<'_> {
+        fn visit(&mut self, func: FunctionId) {
+            let index = self.index.len();
+            self.index.insert(func, (index, index));
+            self.stack.push(func);
+            for callee in &self.calls[func] {
+                let low = match self.index.get(callee) {
+                    None => {
+                        self.visit(*callee);
+                        self.index[callee].1
+                    }
+                    Some(&(callee_index, _)) if self.stack.contains(callee) => callee_index,
+                    // in a component that is done already
+                    Some(_) => continue,
+                };
+                let entry = self.index.get_mut(&func).unwrap();
+                entry.1 = entry.1.min(low);
+            }
+            let (index, low) = self.index[&func];
+            if index == low {
+                let start = self.stack.iter().position(|id| *id == func).unwrap();
+                self.components.push(self.stack.split_off(start));
+            }
+        }
+    }
+
+    let mut tarjan = Tarjan {
+        calls: &program.calls,
+        index: HashMap::new(),
+        stack: Vec::new(),
+        components: Vec::new(),
+    };
+    for id in program.functions.count() {
+        if !tarjan.index.contains_key(&id) {
+            tarjan.visit(id);
+        }
+    }
+    tarjan.components
+}
+
+struct Inference<'a, 'input> {
+    program: &'a Program<'input>,
+    /// What each type variable stands for, if anything yet.
+    bindings: Vec<Option<Term>>,
+    variables: HashMap<VariableId, Term>,
+    /// The generalised signatures of the functions inferred so far.
+    signatures: HashMap<FunctionId, Signature>,
+    /// The parameter and return types of the functions of the component being inferred.
+    inferring: HashMap<FunctionId, (Vec<Term>, Term)>,
+    /// The function being inferred.
+    function: FunctionId,
+    /// Its return type.
+    ret: Term,
+    /// Whether the function being inferred has a return statement.
+    returned: bool,
+}
+
+impl Inference<'_, '_> {
+    fn fresh(&mut self) -> Term {
+        self.bindings.push(None);
+        Term::Var(self.bindings.len() - 1)
+    }
+
+    fn annotated(&mut self, annotation: &Option<crate::resolved_ast::Type>) -> Term {
+        match annotation.as_ref().map(Type::from_annotation) {
+            Some(Type::Unknown) | None => self.fresh(),
+            Some(type_) => Term::Known(type_),
+        }
+    }
+
+    /// Follow the bindings of a variable to what it stands for.
+    fn resolve(&self, term: &Term) -> Term {
+        match term {
+            Term::Var(var) => match &self.bindings[*var] {
+                Some(bound) => self.resolve(bound),
+                None => term.clone(),
+            },
+            _ => term.clone(),
+        }
+    }
+
+    /// The term with every bound variable replaced.
+    fn substitute(&self, term: &Term) -> Term {
+        match self.resolve(term) {
+            Term::Function(params, ret) => Term::Function(
+                params.iter().map(|param| self.substitute(param)).collect(),
+                Box::new(self.substitute(&ret)),
+            ),
+            term => term,
+        }
+    }
+
+    fn occurs(&self, var: usize, term: &Term) -> bool {
+        match self.resolve(term) {
+            Term::Var(other) => var == other,
+            Term::Function(params, ret) => {
+                params.iter().any(|param| self.occurs(var, param)) || self.occurs(var, &ret)
+            }
+            Term::Known(_) => false,
+        }
+    }
+
+    /// Make both terms the same type, if they can be. A conflict leaves them as they are.
+    fn unify(&mut self, a: &Term, b: &Term) {
+        match (self.resolve(a), self.resolve(b)) {
+            (Term::Var(a), Term::Var(b)) if a == b => {}
+            // an infinite type is a conflict
+            (Term::Var(var), term) | (term, Term::Var(var)) if !self.occurs(var, &term) => {
+                self.bindings[var] = Some(term);
+            }
+            (Term::Function(a_params, a_ret), Term::Function(b_params, b_ret))
+                if a_params.len() == b_params.len() =>
+            {
+                for (a, b) in a_params.iter().zip(&b_params) {
+                    self.unify(a, b);
+                }
+                self.unify(&a_ret, &b_ret);
+            }
+            _ => {}
+        }
+    }
+
+    /// A signature with fresh variables in place of its generic ones.
+    fn instantiate(&mut self, signature: &Signature) -> (Vec<Term>, Term) {
+        fn replace(
+            inference: &mut Inference,
+            term: &Term,
+            fresh: &mut HashMap<usize, Term>,
+        ) -> Term {
+            match term {
+                Term::Var(var) => match fresh.get(var) {
+                    Some(term) => term.clone(),
+                    None => {
+                        let term = inference.fresh();
+                        fresh.insert(*var, term.clone());
+                        term
+                    }
+                },
+                Term::Function(params, ret) => Term::Function(
+                    params
+                        .iter()
+                        .map(|param| replace(inference, param, fresh))
+                        .collect(),
+                    Box::new(replace(inference, ret, fresh)),
+                ),
+                Term::Known(_) => term.clone(),
+            }
+        }
+
+        let mut fresh = HashMap::new();
+        let params = signature
+            .params
+            .iter()
+            .map(|param| replace(self, param, &mut fresh))
+            .collect();
+        let ret = replace(self, &signature.ret, &mut fresh);
+        (params, ret)
+    }
+
+    /// The parameter and return types of a function where it is used.
+    fn function(&mut self, func: FunctionId) -> (Vec<Term>, Term) {
+        match self.inferring.get(&func) {
+            // recursion doesn't get to use a function at different types
+            Some(types) => types.clone(),
+            None => {
+                let signature = self.signatures[&func].clone();
+                self.instantiate(&signature)
+            }
+        }
+    }
+
+    fn infer_component(&mut self, component: &[FunctionId]) {
+        for id in component {
+            let definition = &self.program.functions[*id].definition;
+            let mut params = Vec::new();
+            for param in &definition.params {
+                let type_ = self.annotated(&param.type_);
+                if let Some(var) = param.id {
+                    self.variables.insert(var, type_.clone());
+                }
+                params.push(type_);
+            }
+            let ret = self.annotated(&definition.return_type);
+            self.inferring.insert(*id, (params, ret));
+        }
+        for id in component {
+            let func = &self.program.functions[*id];
+            let (params, ret) = self.inferring[id].clone();
+            for (param, type_) in func.definition.params.iter().zip(&params) {
+                if let Some(default) = &param.default {
+                    let default = self.infer(default);
+                    self.unify(type_, &default);
+                }
+            }
+            self.function = *id;
+            self.ret = ret.clone();
+            self.returned = false;
+            let body = self.infer(&func.body);
+            // a body without a value returns with its return statements, or returns nothing
+            match &*func.body {
+                Expression::Block(_, None, _) if self.returned => {}
+                Expression::Block(_, None, _) => self.unify(&ret, &Term::Known(Type::Unit)),
+                _ => self.unify(&ret, &body),
+            }
+        }
+        for id in component {
+            let (params, ret) = self.inferring.remove(id).unwrap();
+            let mut signature = Signature {
+                params: params.iter().map(|param| self.substitute(param)).collect(),
+                ret: self.substitute(&ret),
+            };
+            normalize(&mut signature);
+            self.signatures.insert(*id, signature);
+        }
+    }
+
+    fn infer_statement(&mut self, stmt: &Statement) {
+        match stmt {
+            Statement::Let(let_) => {
+                let type_ = self.infer(&let_.value);
+                self.variables.insert(let_.id, type_);
+            }
+            Statement::Assign(assign) => {
+                let value = self.infer(&assign.value);
+                if let Some(var) = assign.variable.and_then(|id| self.variables.get(&id)) {
+                    let var = var.clone();
+                    let value = match assign.op {
+                        Some(op) => self.operation(&var, op, &value),
+                        None => value,
+                    };
+                    self.unify(&var, &value);
+                }
+            }
+            Statement::Return(expr, ..) => {
+                let type_ = self.infer(expr);
+                let ret = self.ret.clone();
+                self.unify(&ret, &type_);
+                self.returned = true;
+            }
+            Statement::Assert(assert) => {
+                let condition = self.infer(&assert.condition);
+                self.unify(&condition, &Term::Known(Type::Bool));
+            }
+            Statement::Expression(expr) => {
+                self.infer(expr);
+            }
+            Statement::Comment(_) | Statement::Function(_) => {}
+        }
+    }
+
+    fn operation(&mut self, lhs: &Term, op: Opcode, rhs: &Term) -> Term {
+        match op {
+            Opcode::Eq | Opcode::NotEq => {
+                self.unify(lhs, rhs);
+                Term::Known(Type::Bool)
+            }
+            Opcode::Add | Opcode::Sub | Opcode::Mul | Opcode::Div | Opcode::Concat => {
+                let type_ = Term::Known(match op {
+                    Opcode::Concat => Type::String,
+                    _ => Type::Int,
+                });
+                self.unify(lhs, &type_);
+                self.unify(rhs, &type_);
+                type_
+            }
+        }
+    }
+
+    fn infer(&mut self, expr: &Expression) -> Term {
+        match expr {
+            Expression::Expression(expr) | Expression::Paren(expr, _) => self.infer(expr),
+            Expression::ExpressionComment((expr, _)) => self.infer(expr),
+            Expression::Block(block, tail, _) => {
+                for stmt in block {
+                    self.infer_statement(stmt);
+                }
+                match tail {
+                    Some(tail) => self.infer(tail),
+                    None => Term::Known(Type::Unit),
+                }
+            }
+            Expression::Number(_) => Term::Known(Type::Int),
+            Expression::String(_) => Term::Known(Type::String),
+            Expression::Unit(_, _) => Term::Known(Type::Unit),
+            Expression::Bool(..) => Term::Known(Type::Bool),
+            Expression::Variable(var) => match var.id {
+                Some(id) => match self.variables.get(&id) {
+                    Some(type_) => type_.clone(),
+                    None => self.fresh(),
+                },
+                // a function used as a value
+                None => match self.program.function_named(self.function, var.name) {
+                    Some(func) => {
+                        let (params, ret) = self.function(func);
+                        Term::Function(params, Box::new(ret))
+                    }
+                    None => self.fresh(),
+                },
+            },
+            Expression::Op(lhs, op, rhs, _) => {
+                let lhs = self.infer(lhs);
+                let rhs = self.infer(rhs);
+                self.operation(&lhs, *op, &rhs)
+            }
+            Expression::If(if_) => self.infer_if(if_),
+            Expression::FunctionCall(FunctionCall::Direct {
+                function,
+                args,
+                defaulted,
+                ..
+            }) => {
+                let (params, ret) = self.function(*function);
+                // the filled in defaults are inferred with their function
+                for (arg, param) in args[..args.len() - defaulted].iter().zip(&params) {
+                    let arg = self.infer(arg);
+                    self.unify(param, &arg);
+                }
+                ret
+            }
+            Expression::FunctionCall(FunctionCall::Intrinsic {
+                intrinsic, args, ..
+            }) => {
+                let (params, ret) = intrinsics::signature(*intrinsic);
+                for (i, arg) in args.iter().enumerate() {
+                    let arg = self.infer(arg);
+                    match params.get(i) {
+                        Some(Type::Unknown) | None => {}
+                        Some(param) => self.unify(&Term::Known(*param), &arg),
+                    }
+                }
+                match ret {
+                    Type::Unknown => self.fresh(),
+                    ret => Term::Known(ret),
+                }
+            }
+            Expression::FunctionCall(FunctionCall::Indirect { callee, args, .. }) => {
+                let callee = self.infer(callee);
+                let args = args.iter().map(|arg| self.infer(arg)).collect();
+                let ret = self.fresh();
+                self.unify(&callee, &Term::Function(args, Box::new(ret.clone())));
+                ret
+            }
+            Expression::Lambda(lambda) => {
+                let params = lambda
+                    .params
+                    .iter()
+                    .map(|param| {
+                        let type_ = self.annotated(&param.type_);
+                        if let Some(id) = param.id {
+                            self.variables.insert(id, type_.clone());
+                        }
+                        type_
+                    })
+                    .collect();
+                let body = self.infer(&lambda.body);
+                Term::Function(params, Box::new(body))
+            }
+            Expression::Try(try_) => {
+                let body = self.infer(&try_.body);
+                let rescue = self.infer(&try_.rescue);
+                self.unify(&body, &rescue);
+                body
+            }
+            Expression::EnumConstructor(constructor) => {
+                for arg in &constructor.args {
+                    self.infer(arg);
+                }
+                self.fresh()
+            }
+            Expression::Some(value, _, _) => {
+                self.infer(value);
+                self.fresh()
+            }
+            Expression::Field(field) => {
+                self.infer(&field.object);
+                self.fresh()
+            }
+            Expression::MethodCall(call) => {
+                self.infer(&call.object);
+                for arg in &call.args {
+                    self.infer(arg);
+                }
+                self.fresh()
+            }
+            Expression::Range(range) => {
+                let start = self.infer(&range.start);
+                let end = self.infer(&range.end);
+                self.unify(&start, &Term::Known(Type::Int));
+                self.unify(&end, &Term::Known(Type::Int));
+                self.fresh()
+            }
+            Expression::None(_, _) | Expression::Error(_) => self.fresh(),
+        }
+    }
+
+    fn infer_if(&mut self, if_: &If) -> Term {
+        let condition = self.infer(&if_.condition);
+        self.unify(&condition, &Term::Known(Type::Bool));
+        let body = self.infer(&if_.body);
+        let else_ = match &if_.else_body {
+            Some(Else::Block(block)) => self.infer(block),
+            Some(Else::If(else_if)) => self.infer_if(else_if),
+            None => Term::Known(Type::Unit),
+        };
+        self.unify(&body, &else_);
+        body
+    }
+}
+
+/// Number the variables of a signature in order of appearance.
+fn normalize(signature: &mut Signature) {
+    fn rename(term: &mut Term, names: &mut HashMap<usize, usize>) {
+        match term {
+            Term::Var(var) => {
+                let next = names.len();
+                *var = *names.entry(*var).or_insert(next);
+            }
+            Term::Function(params, ret) => {
+                for param in params {
+                    rename(param, names);
+                }
+                rename(ret, names);
+            }
+            Term::Known(_) => {}
+        }
+    }
+
+    let mut names = HashMap::new();
+    for param in &mut signature.params {
+        rename(param, &mut names);
+    }
+    rename(&mut signature.ret, &mut names);
+}
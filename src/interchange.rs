@@ -0,0 +1,73 @@
+//! The `.wknb` interchange format.
+//!
+//! A `.wknb` file starts with the magic bytes `WKNB` and a little endian `u16` format version,
+//! followed by the bincode encoding of a [`Program`]. Older layouts aren't kept around, so a file
+//! can only be read by a build with the same format version. Readers check the version before
+//! decoding, so tools can reject (or fall back to reparsing) files written by a different version
+//! of wryneck instead of misinterpreting them.
+use std::fmt::{Display, Error};
+
+use crate::resolved_ast::Program;
+
+/// The magic bytes every `.wknb` file starts with.
+pub const MAGIC: &[u8; 4] = b"WKNB";
+
+/// The format version written by [`encode`], and the only one [`decode`] reads.
+/// Bump this whenever the layout of the resolved AST changes.
+pub const VERSION: u16 = 44;
+
+const HEADER_LEN: usize = MAGIC.len() + std::mem::size_of::<u16>();
+
+#[derive(Debug)]
+pub enum DecodeError {
+    /// The input does not start with [`MAGIC`].
+    NotWknb,
+    /// The input was written with another format version than this build's.
+    UnsupportedVersion(u16),
+    /// The header is fine, but the payload could not be decoded.
+    Malformed(bincode::Error),
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> Result<(), Error> {
+        match self {
+            DecodeError::NotWknb => write!(fmt, "not a .wknb file"),
+            DecodeError::UnsupportedVersion(version) => write!(
+                fmt,
+                "format version {} can't be read, only {} can; compile the file again",
+                version, VERSION
+            ),
+            DecodeError::Malformed(err) => write!(fmt, "malformed program: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// Encode the program with the current format version.
+pub fn encode(program: &Program) -> Vec<u8> {
+    let mut bytes = Vec::from(&MAGIC[..]);
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+    bincode::serialize_into(&mut bytes, program).expect("serializing a program can not fail");
+    bytes
+}
+
+/// Read the format version from the header without decoding the program.
+pub fn version(bytes: &[u8]) -> Result<u16, DecodeError> {
+    if bytes.len() < HEADER_LEN || &bytes[..MAGIC.len()] != MAGIC {
+        return Err(DecodeError::NotWknb);
+    }
+    Ok(u16::from_le_bytes([
+        bytes[MAGIC.len()],
+        bytes[MAGIC.len() + 1],
+    ]))
+}
+
+/// Decode a program, borrowing its strings from `bytes`.
+pub fn decode(bytes: &[u8]) -> Result<Program<'_>, DecodeError> {
+    let version = version(bytes)?;
+    if version != VERSION {
+        return Err(DecodeError::UnsupportedVersion(version));
+    }
+    bincode::deserialize(&bytes[HEADER_LEN..]).map_err(DecodeError::Malformed)
+}
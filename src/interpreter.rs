@@ -0,0 +1,625 @@
+//! Runs linked programs by walking their resolved AST.
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::resolved_ast::*;
+use crate::runtime::{self, Failure};
+use crate::Span;
+
+/// How many calls deep a program may go before it fails, instead of overflowing the stack.
+pub const MAX_DEPTH: usize = 1_000;
+
+/// Run a function on a thread with enough stack for the interpreter to go [`MAX_DEPTH`] calls
+/// deep, every call of the program takes a few calls of the interpreter.
+pub fn with_large_stack<T: Send>(run: impl FnOnce() -> T + Send) -> T {
+    std::thread::scope(|scope| {
+        std::thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn_scoped(scope, run)
+            .unwrap()
+            .join()
+            .unwrap()
+    })
+}
+
+const STACK_SIZE: usize = 256 * 1024 * 1024;
+
+/// How far a program may go before it fails, so programs that can't be trusted to finish can
+/// still be run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// How many expressions it may evaluate in total.
+    pub steps: Option<u64>,
+    /// How many calls deep it may go, at most [`MAX_DEPTH`], which is what the stack has room for.
+    pub depth: usize,
+    /// How many bytes a single value may take. A program holds a few values per call, and the
+    /// calls are limited by `depth`, so this keeps the memory it takes in check.
+    pub memory: Option<usize>,
+}
+
+impl Default for Limits {
+    /// Only as deep as the stack allows.
+    fn default() -> Self {
+        Self {
+            steps: None,
+            depth: MAX_DEPTH,
+            memory: None,
+        }
+    }
+}
+
+/// What the interpreter computes with.
+pub type Value<'a> = runtime::Value<'a, Closure<'a>>;
+
+/// A lambda with the function it is written in and the variables it can see.
+#[derive(Debug, Clone)]
+pub struct Closure<'a> {
+    pub lambda: &'a Lambda<'a>,
+    pub function: FunctionId,
+    pub variables: HashMap<VariableId, Value<'a>>,
+}
+
+impl runtime::Closure for Closure<'_> {
+    fn params(&self) -> Vec<&str> {
+        self.lambda.params.iter().map(|param| param.name).collect()
+    }
+}
+
+/// Why evaluation stopped before it got to a value.
+enum Unwind<'a> {
+    Return(Value<'a>),
+    Failure(Failure),
+}
+
+impl From<Failure> for Unwind<'_> {
+    fn from(failure: Failure) -> Self {
+        Unwind::Failure(failure)
+    }
+}
+
+/// Where the program is about to go when an [`Observer`] is told.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// A function was called and its parameters have their values.
+    Call,
+    /// A statement, or the value at the end of a block, is about to be evaluated.
+    Evaluate,
+}
+
+/// Watches a program run, which is how the [`crate::debugger`] stops it.
+pub trait Observer<'a> {
+    /// Told before the program goes on at `span` in `function`, `depth` calls deep, with the
+    /// variables that have a value. Returning `false` stops the program, which no `try`
+    /// rescues.
+    fn observe(
+        &mut self,
+        event: Event,
+        function: FunctionId,
+        span: &Span,
+        depth: usize,
+        variables: &HashMap<VariableId, Value<'a>>,
+    ) -> bool;
+}
+
+/// The variables of a call.
+struct Frame<'a> {
+    /// The function being run, which decides the nested functions that are visible.
+    function: FunctionId,
+    variables: HashMap<VariableId, Value<'a>>,
+}
+
+pub struct Interpreter<'a> {
+    program: &'a Program<'a>,
+    depth: usize,
+    /// How many expressions have been evaluated.
+    steps: u64,
+    limits: Limits,
+    /// Whether the program may read and write stdin, stdout and files.
+    io: bool,
+    /// The functions that may be called, or `None` for all of them.
+    only: Option<&'a [FunctionId]>,
+    observer: Option<Box<dyn Observer<'a> + 'a>>,
+    /// Whether the observer stopped the program.
+    stopped: bool,
+    /// The last failure of the program going past its limits.
+    exceeded: Option<Failure>,
+}
+
+impl<'a> Interpreter<'a> {
+    /// An interpreter for programs that don't do I/O.
+    pub fn new(program: &'a Program<'a>) -> Self {
+        Self {
+            program,
+            depth: 0,
+            steps: 0,
+            limits: Limits::default(),
+            io: false,
+            only: None,
+            observer: None,
+            stopped: false,
+            exceeded: None,
+        }
+    }
+
+    /// Tell the observer where the program goes, from now on.
+    pub fn with_observer(self, observer: Box<dyn Observer<'a> + 'a>) -> Self {
+        Self {
+            observer: Some(observer),
+            ..self
+        }
+    }
+
+    /// Whether the observer stopped the program, rather than it failing by itself.
+    pub fn stopped(&self) -> bool {
+        self.stopped
+    }
+
+    /// Whether the failure is the program going past its limits, rather than failing by itself.
+    pub fn exceeded(&self, failure: &Failure) -> bool {
+        self.exceeded.as_ref() == Some(failure)
+    }
+
+    fn exceed(&mut self, message: String, expr: &Expression) -> Failure {
+        let failure = Failure::new(message, &expr.span());
+        self.exceeded = Some(failure.clone());
+        failure
+    }
+
+    /// Stop the program once it goes past the limits, which count from the start of the
+    /// interpreter rather than from every call.
+    pub fn with_limits(self, limits: Limits) -> Self {
+        let depth = limits.depth.min(MAX_DEPTH);
+        Self {
+            limits: Limits { depth, ..limits },
+            ..self
+        }
+    }
+
+    /// Allow or forbid the program to read and write stdin, stdout and files.
+    pub fn with_io(self, io: bool) -> Self {
+        Self { io, ..self }
+    }
+
+    /// Only allow the given functions to be called, which have to include the functions they
+    /// call, like [`Program::callees`] does. The others fail if they are called.
+    pub fn with_only(self, functions: &'a [FunctionId]) -> Self {
+        Self {
+            only: Some(functions),
+            ..self
+        }
+    }
+
+    /// Call a function with the given arguments, the parameters left out get their defaults.
+    pub fn call(
+        &mut self,
+        function: FunctionId,
+        args: Vec<Value<'a>>,
+        span: &Span,
+    ) -> Result<Value<'a>, Failure> {
+        let func = &self.program.functions[function];
+        if self.only.is_some_and(|only| !only.contains(&function)) {
+            return Err(Failure::new(
+                format!("`{}` was left out of the program", func.definition.name),
+                span,
+            ));
+        }
+        let params = &func.definition.params;
+        if args.len() > params.len() {
+            return Err(Failure::new(
+                format!(
+                    "`{}` takes {} arguments, but got {}",
+                    func.definition.name,
+                    params.len(),
+                    args.len()
+                ),
+                span,
+            ));
+        }
+        let mut frame = Frame {
+            function,
+            variables: HashMap::new(),
+        };
+        let mut args = args.into_iter();
+        for param in params {
+            // defaults can refer to the parameters before them
+            let value = match (args.next(), &param.default) {
+                (Some(value), _) => value,
+                (None, Some(default)) => self.run(&mut frame, default)?,
+                (None, None) => {
+                    return Err(Failure::new(
+                        format!(
+                            "`{}` is missing an argument for `{}`",
+                            func.definition.name, param.name
+                        ),
+                        span,
+                    ))
+                }
+            };
+            if let Some(id) = param.id {
+                frame.variables.insert(id, value);
+            }
+        }
+        // the body runs one call deeper
+        self.depth += 1;
+        let observed = self.observe(Event::Call, &frame, &func.definition.span);
+        self.depth -= 1;
+        observed?;
+        self.run(&mut frame, &func.body)
+    }
+
+    /// Tell the observer, if there is one, and fail if it stops the program.
+    fn observe(&mut self, event: Event, frame: &Frame<'a>, span: &Span) -> Result<(), Failure> {
+        let observer = match &mut self.observer {
+            Some(observer) => observer,
+            None => return Ok(()),
+        };
+        if !observer.observe(event, frame.function, span, self.depth, &frame.variables) {
+            self.stopped = true;
+        }
+        if self.stopped {
+            return Err(Failure::new("the program was stopped", span));
+        }
+        Ok(())
+    }
+
+    /// Evaluate an expression that belongs to a function without being part of its body, like
+    /// the input of one of its tests, with the variables it has besides, like those of `forall`.
+    pub fn evaluate(
+        &mut self,
+        function: FunctionId,
+        variables: HashMap<VariableId, Value<'a>>,
+        expr: &'a Expression<'a>,
+    ) -> Result<Value<'a>, Failure> {
+        let mut frame = Frame {
+            function,
+            variables,
+        };
+        self.run(&mut frame, expr)
+    }
+
+    /// Evaluate an expression that is the body of a call, where a return has the value.
+    fn run(
+        &mut self,
+        frame: &mut Frame<'a>,
+        expr: &'a Expression<'a>,
+    ) -> Result<Value<'a>, Failure> {
+        if self.depth == self.limits.depth {
+            return Err(self.exceed(
+                format!(
+                    "the program went more than {} calls deep",
+                    self.limits.depth
+                ),
+                expr,
+            ));
+        }
+        self.depth += 1;
+        let value = self.eval(frame, expr);
+        self.depth -= 1;
+        match value {
+            Ok(value) | Err(Unwind::Return(value)) => Ok(value),
+            Err(Unwind::Failure(failure)) => Err(failure),
+        }
+    }
+
+    fn call_value(
+        &mut self,
+        callee: Value<'a>,
+        args: Vec<Value<'a>>,
+        span: &Span,
+    ) -> Result<Value<'a>, Failure> {
+        match callee {
+            Value::Function(function, _) => self.call(function, args, span),
+            Value::Closure(closure) => {
+                let lambda = closure.lambda;
+                if args.len() != lambda.params.len() {
+                    return Err(Failure::new(
+                        format!(
+                            "the lambda takes {} arguments, but got {}",
+                            lambda.params.len(),
+                            args.len()
+                        ),
+                        span,
+                    ));
+                }
+                let mut frame = Frame {
+                    function: closure.function,
+                    variables: closure.variables.clone(),
+                };
+                for (param, value) in lambda.params.iter().zip(args) {
+                    if let Some(id) = param.id {
+                        frame.variables.insert(id, value);
+                    }
+                }
+                self.run(&mut frame, &lambda.body)
+            }
+            callee => Err(Failure::new(format!("{} is not a function", callee), span)),
+        }
+    }
+
+    fn eval(
+        &mut self,
+        frame: &mut Frame<'a>,
+        expr: &'a Expression<'a>,
+    ) -> Result<Value<'a>, Unwind<'a>> {
+        self.steps += 1;
+        if self.limits.steps.is_some_and(|steps| self.steps > steps) {
+            let steps = self.steps - 1;
+            return Err(self
+                .exceed(format!("the program took more than {} steps", steps), expr)
+                .into());
+        }
+        let value = match expr {
+            Expression::Expression(expr)
+            | Expression::Paren(expr, _)
+            | Expression::ExpressionComment((expr, _)) => self.eval(frame, expr)?,
+            Expression::Block(block, tail, _) => {
+                for stmt in block {
+                    self.observe(Event::Evaluate, frame, &stmt.span(self.program))?;
+                    self.exec(frame, stmt)?;
+                }
+                match tail {
+                    Some(tail) => {
+                        self.observe(Event::Evaluate, frame, &tail.span())?;
+                        self.eval(frame, tail)?
+                    }
+                    None => Value::Unit,
+                }
+            }
+            Expression::FunctionCall(FunctionCall::Direct {
+                function,
+                args,
+                defaulted,
+                span,
+                ..
+            }) => {
+                // the defaults are evaluated by the callee, where they can see its parameters
+                let args = self.eval_all(frame, &args[..args.len() - defaulted])?;
+                self.call(*function, args, span)?
+            }
+            Expression::FunctionCall(FunctionCall::Intrinsic {
+                intrinsic,
+                args,
+                span,
+                ..
+            }) => {
+                let args = self.eval_all(frame, args)?;
+                runtime::intrinsic(*intrinsic, args, self.io, span)?
+            }
+            Expression::FunctionCall(FunctionCall::Indirect {
+                callee, args, span, ..
+            }) => {
+                let callee = self.eval(frame, callee)?;
+                let args = self.eval_all(frame, args)?;
+                self.call_value(callee, args, span)?
+            }
+            Expression::EnumConstructor(constructor) => {
+                let variant = self
+                    .program
+                    .enums
+                    .values()
+                    .filter(|enum_| enum_.name == constructor.enum_name)
+                    .flat_map(|enum_| &enum_.variants)
+                    .find(|variant| variant.name == constructor.variant)
+                    .ok_or_else(|| {
+                        Failure::new(
+                            format!(
+                                "`{}::{}` is not defined",
+                                constructor.enum_name, constructor.variant
+                            ),
+                            &constructor.span,
+                        )
+                    })?;
+                if constructor.args.len() != variant.fields.len() {
+                    return Err(Failure::new(
+                        format!(
+                            "`{}::{}` has {} fields, but got {}",
+                            constructor.enum_name,
+                            constructor.variant,
+                            variant.fields.len(),
+                            constructor.args.len()
+                        ),
+                        &constructor.span,
+                    )
+                    .into());
+                }
+                let values = self.eval_all(frame, &constructor.args)?;
+                Value::Enum {
+                    name: constructor.enum_name,
+                    variant: constructor.variant,
+                    fields: variant
+                        .fields
+                        .iter()
+                        .map(|field| field.name)
+                        .zip(values)
+                        .collect(),
+                }
+            }
+            Expression::Variable(var) => match var.id {
+                Some(id) => frame.variables.get(&id).cloned().ok_or_else(|| {
+                    Failure::new(format!("`{}` has no value here", var.name), &var.span)
+                })?,
+                None => match self.program.function_named(frame.function, var.name) {
+                    Some(function) => Value::Function(function, var.name),
+                    None => {
+                        return Err(Failure::new(
+                            format!("`{}` is not defined", var.name),
+                            &var.span,
+                        )
+                        .into())
+                    }
+                },
+            },
+            Expression::Number(number) => Value::Int(number.value),
+            Expression::String(string) => Value::String(string.value.to_string()),
+            Expression::Unit(_, _) => Value::Unit,
+            Expression::Bool(value, _, _) => Value::Bool(*value),
+            Expression::None(_, _) => Value::None,
+            Expression::Some(value, _, _) => Value::Some(Box::new(self.eval(frame, value)?)),
+            Expression::If(if_) => self.eval_if(frame, if_)?,
+            Expression::Lambda(lambda) => Value::Closure(Rc::new(Closure {
+                lambda,
+                function: frame.function,
+                variables: frame.variables.clone(),
+            })),
+            Expression::Field(field) => {
+                let object = self.eval(frame, &field.object)?;
+                let value = match (&object, field.name) {
+                    (Value::Enum { fields, .. }, name) => fields
+                        .iter()
+                        .find(|(field, _)| *field == name)
+                        .map(|(_, value)| value.clone()),
+                    (Value::Some(value), "value") => Some((**value).clone()),
+                    (Value::Range { start, .. }, "start") => Some(Value::Int(*start)),
+                    (Value::Range { end, .. }, "end") => Some(Value::Int(*end)),
+                    _ => None,
+                };
+                value.ok_or_else(|| {
+                    Failure::new(
+                        format!("{} has no field `{}`", object, field.name),
+                        &field.span,
+                    )
+                })?
+            }
+            Expression::MethodCall(call) => {
+                let object = self.eval(frame, &call.object)?;
+                self.eval_all(frame, &call.args)?;
+                return Err(Failure::new(
+                    format!("{} has no method `{}`", object, call.method),
+                    &call.span,
+                )
+                .into());
+            }
+            Expression::Try(try_) => match self.eval(frame, &try_.body) {
+                Err(Unwind::Failure(failure)) if !self.stopped => {
+                    frame
+                        .variables
+                        .insert(try_.error_id, Value::String(failure.message));
+                    self.eval(frame, &try_.rescue)?
+                }
+                value => value?,
+            },
+            Expression::Range(range) => {
+                let start = self.eval(frame, &range.start)?;
+                let end = self.eval(frame, &range.end)?;
+                match (start, end) {
+                    (Value::Int(start), Value::Int(end)) => Value::Range {
+                        start,
+                        end,
+                        inclusive: range.inclusive,
+                    },
+                    (start, end) => {
+                        return Err(Failure::new(
+                            format!("a range has to be between ints, not {} and {}", start, end),
+                            &range.span,
+                        )
+                        .into())
+                    }
+                }
+            }
+            Expression::Op(lhs, op, rhs, span) => {
+                let lhs = self.eval(frame, lhs)?;
+                let rhs = self.eval(frame, rhs)?;
+                runtime::operation(lhs, *op, rhs, span)?
+            }
+            Expression::Error(span) => {
+                return Err(Failure::new("the program has a syntax error here", span).into())
+            }
+        };
+        if let Some(memory) = self.limits.memory {
+            let bytes = value.bytes();
+            if bytes > memory {
+                return Err(self
+                    .exceed(
+                        format!(
+                            "the program built a value of {} bytes, more than the {} it may",
+                            bytes, memory
+                        ),
+                        expr,
+                    )
+                    .into());
+            }
+        }
+        Ok(value)
+    }
+
+    fn eval_all(
+        &mut self,
+        frame: &mut Frame<'a>,
+        exprs: &'a [Box<Expression<'a>>],
+    ) -> Result<Vec<Value<'a>>, Unwind<'a>> {
+        exprs.iter().map(|expr| self.eval(frame, expr)).collect()
+    }
+
+    fn eval_if(&mut self, frame: &mut Frame<'a>, if_: &'a If<'a>) -> Result<Value<'a>, Unwind<'a>> {
+        let condition = match self.eval(frame, &if_.condition)? {
+            Value::Bool(condition) => condition,
+            value => {
+                return Err(Failure::new(
+                    format!("the condition has to be a comparison, but is {}", value),
+                    &if_.condition.span(),
+                )
+                .into())
+            }
+        };
+        if condition {
+            return self.eval(frame, &if_.body);
+        }
+        match &if_.else_body {
+            Some(Else::Block(block)) => self.eval(frame, block),
+            Some(Else::If(else_if)) => self.eval_if(frame, else_if),
+            None => Ok(Value::Unit),
+        }
+    }
+
+    fn exec(&mut self, frame: &mut Frame<'a>, stmt: &'a Statement<'a>) -> Result<(), Unwind<'a>> {
+        match stmt {
+            Statement::Let(let_) => {
+                let value = self.eval(frame, &let_.value)?;
+                frame.variables.insert(let_.id, value);
+            }
+            Statement::Assign(assign) => {
+                let value = self.eval(frame, &assign.value)?;
+                let current = assign
+                    .variable
+                    .and_then(|id| frame.variables.get_mut(&id))
+                    .ok_or_else(|| {
+                        Failure::new(format!("`{}` is not defined", assign.target), &assign.span)
+                    })?;
+                *current = match assign.op {
+                    Some(op) => runtime::operation(
+                        std::mem::replace(current, Value::Unit),
+                        op,
+                        value,
+                        &assign.span,
+                    )?,
+                    None => value,
+                };
+            }
+            Statement::Expression(expr) => {
+                self.eval(frame, expr)?;
+            }
+            Statement::Return(value, _, _) => {
+                return Err(Unwind::Return(self.eval(frame, value)?));
+            }
+            Statement::Assert(assert) => match self.eval(frame, &assert.condition)? {
+                Value::Bool(true) => {}
+                Value::Bool(false) => {
+                    return Err(Failure::new(
+                        format!("assertion `{}` failed", assert.text),
+                        &assert.span,
+                    )
+                    .into())
+                }
+                value => {
+                    return Err(Failure::new(
+                        format!("the condition has to be a comparison, but is {}", value),
+                        &assert.condition.span(),
+                    )
+                    .into())
+                }
+            },
+            Statement::Comment(_) | Statement::Function(_) => {}
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,193 @@
+//! What the functions built into the language do, the same for every backend: math on ints, as
+//! there are no other numbers, working with strings, which are counted in characters, and reading
+//! and writing stdin, stdout and files, if the program is allowed to.
+use std::fmt::{Display, Error};
+use std::io::{BufRead, Write};
+
+use crate::resolved_ast::Intrinsic;
+use crate::typecheck::Type;
+
+/// The values intrinsics take and return, which every backend converts its values to and from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operand {
+    Int(i64),
+    String(String),
+    Bool(bool),
+    Unit,
+    List(Vec<Operand>),
+}
+
+impl Display for Operand {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> Result<(), Error> {
+        match self {
+            Operand::Int(value) => write!(fmt, "{}", value),
+            Operand::String(value) => write!(fmt, "\"{}\"", value),
+            Operand::Bool(value) => write!(fmt, "{}", value),
+            Operand::Unit => write!(fmt, "()"),
+            Operand::List(values) => {
+                let values = values.iter().map(Operand::to_string).collect::<Vec<_>>();
+                write!(fmt, "[{}]", values.join(", "))
+            }
+        }
+    }
+}
+
+/// The types of the parameters of an intrinsic, and the type it returns.
+pub fn signature(intrinsic: Intrinsic) -> (&'static [Type], Type) {
+    match intrinsic {
+        Intrinsic::Abs | Intrinsic::Sqrt => (&[Type::Int], Type::Int),
+        Intrinsic::Min | Intrinsic::Max | Intrinsic::Pow => (&[Type::Int, Type::Int], Type::Int),
+        // a `string` or a list, which have no type of their own
+        Intrinsic::Len => (&[Type::Unknown], Type::Int),
+        Intrinsic::Get => (&[Type::Unknown, Type::Int], Type::Unknown),
+        Intrinsic::Concat => (&[Type::String, Type::String], Type::String),
+        Intrinsic::Slice => (&[Type::String, Type::Int, Type::Int], Type::String),
+        Intrinsic::Contains => (&[Type::String, Type::String], Type::Bool),
+        Intrinsic::ReadLine => (&[], Type::String),
+        Intrinsic::Print => (&[Type::String], Type::Unit),
+        Intrinsic::ReadFile => (&[Type::String], Type::String),
+        Intrinsic::WriteFile => (&[Type::String, Type::String], Type::Unit),
+    }
+}
+
+/// Call an intrinsic with as many operands as its [`Intrinsic::arity`]. The ones that
+/// [`Intrinsic::does_io`] fail unless `io` allows them.
+pub fn evaluate(intrinsic: Intrinsic, args: &[Operand], io: bool) -> Result<Operand, String> {
+    let call = || {
+        let args = args.iter().map(Operand::to_string).collect::<Vec<_>>();
+        format!("`{}({})`", intrinsic.name(), args.join(", "))
+    };
+    let overflow = || format!("{} overflows, the result doesn't fit in an `int`", call());
+    let failed = |error: std::io::Error| format!("{} failed: {}", call(), error);
+    if intrinsic.does_io() && !io {
+        return Err(format!(
+            "`{}` reads or writes outside of the program, which it isn't allowed to",
+            intrinsic.name()
+        ));
+    }
+    let value = match (intrinsic, args) {
+        (Intrinsic::Abs, [Operand::Int(value)]) => {
+            Operand::Int(value.checked_abs().ok_or_else(overflow)?)
+        }
+        (Intrinsic::Min, [Operand::Int(lhs), Operand::Int(rhs)]) => Operand::Int(*lhs.min(rhs)),
+        (Intrinsic::Max, [Operand::Int(lhs), Operand::Int(rhs)]) => Operand::Int(*lhs.max(rhs)),
+        (Intrinsic::Pow, [Operand::Int(_), Operand::Int(exponent)]) if *exponent < 0 => {
+            return Err(format!(
+                "`pow` can't raise to the negative power {}, the result isn't an `int`",
+                exponent
+            ))
+        }
+        (Intrinsic::Pow, [Operand::Int(base), Operand::Int(exponent)]) => Operand::Int(
+            u32::try_from(*exponent)
+                .ok()
+                .and_then(|exponent| base.checked_pow(exponent))
+                .ok_or_else(overflow)?,
+        ),
+        (Intrinsic::Sqrt, [Operand::Int(value)]) if *value < 0 => {
+            return Err(format!(
+                "`sqrt` of the negative number {} isn't an `int`",
+                value
+            ))
+        }
+        // rounded down
+        (Intrinsic::Sqrt, [Operand::Int(value)]) => Operand::Int(value.isqrt()),
+        (Intrinsic::Len, [Operand::String(string)]) => Operand::Int(string.chars().count() as i64),
+        (Intrinsic::Len, [Operand::List(values)]) => Operand::Int(values.len() as i64),
+        (Intrinsic::Get, [Operand::List(values), Operand::Int(index)]) => usize::try_from(*index)
+            .ok()
+            .and_then(|index| values.get(index))
+            .cloned()
+            .ok_or_else(|| {
+                format!(
+                    "{} is out of bounds, the list has {} element{}",
+                    call(),
+                    values.len(),
+                    if values.len() == 1 { "" } else { "s" }
+                )
+            })?,
+        (Intrinsic::Concat, [Operand::String(lhs), Operand::String(rhs)]) => {
+            Operand::String(lhs.clone() + rhs)
+        }
+        (Intrinsic::Slice, [Operand::String(string), Operand::Int(start), Operand::Int(end)]) => {
+            let len = string.chars().count() as i64;
+            if *start < 0 || start > end || *end > len {
+                return Err(format!(
+                    "{} is out of bounds, the string has {} characters",
+                    call(),
+                    len
+                ));
+            }
+            Operand::String(
+                string
+                    .chars()
+                    .skip(*start as usize)
+                    .take((end - start) as usize)
+                    .collect(),
+            )
+        }
+        (Intrinsic::Contains, [Operand::String(string), Operand::String(part)]) => {
+            Operand::Bool(string.contains(part.as_str()))
+        }
+        (Intrinsic::ReadLine, []) => {
+            let mut line = String::new();
+            if std::io::stdin()
+                .lock()
+                .read_line(&mut line)
+                .map_err(failed)?
+                == 0
+            {
+                return Err(format!("{} failed: the input has ended", call()));
+            }
+            let line = line.strip_suffix('\n').unwrap_or(&line);
+            Operand::String(line.strip_suffix('\r').unwrap_or(line).to_string())
+        }
+        (Intrinsic::Print, [Operand::String(line)]) => {
+            writeln!(std::io::stdout(), "{}", line).map_err(failed)?;
+            Operand::Unit
+        }
+        (Intrinsic::ReadFile, [Operand::String(path)]) => {
+            Operand::String(std::fs::read_to_string(path).map_err(failed)?)
+        }
+        (Intrinsic::WriteFile, [Operand::String(path), Operand::String(contents)]) => {
+            std::fs::write(path, contents).map_err(failed)?;
+            Operand::Unit
+        }
+        (intrinsic, args) if args.len() != intrinsic.arity() => {
+            return Err(format!(
+                "`{}` takes {} argument{}, but got {}",
+                intrinsic.name(),
+                intrinsic.arity(),
+                if intrinsic.arity() == 1 { "" } else { "s" },
+                args.len()
+            ))
+        }
+        (intrinsic, args) => {
+            let args = args.iter().map(Operand::to_string).collect::<Vec<_>>();
+            return Err(wrong_arguments(intrinsic, &args));
+        }
+    };
+    Ok(value)
+}
+
+/// The failure of a call whose arguments, as the backend writes them, have the wrong types.
+pub fn wrong_arguments(intrinsic: Intrinsic, args: &[String]) -> String {
+    let (params, _) = signature(intrinsic);
+    let expected = match (intrinsic, params.split_last()) {
+        (Intrinsic::Len, _) => "a `string` or a list".to_string(),
+        (Intrinsic::Get, _) => "a list and an `int`".to_string(),
+        (_, params) => match params {
+            Some((last, [])) => last.to_string(),
+            Some((last, rest)) => {
+                let rest = rest.iter().map(Type::to_string).collect::<Vec<_>>();
+                format!("{} and {}", rest.join(", "), last)
+            }
+            None => "no arguments".to_string(),
+        },
+    };
+    format!(
+        "`{}` expects {}, but got {}",
+        intrinsic.name(),
+        expected,
+        args.join(", ")
+    )
+}
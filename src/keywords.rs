@@ -0,0 +1,73 @@
+//! The keywords of the language, each of which has an ASCII and an emoji spelling.
+//!
+//! The grammar accepts both spellings of every keyword and records which one was used, so the
+//! formatter can keep it or settle on one of them.
+use serde::{Deserialize, Serialize};
+
+/// Which of its two spellings a keyword was written with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Spelling {
+    #[default]
+    Ascii,
+    Emoji,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Keyword {
+    Function,
+    Return,
+    Let,
+    Mut,
+    If,
+    Else,
+    Try,
+    Rescue,
+    Enum,
+    Import,
+    Assert,
+    Unit,
+    None,
+    Some,
+    True,
+    False,
+    Forall,
+    In,
+    /// The name of the entry point, a keyword only where a function is named.
+    Hatch,
+}
+
+/// Every keyword with its ASCII and emoji spelling. The grammar spells out the same tokens.
+pub const KEYWORDS: &[(Keyword, &str, &str)] = &[
+    (Keyword::Function, "egg", "🥚"),
+    (Keyword::Return, "*)>", "🐔"),
+    (Keyword::Let, "let", "📦"),
+    (Keyword::Mut, "mut", "🔓"),
+    (Keyword::If, "if", "❓"),
+    (Keyword::Else, "else", "❗"),
+    (Keyword::Try, "try", "🤞"),
+    (Keyword::Rescue, "rescue", "🛟"),
+    (Keyword::Enum, "enum", "🧺"),
+    (Keyword::Import, "import", "📥"),
+    (Keyword::Assert, "assert", "🔍"),
+    (Keyword::Unit, "()", "🫥"),
+    (Keyword::None, "none", "🪹"),
+    (Keyword::Some, "some", "🪺"),
+    (Keyword::True, "true", "✅"),
+    (Keyword::False, "false", "❌"),
+    (Keyword::Forall, "forall", "🎲"),
+    (Keyword::In, "in", "👉"),
+    (Keyword::Hatch, "hatch", "🐣"),
+];
+
+impl Keyword {
+    pub fn spell(self, spelling: Spelling) -> &'static str {
+        let (_, ascii, emoji) = KEYWORDS
+            .iter()
+            .find(|(keyword, _, _)| *keyword == self)
+            .unwrap();
+        match spelling {
+            Spelling::Ascii => ascii,
+            Spelling::Emoji => emoji,
+        }
+    }
+}
@@ -0,0 +1,288 @@
+#[macro_use]
+extern crate lalrpop_util;
+pub mod arity;
+pub mod assignments;
+pub mod base_ast;
+pub mod bench;
+pub mod bytecode;
+pub mod call_graph;
+pub mod control_flow;
+pub mod coverage;
+pub mod dead_code;
+pub mod debugger;
+pub mod diagnostics;
+pub mod diff;
+pub mod eliminate;
+pub mod fold;
+pub mod formatter;
+pub mod imports;
+pub mod infer;
+pub mod interchange;
+pub mod interpreter;
+pub mod intrinsics;
+pub mod keywords;
+pub mod line_index;
+pub mod lints;
+pub mod names;
+pub mod purity;
+#[cfg(feature = "pyo3")]
+pub mod python;
+pub mod resolved_ast;
+pub mod runtime;
+pub mod sarif;
+pub mod script;
+pub mod serve;
+pub mod shadowing;
+pub mod snapshot;
+#[cfg(feature = "spellcheck")]
+pub mod spellcheck;
+pub mod suggest;
+pub mod symbols;
+pub mod tail_calls;
+pub mod test_coverage;
+pub mod test_report;
+pub mod test_runner;
+pub mod timings;
+pub mod typecheck;
+pub mod units;
+pub mod unused;
+pub mod visit;
+pub mod vm;
+
+use std::fmt::{Display, Error};
+use std::num::ParseIntError;
+use std::path::{Path, PathBuf};
+
+use lalrpop_util::{lexer::Token, ErrorRecovery, ParseError};
+
+lalrpop_mod!(
+    #[allow(clippy::all)]
+    pub wryneck
+);
+
+/// The byte offsets of a piece of source, from its first byte up to the one after its last.
+pub type Span = std::ops::Range<usize>;
+
+/// Errors reported by the grammar's actions, in addition to lalrpop's own.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GrammarError {
+    /// A number literal that is empty or does not fit in an `i64`.
+    InvalidNumber(Span, ParseIntError),
+    /// A statement that isn't followed by a `;`, where it goes.
+    MissingSemicolon(usize),
+}
+
+impl Display for GrammarError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> Result<(), Error> {
+        match self {
+            GrammarError::InvalidNumber(_, err) => write!(fmt, "Invalid number literal: {}", err),
+            GrammarError::MissingSemicolon(_) => write!(fmt, "expected `;` after statement"),
+        }
+    }
+}
+
+pub type RecoveredError<'input> = ErrorRecovery<usize, Token<'input>, GrammarError>;
+
+/// What [`parse`] fails with: the error the parser stopped at, and the errors it recovered from
+/// before it, in the order they are in the input.
+#[derive(Debug)]
+pub struct ParseFailure<'input> {
+    pub recovered: Vec<RecoveredError<'input>>,
+    pub error: ParseError<usize, Token<'input>, GrammarError>,
+}
+
+/// Parse the input into a base AST, returning the recovered errors alongside it.
+pub fn parse(
+    input: &str,
+) -> Result<(base_ast::Program<'_>, Vec<RecoveredError<'_>>), ParseFailure<'_>> {
+    let _span = tracing::info_span!("parse").entered();
+    let mut errors = Vec::new();
+    let ast = wryneck::ProgramParser::new().parse(&mut errors, input);
+    let ast = match ast {
+        Ok(ast) => ast,
+        Err(error) => {
+            tracing::debug!(errors = errors.len(), "parsing stopped at an error");
+            return Err(ParseFailure {
+                recovered: errors,
+                error,
+            });
+        }
+    };
+    tracing::debug!(items = ast.things.len(), errors = errors.len(), "parsed");
+
+    Ok((ast, errors))
+}
+
+/// The given files and all `.wy` files in the given directories, searching them recursively.
+/// Files and directories in them whose name matches one of the `exclude` patterns are left out,
+/// in which `*` stands for any number of characters and `?` for one.
+///
+/// A path that doesn't exist but has a pattern in it, like `src/**/*.wy`, stands for the paths it
+/// matches, see [`expand_glob`], for shells that don't expand them or where they are quoted. One
+/// that matches nothing is kept as it is, for reading it to fail on.
+///
+/// Fails if a directory to search, or one in it, can't be read, with an error that names it.
+pub fn find_sources(paths: &[PathBuf], exclude: &[String]) -> std::io::Result<Vec<PathBuf>> {
+    let mut sources = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            search_sources(path, exclude, &mut sources)?;
+        } else if !path.exists() && is_pattern(&path.to_string_lossy()) {
+            let matches = expand_glob(path);
+            if matches.is_empty() {
+                sources.push(path.clone());
+            }
+            for path in matches {
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                if exclude
+                    .iter()
+                    .any(|pattern| matches_pattern(pattern, &name))
+                {
+                    continue;
+                }
+                if path.is_dir() {
+                    search_sources(&path, exclude, &mut sources)?;
+                } else {
+                    sources.push(path);
+                }
+            }
+        } else {
+            sources.push(path.clone());
+        }
+    }
+    Ok(sources)
+}
+
+fn is_pattern(text: &str) -> bool {
+    text.contains(['*', '?'])
+}
+
+/// The files and directories a pattern matches, sorted. In every part of the path `*` stands for
+/// any number of characters and `?` for one, and a part that is `**` for any number of
+/// directories, none included. Like in shells, names starting with `.` are only matched by parts
+/// that start with `.` too.
+pub fn expand_glob(pattern: &Path) -> Vec<PathBuf> {
+    let mut matches = vec![PathBuf::new()];
+    for component in pattern.components() {
+        let part = component.as_os_str().to_string_lossy();
+        matches = if part == "**" {
+            let mut dirs = Vec::new();
+            for dir in matches {
+                dirs.push(dir.clone());
+                subdirectories(&dir, &mut dirs);
+            }
+            dirs
+        } else if is_pattern(&part) {
+            matches
+                .iter()
+                .flat_map(|dir| entries(dir))
+                .filter(|path| {
+                    let name = path.file_name().unwrap_or_default().to_string_lossy();
+                    (!name.starts_with('.') || part.starts_with('.'))
+                        && matches_pattern(&part, &name)
+                })
+                .collect()
+        } else {
+            matches
+                .into_iter()
+                .map(|path| path.join(component))
+                .filter(|path| path.exists())
+                .collect()
+        };
+    }
+    matches
+}
+
+/// What is in a directory, sorted, with nothing for what isn't one. The empty path is the current
+/// directory, whose entries are relative.
+fn entries(dir: &Path) -> Vec<PathBuf> {
+    let read = if dir.as_os_str().is_empty() {
+        std::fs::read_dir(".")
+    } else {
+        std::fs::read_dir(dir)
+    };
+    let mut entries = read
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| dir.join(entry.file_name()))
+        .collect::<Vec<_>>();
+    entries.sort();
+    entries
+}
+
+/// Every directory in a directory, and in those, that doesn't start with `.`.
+fn subdirectories(dir: &Path, dirs: &mut Vec<PathBuf>) {
+    for path in entries(dir) {
+        let hidden = path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+        if path.is_dir() && !hidden {
+            dirs.push(path.clone());
+            subdirectories(&path, dirs);
+        }
+    }
+}
+
+fn search_sources(
+    dir: &Path,
+    exclude: &[String],
+    sources: &mut Vec<PathBuf>,
+) -> std::io::Result<()> {
+    let named = |error: std::io::Error| {
+        std::io::Error::new(error.kind(), format!("{}: {}", dir.display(), error))
+    };
+    let mut entries = std::fs::read_dir(dir)
+        .and_then(|entries| entries.collect::<std::io::Result<Vec<_>>>())
+        .map_err(named)?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if exclude
+            .iter()
+            .any(|pattern| matches_pattern(pattern, &name))
+        {
+            continue;
+        }
+        let path = entry.path();
+        if path.is_dir() {
+            search_sources(&path, exclude, sources)?;
+        } else if path.extension().is_some_and(|ext| ext == "wy") {
+            sources.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Whether a name matches a pattern in which `*` stands for any number of characters and `?` for
+/// one.
+pub fn matches_pattern(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let name = name.chars().collect::<Vec<_>>();
+    // where the last `*` was, and where in the name what it stands for ends
+    let mut star = None;
+    let (mut p, mut n) = (0, 0);
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            // let the last `*` stand for one more character
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    star = Some((star_p, star_n + 1));
+                    p = star_p + 1;
+                    n = star_n + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
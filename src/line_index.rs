@@ -0,0 +1,58 @@
+//! Lines and columns of the offsets in a text, so messages can point at `file.wy:12:8` rather than
+//! at the byte offsets the parser and the spans count in.
+use crate::Span;
+
+/// Where the lines of a text start, found once for the whole text, so the line of an offset is a
+/// binary search away.
+#[derive(Debug, Clone)]
+pub struct LineIndex<'t> {
+    text: &'t str,
+    /// The offset every line starts at, the first one 0.
+    starts: Vec<usize>,
+}
+
+impl<'t> LineIndex<'t> {
+    pub fn new(text: &'t str) -> Self {
+        let starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self { text, starts }
+    }
+
+    /// The text the lines are of.
+    pub fn text(&self) -> &'t str {
+        self.text
+    }
+
+    /// The line and column of an offset, both counted from 1. Columns count characters, not
+    /// bytes, and an offset in a character or past the end is at its start or at the end.
+    pub fn position(&self, offset: usize) -> (usize, usize) {
+        let mut offset = offset.min(self.text.len());
+        while !self.text.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        let line = self.starts.partition_point(|&start| start <= offset);
+        let start = self.starts[line - 1];
+        let column = self.text[start..offset].chars().count() + 1;
+        (line, column)
+    }
+
+    /// Where a line, counted from 1, is in the text, without the line break that ends it.
+    pub fn line_span(&self, line: usize) -> Span {
+        let start = self.starts[line - 1];
+        let end = self
+            .starts
+            .get(line)
+            .map_or(self.text.len(), |&next| next - 1);
+        if self.text[start..end].ends_with('\r') {
+            start..end - 1
+        } else {
+            start..end
+        }
+    }
+
+    /// The text of a line, counted from 1.
+    pub fn line(&self, line: usize) -> &'t str {
+        &self.text[self.line_span(line)]
+    }
+}
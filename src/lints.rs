@@ -0,0 +1,315 @@
+//! Checks for code that is likely a mistake but not wrong, which can be allowed or denied one by
+//! one, like `check --allow unused-variable --deny dead-function`, or in the code with a
+//! `// wryneck-allow(unused-variable)` comment before a statement or function.
+use std::fmt::{Display, Error};
+
+use crate::diagnostics::{Diagnostic, Severity};
+use crate::resolved_ast::*;
+use crate::visit::{walk_expression, walk_function, Visitor};
+use crate::{dead_code, purity, shadowing, suggest, tail_calls, unused, Span};
+
+/// What to do with the diagnostics of a lint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    /// Don't report them.
+    Allow,
+    /// Report them as warnings.
+    Warn,
+    /// Report them as errors.
+    Deny,
+}
+
+impl Display for Level {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> Result<(), Error> {
+        match self {
+            Level::Allow => write!(fmt, "allow"),
+            Level::Warn => write!(fmt, "warn"),
+            Level::Deny => write!(fmt, "deny"),
+        }
+    }
+}
+
+pub trait Lint {
+    /// The name used to configure the lint, in kebab case.
+    fn name(&self) -> &'static str;
+
+    /// The level of the lint unless it is configured otherwise.
+    fn level(&self) -> Level {
+        Level::Warn
+    }
+
+    /// The diagnostics of the lint for a linked program, their severity is set by the registry.
+    fn check(&self, program: &Program) -> Vec<Diagnostic>;
+}
+
+/// `let` bindings whose value is never read, see [`unused`].
+pub struct UnusedVariable;
+
+impl Lint for UnusedVariable {
+    fn name(&self) -> &'static str {
+        "unused-variable"
+    }
+
+    fn check(&self, program: &Program) -> Vec<Diagnostic> {
+        unused::check(program)
+    }
+}
+
+/// Functions that can never be called, see [`dead_code`].
+pub struct DeadFunction;
+
+impl Lint for DeadFunction {
+    fn name(&self) -> &'static str {
+        "dead-function"
+    }
+
+    fn check(&self, program: &Program) -> Vec<Diagnostic> {
+        dead_code::check(program)
+    }
+}
+
+/// `let` bindings that hide a parameter or an earlier binding, see [`shadowing`].
+pub struct Shadowing;
+
+impl Lint for Shadowing {
+    fn name(&self) -> &'static str {
+        "shadowing"
+    }
+
+    fn check(&self, program: &Program) -> Vec<Diagnostic> {
+        shadowing::check(program)
+    }
+}
+
+/// Calls of pure functions whose value is discarded, see [`purity`].
+pub struct DiscardedPureCall;
+
+impl Lint for DiscardedPureCall {
+    fn name(&self) -> &'static str {
+        "discarded-pure-call"
+    }
+
+    fn check(&self, program: &Program) -> Vec<Diagnostic> {
+        purity::check(program)
+    }
+}
+
+/// Recursive calls outside of a tail position, see [`tail_calls`]. Most recursion doesn't go
+/// deep enough for this to matter, so it is allowed unless asked for.
+pub struct NonTailRecursion;
+
+impl Lint for NonTailRecursion {
+    fn name(&self) -> &'static str {
+        "non-tail-recursion"
+    }
+
+    fn level(&self) -> Level {
+        Level::Allow
+    }
+
+    fn check(&self, program: &Program) -> Vec<Diagnostic> {
+        tail_calls::check(program)
+    }
+}
+
+/// The lints with their configured levels.
+pub struct Registry {
+    lints: Vec<(Box<dyn Lint>, Level)>,
+    /// What happens to every warning, whether it comes from a lint or not.
+    warnings: Level,
+}
+
+/// The name that configures all warnings at once, like `--deny warnings`.
+pub const WARNINGS: &str = "warnings";
+
+impl Registry {
+    /// The built-in lints at their default levels.
+    pub fn new() -> Self {
+        let lints: Vec<Box<dyn Lint>> = vec![
+            Box::new(UnusedVariable),
+            Box::new(Shadowing),
+            Box::new(DeadFunction),
+            Box::new(DiscardedPureCall),
+            Box::new(NonTailRecursion),
+        ];
+        Self {
+            lints: lints
+                .into_iter()
+                .map(|lint| {
+                    let level = lint.level();
+                    (lint, level)
+                })
+                .collect(),
+            warnings: Level::Warn,
+        }
+    }
+
+    /// The lints with their levels, in order of registration.
+    pub fn lints(&self) -> impl Iterator<Item = (&dyn Lint, Level)> {
+        self.lints.iter().map(|(lint, level)| (&**lint, *level))
+    }
+
+    /// Configure the level of a lint, or of all warnings with [`WARNINGS`].
+    pub fn set_level(&mut self, name: &str, level: Level) -> Result<(), String> {
+        if name == WARNINGS {
+            self.warnings = level;
+            return Ok(());
+        }
+        match self.lints.iter_mut().find(|(lint, _)| lint.name() == name) {
+            Some((_, configured)) => {
+                *configured = level;
+                Ok(())
+            }
+            None => {
+                let names = self.lints.iter().map(|(lint, _)| lint.name());
+                let mut message = format!("unknown lint `{}`", name);
+                if let Some(closest) = suggest::closest(name, names).first() {
+                    message += &format!(", did you mean `{}`?", closest);
+                }
+                Err(message)
+            }
+        }
+    }
+
+    /// Run the lints that aren't allowed, leaving out what allow comments allow.
+    pub fn check(&self, program: &Program) -> Vec<Diagnostic> {
+        let allowed = allowed(program);
+        let mut diagnostics = Vec::new();
+        for (lint, level) in &self.lints {
+            let severity = match level {
+                Level::Allow => continue,
+                Level::Warn => Severity::Warning,
+                Level::Deny => Severity::Error,
+            };
+            diagnostics.extend(
+                lint.check(program)
+                    .into_iter()
+                    .filter(|diagnostic| {
+                        !allowed.iter().any(|(name, span)| {
+                            name == lint.name()
+                                && span.start <= diagnostic.span.start
+                                && diagnostic.span.end <= span.end
+                        })
+                    })
+                    .map(|diagnostic| Diagnostic {
+                        severity,
+                        ..diagnostic
+                    }),
+            );
+        }
+        diagnostics
+    }
+
+    /// Apply the level of all warnings to a diagnostic, dropping it if warnings are allowed.
+    pub fn configure(&self, diagnostic: Diagnostic) -> Option<Diagnostic> {
+        match (diagnostic.severity, self.warnings) {
+            (Severity::Warning, Level::Allow) => None,
+            (Severity::Warning, Level::Deny) => Some(Diagnostic {
+                severity: Severity::Error,
+                ..diagnostic
+            }),
+            _ => Some(diagnostic),
+        }
+    }
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The comment that allows lints in the statement or function after it, like
+/// `// wryneck-allow(unused-variable, shadowing)`.
+pub const ALLOW: &str = "wryneck-allow";
+
+/// The lints a comment allows, if it is an allow comment on a line of its own. Their names may
+/// be written in snake case too, `unused_variable`.
+fn allows(comment: &Comment) -> Vec<String> {
+    let names = match comment
+        .text
+        .trim_end()
+        .strip_prefix(ALLOW)
+        .and_then(|rest| rest.trim_start().strip_prefix('('))
+        .and_then(|rest| rest.strip_suffix(')'))
+    {
+        Some(names) if !comment.trailing => names,
+        _ => return Vec::new(),
+    };
+    names
+        .split(',')
+        .map(|name| name.trim().replace('_', "-"))
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+/// A comment, or the span of the statement, function or value after it.
+enum Item<'c> {
+    Comment(&'c Comment<'c>),
+    Code(Span),
+}
+
+/// The lints allowed by allow comments in the files that aren't imported, each with the span it
+/// is allowed in: of the statement or function after the comment, with only comments between.
+fn allowed(program: &Program) -> Vec<(String, Span)> {
+    let mut allowed = Allowed {
+        program,
+        allowed: Vec::new(),
+    };
+    allowed.record(program.things.iter().map(|thing| match thing {
+        TopLevel::Function(id) => Some(Item::Code(function_span(&program.functions[*id]))),
+        TopLevel::Statement(index) => Some(Item::Code(
+            program.script_statements()[*index].span(program),
+        )),
+        TopLevel::Comment(comment) => Some(Item::Comment(comment)),
+        TopLevel::Enum(_) | TopLevel::Import(_) => None,
+    }));
+    for (id, func) in &program.functions {
+        if !program.is_imported(id) {
+            walk_function(&mut allowed, func);
+        }
+    }
+    allowed.allowed
+}
+
+/// From the start of the definition to the end of the body.
+fn function_span(func: &Function) -> Span {
+    func.definition.span.start..func.body.span().end
+}
+
+struct Allowed<'p> {
+    program: &'p Program<'p>,
+    allowed: Vec<(String, Span)>,
+}
+
+impl Allowed<'_> {
+    /// Allow the lints of the allow comments among `items` in the item after them. `None` is
+    /// something else, which nothing is allowed in, like an enum.
+    fn record<'c>(&mut self, items: impl Iterator<Item = Option<Item<'c>>>) {
+        let mut names = Vec::new();
+        for item in items {
+            match item {
+                Some(Item::Comment(comment)) => names.extend(allows(comment)),
+                Some(Item::Code(span)) => self
+                    .allowed
+                    .extend(names.drain(..).map(|name| (name, span.clone()))),
+                None => names.clear(),
+            }
+        }
+    }
+}
+
+impl<'input> Visitor<'input> for Allowed<'_> {
+    fn visit_expression(&mut self, expr: &Expression<'input>) {
+        if let Expression::Block(stmts, tail, _) = expr {
+            let program = self.program;
+            let items = stmts.iter().map(|stmt| match stmt {
+                Statement::Comment(comment) => Some(Item::Comment(comment)),
+                Statement::Function(id) => Some(Item::Code(function_span(&program.functions[*id]))),
+                stmt => Some(Item::Code(stmt.span(program))),
+            });
+            self.record(items.chain(tail.iter().map(|tail| Some(Item::Code(tail.span())))));
+        }
+        walk_expression(self, expr);
+    }
+}
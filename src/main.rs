@@ -1,256 +1,1829 @@
-#[macro_use]
-extern crate lalrpop_util;
-pub mod base_ast;
-pub mod formatter;
-pub mod resolved_ast;
-use std::ops::Range;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::{io::IsTerminal, path::PathBuf, str::FromStr};
 
 use colored::*;
-use lalrpop_util::{lexer::Token, ErrorRecovery, ParseError};
+use lalrpop_util::{lexer::Token, ParseError};
 use structopt::StructOpt;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::prelude::*;
+use wryneck::diagnostics::{self, Diagnostic, Severity};
+use wryneck::line_index::LineIndex;
+use wryneck::{
+    arity, assignments, bench, bytecode, call_graph, control_flow, coverage, debugger, diff,
+    eliminate, find_sources, fold, formatter, imports, infer, interchange, interpreter, lints,
+    names, parse, resolved_ast, runtime, sarif, script, serve, snapshot, tail_calls, test_coverage,
+    test_report, test_runner, timings, typecheck, units, vm, GrammarError, ParseFailure,
+    RecoveredError,
+};
 
-lalrpop_mod!(pub wryneck);
+/// What `wryneck` exits with when it reported an error in a program, or a check or a test failed.
+const FAILURE: i32 = 1;
+/// What `wryneck` exits with when it couldn't do what it was asked, because of its arguments, its
+/// configuration, or a file it can't read or write.
+const USAGE_ERROR: i32 = 2;
+
+/// How many errors and warnings [`print_diagnostic`] printed, and how many it left out after
+/// [`ERROR_LIMIT`] errors, for the summary at the end. `wryneck` exits with [`FAILURE`] if there
+/// were errors.
+static ERRORS: AtomicUsize = AtomicUsize::new(0);
+static WARNINGS: AtomicUsize = AtomicUsize::new(0);
+static LEFT_OUT: AtomicUsize = AtomicUsize::new(0);
+/// Whether one of the files a command was given couldn't be read, which it reported as an error
+/// before carrying on with the others. `wryneck` exits with [`USAGE_ERROR`] then.
+static UNREADABLE: AtomicBool = AtomicBool::new(false);
+/// How many errors are printed before the errors and warnings after them are left out, 0 for no
+/// limit, `--error-limit`.
+static ERROR_LIMIT: AtomicUsize = AtomicUsize::new(20);
+/// Whether the diagnostics are printed as JSON, which is for tools that don't need a summary.
+static JSON: AtomicBool = AtomicBool::new(false);
+/// The diagnostics of `--message-format sarif`, printed as one log in place of the summary.
+static SARIF: Mutex<Option<sarif::Log>> = Mutex::new(None);
+/// Whether the summary is left out, `--quiet`.
+static QUIET: AtomicBool = AtomicBool::new(false);
+/// How long the phases took, printed with the summary, `--timings`.
+static TIMINGS: OnceLock<timings::Timings> = OnceLock::new();
+
+/// The line that ends the output if there were errors or warnings, like "3 errors, 2 warnings
+/// emitted".
+fn summary(errors: usize, warnings: usize, left_out: usize, limit: usize) -> Option<String> {
+    let count = |count: usize, what: &str| match count {
+        0 => None,
+        1 => Some(format!("1 {}", what)),
+        count => Some(format!("{} {}s", count, what)),
+    };
+    let emitted = [count(errors, "error"), count(warnings, "warning")]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+    if emitted.is_empty() {
+        return None;
+    }
+    let mut summary = format!("{} emitted", emitted.join(", "));
+    if left_out > 0 {
+        summary += &format!(
+            ", {} more left out after the first {}",
+            left_out,
+            count(limit, "error").unwrap()
+        );
+    }
+    Some(summary)
+}
+
+/// Print the summary of the errors and warnings, if there were any, or the SARIF log of them, and
+/// how long the phases took.
+fn print_summary() {
+    if let Some(timings) = TIMINGS.get() {
+        eprint!("{}", timings.report());
+    }
+    if let Some(log) = SARIF.lock().unwrap().take() {
+        println!("{}", log.to_json());
+        return;
+    }
+    if JSON.load(Ordering::Relaxed) || QUIET.load(Ordering::Relaxed) {
+        return;
+    }
+    let errors = ERRORS.load(Ordering::Relaxed);
+    let summary = summary(
+        errors,
+        WARNINGS.load(Ordering::Relaxed),
+        LEFT_OUT.load(Ordering::Relaxed),
+        ERROR_LIMIT.load(Ordering::Relaxed),
+    );
+    match summary {
+        Some(summary) if errors > 0 => eprintln!("{}", summary.red().bold()),
+        Some(summary) => eprintln!("{}", summary.yellow().bold()),
+        None => {}
+    }
+}
+
+/// Print the summary of the errors and warnings, and exit. A failure is a [`USAGE_ERROR`] if one
+/// of the files the command was given couldn't be read.
+fn exit(code: i32) -> ! {
+    print_summary();
+    if code == FAILURE && UNREADABLE.load(Ordering::Relaxed) {
+        std::process::exit(USAGE_ERROR)
+    }
+    std::process::exit(code)
+}
+
+/// Exit with [`FAILURE`] if errors were reported, so a program the parser only got past isn't
+/// run as if it were fine.
+fn exit_if_errors() {
+    if ERRORS.load(Ordering::Relaxed) > 0 {
+        exit(FAILURE);
+    }
+}
 
 /// Print a parse error to error stream.
-fn print_parse_error(error: &ParseError<usize, Token, &str>, input: &str) {
-    match error.clone() {
-        ParseError::InvalidToken { location } => {
-            println!(
-                "Parse error: {}",
-                format!("Invalid token at {}", location).red()
-            );
-            print_error_line(input, location..location + 1);
-        }
-        ParseError::UnrecognizedEOF {
-            location: _,
-            expected,
-        } => {
-            println!(
-                "{}",
-                format!(
-                    "Parse error: {}",
-                    format!(
-                        "Unexpected end of file. Expected one of {}",
-                        expected.join(", ")
-                    )
-                    .red()
-                )
-                .red()
-            );
-        }
-        ParseError::UnrecognizedToken {
-            token: (start_pos, token, end_pos),
-            expected,
-        } => {
+fn print_parse_error(error: &ParseError<usize, Token, GrammarError>, source: &Source) {
+    print_diagnostic(
+        &Diagnostic::from_parse_error(error, source.lines.text()),
+        source,
+    );
+}
+
+/// prints all errors in the given input
+fn print_parse_errs(errs: &[RecoveredError], source: &Source) {
+    for err in errs {
+        print_parse_error(&err.error, source);
+    }
+}
+
+/// Print the errors the parser recovered from, and the one it stopped at.
+fn print_parse_failure(failure: &ParseFailure, source: &Source) {
+    print_parse_errs(&failure.recovered, source);
+    print_parse_error(&failure.error, source);
+}
+
+/// Check that formatting what a file formatted to changes nothing, and print what went wrong if it
+/// doesn't parse or formatting it again changes it.
+fn verify_format(formatted: &str, name: &str, config: formatter::FormatConfig) -> bool {
+    let program = match parse(formatted) {
+        Ok((program, errors)) if errors.is_empty() => program,
+        parsed => {
             eprintln!(
                 "{}",
-                format!(
-                    "Unrecognized token `{}` found at {}..{}",
-                    token, start_pos, end_pos
-                )
-                .red()
+                format!("{} doesn't parse once formatted:", name).red()
             );
+            let source = Source::new(name, formatted);
+            match parsed {
+                Ok((_, errors)) => print_parse_errs(&errors, &source),
+                Err(failure) => print_parse_failure(&failure, &source),
+            }
+            return false;
+        }
+    };
+    let again = formatter::format_source(&resolved_ast::Program::from(program), formatted, config);
+    if again != formatted {
+        eprintln!("{}", format!("formatting {} again changes it:", name).red());
+        eprint!(
+            "{}",
+            diff::unified(formatted, &again, "formatted", "formatted again")
+        );
+        return false;
+    }
+    true
+}
 
-            eprintln!("{}", format!("Expected: {}", expected.join(" or ")).red());
+/// A file that messages point into, by its name and the line and column of the offset.
+struct Source<'a> {
+    name: String,
+    lines: LineIndex<'a>,
+    format: MessageFormat,
+}
 
-            print_error_line(input, start_pos..end_pos);
+impl<'a> Source<'a> {
+    fn new(name: impl std::fmt::Display, text: &'a str) -> Self {
+        Self {
+            name: name.to_string(),
+            lines: LineIndex::new(text),
+            format: MessageFormat::Human,
         }
-        ParseError::ExtraToken {
-            token: (start_pos, token, end_pos),
-        } => {
-            eprintln!(
-                "{}",
-                format!(
-                    "Extra token `{}` found at {}..{}",
-                    token, start_pos, end_pos
-                )
-                .red()
-            );
+    }
+
+    fn with_format(self, format: MessageFormat) -> Self {
+        Self { format, ..self }
+    }
+}
+
+#[derive(Debug, StructOpt)]
+struct Cli {
+    /// When to color what is printed: auto colors it for a terminal unless NO_COLOR is set,
+    /// always, or never
+    #[structopt(long, global = true, default_value = "auto")]
+    color: ColorChoice,
+
+    /// How many errors to print before leaving out the errors and warnings after them, 0 for no
+    /// limit
+    #[structopt(long, global = true, default_value = "20")]
+    error_limit: usize,
+
+    /// Log what is being done to error stream, -v for the phases and the files read, -vv for
+    /// details of each phase
+    #[structopt(short, long, global = true, parse(from_occurrences))]
+    verbose: u8,
+
+    /// Print only the errors and warnings, without the summary or logged warnings
+    #[structopt(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Print how long parsing, resolving, formatting, checking and executing took
+    #[structopt(long, global = true)]
+    timings: bool,
+
+    #[structopt(subcommand)]
+    command: Opt,
+}
+
+#[derive(Debug, StructOpt)]
+enum Opt {
+    /// Format files
+    Fmt {
+        /// The files to format, and directories to search for `.wy` files to format. `-` reads a
+        /// file from stdin
+        #[structopt(parse(from_os_str), required_unless = "snapshot")]
+        paths: Vec<PathBuf>,
+
+        /// The path of the file read from stdin, to name it in errors and find its wryneck.toml
+        #[structopt(long, parse(from_os_str))]
+        assume_filename: Option<PathBuf>,
+
+        /// Leave out files and directories whose name matches this pattern, in which `*` stands
+        /// for any number of characters and `?` for one
+        #[structopt(long, number_of_values = 1)]
+        exclude: Vec<String>,
+
+        /// Instead of printing the formatted files, replace the ones that aren't formatted
+        #[structopt(long, conflicts_with_all = &["snapshot", "ast", "check"])]
+        write: bool,
+
+        /// Print the AST
+        #[structopt(short, long)]
+        ast: bool,
+
+        /// Where to put a comma after the last item of a list: vertical, always or never
+        /// [default: vertical, or as the closest wryneck.toml says]
+        #[structopt(long)]
+        trailing_commas: Option<formatter::TrailingCommas>,
+
+        /// How to spell keywords: classic, preserve, ascii or emoji [default: classic, or as the
+        /// closest wryneck.toml says]
+        #[structopt(long, alias = "keywords")]
+        keyword_style: Option<formatter::KeywordStyle>,
+
+        /// Where to put parentheses around operations: full, minimal or preserve [default: full,
+        /// or as the closest wryneck.toml says]
+        #[structopt(long)]
+        parens: Option<formatter::Parens>,
+
+        /// Instead of one file, compare what every file in this directory formats to with its
+        /// `.expected` file
+        #[structopt(long, parse(from_os_str), conflicts_with = "paths")]
+        snapshot: Option<PathBuf>,
+
+        /// Create missing `.expected` files and replace those that differ
+        #[structopt(long, requires = "snapshot")]
+        update: bool,
+
+        /// Instead of printing the formatted files, exit with 1 if one isn't formatted already
+        #[structopt(long, conflicts_with_all = &["snapshot", "ast"])]
+        check: bool,
+
+        /// Instead of printing the formatted files, print what formatting changes as unified
+        /// diffs
+        #[structopt(long, conflicts_with_all = &["snapshot", "ast"])]
+        diff: bool,
+
+        /// Format the formatted files again, and exit with 1 if one doesn't parse or changes,
+        /// which is a bug in the formatter. Files that fail are never written
+        #[structopt(long, conflicts_with_all = &["snapshot", "ast"])]
+        verify: bool,
+    },
+    /// Parse files and report problems without formatting them
+    Check {
+        /// The files to check, and directories to search for `.wy` files to check, which can be
+        /// patterns like `src/**/*.wy`
+        #[structopt(parse(from_os_str), required = true)]
+        paths: Vec<PathBuf>,
+
+        /// Don't report a lint, or any warnings with `warnings`
+        #[structopt(short = "A", long, number_of_values = 1)]
+        allow: Vec<String>,
+
+        /// Report a lint as warnings, which is the default for all built-in lints
+        #[structopt(short = "W", long, number_of_values = 1)]
+        warn: Vec<String>,
+
+        /// Report a lint as errors, or all warnings with `warnings`
+        #[structopt(short = "D", long, number_of_values = 1)]
+        deny: Vec<String>,
+
+        /// Also print a representation of the program: callgraph prints GraphViz DOT, signatures
+        /// prints the inferred signature of every function, tailcalls the calls in tail position
+        #[structopt(long)]
+        emit: Option<Emit>,
+
+        /// How to print diagnostics: human, json for one JSON object a line with the file,
+        /// severity, code, message, spans and help of each, or sarif for a SARIF 2.1 log of all
+        /// of them to stdout
+        #[structopt(long, default_value = "human")]
+        message_format: MessageFormat,
+
+        /// Apply the fixes of the diagnostics that have one to the files, except those that
+        /// overlap another fix
+        #[structopt(long)]
+        fix: bool,
+
+        /// Check comments and string literals for misspellings
+        #[cfg(feature = "spellcheck")]
+        #[structopt(long)]
+        spellcheck: bool,
+    },
+    /// Print the syntax tree of files, with the files they import linked in
+    Ast {
+        /// The files to print, and directories to search for `.wy` files to print, which can be
+        /// patterns like `src/**/*.wy`
+        #[structopt(parse(from_os_str), required = true)]
+        paths: Vec<PathBuf>,
+
+        /// How to print the tree: debug as Rust debug output, or json for other tools to read
+        #[structopt(long, default_value = "debug")]
+        format: AstFormat,
+
+        /// Which tree to print: base as the parser makes it, or resolved with the names resolved
+        /// and the imported files linked in
+        #[structopt(long, default_value = "resolved")]
+        stage: Stage,
+    },
+    /// Compile files into `.wknb` files
+    Compile {
+        /// The files to compile, and directories to search for `.wy` files to compile, which can be
+        /// patterns like `src/**/*.wy`
+        #[structopt(parse(from_os_str), required = true)]
+        paths: Vec<PathBuf>,
+
+        /// The output file, for a single input file, defaults to the input file with a `.wknb`
+        /// extension
+        #[structopt(short, long, parse(from_os_str))]
+        output: Option<PathBuf>,
+
+        /// Fold constant arithmetic and remove dead code before writing the file
+        #[structopt(long)]
+        optimize: bool,
+    },
+    /// Reconstruct the source of a `.wknb` file from the resolved syntax tree it holds. Bytecode
+    /// is never written to a file, so there is none to decompile
+    Decompile {
+        /// The input file
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+    },
+    /// Run a file, from its top level statements or its `hatch` function
+    Run {
+        /// The input file
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+
+        /// What runs the program: interpreter walks the syntax tree, vm compiles it to bytecode
+        #[structopt(long, default_value = "interpreter")]
+        backend: runtime::Backend,
+
+        /// Let the program read stdin and files and write stdout and files
+        #[structopt(long)]
+        allow_io: bool,
+
+        /// Run this top level function instead of the program, with only the functions it calls
+        #[structopt(long, conflicts_with = "hatch-args")]
+        only: Option<String>,
+
+        /// An argument of the function `--only` runs, as JSON like `5` or `[1, 2]`, or else as a
+        /// string. Given once for every argument
+        #[structopt(long = "args", requires = "only", number_of_values = 1)]
+        only_args: Vec<String>,
+
+        /// Passed to `hatch` as a list of strings
+        #[structopt(name = "hatch-args")]
+        args: Vec<String>,
+    },
+    /// Run a file like `run` does, stopping at breakpoints and step by step
+    Debug {
+        /// The input file
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+
+        /// Let the program read stdin and files and write stdout and files
+        #[structopt(long)]
+        allow_io: bool,
 
-            print_error_line(input, start_pos..end_pos);
+        /// Passed to `hatch` as a list of strings
+        args: Vec<String>,
+    },
+    /// Serve the top level functions of a file over HTTP: `POST /name` with a JSON array of
+    /// arguments calls the function and answers with its result as JSON
+    ServeHttp {
+        /// The input file
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+
+        /// The port to listen on, on localhost
+        #[structopt(long, default_value = "8080")]
+        port: u16,
+
+        /// Let the functions read stdin and files and write stdout and files
+        #[structopt(long)]
+        allow_io: bool,
+    },
+    /// Run the tests attached to the functions of files
+    Test {
+        /// The files to test, and directories to search for `.wy` files to test, which can be
+        /// patterns like `src/**/*.wy`
+        #[structopt(parse(from_os_str), required = true)]
+        paths: Vec<PathBuf>,
+
+        /// How many expressions a test may evaluate [default: 10000000]
+        #[structopt(long)]
+        max_steps: Option<u64>,
+
+        /// How many calls deep a test may go, at most 1000 [default: 1000]
+        #[structopt(long)]
+        max_depth: Option<usize>,
+
+        /// How many bytes a single value of a test may take [default: 67108864]
+        #[structopt(long)]
+        max_memory: Option<usize>,
+
+        /// Only run the tests whose name or function contains this
+        #[structopt(long)]
+        filter: Option<String>,
+
+        /// How to print the results: text, or json or junit for other tools to read
+        #[structopt(long, default_value = "text")]
+        format: Report,
+
+        /// Also show which functions and statements the tests ran
+        #[structopt(long)]
+        coverage: bool,
+    },
+    /// Time the benchmarks attached to the functions of files
+    Bench {
+        /// The files to benchmark, and directories to search for `.wy` files to benchmark, which can be
+        /// patterns like `src/**/*.wy`
+        #[structopt(parse(from_os_str), required = true)]
+        paths: Vec<PathBuf>,
+
+        /// What runs the benchmarks: interpreter walks the syntax tree, vm compiles it to bytecode
+        #[structopt(long, default_value = "interpreter")]
+        backend: runtime::Backend,
+
+        /// How many times every benchmark is timed
+        #[structopt(long, default_value = "20")]
+        runs: usize,
+    },
+    /// Report which language constructs the given files don't use
+    GrammarCoverage {
+        /// Files or directories to search for `.wy` files
+        #[structopt(parse(from_os_str), default_value = "tests")]
+        paths: Vec<PathBuf>,
+    },
+    /// Explain what a diagnostic code, like E010, means, or list every code
+    Explain {
+        /// The code, listed with `check` errors and warnings
+        code: Option<String>,
+    },
+}
+
+/// What `check --emit` prints.
+#[derive(Debug, Clone, Copy)]
+enum Emit {
+    CallGraph,
+    Signatures,
+    TailCalls,
+}
+
+impl FromStr for Emit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "callgraph" => Ok(Self::CallGraph),
+            "signatures" => Ok(Self::Signatures),
+            "tailcalls" => Ok(Self::TailCalls),
+            _ => Err(format!(
+                "unknown output `{}`, expected callgraph, signatures or tailcalls",
+                s
+            )),
         }
-        ParseError::User { error } => {
-            eprintln!("{}", format!("{}", error).red());
+    }
+}
+
+/// How `ast --format` prints the syntax tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AstFormat {
+    Debug,
+    Json,
+}
+
+impl FromStr for AstFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "debug" => Ok(Self::Debug),
+            "json" => Ok(Self::Json),
+            _ => Err(format!("unknown format `{}`, expected debug or json", s)),
         }
     }
 }
 
-/// prints all errors in the given input
-fn print_parse_errs(errs: &Vec<ErrorRecovery<usize, Token, &str>>, input: &str) {
-    for err in errs {
-        print_parse_error(&err.error, input);
+/// Which syntax tree `ast --stage` prints.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stage {
+    /// What the parser makes of a file.
+    Base,
+    /// What [`resolved_ast::Program::link`] makes of that and the files it imports.
+    Resolved,
+}
+
+impl FromStr for Stage {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "base" => Ok(Self::Base),
+            "resolved" => Ok(Self::Resolved),
+            _ => Err(format!("unknown stage `{}`, expected base or resolved", s)),
+        }
     }
 }
 
-/// finds the end of the character at the given position
-fn find_end(s: &str, mut end: usize) -> usize {
-    // use the following, as soon as round_char_boundary is available
-    // let end = input.floor_char_boundary(start_pos + 5 + (end_pos - start_pos));
-    assert!(end < s.len());
-    while !s.is_char_boundary(end + 1) {
-        end += 1;
+/// How `test --format` prints the results.
+#[derive(Debug, Clone, Copy)]
+enum Report {
+    Text,
+    Json,
+    Junit,
+}
+
+impl FromStr for Report {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            "junit" => Ok(Self::Junit),
+            _ => Err(format!(
+                "unknown format `{}`, expected text, json or junit",
+                s
+            )),
+        }
     }
-    end + 1
 }
 
-/// prints the line of the given input at the given position
-fn print_error_line(input: &str, range: Range<usize>) {
-    let (start_pos, end_pos) = (range.start, range.end);
-    let mut input = input.to_string();
-    // replace the character with a space
-    input.insert_str(start_pos, "\x1B[31m");
-    let end = find_end(&input, start_pos + 4 + (end_pos - start_pos));
-    input.insert_str(end, "\x1B[0m");
-    let mut line = 0;
-    let mut col = 0;
-    let lines = input.lines().collect::<Vec<_>>();
-    {
-        // find the line number and the column number
-        let mut pos = 0;
-        for (i, l) in lines.iter().enumerate() {
-            if pos + l.len() >= start_pos {
-                line = i;
-                col = start_pos - pos + 1;
-                break;
+/// When to color what is printed, `--color`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Whether to color what is printed to a stream, which may be a terminal. Like
+    /// <https://no-color.org> asks, a `NO_COLOR` that isn't empty turns color off, unless it is
+    /// asked for with `always`.
+    fn enabled(self, terminal: bool) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                terminal && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
             }
-            pos += l.len() + 1;
         }
     }
-    let line_num_width = (line + 1).to_string().len();
-    // print the line and the previous one
-    if line > 1 {
-        eprintln!("{:>line_num_width$}: {}", line, lines[line - 1]);
+}
+
+impl FromStr for ColorChoice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Self::Auto),
+            "always" => Ok(Self::Always),
+            "never" => Ok(Self::Never),
+            _ => Err(format!(
+                "unknown color choice `{}`, expected auto, always or never",
+                s
+            )),
+        }
     }
-    eprintln!("{:>line_num_width$}: {}", line + 1, lines[line]);
-    let num_spaces = col + line_num_width + 1;
-    eprintln!("{}^", "-".repeat(num_spaces),);
 }
 
-fn parse(
-    input: &str,
-) -> Result<
-    (base_ast::Program, Vec<ErrorRecovery<usize, Token, &str>>),
-    ParseError<usize, Token, &str>,
-> {
-    let mut errors = Vec::new();
-    let ast = wryneck::ProgramParser::new().parse(&mut errors, input);
-    let ast = match ast {
-        Ok(ast) => ast,
-        Err(err) => {
-            return Err(err);
+/// How `check --message-format` prints diagnostics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MessageFormat {
+    /// With the lines they point at, to error stream.
+    Human,
+    /// One JSON object a line.
+    Json,
+    /// A SARIF log of all of them, to stdout at the end.
+    Sarif,
+}
+
+impl FromStr for MessageFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "human" => Ok(Self::Human),
+            "json" => Ok(Self::Json),
+            "sarif" => Ok(Self::Sarif),
+            _ => Err(format!(
+                "unknown message format `{}`, expected human, json or sarif",
+                s
+            )),
         }
-    };
+    }
+}
+
+/// Parse a file and the files it imports and link them into one program, printing any errors.
+fn read_linked_program(
+    files: &[imports::SourceFile],
+    format: MessageFormat,
+) -> Option<resolved_ast::Program<'_>> {
+    let mut asts = Vec::new();
+    for file in files {
+        let source = Source::new(file.path.display(), &file.text).with_format(format);
+        match parse(&file.text) {
+            Ok((ast, errors)) => {
+                print_parse_errs(&errors, &source);
+                asts.push(ast);
+            }
+            Err(err) => {
+                print_parse_failure(&err, &source);
+                return None;
+            }
+        }
+    }
+    let mut asts = asts.into_iter();
+    let main = asts.next()?;
+    Some(resolved_ast::Program::link(main, asts.collect()))
+}
 
-    Ok((ast, errors))
+/// Print a syntax tree to stdout.
+fn print_ast(ast: &(impl std::fmt::Debug + serde::Serialize), format: AstFormat) {
+    match format {
+        AstFormat::Debug => println!("{:#?}", ast),
+        AstFormat::Json => println!("{}", serde_json::to_string_pretty(ast).unwrap()),
+    }
 }
 
-#[derive(Debug, StructOpt)]
-struct Opt {
-    /// The input file
-    #[structopt(parse(from_os_str))]
-    input: std::path::PathBuf,
+/// Exit with [`USAGE_ERROR`], saying why a file can't be read or written.
+fn exit_io(path: &std::path::Path, action: &str, error: std::io::Error) -> ! {
+    eprintln!(
+        "{}",
+        format!("cannot {} {}: {}", action, path.display(), error).red()
+    );
+    exit(USAGE_ERROR)
+}
+
+/// Exit with [`USAGE_ERROR`] if what was printed couldn't be written, like when stdout is a
+/// pipe that was closed.
+fn exit_unprintable(result: std::io::Result<()>) {
+    if let Err(error) = result {
+        eprintln!("{}", format!("cannot write to stdout: {}", error).red());
+        exit(USAGE_ERROR)
+    }
+}
 
-    /// Print the AST
-    #[structopt(short, long)]
-    ast: bool,
+/// The files a command is given, see [`find_sources`], or exit with [`USAGE_ERROR`] if a directory
+/// can't be searched.
+fn find_files(paths: &[PathBuf], exclude: &[String]) -> Vec<PathBuf> {
+    find_sources(paths, exclude).unwrap_or_else(|error| {
+        eprintln!("{}", format!("cannot search {}", error).red());
+        exit(USAGE_ERROR)
+    })
 }
 
-fn main() {
-    let opt: Opt = Opt::from_args();
-    let input = std::fs::read_to_string(opt.input).unwrap();
-    let program = match parse(&input) {
-        Ok(ast) => {
-            print_parse_errs(&ast.1, &input);
-            // if opt.ast {
-            //     println!("{:#?}", ast.0);
-            // } else {
-            //     print!("{}", formatter::format(&ast.0));
-            // }
-            ast.0
-        }
-        Err(err) => {
-            print_parse_error(&err, &input);
-            return;
+/// Read a file, or exit with [`USAGE_ERROR`] if it can't be read.
+fn read_file(path: &std::path::Path) -> String {
+    std::fs::read_to_string(path).unwrap_or_else(|error| exit_io(path, "read", error))
+}
+
+/// Write a file, or exit with [`USAGE_ERROR`] if it can't be written.
+fn write_file(path: &std::path::Path, contents: impl AsRef<[u8]>) {
+    std::fs::write(path, contents).unwrap_or_else(|error| exit_io(path, "write", error))
+}
+
+/// Report that one of the files a command was given can't be read, as an error the command
+/// carries on after with the other files.
+fn print_unreadable(path: &std::path::Path, error: std::io::Error, format: MessageFormat) {
+    UNREADABLE.store(true, Ordering::Relaxed);
+    let diagnostic = Diagnostic::error(format!("cannot read {}: {}", path.display(), error), 0..0)
+        .with_code("E015");
+    print_diagnostic(
+        &diagnostic,
+        &Source::new(path.display(), "").with_format(format),
+    );
+}
+
+/// Read one of the files a command was given, reporting it if it can't be read.
+fn read_source(path: &std::path::Path, format: MessageFormat) -> Option<String> {
+    std::fs::read_to_string(path)
+        .map_err(|error| print_unreadable(path, error, format))
+        .ok()
+}
+
+/// Read a file and everything it imports, printing any import errors, or that the file can't be
+/// read.
+fn load_files(path: &std::path::Path, format: MessageFormat) -> Option<Vec<imports::SourceFile>> {
+    let (files, errors) = match imports::load(path) {
+        Ok(loaded) => loaded,
+        Err(error) => {
+            print_unreadable(path, error, format);
+            return None;
         }
     };
-    let program: resolved_ast::Program = program.into();
+    for error in &errors {
+        let file = &files[error.file];
+        let source = Source::new(file.path.display(), &file.text).with_format(format);
+        print_diagnostic(error, &source);
+    }
+    errors.is_empty().then_some(files)
+}
+
+/// Apply the fixes of the parse errors of every file to it, and those of the diagnostics that
+/// point into it if it parses without errors, printing how many each file took. The diagnostics of
+/// a file that doesn't parse are about what the parser made of it, so their fixes may not be.
+fn fix_files(files: &[imports::SourceFile], diagnostics: &[Diagnostic]) {
+    for (i, file) in files.iter().enumerate() {
+        let mut text = file.text.clone();
+        let mut applied = 0;
+        // the parser skips some of what follows an error to carry on, so only the first error is
+        // sure to be what it says, and what follows it is parsed again once it is fixed
+        let mut previous = None;
+        let parses = loop {
+            let error = match parse(&text) {
+                Ok((_, errors)) => match errors.first() {
+                    Some(error) => Diagnostic::from_parse_error(&error.error, &text),
+                    None => break true,
+                },
+                Err(failure) => match failure.recovered.first() {
+                    Some(error) => Diagnostic::from_parse_error(&error.error, &text),
+                    None => Diagnostic::from_parse_error(&failure.error, &text),
+                },
+            };
+            // a fix moves the first error further along, unless it didn't fix it
+            match error.fix {
+                Some(fix) if previous < Some(error.span.start) => {
+                    previous = Some(error.span.start);
+                    text = diagnostics::apply_fixes(&text, [&fix]).0;
+                    applied += 1;
+                }
+                _ => break false,
+            }
+        };
+        if parses && applied == 0 {
+            let fixes = diagnostics
+                .iter()
+                .filter(|diagnostic| diagnostic.file == i)
+                .filter_map(|diagnostic| diagnostic.fix.as_ref());
+            (text, applied) = diagnostics::apply_fixes(&text, fixes);
+        }
+        if applied > 0 {
+            write_file(&file.path, text);
+            eprintln!(
+                "{}",
+                format!(
+                    "Fixed {} problem{} in {}",
+                    applied,
+                    if applied == 1 { "" } else { "s" },
+                    file.path.display()
+                )
+                .green()
+            );
+        }
+    }
+}
+
+/// Spell check the program, using the bundled dictionary plus the closest `wryneck.dic`.
+#[cfg(feature = "spellcheck")]
+fn print_misspellings(
+    program: &resolved_ast::Program,
+    input: &str,
+    path: &std::path::Path,
+    format: MessageFormat,
+) {
+    use wryneck::spellcheck;
+
+    let mut dictionary = spellcheck::Dictionary::bundled();
+    let project_dictionary = path
+        .canonicalize()
+        .unwrap_or_else(|_| path.to_path_buf())
+        .ancestors()
+        .skip(1)
+        .map(|dir| dir.join(spellcheck::DICTIONARY_FILE))
+        .find(|file| file.is_file());
+    if let Some(file) = project_dictionary {
+        dictionary.extend_from_str(&read_file(&file));
+    }
+
+    let source = Source::new(path.display(), input).with_format(format);
+    for misspelling in spellcheck::check(program, &dictionary) {
+        let mut diagnostic = Diagnostic::warning(
+            format!("possible misspelling `{}`", misspelling.word),
+            misspelling.span,
+        )
+        .with_code("W020");
+        if !misspelling.suggestions.is_empty() {
+            diagnostic = diagnostic.with_help(format!(
+                "did you mean {}?",
+                misspelling.suggestions.join(", ")
+            ));
+        }
+        print_diagnostic(&diagnostic, &source);
+    }
+}
 
-    if opt.ast {
-        println!("{:#?}", program);
+/// The arguments `hatch` is called with: the arguments of the command line as a list, if it
+/// takes them.
+fn hatch_args<'a, C>(args: Vec<String>, takes_args: bool) -> Vec<runtime::Value<'a, C>> {
+    if takes_args {
+        vec![runtime::Value::List(
+            args.into_iter().map(runtime::Value::String).collect(),
+        )]
     } else {
-        print!("{}", formatter::format(&program));
+        vec![]
     }
 }
 
-// Test the parser
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// The arguments of the function `wryneck run` calls, made into values by the backend that runs
+/// it.
+enum Args {
+    /// The arguments of `hatch`, and whether it is given them.
+    Hatch(Vec<String>, bool),
+    /// The arguments `--args` gives the function `--only` runs: the values they are as JSON, like
+    /// `5` or `[1, 2]`, or else the strings they are.
+    Json(Vec<String>),
+}
 
-    #[test]
-    fn test_parse() {
-        let input = r#"
-        egg hatch() {
-            // does this work?
-            let x = {
-                let test = 420; // testing
-            };
-        
-            let str = "hello";
-            "hello";
-        
-            if true {
-                if false {
-                    let x = 420;
-                } else {
-                    let x = "hello";
-                };
-                "world";
-            };
-        
-            let y = count_Pigeons(test);
-            *)> (69 + {*)>30 + 1;}) * 3;
+impl Args {
+    fn values<'a, C: runtime::Closure>(self) -> Vec<runtime::Value<'a, C>> {
+        match self {
+            Args::Hatch(args, takes_args) => hatch_args(args, takes_args),
+            Args::Json(args) => args
+                .into_iter()
+                .map(|arg| {
+                    serde_json::from_str(&arg)
+                        .ok()
+                        .and_then(|json| runtime::Value::from_json(&json).ok())
+                        .unwrap_or(runtime::Value::String(arg))
+                })
+                .collect(),
         }
-        // does things
-        🥚 count_Pigeons(pigeons) {
-            *)> 1;
-            // and stuffs
+    }
+}
+
+/// What to print for the value a program comes to and the code to exit with: an `int` is the
+/// exit code, unless a single function was run, and anything else is printed unless it is `()`.
+fn ending<C: runtime::Closure>(value: runtime::Value<C>, only: bool) -> (Option<String>, i64) {
+    match value {
+        runtime::Value::Int(code) if !only => (None, code),
+        runtime::Value::Unit => (None, 0),
+        value => (Some(value.to_string()), 0),
+    }
+}
+
+/// Print how many tests of every function passed and the ones that didn't.
+fn print_test_results(
+    program: &resolved_ast::Program,
+    input: &str,
+    results: &[test_runner::FunctionResults],
+) {
+    for function in results {
+        let name = program.functions[function.function].definition.name;
+        let summary = format!(
+            "{}: {} passed, {} failed",
+            name,
+            function.passed(),
+            function.failed()
+        );
+        if function.failed() == 0 {
+            println!("{}", summary.green());
+        } else {
+            println!("{}", summary.red());
         }
-        [
-            2 = 0,
-            4 = 0,
-        ]
-        
-        "#;
-        let program = match parse(&input) {
-            Ok(ast) => {
-                print_parse_errs(&ast.1, &input);
-                // if opt.ast {
-                //     println!("{:#?}", ast.0);
-                // } else {
-                //     print!("{}", formatter::format(&ast.0));
-                // }
-                ast.0
-            }
-            Err(err) => {
-                print_parse_error(&err, &input);
-                return;
+        for test in &function.tests {
+            let problem = match &test.outcome {
+                test_runner::Outcome::Passed => continue,
+                test_runner::Outcome::Failed { expected, got } => {
+                    format!("expected {}, got {}", expected, got)
+                }
+                test_runner::Outcome::Error(failure) => failure.message.clone(),
+            };
+            match &test.case {
+                Some(case) => println!(
+                    "    `{}`: {}, for {}",
+                    &input[test.span.clone()],
+                    problem,
+                    case
+                ),
+                None => println!("    `{}`: {}", &input[test.span.clone()], problem),
             }
-        };
-        let program: resolved_ast::Program = program.into();
+        }
+    }
+    let failed = results
+        .iter()
+        .map(|function| function.failed())
+        .sum::<usize>();
+    let passed = results
+        .iter()
+        .map(|function| function.passed())
+        .sum::<usize>();
+    println!("\n{} passed, {} failed", passed, failed);
+}
 
-        let output = r#"🥚 🐣() {
-    // does this work?
-    let x = {
-        let test = 420;
-        // testing
+/// Print the snapshots that don't match, with the lines that differ, and how many of each kind
+/// there are.
+fn print_snapshots(snapshots: &[snapshot::Snapshot]) {
+    for snapshot in snapshots {
+        let path = snapshot.path.display();
+        let expected = snapshot::expected_path(&snapshot.path);
+        match &snapshot.outcome {
+            snapshot::Outcome::Matches => {}
+            snapshot::Outcome::Missing => println!(
+                "{}",
+                format!("{}: there is no {}", path, expected.display()).red()
+            ),
+            snapshot::Outcome::Differs {
+                expected: expected_text,
+                formatted,
+            } => {
+                println!(
+                    "{}",
+                    format!("{}: differs from {}", path, expected.display()).red()
+                );
+                let expected_lines = expected_text.lines().collect::<Vec<_>>();
+                let formatted_lines = formatted.lines().collect::<Vec<_>>();
+                for line in 0..expected_lines.len().max(formatted_lines.len()) {
+                    let (old, new) = (expected_lines.get(line), formatted_lines.get(line));
+                    if old != new {
+                        println!("    line {}:", line + 1);
+                        if let Some(old) = old {
+                            println!("{}", format!("    - {}", old).red());
+                        }
+                        if let Some(new) = new {
+                            println!("{}", format!("    + {}", new).green());
+                        }
+                    }
+                }
+            }
+            snapshot::Outcome::Created => println!("{}: created {}", path, expected.display()),
+            snapshot::Outcome::Updated => println!("{}: updated {}", path, expected.display()),
+            snapshot::Outcome::Unparsable => {
+                println!("{}", format!("{}: does not parse", path).yellow())
+            }
+        }
+    }
+    let count = |outcome: fn(&snapshot::Outcome) -> bool| {
+        snapshots
+            .iter()
+            .filter(|snapshot| outcome(&snapshot.outcome))
+            .count()
     };
-    let str = "hello";
+    println!(
+        "\n{} match, {} differ, {} missing, {} created, {} updated",
+        count(|outcome| *outcome == snapshot::Outcome::Matches),
+        count(|outcome| matches!(outcome, snapshot::Outcome::Differs { .. })),
+        count(|outcome| *outcome == snapshot::Outcome::Missing),
+        count(|outcome| *outcome == snapshot::Outcome::Created),
+        count(|outcome| *outcome == snapshot::Outcome::Updated),
+    );
+}
+
+/// Which functions and statements the tests ran, as a summary and the lines of the file, marked
+/// `+` if everything that starts on them ran and `-` if something didn't.
+fn format_coverage(
+    program: &resolved_ast::Program,
+    input: &str,
+    executed: &test_coverage::Executed,
+) -> String {
+    let coverage = test_coverage::coverage(program, executed);
+    let mut text = format!(
+        "\ncoverage:\n{}\n",
+        test_coverage::summary(program, &coverage)
+    );
+    for (number, (line, source)) in test_coverage::lines(program, input, &coverage)
+        .into_iter()
+        .enumerate()
+    {
+        let line = match line {
+            test_coverage::Line::Other => format!("  {:>4} | {}", number + 1, source).normal(),
+            test_coverage::Line::Executed => format!("+ {:>4} | {}", number + 1, source).green(),
+            test_coverage::Line::Missed => format!("- {:>4} | {}", number + 1, source).red(),
+        };
+        text += &format!("{}\n", line);
+    }
+    text
+}
+
+/// Print a diagnostic to error stream, for people or as JSON, or add it to the SARIF log, unless
+/// the error limit is reached.
+fn print_diagnostic(diagnostic: &Diagnostic, source: &Source) {
+    let limit = ERROR_LIMIT.load(Ordering::Relaxed);
+    if limit != 0 && ERRORS.load(Ordering::Relaxed) >= limit {
+        LEFT_OUT.fetch_add(1, Ordering::Relaxed);
+        return;
+    }
+    match diagnostic.severity {
+        Severity::Error => ERRORS.fetch_add(1, Ordering::Relaxed),
+        Severity::Warning => WARNINGS.fetch_add(1, Ordering::Relaxed),
+    };
+    match source.format {
+        MessageFormat::Human => {
+            let color = colored::control::SHOULD_COLORIZE.should_colorize();
+            eprint!("{}", diagnostic.render(&source.name, &source.lines, color));
+        }
+        MessageFormat::Json => {
+            JSON.store(true, Ordering::Relaxed);
+            eprintln!("{}", diagnostic.json(&source.name, &source.lines));
+        }
+        MessageFormat::Sarif => SARIF
+            .lock()
+            .unwrap()
+            .get_or_insert_with(sarif::Log::new)
+            .add(diagnostic, &source.name, &source.lines),
+    }
+}
+
+fn main() {
+    let Cli {
+        color,
+        error_limit,
+        verbose,
+        quiet,
+        timings,
+        command,
+    } = Cli::from_args_safe().unwrap_or_else(|error| {
+        // help and the version are printed to stdout
+        if !error.use_stderr() {
+            error.exit();
+        }
+        eprintln!("{}", error.message);
+        std::process::exit(USAGE_ERROR);
+    });
+    // most of what is colored goes to error stream, what the formatter prints to stdout is only
+    // colored if that is a terminal as well
+    colored::control::set_override(color.enabled(std::io::stderr().is_terminal()));
+    ERROR_LIMIT.store(error_limit, Ordering::Relaxed);
+    QUIET.store(quiet, Ordering::Relaxed);
+    let level = match (quiet, verbose) {
+        (true, _) => LevelFilter::ERROR,
+        (false, 0) => LevelFilter::WARN,
+        (false, 1) => LevelFilter::INFO,
+        (false, 2) => LevelFilter::DEBUG,
+        (false, _) => LevelFilter::TRACE,
+    };
+    let log = tracing_subscriber::fmt::layer()
+        .with_writer(std::io::stderr)
+        .with_ansi(color.enabled(std::io::stderr().is_terminal()))
+        .without_time()
+        .with_target(false)
+        .with_filter(level);
+    let timings = timings.then(|| TIMINGS.get_or_init(timings::Timings::new).clone());
+    tracing_subscriber::registry()
+        .with(log)
+        .with(timings)
+        .init();
+    execute(command, color);
+    if ERRORS.load(Ordering::Relaxed) > 0 {
+        exit(FAILURE);
+    }
+    print_summary();
+}
+
+/// Do what a command asks, exiting early if it fails for something other than errors in the
+/// program.
+fn execute(command: Opt, color: ColorChoice) {
+    match command {
+        Opt::Fmt {
+            paths,
+            assume_filename,
+            exclude,
+            write,
+            ast,
+            trailing_commas,
+            keyword_style,
+            parens,
+            snapshot,
+            update,
+            check,
+            diff,
+            verify,
+        } => {
+            // only what is printed to a terminal as it is may be in color
+            let color = !write
+                && !check
+                && !diff
+                && snapshot.is_none()
+                && color.enabled(std::io::stdout().is_terminal());
+            // the closest wryneck.toml to a path, with the flags taking precedence
+            let config = |path: &PathBuf| {
+                let mut config = formatter::FormatConfig::default();
+                if let Some(file) = formatter::FormatConfig::find(path) {
+                    let toml = read_file(&file);
+                    config = match config.with_toml(&toml) {
+                        Ok(config) => config,
+                        Err(message) => {
+                            eprintln!(
+                                "{}",
+                                format!("error in {}: {}", file.display(), message).red()
+                            );
+                            exit(USAGE_ERROR);
+                        }
+                    };
+                }
+                formatter::FormatConfig {
+                    trailing_commas: trailing_commas.unwrap_or(config.trailing_commas),
+                    keywords: keyword_style.unwrap_or(config.keywords),
+                    parens: parens.unwrap_or(config.parens),
+                    color,
+                    ..config
+                }
+            };
+            if let Some(dir) = snapshot {
+                let snapshots = match snapshot::check(&dir, config(&dir), update) {
+                    Ok(snapshots) => snapshots,
+                    Err(error) => {
+                        eprintln!("{}", format!("error: {}", error).red());
+                        exit(USAGE_ERROR);
+                    }
+                };
+                print_snapshots(&snapshots);
+                if snapshots.iter().any(snapshot::Snapshot::failed) {
+                    exit(FAILURE);
+                }
+                return;
+            }
+            let sources = find_files(&paths, &exclude);
+            if sources.len() > 1 && !(write || check || diff || ast) {
+                eprintln!(
+                    "{}",
+                    "formatting more than one file needs --write, --check or --diff".red()
+                );
+                exit(USAGE_ERROR);
+            }
+            let mut failed = false;
+            for path in &sources {
+                let stdin = path.as_os_str() == "-";
+                let input = if stdin {
+                    std::io::read_to_string(std::io::stdin())
+                        .unwrap_or_else(|error| exit_io(path, "read", error))
+                } else {
+                    read_file(path)
+                };
+                // what formatting stdin amounts to is printing it
+                let write = write && !stdin;
+                let path = match &assume_filename {
+                    Some(assumed) if stdin => assumed,
+                    _ => path,
+                };
+                let name = path.display().to_string();
+                let source = Source::new(&name, &input);
+                let (program, errors) = match parse(&input) {
+                    Ok(parsed) => parsed,
+                    Err(failure) => {
+                        print_parse_failure(&failure, &source);
+                        failed = true;
+                        continue;
+                    }
+                };
+                if !errors.is_empty() {
+                    print_parse_errs(&errors, &source);
+                    // the file would be replaced by what the parser made of the errors
+                    if write {
+                        failed = true;
+                        continue;
+                    }
+                }
+                let program = resolved_ast::Program::from(program);
+
+                if ast {
+                    println!("{:#?}", program);
+                    continue;
+                }
+                let format_config = config(path);
+                if !write && !check && !diff && !verify {
+                    let mut stdout = std::io::stdout().lock();
+                    let printed =
+                        formatter::format_source_to(&program, &input, &mut stdout, &format_config);
+                    exit_unprintable(printed);
+                    continue;
+                }
+                let formatted = formatter::format_source(&program, &input, format_config);
+                if verify && !verify_format(&formatted, &name, format_config) {
+                    failed = true;
+                    continue;
+                }
+                if !write && !check && !diff {
+                    print!("{}", formatted);
+                    continue;
+                }
+                if diff {
+                    print!("{}", diff::unified(&input, &formatted, &name, &name));
+                } else if formatted != input && check {
+                    eprintln!("{}", format!("{} is not formatted", name).red());
+                }
+                if formatted != input {
+                    if write {
+                        write_file(path, &formatted);
+                        println!("formatted {}", name);
+                    }
+                    failed |= check;
+                }
+            }
+            if failed && (write || check || verify) {
+                exit(FAILURE);
+            }
+        }
+        Opt::Check {
+            paths,
+            allow,
+            warn,
+            deny,
+            emit,
+            message_format,
+            fix,
+            #[cfg(feature = "spellcheck")]
+            spellcheck,
+        } => {
+            let mut lints = lints::Registry::new();
+            let levels = [
+                (allow, lints::Level::Allow),
+                (warn, lints::Level::Warn),
+                (deny, lints::Level::Deny),
+            ];
+            for (names, level) in levels {
+                for name in names {
+                    if let Err(message) = lints.set_level(&name, level) {
+                        eprintln!("{}", message.red());
+                        exit(USAGE_ERROR);
+                    }
+                }
+            }
+            if message_format == MessageFormat::Sarif {
+                // a log without results if there is nothing to report
+                *SARIF.lock().unwrap() = Some(sarif::Log::new());
+            }
+            for path in find_files(&paths, &[]) {
+                let files = match load_files(&path, message_format) {
+                    Some(files) => files,
+                    None => continue,
+                };
+                let program = read_linked_program(&files, message_format);
+                let input = &files[0].text;
+                let sources = files
+                    .iter()
+                    .map(|file| {
+                        Source::new(file.path.display(), &file.text).with_format(message_format)
+                    })
+                    .collect::<Vec<_>>();
+                let check = tracing::info_span!("check").entered();
+                let diagnostics = match &program {
+                    Some(program) => assignments::check(program)
+                        .into_iter()
+                        .chain(names::check(program))
+                        .chain(arity::check(program))
+                        .chain(units::check(program))
+                        .chain(fold::check(program))
+                        .chain(typecheck::check(program))
+                        .chain(control_flow::check(program))
+                        .chain(script::check(program))
+                        .chain(lints.check(program))
+                        .filter_map(|diagnostic| lints.configure(diagnostic))
+                        .collect(),
+                    None => Vec::new(),
+                };
+                drop(check);
+                for diagnostic in &diagnostics {
+                    print_diagnostic(diagnostic, &sources[diagnostic.file]);
+                }
+                if fix {
+                    fix_files(&files, &diagnostics);
+                }
+                let program = match program {
+                    Some(program) => program,
+                    None => continue,
+                };
+                #[cfg(feature = "spellcheck")]
+                if spellcheck {
+                    print_misspellings(&program, input, &path, message_format);
+                }
+                match emit {
+                    Some(Emit::CallGraph) => print!("{}", call_graph::dot(&program)),
+                    Some(Emit::Signatures) => {
+                        let signatures = infer::signatures(&program);
+                        for (id, func) in &program.functions {
+                            if Some(id) != program.script && !program.is_imported(id) {
+                                println!("{}", signatures[id].describe(&func.definition));
+                            }
+                        }
+                    }
+                    Some(Emit::TailCalls) => {
+                        for (id, func) in &program.functions {
+                            if program.is_imported(id) {
+                                continue;
+                            }
+                            for span in tail_calls::spans(func) {
+                                println!("{}: {}", func.definition.name, &input[span]);
+                            }
+                        }
+                    }
+                    None => {}
+                }
+            }
+        }
+        Opt::Ast {
+            paths,
+            format,
+            stage,
+        } => {
+            // a program with syntax errors is printed all the same, it is what the errors are about
+            for path in find_files(&paths, &[]) {
+                if stage == Stage::Base {
+                    let input = match read_source(&path, MessageFormat::Human) {
+                        Some(input) => input,
+                        None => continue,
+                    };
+                    let source = Source::new(path.display(), &input);
+                    match parse(&input) {
+                        Ok((program, errors)) => {
+                            print_parse_errs(&errors, &source);
+                            print_ast(&program, format);
+                        }
+                        Err(failure) => print_parse_failure(&failure, &source),
+                    }
+                    continue;
+                }
+                let files = match load_files(&path, MessageFormat::Human) {
+                    Some(files) => files,
+                    None => continue,
+                };
+                if let Some(program) = read_linked_program(&files, MessageFormat::Human) {
+                    print_ast(&program, format);
+                }
+            }
+        }
+        Opt::Compile {
+            paths,
+            output,
+            optimize,
+        } => {
+            let sources = find_files(&paths, &[]);
+            if output.is_some() && sources.len() > 1 {
+                eprintln!(
+                    "{}",
+                    "--output can't be used when compiling more than one file".red()
+                );
+                exit(USAGE_ERROR);
+            }
+            for path in sources {
+                let output = output
+                    .clone()
+                    .unwrap_or_else(|| path.with_extension("wknb"));
+                let files = match load_files(&path, MessageFormat::Human) {
+                    Some(files) => files,
+                    None => continue,
+                };
+                let mut program = match read_linked_program(&files, MessageFormat::Human) {
+                    Some(program) => program,
+                    None => continue,
+                };
+                if optimize {
+                    let diagnostics = fold::fold(&mut program);
+                    let source = Source::new(files[0].path.display(), &files[0].text);
+                    for diagnostic in &diagnostics {
+                        print_diagnostic(diagnostic, &source);
+                    }
+                    if !diagnostics.is_empty() {
+                        continue;
+                    }
+                    eliminate::eliminate(&mut program);
+                }
+                write_file(&output, interchange::encode(&program));
+            }
+        }
+        Opt::Decompile { input } => {
+            let bytes =
+                std::fs::read(&input).unwrap_or_else(|error| exit_io(&input, "read", error));
+            match interchange::decode(&bytes) {
+                Ok(program) => {
+                    let config = formatter::FormatConfig {
+                        color: color.enabled(std::io::stdout().is_terminal()),
+                        ..Default::default()
+                    };
+                    let printed =
+                        formatter::format_to(&program, &mut std::io::stdout().lock(), &config);
+                    exit_unprintable(printed);
+                }
+                Err(err) => {
+                    eprintln!("{}", format!("Could not read .wknb file: {}", err).red());
+                    exit(USAGE_ERROR);
+                }
+            }
+        }
+        Opt::Run {
+            input: path,
+            backend,
+            allow_io,
+            only,
+            only_args,
+            args,
+        } => {
+            let files = match load_files(&path, MessageFormat::Human) {
+                Some(files) => files,
+                None => return,
+            };
+            let program = match read_linked_program(&files, MessageFormat::Human) {
+                Some(program) => program,
+                None => return,
+            };
+            exit_if_errors();
+            let entry = match &only {
+                Some(name) => program.top_level_function(name).ok_or_else(|| {
+                    format!("error: there is no top level function named `{}`", name)
+                }),
+                None => program.hatch().ok_or_else(|| {
+                    "error: there are no top level statements and no `hatch` function to run"
+                        .to_string()
+                }),
+            };
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(message) => {
+                    eprintln!("{}", message.red());
+                    exit(FAILURE);
+                }
+            };
+            let span = program.functions[entry].definition.span.clone();
+            // a `hatch` without parameters is only given arguments to fail on them
+            let takes_args =
+                !program.functions[entry].definition.params.is_empty() || !args.is_empty();
+            let args = match only {
+                Some(_) => Args::Json(only_args),
+                None => Args::Hatch(args, takes_args),
+            };
+            // a single function only needs what it calls, the rest of the program is left out
+            let callees = only.as_ref().map(|_| program.callees(entry));
+            let execute = tracing::info_span!("execute").entered();
+            let result = match backend {
+                runtime::Backend::Interpreter => interpreter::with_large_stack(|| {
+                    let mut interpreter = interpreter::Interpreter::new(&program).with_io(allow_io);
+                    if let Some(callees) = &callees {
+                        interpreter = interpreter.with_only(callees);
+                    }
+                    interpreter
+                        .call(entry, args.values(), &span)
+                        .map(|value| ending(value, only.is_some()))
+                }),
+                runtime::Backend::Vm => {
+                    let compiled = match &callees {
+                        Some(callees) => bytecode::compile_only(&program, callees),
+                        None => bytecode::compile(&program),
+                    };
+                    vm::Vm::new(&compiled)
+                        .with_io(allow_io)
+                        .call(entry, args.values(), &span)
+                        .map(|value| ending(value, only.is_some()))
+                }
+            };
+            // the span is only timed once it closes, which exiting would keep it from
+            drop(execute);
+            match result {
+                Ok((output, code)) => {
+                    if let Some(output) = output {
+                        println!("{}", output);
+                    }
+                    // codes the platform can't exit with are a failure all the same
+                    exit(i32::try_from(code).unwrap_or(FAILURE));
+                }
+                Err(failure) => {
+                    print_diagnostic(
+                        &Diagnostic::error(failure.message, failure.span).with_code("E100"),
+                        &Source::new(files[0].path.display(), &files[0].text),
+                    );
+                    exit(FAILURE);
+                }
+            }
+        }
+        Opt::Debug {
+            input: path,
+            allow_io,
+            args,
+        } => {
+            let files = match load_files(&path, MessageFormat::Human) {
+                Some(files) => files,
+                None => return,
+            };
+            let program = match read_linked_program(&files, MessageFormat::Human) {
+                Some(program) => program,
+                None => return,
+            };
+            exit_if_errors();
+            let hatch = match program.hatch() {
+                Some(hatch) => hatch,
+                None => {
+                    eprintln!(
+                        "{}",
+                        "error: there are no top level statements and no `hatch` function to run"
+                            .red()
+                    );
+                    exit(FAILURE);
+                }
+            };
+            let span = program.functions[hatch].definition.span.clone();
+            let takes_args =
+                !program.functions[hatch].definition.params.is_empty() || !args.is_empty();
+            let text = &files[0].text;
+            let execute = tracing::info_span!("execute").entered();
+            let result = interpreter::with_large_stack(|| {
+                let debugger = debugger::Debugger::new(
+                    &program,
+                    text,
+                    std::io::stdin().lock(),
+                    std::io::stdout(),
+                );
+                let mut interpreter = interpreter::Interpreter::new(&program)
+                    .with_io(allow_io)
+                    .with_observer(Box::new(debugger));
+                // a program stopped in the debugger ends quietly
+                match interpreter.call(hatch, hatch_args(args, takes_args), &span) {
+                    Err(_) if interpreter.stopped() => Ok((None, 0)),
+                    result => result.map(|value| ending(value, false)),
+                }
+            });
+            drop(execute);
+            match result {
+                Ok((output, code)) => {
+                    if let Some(output) = output {
+                        println!("{}", output);
+                    }
+                    exit(i32::try_from(code).unwrap_or(FAILURE));
+                }
+                Err(failure) => {
+                    print_diagnostic(
+                        &Diagnostic::error(failure.message, failure.span).with_code("E100"),
+                        &Source::new(files[0].path.display(), &files[0].text),
+                    );
+                    exit(FAILURE);
+                }
+            }
+        }
+        Opt::ServeHttp {
+            input: path,
+            port,
+            allow_io,
+        } => {
+            let files = match load_files(&path, MessageFormat::Human) {
+                Some(files) => files,
+                None => return,
+            };
+            let program = match read_linked_program(&files, MessageFormat::Human) {
+                Some(program) => program,
+                None => return,
+            };
+            exit_if_errors();
+            let listener = match std::net::TcpListener::bind(("127.0.0.1", port)) {
+                Ok(listener) => listener,
+                Err(err) => {
+                    eprintln!(
+                        "{}",
+                        format!("error: cannot listen on port {}: {}", port, err).red()
+                    );
+                    exit(FAILURE);
+                }
+            };
+            eprintln!("serving {} on http://127.0.0.1:{}", path.display(), port);
+            // a request that doesn't finish fails like a test that doesn't
+            let settings = serve::Settings {
+                io: allow_io,
+                limits: test_runner::LIMITS,
+            };
+            serve::serve(&program, &listener, settings);
+        }
+        Opt::Test {
+            paths,
+            max_steps,
+            max_depth,
+            max_memory,
+            filter,
+            format,
+            coverage,
+        } => {
+            let sources = find_files(&paths, &[]);
+            // the results of all files are one document, named after the file if there is one
+            let mut junit = test_report::Junit::new(&match &sources[..] {
+                [path] => path.display().to_string(),
+                _ => "wryneck".to_string(),
+            });
+            let mut failed = false;
+            for path in &sources {
+                let files = match load_files(path, MessageFormat::Human) {
+                    Some(files) => files,
+                    None => continue,
+                };
+                let program = match read_linked_program(&files, MessageFormat::Human) {
+                    Some(program) => program,
+                    None => continue,
+                };
+                let input = &files[0].text;
+                let limits = interpreter::Limits {
+                    steps: max_steps.or(test_runner::LIMITS.steps),
+                    depth: max_depth.unwrap_or(test_runner::LIMITS.depth),
+                    memory: max_memory.or(test_runner::LIMITS.memory),
+                };
+                let (results, executed) = if coverage {
+                    let (results, executed) =
+                        test_runner::run_with_coverage(&program, limits, filter.as_deref());
+                    (results, Some(executed))
+                } else {
+                    (test_runner::run(&program, limits, filter.as_deref()), None)
+                };
+                failed |= results.iter().any(|function| function.failed() > 0);
+                let file = path.display().to_string();
+                match format {
+                    Report::Text => {
+                        if sources.len() > 1 {
+                            println!("{}", file.bold());
+                        }
+                        print_test_results(&program, input, &results);
+                    }
+                    Report::Json => {
+                        println!("{}", test_report::json(&program, &file, input, &results))
+                    }
+                    Report::Junit => junit.add(&program, &file, input, &results),
+                }
+                if let Some(executed) = executed {
+                    let coverage = format_coverage(&program, input, &executed);
+                    // the results may be for other tools to read
+                    match format {
+                        Report::Text => print!("{}", coverage),
+                        Report::Json | Report::Junit => eprint!("{}", coverage),
+                    }
+                }
+            }
+            if matches!(format, Report::Junit) {
+                print!("{}", junit.to_xml());
+            }
+            if failed {
+                exit(FAILURE);
+            }
+        }
+        Opt::Bench {
+            paths,
+            backend,
+            runs,
+        } => {
+            let sources = find_files(&paths, &[]);
+            let mut failed = false;
+            for path in &sources {
+                let files = match load_files(path, MessageFormat::Human) {
+                    Some(files) => files,
+                    None => continue,
+                };
+                let program = match read_linked_program(&files, MessageFormat::Human) {
+                    Some(program) => program,
+                    None => continue,
+                };
+                let input = &files[0].text;
+                let results = bench::run(&program, backend, runs);
+                if sources.len() > 1 {
+                    println!("{}", path.display().to_string().bold());
+                }
+                for function in &results {
+                    println!("{}", program.functions[function.function].definition.name);
+                    for benchmark in &function.benchmarks {
+                        let text = &input[benchmark.span.clone()];
+                        match &benchmark.timings {
+                            Ok(timings) => println!(
+                                "    `{}`: mean {:.3?}, median {:.3?} over {} runs",
+                                text, timings.mean, timings.median, timings.runs
+                            ),
+                            Err(failure) => {
+                                failed = true;
+                                println!("    `{}`: {}", text, failure.message.red());
+                            }
+                        }
+                    }
+                }
+            }
+            if failed {
+                exit(FAILURE);
+            }
+        }
+        Opt::GrammarCoverage { paths } => {
+            let mut coverage = coverage::Coverage::new();
+            for path in find_files(&paths, &[]) {
+                let input = read_file(&path);
+                match parse(&input) {
+                    Ok((program, errors)) if errors.is_empty() => {
+                        coverage.record(&program.into());
+                    }
+                    _ => eprintln!(
+                        "{}",
+                        format!("Skipping {}, it does not parse", path.display()).yellow()
+                    ),
+                }
+            }
+
+            for (construct, count) in &coverage.counts {
+                println!("{:>5} {}", count, construct);
+            }
+            let missing = coverage.missing();
+            println!(
+                "\n{}/{} constructs covered",
+                coverage::CONSTRUCTS.len() - missing.len(),
+                coverage::CONSTRUCTS.len()
+            );
+            for construct in missing {
+                println!("{}", format!("untested: {}", construct).red());
+            }
+        }
+        Opt::Explain { code: None } => {
+            for explanation in diagnostics::EXPLANATIONS {
+                println!("{} {}", explanation.code, explanation.title);
+            }
+        }
+        Opt::Explain { code: Some(code) } => match diagnostics::explain(&code) {
+            Some(explanation) => println!(
+                "{}: {}\n\n{}",
+                explanation.code, explanation.title, explanation.description
+            ),
+            None => {
+                eprintln!("{}", format!("unknown code `{}`", code).red());
+                exit(USAGE_ERROR);
+            }
+        },
+    }
+}
+
+// Test the parser
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use id_collections::Id;
+    use wryneck::diagnostics::Label;
+    use wryneck::resolved_ast::{
+        Expression, FunctionCall, Intrinsic, Opcode, Purity, Statement, StringKind,
+    };
+    use wryneck::symbols::{Symbol, SymbolTable};
+    use wryneck::visit::{walk_expression, walk_program, walk_statement, Visitor};
+    use wryneck::{dead_code, interpreter, matches_pattern, purity, shadowing};
+
+    #[test]
+    fn test_parse() {
+        let input = r#"
+        egg hatch() {
+            // does this work?
+            let x = {
+                let test = 420; // testing
+            };
+        
+            let str = "hello";
+            "hello";
+        
+            if true {
+                if false {
+                    let x = 420;
+                } else {
+                    let x = "hello";
+                };
+                "world";
+            };
+        
+            let y = count_Pigeons(test);
+            *)> (69 + {*)>30 + 1;}) * 3;
+        }
+        // does things
+        🥚 count_Pigeons(pigeons) {
+            *)> 1;
+            // and stuffs
+        }
+        [
+            2 = 0,
+            4 = 0,
+        ]
+        
+        "#;
+        let program = match parse(input) {
+            Ok(ast) => {
+                print_parse_errs(&ast.1, &Source::new("test.wy", input));
+                // if opt.ast {
+                //     println!("{:#?}", ast.0);
+                // } else {
+                //     print!("{}", formatter::format(&ast.0));
+                // }
+                ast.0
+            }
+            Err(err) => {
+                print_parse_failure(&err, &Source::new("test.wy", input));
+                return;
+            }
+        };
+        let program: resolved_ast::Program = program.into();
+
+        let output = r#"🥚 🐣() {
+    // does this work?
+    let x = {
+        let test = 420; // testing
+    };
+    let str = "hello";
     "hello";
     if true {
         if false {
             let x = 420;
-        }{
+        } else {
             let x = "hello";
         };
         "world";
@@ -274,4 +1847,4530 @@ mod tests {
 
         assert_eq!(formatter::format(&program), output);
     }
+
+    #[test]
+    fn test_enum() {
+        let input = r#"
+        enum Shape { Circle(radius), Square(side,   other), Empty }
+        egg make() {
+            *)> Shape::Circle(3);
+        }
+        "#;
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+        assert_eq!(program.enums.len(), 1);
+
+        let output = r#"enum Shape {
+    Circle(radius),
+    Square(side, other),
+    Empty,
+}
+
+🥚 make() {
+    🐔 Shape::Circle(3);
+}
+
+"#;
+
+        assert_eq!(formatter::format(&program), output);
+    }
+
+    #[test]
+    fn test_decompile() {
+        let input = r#"
+        enum Flock { Empty, Pigeons(count) }
+        // does things
+        🥚 count_Pigeons(pigeons) {
+            let x = if pigeons { Flock::Pigeons(pigeons) } else { Flock::Empty };
+            *)> (1 + x) * "two";
+        }
+        [
+            2 = 0,
+        ]
+        "#;
+        let (program, _) = parse(input).unwrap();
+        let program: resolved_ast::Program = program.into();
+        let bytes = interchange::encode(&program);
+        assert_eq!(interchange::version(&bytes).unwrap(), interchange::VERSION);
+        let decompiled = interchange::decode(&bytes).unwrap();
+
+        assert_eq!(formatter::format(&decompiled), formatter::format(&program));
+
+        // a file written by an older build can't be read either
+        for version in [interchange::VERSION - 1, interchange::VERSION + 1] {
+            let mut other = bytes.clone();
+            other[4..6].copy_from_slice(&version.to_le_bytes());
+            assert!(matches!(
+                interchange::decode(&other),
+                Err(interchange::DecodeError::UnsupportedVersion(v)) if v == version
+            ));
+        }
+    }
+
+    #[test]
+    fn test_else_if() {
+        let input = r#"
+        egg sign(x) {
+            if x { *)> 1; } else if y { *)> 2; } else { *)> 3; };
+        }
+        "#;
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+
+        let output = r#"🥚 sign(x) {
+    if x {
+        🐔 1;
+    } else if y {
+        🐔 2;
+    } else {
+        🐔 3;
+    };
+}
+
+"#;
+
+        assert_eq!(formatter::format(&program), output);
+    }
+
+    #[test]
+    fn test_number_literals() {
+        let input = "egg hatch() { *)> 0xFF + 0b1010 * 0o77 - 1_000_000; }";
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+
+        let output = r#"🥚 🐣() {
+    🐔 ((0xFF + (0b1010 * 0o77)) - 1_000_000);
+}
+
+"#;
+
+        assert_eq!(formatter::format(&program), output);
+    }
+
+    #[test]
+    fn test_number_overflow() {
+        let input = "egg hatch() { *)> 3000000000 + 99999999999999999999; }";
+        let (program, errors) = parse(input).unwrap();
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(
+            &errors[0].error,
+            ParseError::User {
+                error: GrammarError::InvalidNumber(span, _)
+            } if *span == (31..51)
+        ));
+        let program: resolved_ast::Program = program.into();
+
+        let output = r#"🥚 🐣() {
+    🐔 (3000000000 + error);
+}
+
+"#;
+
+        assert_eq!(formatter::format(&program), output);
+    }
+
+    #[test]
+    fn test_assignment() {
+        let input = r#"
+        egg count(pigeons) {
+            let mut flock = pigeons;
+            flock += 2;
+            pigeons = 1;
+            { let mut eggs = 2; };
+            eggs = flock * 2;
+        }
+        "#;
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+
+        let diagnostics = assignments::check(&program);
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(&input[diagnostics[0].span.clone()], "pigeons = 1");
+        assert_eq!(&input[diagnostics[1].span.clone()], "eggs = flock * 2");
+
+        let output = r#"🥚 count(pigeons) {
+    let mut flock = pigeons;
+    flock += 2;
+    pigeons = 1;
+    {
+        let mut eggs = 2;
+    };
+    eggs = (flock * 2);
+}
+
+"#;
+
+        assert_eq!(formatter::format(&program), output);
+    }
+
+    #[test]
+    fn test_lambda() {
+        let input = r#"
+        egg nest(flock) {
+            let eggs = 2;
+            let add = |x| |y| x + y + eggs + flock;
+            let count = || { let z = 1; z + eggs; };
+        }
+        "#;
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+
+        let captures = match &*program.functions.values().next().unwrap().body {
+            Expression::Block(block, _, _) => block
+                .iter()
+                .filter_map(|stmt| match stmt {
+                    Statement::Let(resolved_ast::Let { value, .. }) => match &**value {
+                        Expression::Lambda(lambda) => Some(lambda.captures.clone()),
+                        _ => None,
+                    },
+                    _ => None,
+                })
+                .collect::<Vec<_>>(),
+            _ => unreachable!(),
+        };
+        assert_eq!(captures, vec![vec!["eggs", "flock"], vec!["eggs"]]);
+
+        let output = r#"🥚 nest(flock) {
+    let eggs = 2;
+    let add = |x| |y| (((x + y) + eggs) + flock);
+    let count = || {
+        let z = 1;
+        (z + eggs);
+    };
+}
+
+"#;
+
+        assert_eq!(formatter::format(&program), output);
+    }
+
+    #[test]
+    fn test_function_values() {
+        let input = r#"
+        egg count(pigeons) {
+            let f = count;
+            f(pigeons);
+            count(1);
+            let twice = |count| count(count(pigeons));
+        }
+        "#;
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+
+        let stmts = match &*program.functions.values().next().unwrap().body {
+            Expression::Block(block, _, _) => block,
+            _ => panic!("function body is not a block"),
+        };
+        assert!(matches!(
+            &stmts[1],
+            Statement::Expression(expr) if matches!(
+                &**expr,
+                Expression::FunctionCall(FunctionCall::Indirect { .. })
+            )
+        ));
+        assert!(matches!(
+            &stmts[2],
+            Statement::Expression(expr) if matches!(
+                &**expr,
+                Expression::FunctionCall(FunctionCall::Direct { function, .. })
+                    if program.functions[*function].definition.name == "count"
+            )
+        ));
+        match &stmts[3] {
+            Statement::Let(resolved_ast::Let { value, .. }) => match &**value {
+                Expression::Lambda(lambda) => {
+                    // the parameter shadows the function
+                    assert_eq!(lambda.captures, vec!["pigeons"]);
+                    assert!(matches!(
+                        &*lambda.body,
+                        Expression::FunctionCall(FunctionCall::Indirect { .. })
+                    ));
+                }
+                _ => panic!("expected a lambda"),
+            },
+            _ => panic!("expected a let"),
+        }
+
+        let output = r#"🥚 count(pigeons) {
+    let f = count;
+    f(pigeons);
+    count(1);
+    let twice = |count| count(count(pigeons));
+}
+
+"#;
+        assert_eq!(formatter::format(&program), output);
+    }
+
+    #[test]
+    fn test_type_annotations() {
+        let input = r#"
+        🥚 add(a: int, b:int)->int { *)> a + b; }
+        egg apply(f, x: int) { *)> f(x); }
+        "#;
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+
+        let output = r#"🥚 add(a: int, b: int) -> int {
+    🐔 (a + b);
+}
+
+🥚 apply(f, x: int) {
+    🐔 f(x);
+}
+
+"#;
+        assert_eq!(formatter::format(&program), output);
+
+        // formatting is idempotent
+        let (program, errors) = parse(output).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+        assert_eq!(formatter::format(&program), output);
+    }
+
+    #[test]
+    fn test_imports() {
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/imports");
+
+        let (files, errors) = imports::load(&dir.join("main.wy")).unwrap();
+        assert!(errors.is_empty());
+        assert_eq!(files.len(), 2);
+        let program = read_linked_program(&files, MessageFormat::Human).unwrap();
+        assert_eq!(program.functions.len(), 2);
+        let count = match &*program.functions.values().next().unwrap().body {
+            Expression::Block(block, _, _) => match &block[0] {
+                Statement::Return(expr, ..) => match &**expr {
+                    Expression::Op(lhs, _, _, _) => match &**lhs {
+                        Expression::FunctionCall(FunctionCall::Direct { function, .. }) => {
+                            *function
+                        }
+                        _ => panic!("expected a direct call"),
+                    },
+                    _ => panic!("expected an addition"),
+                },
+                _ => panic!("expected a return"),
+            },
+            _ => panic!("function body is not a block"),
+        };
+        assert_eq!(program.functions[count].definition.name, "count");
+        // only the main file is formatted
+        assert_eq!(
+            formatter::format(&program),
+            "import \"birds.wy\";\n\n🥚 flock(pigeons) {\n    🐔 (count(pigeons) + 1);\n}\n\n"
+        );
+
+        let (files, errors) = imports::load(&dir.join("cycle_a.wy")).unwrap();
+        assert_eq!(files.len(), 2);
+        let messages = errors
+            .iter()
+            .map(|error| error.message.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(messages.len(), 2);
+        let cycle = messages[0].strip_prefix("import cycle: ").unwrap();
+        let cycle = cycle
+            .split(" -> ")
+            .map(|path| std::path::Path::new(path).file_name().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(cycle, ["cycle_a.wy", "cycle_b.wy", "cycle_a.wy"]);
+        assert!(messages[1].starts_with("cannot import `missing.wy`"));
+        assert_eq!(
+            &files[errors[1].file].text[errors[1].span.clone()],
+            "import \"missing.wy\""
+        );
+    }
+
+    #[test]
+    fn test_nested_functions() {
+        let input = r#"
+        egg count(pigeons) {
+            egg double(x) {
+                egg inner(y) { *)> y; }
+                *)> inner(x) * 2;
+            }
+            *)> double(pigeons) + inner(1);
+        }
+        egg inner(y) { *)> 0; }
+        "#;
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+
+        let names = program
+            .functions
+            .values()
+            .map(|func| {
+                let parent = func
+                    .parent
+                    .map(|parent| program.functions[parent].definition.name);
+                (func.definition.name, parent)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                ("count", None),
+                ("double", Some("count")),
+                ("inner", Some("double")),
+                ("inner", None),
+            ]
+        );
+
+        // calls resolve to the innermost visible function of that name
+        let called = |func: &resolved_ast::Function| {
+            let mut called = Vec::new();
+            let mut exprs = vec![&*func.body];
+            while let Some(expr) = exprs.pop() {
+                match expr {
+                    Expression::Block(block, _, _) => {
+                        exprs.extend(block.iter().filter_map(|stmt| match stmt {
+                            Statement::Return(expr, ..) => Some(&**expr),
+                            _ => None,
+                        }))
+                    }
+                    Expression::Op(lhs, _, rhs, _) => exprs.extend([&**lhs, &**rhs]),
+                    Expression::FunctionCall(FunctionCall::Direct { function, .. }) => {
+                        called.push(function.to_index())
+                    }
+                    _ => {}
+                }
+            }
+            called.sort();
+            called
+        };
+        let functions = program.functions.values().collect::<Vec<_>>();
+        assert_eq!(called(functions[0]), [1, 3]);
+        assert_eq!(called(functions[1]), [2]);
+
+        let output = r#"🥚 count(pigeons) {
+    🥚 double(x) {
+        🥚 inner(y) {
+            🐔 y;
+        }
+        🐔 (inner(x) * 2);
+    }
+    🐔 (double(pigeons) + inner(1));
+}
+
+🥚 inner(y) {
+    🐔 0;
+}
+
+"#;
+        assert_eq!(formatter::format(&program), output);
+    }
+
+    #[test]
+    fn test_pipeline() {
+        let input = r#"
+        egg double(x) { *)> x * 2; }
+        egg add(x, y) { *)> x + y; }
+        egg pipe(x) {
+            (|y| y) |> double;
+            *)> x + 1 |> double |> add(3);
+        }
+        "#;
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+
+        // desugared into add(double(x + 1), 3)
+        let pipe = program.functions.values().nth(2).unwrap();
+        let call = match &*pipe.body {
+            Expression::Block(block, _, _) => match &block[1] {
+                Statement::Return(expr, ..) => match &**expr {
+                    Expression::FunctionCall(call) => call,
+                    _ => panic!("expected a call"),
+                },
+                _ => panic!("expected a return"),
+            },
+            _ => panic!("function body is not a block"),
+        };
+        let add = call;
+        assert!(add.piped());
+        assert!(matches!(add, FunctionCall::Direct { name: "add", .. }));
+        assert_eq!(add.args().len(), 2);
+        assert!(matches!(
+            &*add.args()[0],
+            Expression::FunctionCall(FunctionCall::Direct { name: "double", .. })
+        ));
+
+        let output = r#"🥚 pipe(x) {
+    (|y| y) |> double;
+    🐔 (x + 1) |> double |> add(3);
+}
+
+"#;
+        assert!(formatter::format(&program).ends_with(output));
+    }
+
+    #[test]
+    fn test_range() {
+        let input = r#"
+        egg days(weeks) {
+            let all = 0..weeks * 7;
+            let first = 1..=7 |> count;
+            let nested = (0..1)..(2..=3);
+        }
+        "#;
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+
+        let output = r#"🥚 days(weeks) {
+    let all = 0..(weeks * 7);
+    let first = 1..=7 |> count;
+    let nested = (0..1)..(2..=3);
+}
+
+"#;
+        assert_eq!(formatter::format(&program), output);
+    }
+
+    #[test]
+    fn test_strings() {
+        let input = r####"
+        egg say() {
+            "plain";
+            """a "quoted"  word
+  on two lines""";
+            """ends with a quote"""";
+            r"C:\path\n";
+            r#"say "hi""#;
+            "";
+        }
+        "####;
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+
+        let strings = match &*program.functions.values().next().unwrap().body {
+            Expression::Block(block, _, _) => block
+                .iter()
+                .map(|stmt| match stmt {
+                    Statement::Expression(expr) => match &**expr {
+                        Expression::String(string) => (string.value, string.kind),
+                        _ => panic!("expected a string"),
+                    },
+                    _ => panic!("expected an expression"),
+                })
+                .collect::<Vec<_>>(),
+            _ => panic!("function body is not a block"),
+        };
+        assert_eq!(
+            strings,
+            [
+                ("plain", StringKind::Plain),
+                ("a \"quoted\"  word\n  on two lines", StringKind::Multiline),
+                ("ends with a quote\"", StringKind::Multiline),
+                ("C:\\path\\n", StringKind::Raw { hashes: 0 }),
+                ("say \"hi\"", StringKind::Raw { hashes: 1 }),
+                ("", StringKind::Plain),
+            ]
+        );
+
+        // formatting preserves the contents byte for byte
+        let output = r####"🥚 say() {
+    "plain";
+    """a "quoted"  word
+  on two lines""";
+    """ends with a quote"""";
+    r"C:\path\n";
+    r#"say "hi""#;
+    "";
+}
+
+"####;
+        assert_eq!(formatter::format(&program), output);
+    }
+
+    #[test]
+    fn test_trailing_commas() {
+        let input = "
+        enum Nest { Twig, Moss(amount,), }
+        egg lay(a, b,) {
+            count(a, b,);
+        }[
+            lay(1, 2,) = 3,
+        ]
+        ";
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+
+        let format = |trailing_commas| {
+            let config = formatter::FormatConfig {
+                trailing_commas,
+                ..Default::default()
+            };
+            formatter::format_with(&program, config)
+        };
+        assert_eq!(
+            format(formatter::TrailingCommas::Vertical),
+            "enum Nest {
+    Twig,
+    Moss(amount),
+}
+
+🥚 lay(a, b) {
+    count(a, b);
+}[
+    lay(1, 2) = 3,
+]
+
+"
+        );
+        assert_eq!(
+            format(formatter::TrailingCommas::Always),
+            "enum Nest {
+    Twig,
+    Moss(amount,),
+}
+
+🥚 lay(a, b,) {
+    count(a, b,);
+}[
+    lay(1, 2,) = 3,
+]
+
+"
+        );
+        assert_eq!(
+            format(formatter::TrailingCommas::Never),
+            "enum Nest {
+    Twig,
+    Moss(amount)
+}
+
+🥚 lay(a, b) {
+    count(a, b);
+}[
+    lay(1, 2) = 3
+]
+
+"
+        );
+    }
+
+    #[test]
+    fn test_block_value() {
+        let input = "
+        egg count(pigeons) {
+            let flock = { let doves = 2; pigeons + doves };
+            flock
+        }
+        ";
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+
+        let (stmts, tail) = match &*program.functions.values().next().unwrap().body {
+            Expression::Block(block, tail, _) => (block, tail),
+            _ => panic!("function body is not a block"),
+        };
+        assert_eq!(stmts.len(), 1);
+        assert!(matches!(tail.as_deref(), Some(Expression::Variable(_))));
+        match &stmts[0] {
+            Statement::Let(resolved_ast::Let { value, .. }) => assert!(matches!(
+                &**value,
+                Expression::Block(block, Some(_), _) if block.len() == 1
+            )),
+            _ => panic!("expected a let"),
+        }
+
+        let output = r#"🥚 count(pigeons) {
+    let flock = {
+        let doves = 2;
+        (pigeons + doves)
+    };
+    flock
+}
+
+"#;
+        assert_eq!(formatter::format(&program), output);
+    }
+
+    #[test]
+    fn test_conditional() {
+        let input = "
+        egg pick(hen, rooster) {
+            let chick = hen ? 1 : rooster ? 2 : 3;
+            let size = (hen ? 1 : 2) + 3;
+            if hen { rooster } else { 4 };
+            if hen { pick(rooster); 1 } else { 2 };
+        }
+        ";
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+
+        let stmts = match &*program.functions.values().next().unwrap().body {
+            Expression::Block(block, _, _) => block,
+            _ => panic!("function body is not a block"),
+        };
+        match &stmts[0] {
+            Statement::Let(resolved_ast::Let { value, .. }) => match &**value {
+                Expression::If(if_) => {
+                    let (then, else_) = if_.conditional_branches().unwrap();
+                    assert!(matches!(then, Expression::Number(_)));
+                    assert!(matches!(else_, Expression::If(_)));
+                }
+                _ => panic!("expected an if"),
+            },
+            _ => panic!("expected a let"),
+        }
+
+        let output = r#"🥚 pick(hen, rooster) {
+    let chick = hen ? 1 : rooster ? 2 : 3;
+    let size = ((hen ? 1 : 2) + 3);
+    hen ? rooster : 4;
+    if hen {
+        pick(rooster);
+        1
+    } else {
+        2
+    };
+}
+
+"#;
+        assert_eq!(formatter::format(&program), output);
+    }
+
+    #[test]
+    fn test_default_parameters() {
+        let input = r#"
+        egg greet(name = "world", times = 1) {
+            name;
+        }
+        egg coop() {
+            greet();
+            greet("hen");
+            greet("hen", 2, 3);
+            count(1);
+        }
+        egg count(hens, roosters) {
+            hens;
+        }
+        "#;
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+
+        let stmts = match &*program.functions.values().nth(1).unwrap().body {
+            Expression::Block(block, _, _) => block,
+            _ => panic!("function body is not a block"),
+        };
+        match &stmts[0] {
+            Statement::Expression(expr) => match &**expr {
+                Expression::FunctionCall(FunctionCall::Direct {
+                    args, defaulted, ..
+                }) => {
+                    assert_eq!(args.len(), 2);
+                    assert_eq!(*defaulted, 2);
+                    assert!(matches!(*args[1], Expression::Number(_)));
+                }
+                _ => panic!("expected a direct call"),
+            },
+            _ => panic!("expected an expression"),
+        }
+
+        let diagnostics = arity::check(&program);
+        assert_eq!(
+            diagnostics
+                .iter()
+                .map(|diagnostic| (diagnostic.message.as_str(), &input[diagnostic.span.clone()]))
+                .collect::<Vec<_>>(),
+            [
+                (
+                    "`greet` takes 2 arguments, but 3 were given",
+                    r#"greet("hen", 2, 3)"#
+                ),
+                (
+                    "missing an argument for `roosters` in the call to `count`",
+                    "count(1)"
+                ),
+            ]
+        );
+        // both point at the definition of the function they call
+        assert_eq!(
+            diagnostics
+                .iter()
+                .flat_map(|diagnostic| &diagnostic.notes)
+                .map(|(message, span)| (message.as_str(), &input[span.clone()]))
+                .collect::<Vec<_>>(),
+            [
+                (
+                    "`greet` is defined here",
+                    r#"egg greet(name = "world", times = 1)"#
+                ),
+                ("`count` is defined here", "egg count(hens, roosters)"),
+            ]
+        );
+
+        // the filled in defaults are not part of the source
+        let output = r#"🥚 greet(name = "world", times = 1) {
+    name;
+}
+
+🥚 coop() {
+    greet();
+    greet("hen");
+    greet("hen", 2, 3);
+    count(1);
+}
+
+🥚 count(hens, roosters) {
+    hens;
+}
+
+"#;
+        assert_eq!(formatter::format(&program), output);
+
+        // a default is checked where it is written, not again in every call that fills it in
+        let input = "egg f(a, b = 1 / 0) { *)> a + b; }\negg hatch() { f(1); f(2); f(3); }\n";
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+        let diagnostics = fold::check(&program);
+        assert_eq!(
+            diagnostics
+                .iter()
+                .map(|diagnostic| &input[diagnostic.span.clone()])
+                .collect::<Vec<_>>(),
+            ["1 / 0"]
+        );
+    }
+
+    #[test]
+    fn test_unit() {
+        let input = "
+        egg idle(hen) {
+            let nothing = ();
+            let quiet = 🫥;
+            let empty = {};
+            nothing + 1;
+            2 * {
+                idle(hen);
+            };
+            3 + {
+                *)> 4;
+            };
+            let nothing = 3;
+            nothing - hen;
+        }
+        ";
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+
+        let diagnostics = units::check(&program);
+        assert_eq!(
+            diagnostics
+                .iter()
+                .map(|diagnostic| (diagnostic.message.as_str(), &input[diagnostic.span.clone()]))
+                .collect::<Vec<_>>(),
+            [
+                ("cannot use a unit value with `+`", "nothing + 1"),
+                (
+                    "cannot use a unit value with `*`",
+                    "2 * {\n                idle(hen);\n            }"
+                ),
+            ]
+        );
+
+        let output = r#"🥚 idle(hen) {
+    let nothing = ();
+    let quiet = ();
+    let empty = {};
+    (nothing + 1);
+    (2 * {
+        idle(hen);
+    });
+    (3 + {
+        🐔 4;
+    });
+    let nothing = 3;
+    (nothing - hen);
+}
+
+"#;
+        assert_eq!(formatter::format(&program), output);
+    }
+
+    #[test]
+    fn test_try() {
+        let input = "
+        egg fragile(shell) {
+            let cracked = try {
+                fragile(shell)
+            } rescue fragile {
+                |yolk| fragile(yolk)
+            };
+            fragile = 1;
+        }
+        ";
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+
+        let stmts = match &*program.functions.values().next().unwrap().body {
+            Expression::Block(block, _, _) => block,
+            _ => panic!("function body is not a block"),
+        };
+        let try_ = match &stmts[0] {
+            Statement::Let(resolved_ast::Let { value, .. }) => match &**value {
+                Expression::Try(try_) => try_,
+                _ => panic!("expected a try"),
+            },
+            _ => panic!("expected a let"),
+        };
+        assert_eq!(try_.error, "fragile");
+        // the body calls the function, the rescue block the error it binds
+        assert!(matches!(
+            &*try_.body,
+            Expression::Block(_, Some(tail), _) if matches!(
+                &**tail,
+                Expression::FunctionCall(FunctionCall::Direct { .. })
+            )
+        ));
+        match &*try_.rescue {
+            Expression::Block(_, Some(tail), _) => match &**tail {
+                Expression::Lambda(lambda) => {
+                    assert_eq!(lambda.captures, vec!["fragile"]);
+                    assert!(matches!(
+                        &*lambda.body,
+                        Expression::FunctionCall(FunctionCall::Indirect { .. })
+                    ));
+                }
+                _ => panic!("expected a lambda"),
+            },
+            _ => panic!("rescue is not a block"),
+        }
+
+        // the error is only bound in the rescue block
+        let diagnostics = assignments::check(&program);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].message,
+            "cannot assign to `fragile`, it is not declared in this scope"
+        );
+
+        let output = r#"🥚 fragile(shell) {
+    let cracked = try {
+        fragile(shell)
+    } rescue fragile {
+        |yolk| fragile(yolk)
+    };
+    fragile = 1;
+}
+
+"#;
+        assert_eq!(formatter::format(&program), output);
+    }
+
+    #[test]
+    fn test_string_operators() {
+        let input = r#"
+        egg crow(times) {
+            let call = "cock" + "a" + "doodle";
+            let mut song = call + times;
+            song += "doo";
+            let count = times + 1;
+            call == "cock" != (count != 2);
+        }
+        "#;
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+
+        let stmts = match &*program.functions.values().next().unwrap().body {
+            Expression::Block(block, _, _) => block,
+            _ => panic!("function body is not a block"),
+        };
+        assert!(matches!(
+            &stmts[..3],
+            [
+                Statement::Let(resolved_ast::Let { value: call, .. }),
+                Statement::Let(resolved_ast::Let { value: song, .. }),
+                Statement::Assign(resolved_ast::Assign {
+                    op: Some(resolved_ast::Opcode::Concat),
+                    ..
+                }),
+            ] if matches!(
+                &**call,
+                Expression::Op(lhs, resolved_ast::Opcode::Concat, _, _)
+                    if matches!(**lhs, Expression::Op(_, resolved_ast::Opcode::Concat, _, _))
+            ) && matches!(**song, Expression::Op(_, resolved_ast::Opcode::Concat, _, _))
+        ));
+        match &stmts[3] {
+            Statement::Let(resolved_ast::Let { value, .. }) => assert!(matches!(
+                **value,
+                Expression::Op(_, resolved_ast::Opcode::Add, _, _)
+            )),
+            _ => panic!("expected a let"),
+        }
+
+        let output = r#"🥚 crow(times) {
+    let call = (("cock" + "a") + "doodle");
+    let mut song = (call + times);
+    song += "doo";
+    let count = (times + 1);
+    ((call == "cock") != (count != 2));
+}
+
+"#;
+        assert_eq!(formatter::format(&program), output);
+    }
+
+    #[test]
+    fn test_fields_and_methods() {
+        let input = "
+        egg inspect(nest, hens) {
+            nest.eggs.count(hens, 1) * nest.size;
+            (hens |> count).total;
+            (0..3).first();
+            (|egg| egg).apply(nest);
+        }
+        ";
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+
+        let stmts = match &*program.functions.values().next().unwrap().body {
+            Expression::Block(block, _, _) => block,
+            _ => panic!("function body is not a block"),
+        };
+        match &stmts[0] {
+            Statement::Expression(expr) => match &**expr {
+                Expression::Op(lhs, _, rhs, _) => {
+                    match &**lhs {
+                        Expression::MethodCall(call) => {
+                            assert_eq!(call.method, "count");
+                            assert_eq!(call.args.len(), 2);
+                            assert!(matches!(
+                                &*call.object,
+                                Expression::Field(resolved_ast::Field { name: "eggs", .. })
+                            ));
+                        }
+                        _ => panic!("expected a method call"),
+                    }
+                    assert!(matches!(
+                        &**rhs,
+                        Expression::Field(resolved_ast::Field { name: "size", .. })
+                    ));
+                }
+                _ => panic!("expected a multiplication"),
+            },
+            _ => panic!("expected an expression"),
+        }
+
+        // objects that bind weaker than a field access keep their parentheses
+        let output = r#"🥚 inspect(nest, hens) {
+    (nest.eggs.count(hens, 1) * nest.size);
+    (hens |> count).total;
+    (0..3).first();
+    (|egg| egg).apply(nest);
+}
+
+"#;
+        assert_eq!(formatter::format(&program), output);
+    }
+
+    #[test]
+    fn test_assert() {
+        let input = "
+        egg lay(eggs) {
+            assert eggs != 0;
+            🔍 count(eggs)   == 2;
+        }
+        ";
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+
+        let stmts = match &*program.functions.values().next().unwrap().body {
+            Expression::Block(block, _, _) => block,
+            _ => panic!("function body is not a block"),
+        };
+        let asserts = stmts
+            .iter()
+            .map(|stmt| match stmt {
+                Statement::Assert(assert) => (assert.text, &input[assert.span.clone()]),
+                _ => panic!("expected an assertion"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            asserts,
+            [
+                ("eggs != 0", "assert eggs != 0"),
+                ("count(eggs)   == 2", "🔍 count(eggs)   == 2"),
+            ]
+        );
+
+        let output = r#"🥚 lay(eggs) {
+    assert (eggs != 0);
+    assert (count(eggs) == 2);
+}
+
+"#;
+        assert_eq!(formatter::format(&program), output);
+    }
+
+    #[test]
+    fn test_optional() {
+        let input = "
+        egg find(nest) {
+            let eggs = nest.eggs?;
+            let total = find(nest)? + eggs;
+            let chick = (eggs ? nest : none)?.chick;
+            some(total)
+        }
+        ";
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+
+        let stmts = match &*program.functions.values().next().unwrap().body {
+            Expression::Block(block, _, _) => block,
+            _ => panic!("function body is not a block"),
+        };
+        let eggs = match &stmts[0] {
+            Statement::Let(let_) => &let_.value,
+            _ => panic!("expected a let"),
+        };
+        assert!(matches!(eggs.propagated(), Some(Expression::Field(_))));
+        // `{ let ? = nest.eggs; if ? == none { 🐔 none; } else { ?.value } }`
+        match &**eggs {
+            Expression::Block(_, Some(tail), _) => match &**tail {
+                Expression::If(if_) => {
+                    assert!(matches!(
+                        &*if_.condition,
+                        Expression::Op(_, Opcode::Eq, none, _)
+                            if matches!(**none, Expression::None(_, _))
+                    ));
+                    assert!(matches!(
+                        &*if_.body,
+                        Expression::Block(block, None, _)
+                            if matches!(&block[..], [Statement::Return(..)])
+                    ));
+                }
+                _ => panic!("expected an if"),
+            },
+            _ => panic!("expected a block"),
+        }
+        assert!(assignments::check(&program).is_empty());
+
+        let output = r#"🥚 find(nest) {
+    let eggs = nest.eggs?;
+    let total = (find(nest)? + eggs);
+    let chick = (eggs ? nest : none)?.chick;
+    some(total)
+}
+
+"#;
+        assert_eq!(formatter::format(&program), output);
+    }
+
+    #[test]
+    fn test_script() {
+        let input = "
+        let eggs = 2;
+        lay(eggs);
+        egg lay(eggs) {
+            *)> eggs;
+        }
+        📦 chicks = lay(3);
+        ";
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+
+        let script = program
+            .script
+            .expect("the top level statements make up a script");
+        assert_eq!(program.functions[script].definition.name, "hatch");
+        assert_eq!(program.script_statements().len(), 3);
+        assert!(script::check(&program).is_empty());
+
+        let output = "let eggs = 2;
+lay(eggs);
+
+🥚 lay(eggs) {
+    🐔 eggs;
+}
+
+let chicks = lay(3);
+";
+        assert_eq!(formatter::format(&program), output);
+
+        let input = "
+        egg hatch() {
+            lay(2);
+        }
+        lay(3);
+        ";
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+        let diagnostics = script::check(&program);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(&input[diagnostics[0].span.clone()], "egg hatch()");
+    }
+
+    #[test]
+    fn test_variable_resolution() {
+        let input = "
+        egg lay(eggs) {
+            let mut eggs = eggs + 1;
+            {
+                let eggs = 2;
+                eggs;
+            };
+            eggs = |eggs| eggs;
+            try { eggs; } rescue eggs { eggs; };
+            chicks;
+        }
+        ";
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+
+        #[derive(Default)]
+        struct Uses(Vec<Option<usize>>);
+        impl<'input> Visitor<'input> for Uses {
+            fn visit_statement(&mut self, stmt: &Statement<'input>) {
+                if let Statement::Assign(assign) = stmt {
+                    self.0.push(assign.variable.map(|id| id.to_index()));
+                }
+                walk_statement(self, stmt);
+            }
+
+            fn visit_expression(&mut self, expr: &Expression<'input>) {
+                if let Expression::Variable(var) = expr {
+                    self.0.push(var.id.map(|id| id.to_index()));
+                }
+                walk_expression(self, expr);
+            }
+        }
+        let mut uses = Uses::default();
+        walk_program(&mut uses, &program);
+        // the parameter, the `let mut`, the inner `let`, the lambda parameter and the error
+        assert_eq!(
+            uses.0,
+            [Some(0), Some(2), Some(1), Some(3), Some(1), Some(4), None]
+        );
+        let bindings = program
+            .variables
+            .values()
+            .map(|binding| (binding.name, binding.mutable))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            bindings,
+            [
+                ("eggs", false),
+                ("eggs", true),
+                ("eggs", false),
+                ("eggs", false),
+                ("eggs", false),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_undefined_variables() {
+        let input = "
+        egg hatch() {
+            let x = {
+                let test = 420;
+            };
+            let f = count_Pigeons;
+            let y = count_Pigeons(test);
+            let z = |pigeons| pigeons + x + pigeon;
+        }
+        egg count_Pigeons(pigeons) {
+            *)> pigeons;
+        }
+        ";
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+
+        let diagnostics = names::check(&program)
+            .into_iter()
+            .map(|diagnostic| (diagnostic.message, &input[diagnostic.span]))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            diagnostics,
+            [
+                ("`test` is not declared in this scope".to_string(), "test"),
+                (
+                    "`pigeon` is not declared in this scope".to_string(),
+                    "pigeon"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_undefined_functions() {
+        let input = "
+        egg hatch() {
+            count_pigeons(2);
+            2 |> count_Pigeon;
+            feed(3);
+            egg feed_pigeons(pigeons) {
+                count_Pigeons(pigeons);
+            }
+        }
+        egg count_Pigeons(pigeons) {
+            feed_pigeon(pigeons);
+        }
+        ";
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+
+        let diagnostics = names::check(&program)
+            .into_iter()
+            .map(|diagnostic| (diagnostic.message, &input[diagnostic.span]))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            diagnostics,
+            [
+                (
+                    "there is no function named `count_pigeons`, did you mean `count_Pigeons`?"
+                        .to_string(),
+                    "count_pigeons"
+                ),
+                (
+                    "there is no function named `count_Pigeon`, did you mean `count_Pigeons`?"
+                        .to_string(),
+                    "count_Pigeon"
+                ),
+                ("there is no function named `feed`".to_string(), "feed"),
+                // nested functions are only visible inside the function they are defined in
+                (
+                    "there is no function named `feed_pigeon`".to_string(),
+                    "feed_pigeon"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_undefined_names_in_imports() {
+        // the imported file is checked on its own, its spans don't point into the main file
+        let main = "import \"lib.wy\";\n\negg hatch() { *)> helper(1000000000000000); }\n";
+        let lib = "egg helper(x) { *)> helpr(x) + y; }\n";
+        let (main, _) = parse(main).unwrap();
+        let (lib, _) = parse(lib).unwrap();
+        let program = resolved_ast::Program::link(main, vec![lib]);
+
+        assert!(names::check(&program).is_empty());
+    }
+
+    #[test]
+    fn test_dead_code() {
+        let input = "
+        egg hatch() {
+            let f = lay;
+            f(brood(2));
+        }
+        egg lay(eggs) {
+            *)> eggs;
+        }
+        egg brood(eggs) {
+            egg turn(eggs) {
+                *)> eggs;
+            }
+            egg warm(eggs) {
+                *)> warm(eggs);
+            }
+            *)> turn(eggs);
+        }
+        egg peck(seeds) {
+            *)> peck(seeds);
+        }
+        ";
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+
+        let diagnostics = dead_code::check(&program)
+            .into_iter()
+            .map(|diagnostic| (diagnostic.message, &input[diagnostic.span]))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            diagnostics,
+            [
+                ("`warm` is never called".to_string(), "egg warm(eggs)"),
+                ("`peck` is never called".to_string(), "egg peck(seeds)"),
+            ]
+        );
+
+        // without a `hatch`, every function may be called by whoever imports them
+        let input = "
+        egg peck(seeds) {
+            *)> seeds;
+        }
+        ";
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        assert!(dead_code::check(&program.into()).is_empty());
+    }
+
+    #[test]
+    fn test_call_graph() {
+        let input = "
+        egg hatch() {
+            let f = lay;
+            f(brood(2));
+            lay(1);
+        }
+        egg lay(eggs) {
+            *)> eggs;
+        }
+        egg brood(eggs, nest = lay(0)) {
+            egg turn(eggs) {
+                *)> turn(eggs);
+            }
+            *)> turn(eggs);
+        }
+        ";
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+
+        let calls = program
+            .calls
+            .values()
+            .map(|callees| callees.iter().map(|id| id.to_index()).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        // the filled in default calls `lay` from `hatch`, which already calls it
+        assert_eq!(calls, [vec![1, 2], vec![], vec![1, 3], vec![3]]);
+
+        let output = r#"digraph calls {
+    0 [label="hatch"];
+    1 [label="lay"];
+    2 [label="brood"];
+    3 [label="brood::turn"];
+    0 -> 1;
+    0 -> 2;
+    2 -> 1;
+    2 -> 3;
+    3 -> 3;
+}
+"#;
+        assert_eq!(call_graph::dot(&program), output);
+    }
+
+    #[test]
+    fn test_typecheck() {
+        let input = r#"
+        egg lay(eggs: int) -> string {
+            let song = "hello" * 3;
+            if 5 { 1; };
+            let mut count = eggs == 2;
+            count = 1;
+            assert eggs;
+            let same = eggs != "two";
+            let again = lay("two") + "!";
+            *)> eggs + lay(1 + song.length) + count;
+        }
+        "#;
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+
+        let diagnostics = typecheck::check(&program)
+            .into_iter()
+            .map(|diagnostic| (diagnostic.message, &input[diagnostic.span]))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            diagnostics,
+            [
+                (
+                    "cannot use a `string` with `*`".to_string(),
+                    r#""hello" * 3"#
+                ),
+                (
+                    "the condition has to be a `bool`, but it is an `int`".to_string(),
+                    "if 5 { 1; }"
+                ),
+                (
+                    "cannot assign an `int` to `count`, which holds a `bool`".to_string(),
+                    "count = 1"
+                ),
+                (
+                    "the condition has to be a `bool`, but it is an `int`".to_string(),
+                    "assert eggs"
+                ),
+                (
+                    "cannot compare an `int` with a `string`".to_string(),
+                    r#"eggs != "two""#
+                ),
+                (
+                    "`lay` expects an `int` for `eggs`, but got a `string`".to_string(),
+                    r#"lay("two")"#
+                ),
+                (
+                    "cannot use a `string` with `+`".to_string(),
+                    "eggs + lay(1 + song.length)"
+                ),
+                (
+                    "cannot use a `bool` with `+`".to_string(),
+                    "eggs + lay(1 + song.length) + count"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_infer_signatures() {
+        let input = r#"
+        egg double(x) { *)> x * 2; }
+        egg same(x) { *)> x; }
+        egg even(n) {
+            if n == 0 { *)> 1 == 1; };
+            *)> odd(n - 1);
+        }
+        egg odd(n) {
+            if n == 0 { *)> 1 == 0; };
+            *)> even(n - 1);
+        }
+        egg apply(f, x) { *)> f(x); }
+        egg greet(name = "world") { print("hello " + name); }
+        egg hatch() {
+            let twice = apply(double, same(2));
+            double("two");
+        }
+        "#;
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+
+        let signatures = infer::signatures(&program);
+        let signatures = program
+            .functions
+            .iter()
+            .map(|(id, func)| signatures[id].describe(&func.definition))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            signatures,
+            [
+                "egg double(x: int) -> int",
+                "egg same(x: a) -> a",
+                "egg even(n: int) -> bool",
+                "egg odd(n: int) -> bool",
+                "egg apply(f: |a| -> b, x: a) -> b",
+                "egg greet(name: string) -> unit",
+                "egg hatch() -> unit",
+            ]
+        );
+
+        // calls to unannotated functions are checked with the inferred signature
+        let diagnostics = typecheck::check(&program)
+            .into_iter()
+            .map(|diagnostic| (diagnostic.message, &input[diagnostic.span]))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            diagnostics,
+            [(
+                "`double` expects an `int` for `x`, but got a `string`".to_string(),
+                r#"double("two")"#
+            )]
+        );
+    }
+
+    #[test]
+    fn test_control_flow() {
+        let input = r#"
+        egg sign(n) {
+            if n == 0 { *)> 0; };
+        }
+        egg both(n) {
+            if n == 0 { *)> 0; } else { *)> 1; };
+            sign(n);
+        }
+        egg early(n) {
+            🐔 n;
+            // a comment is fine
+            sign(n);
+        }
+        egg fine(n) {
+            if n == 0 { *)> 0; };
+            let lay = |x| { *)> x; };
+            *)> 1;
+        }
+        egg maybe(n) {
+            let x = n?;
+            sign(x);
+        }
+        "#;
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+
+        let diagnostics = control_flow::check(&program)
+            .into_iter()
+            .map(|diagnostic| {
+                (
+                    diagnostic.message,
+                    &input[diagnostic.span],
+                    diagnostic
+                        .notes
+                        .into_iter()
+                        .map(|(note, span)| (note, &input[span]))
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            diagnostics,
+            [
+                (
+                    "`sign` returns a value on some paths but not on others".to_string(),
+                    "egg sign(n)",
+                    vec![("it returns a value here".to_string(), "*)> 0")]
+                ),
+                (
+                    "the code after this is unreachable, every path through it returns".to_string(),
+                    "if n == 0 { *)> 0; } else { *)> 1; }",
+                    vec![]
+                ),
+                (
+                    "the code after this return is unreachable".to_string(),
+                    "🐔 n",
+                    vec![]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_constant_folding() {
+        let input = r#"
+        egg lay(eggs) {
+            let clutch = (69 + 31) * 3;
+            let debt = 2 - 0x10;
+            let warm = eggs * (1 + 1);
+            let huge = 9223372036854775807 + 1;
+            let broken = 1 / (2 - 2);
+        }
+        "#;
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let mut program: resolved_ast::Program = program.into();
+
+        let expected = [
+            (
+                "`9223372036854775807 + 1` overflows, the result doesn't fit in an `int`"
+                    .to_string(),
+                "9223372036854775807 + 1",
+            ),
+            ("`1 / 0` divides by zero".to_string(), "1 / (2 - 2)"),
+        ];
+        let messages = |diagnostics: Vec<Diagnostic>| {
+            diagnostics
+                .into_iter()
+                .map(|diagnostic| (diagnostic.message, &input[diagnostic.span]))
+                .collect::<Vec<_>>()
+        };
+        // `check` reports the same problems without folding
+        assert_eq!(messages(fold::check(&program)), expected);
+        assert_eq!(messages(fold::fold(&mut program)), expected);
+
+        let output = r#"🥚 lay(eggs) {
+    let clutch = 300;
+    let debt = (0 - 14);
+    let warm = (eggs * 2);
+    let huge = (9223372036854775807 + 1);
+    let broken = (1 / 0);
+}
+
+"#;
+        assert_eq!(formatter::format(&program), output);
+    }
+
+    #[test]
+    fn test_dead_code_elimination() {
+        let input = r#"
+        egg lay(eggs) {
+            let unused = 1 + 2;
+            let used = 3;
+            let chained = used;
+            let called = lay(eggs);
+            if 2 * 2 == 5 {
+                lay(1);
+            } else if 1 != 1 {
+                lay(2);
+            } else if eggs == 0 {
+                lay(3);
+            } else if 0 == 0 {
+                lay(4);
+            } else {
+                lay(5);
+            };
+            if 1 == 1 {
+                lay(6);
+                lay(7);
+            };
+            if 1 == 2 {
+                lay(8);
+            };
+            "cluck";
+            *)> 1 == 1 ? eggs : 0;
+        }
+        "#;
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let mut program: resolved_ast::Program = program.into();
+        assert!(fold::fold(&mut program).is_empty());
+        eliminate::eliminate(&mut program);
+
+        let output = r#"🥚 lay(eggs) {
+    let called = lay(eggs);
+    if (eggs == 0) {
+        lay(3);
+    } else {
+        lay(4);
+    };
+    lay(6);
+    lay(7);
+    🐔 eggs;
+}
+
+"#;
+        assert_eq!(formatter::format(&program), output);
+    }
+
+    #[test]
+    fn test_spans() {
+        let input = r#"
+        egg lay(eggs) {
+            let clutch = some(eggs + 1);
+            eggs |> lay;
+            *)> clutch?;
+        }
+        "#;
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+
+        let body = &program.functions.values().next().unwrap().body;
+        assert!(input[body.span()].starts_with('{'));
+        let stmts = match &**body {
+            Expression::Block(block, _, _) => block,
+            _ => panic!("function body is not a block"),
+        };
+        let spans = stmts
+            .iter()
+            .map(|stmt| &input[stmt.span(&program)])
+            .collect::<Vec<_>>();
+        assert_eq!(
+            spans,
+            ["let clutch = some(eggs + 1)", "eggs |> lay", "*)> clutch?"]
+        );
+        match &stmts[0] {
+            Statement::Let(let_) => assert_eq!(&input[let_.value.span()], "some(eggs + 1)"),
+            _ => panic!("first statement is not a let"),
+        }
+        // everything `?` desugars to points back at it
+        match &stmts[2] {
+            Statement::Return(value, _, _) => assert_eq!(&input[value.span()], "clutch?"),
+            _ => panic!("last statement is not a return"),
+        }
+    }
+
+    #[test]
+    fn test_symbol_table() {
+        let input = r#"
+        egg lay(eggs) {
+            let clutch = eggs + 1;
+            let warm = |yolk| yolk * clutch;
+            egg turn(n) {
+                *)> n;
+            }
+            turn(clutch);
+        }
+        "#;
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+        let symbols = SymbolTable::new(&program);
+
+        let clutch = symbols.symbol_at(input.find("clutch;").unwrap()).unwrap();
+        assert_eq!(clutch.name(&program), "clutch");
+        assert_eq!(
+            &input[symbols.definitions[&clutch].clone()],
+            "let clutch = eggs + 1"
+        );
+        assert_eq!(symbols.references(clutch).len(), 2);
+
+        let turn = symbols.symbol_at(input.find("turn(clutch)").unwrap());
+        assert!(matches!(turn, Some(Symbol::Function(_))));
+        assert_eq!(symbols.references(turn.unwrap()).len(), 1);
+
+        // the variables of `lay` aren't visible in `turn`
+        let visible = symbols
+            .visible(input.find("n;").unwrap())
+            .into_iter()
+            .map(|symbol| symbol.name(&program))
+            .collect::<Vec<_>>();
+        assert_eq!(visible, ["n", "turn", "lay"]);
+        let visible = symbols
+            .visible(input.find("yolk *").unwrap())
+            .into_iter()
+            .map(|symbol| symbol.name(&program))
+            .collect::<Vec<_>>();
+        assert_eq!(visible, ["yolk", "turn", "clutch", "eggs", "lay"]);
+    }
+
+    #[test]
+    fn test_lints() {
+        let input = "
+        egg hatch() {
+            let mut eggs = 2;
+            eggs = 3;
+            let chicks = 1;
+            *)> chicks + 1;
+        }
+        egg peck(seeds) {
+            *)> seeds;
+        }
+        ";
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+
+        let mut lints = lints::Registry::new();
+        let diagnostics = lints
+            .check(&program)
+            .into_iter()
+            .map(|diagnostic| (diagnostic.severity, diagnostic.message))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            diagnostics,
+            [
+                (Severity::Warning, "`eggs` is never read".to_string()),
+                (Severity::Warning, "`peck` is never called".to_string()),
+            ]
+        );
+
+        lints
+            .set_level("unused-variable", lints::Level::Allow)
+            .unwrap();
+        lints
+            .set_level("dead-function", lints::Level::Deny)
+            .unwrap();
+        let diagnostics = lints.check(&program);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Severity::Error);
+
+        let warning = Diagnostic::warning("", 0..0);
+        assert!(lints.configure(warning.clone()).is_some());
+        lints.set_level("warnings", lints::Level::Deny).unwrap();
+        assert_eq!(
+            lints.configure(warning.clone()).unwrap().severity,
+            Severity::Error
+        );
+        lints.set_level("warnings", lints::Level::Allow).unwrap();
+        assert!(lints.configure(warning).is_none());
+
+        assert_eq!(
+            lints.set_level("dead-functions", lints::Level::Deny),
+            Err("unknown lint `dead-functions`, did you mean `dead-function`?".to_string())
+        );
+    }
+
+    #[test]
+    fn test_shadowing() {
+        let input = "
+        egg lay(eggs) {
+            let eggs = eggs + 1;
+            let clutch = 1;
+            let twice = |clutch| clutch * 2;
+            {
+                let chicks = 2;
+                let clutch = chicks;
+            };
+            let chicks = 3;
+            let warm = |yolk| {
+                let yolk = clutch + chicks;
+                *)> yolk;
+            };
+            *)> eggs;
+        }
+        ";
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+
+        let diagnostics = shadowing::check(&program)
+            .into_iter()
+            .map(|diagnostic| {
+                (
+                    diagnostic.message,
+                    &input[diagnostic.span],
+                    &input[diagnostic.notes[0].1.clone()],
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            diagnostics,
+            [
+                (
+                    "`eggs` shadows the parameter of the same name".to_string(),
+                    "let eggs = eggs + 1",
+                    "eggs"
+                ),
+                (
+                    "`clutch` shadows the binding of the same name".to_string(),
+                    "let clutch = chicks",
+                    "let clutch = 1"
+                ),
+                (
+                    "`yolk` shadows the parameter of the same name".to_string(),
+                    "let yolk = clutch + chicks",
+                    "yolk"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tail_calls() {
+        let input = "
+        egg count(eggs, total) {
+            if eggs == 0 {
+                *)> total;
+            };
+            let counted = count(0, total);
+            *)> try {
+                count(eggs - 1, total + 1)
+            } rescue error {
+                eggs == 1 ? count(0, total) : lay(eggs)
+            };
+        }
+        egg lay(eggs) {
+            let twice = |eggs| lay(eggs);
+            lay(eggs) + 1
+        }
+        ";
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+
+        let spans = program
+            .functions
+            .values()
+            .map(|func| {
+                tail_calls::spans(func)
+                    .into_iter()
+                    .map(|span| &input[span])
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            spans,
+            [vec!["count(0, total)", "lay(eggs)"], vec!["lay(eggs)"]]
+        );
+
+        let diagnostics = tail_calls::check(&program)
+            .into_iter()
+            .map(|diagnostic| (diagnostic.message, &input[diagnostic.span]))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            diagnostics,
+            [
+                (
+                    "`count` calls itself outside of a tail position, deep recursion can overflow \
+                     the stack"
+                        .to_string(),
+                    "count(0, total)"
+                ),
+                (
+                    "`count` calls itself outside of a tail position, deep recursion can overflow \
+                     the stack"
+                        .to_string(),
+                    "count(eggs - 1, total + 1)"
+                ),
+                (
+                    "`lay` calls itself outside of a tail position, deep recursion can overflow \
+                     the stack"
+                        .to_string(),
+                    "lay(eggs)"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_purity() {
+        let input = "
+        egg double(eggs) {
+            *)> eggs * 2;
+        }
+        egg quadruple(eggs) {
+            *)> double(double(eggs));
+        }
+        egg count(eggs) {
+            assert eggs != 0;
+            *)> eggs;
+        }
+        egg lay(eggs) {
+            *)> double(count(eggs));
+        }
+        egg warm(eggs) {
+            let heat = |yolk| yolk;
+            *)> heat(eggs);
+        }
+        egg hatch() {
+            quadruple(2);
+            (double(2));
+            count(2);
+            lay(2);
+            warm(2);
+            let eggs = double(2);
+        }
+        ";
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+
+        let purity = program
+            .functions
+            .values()
+            .map(|func| (func.definition.name, func.purity))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            purity,
+            [
+                ("double", Purity::Pure),
+                ("quadruple", Purity::Pure),
+                ("count", Purity::Effectful),
+                ("lay", Purity::Effectful),
+                ("warm", Purity::Effectful),
+                ("hatch", Purity::Effectful),
+            ]
+        );
+
+        let diagnostics = purity::check(&program)
+            .into_iter()
+            .map(|diagnostic| (diagnostic.message, &input[diagnostic.span]))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            diagnostics,
+            [
+                (
+                    "the value of this call is discarded, and calling `quadruple` has no other \
+                     effect"
+                        .to_string(),
+                    "quadruple(2)"
+                ),
+                (
+                    "the value of this call is discarded, and calling `double` has no other \
+                     effect"
+                        .to_string(),
+                    "double(2)"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_test_runner() {
+        let input = r#"
+        enum Nest { Empty, Full(eggs) }
+        egg double(eggs, times = 2) {
+            *)> eggs * times;
+        }
+        [
+            2 = 4,
+            3 = 7,
+            1 = 1 / 0,
+        ]
+        egg count(eggs) {
+            let mut total = 0;
+            if eggs == 0 {
+                *)> total;
+            };
+            total += eggs + count(eggs - 1);
+            total
+        }
+        [
+            3 = 6,
+        ]
+        egg fill(eggs) {
+            let nest = Nest::Full(eggs);
+            let twice = |eggs| double(eggs);
+            *)> some(twice(nest.eggs));
+        }
+        [
+            2 = some(4),
+        ]
+        egg unwrap(eggs) {
+            *)> eggs? + 1;
+        }
+        [
+            some(1) = 2,
+            none = none,
+        ]
+        egg safe(eggs) {
+            *)> try {
+                assert eggs != 0;
+                "cluck"
+            } rescue error {
+                error
+            };
+        }
+        [
+            0 = "assertion `eggs != 0` failed",
+        ]
+        egg forever() {
+            *)> forever();
+        }
+        [
+            () = 1,
+        ]
+        "#;
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+
+        let results = test_runner::run(&program, test_runner::LIMITS, None)
+            .into_iter()
+            .map(|function| {
+                let tests = function
+                    .tests
+                    .into_iter()
+                    .map(|test| (&input[test.span], test.outcome))
+                    .collect::<Vec<_>>();
+                (program.functions[function.function].definition.name, tests)
+            })
+            .collect::<Vec<_>>();
+        let division = input.find("1 / 0").unwrap();
+        // the call that goes too deep fails at the body it would run
+        let forever = program
+            .functions
+            .iter()
+            .find(|(_, func)| func.definition.name == "forever")
+            .unwrap()
+            .1
+            .body
+            .span();
+        assert_eq!(
+            results,
+            [
+                (
+                    "double",
+                    vec![
+                        ("2 = 4", test_runner::Outcome::Passed),
+                        (
+                            "3 = 7",
+                            test_runner::Outcome::Failed {
+                                expected: "7".to_string(),
+                                got: "6".to_string()
+                            }
+                        ),
+                        (
+                            "1 = 1 / 0",
+                            test_runner::Outcome::Error(runtime::Failure {
+                                message: "`1 / 0` divides by zero".to_string(),
+                                span: division..division + 5,
+                            })
+                        ),
+                    ]
+                ),
+                ("count", vec![("3 = 6", test_runner::Outcome::Passed)]),
+                ("fill", vec![("2 = some(4)", test_runner::Outcome::Passed)]),
+                (
+                    "unwrap",
+                    vec![
+                        ("some(1) = 2", test_runner::Outcome::Passed),
+                        ("none = none", test_runner::Outcome::Passed),
+                    ]
+                ),
+                (
+                    "safe",
+                    vec![(
+                        "0 = \"assertion `eggs != 0` failed\"",
+                        test_runner::Outcome::Passed
+                    )]
+                ),
+                (
+                    "forever",
+                    vec![(
+                        "() = 1",
+                        test_runner::Outcome::Error(runtime::Failure {
+                            message: format!(
+                                "the program went more than {} calls deep",
+                                interpreter::MAX_DEPTH
+                            ),
+                            span: forever,
+                        })
+                    )]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_vm() {
+        let input = r#"
+        enum Nest { Empty, Full(eggs) }
+        egg double(eggs, times = 2) {
+            *)> eggs * times;
+        }
+        egg count(eggs) {
+            let mut total = 0;
+            if eggs == 0 {
+                *)> total;
+            };
+            total += eggs + count(eggs - 1);
+            total
+        }
+        egg safe(eggs) {
+            *)> try {
+                assert eggs != 0;
+                "cluck"
+            } rescue error {
+                error
+            };
+        }
+        egg recursion() {
+            count(100)
+        }
+        egg defaults() {
+            double(3) + double(3, 3)
+        }
+        egg lambdas() {
+            let nest = Nest::Full(2);
+            let add = |a, b| a + b + nest.eggs;
+            let twice = |f| f(double(3), 1);
+            twice(add)
+        }
+        egg rescued() {
+            safe(0) + " " + safe(1)
+        }
+        egg values() {
+            let range = 1..=3;
+            some(range.end) == some(3) ? Nest::Full(range) : Nest::Empty
+        }
+        egg overflow() {
+            let big = 9223372036854775807;
+            *)> { big + 1 };
+        }
+        egg missing() {
+            count()
+        }
+        egg not_a_function() {
+            let eggs = 1;
+            eggs(2)
+        }
+        egg forever() {
+            *)> forever();
+        }
+        "#;
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+        let compiled = bytecode::compile(&program);
+
+        let mut results = Vec::new();
+        for (id, func) in &program.functions {
+            if !func.definition.params.is_empty() {
+                continue;
+            }
+            let span = &func.definition.span;
+            let interpreted = interpreter::with_large_stack(|| {
+                interpreter::Interpreter::new(&program)
+                    .call(id, vec![], span)
+                    .map(|value| value.to_string())
+            });
+            let run = vm::Vm::new(&compiled)
+                .call(id, vec![], span)
+                .map(|value| value.to_string());
+            assert_eq!(interpreted, run, "{}", func.definition.name);
+            results.push((func.definition.name, run.map_err(|failure| failure.message)));
+        }
+        assert_eq!(
+            results,
+            [
+                ("recursion", Ok("5050".to_string())),
+                ("defaults", Ok("15".to_string())),
+                ("lambdas", Ok("9".to_string())),
+                (
+                    "rescued",
+                    Ok("\"assertion `eggs != 0` failed cluck\"".to_string())
+                ),
+                ("values", Ok("Nest::Full(1..=3)".to_string())),
+                (
+                    "overflow",
+                    Err(
+                        "`9223372036854775807 + 1` overflows, the result doesn't fit in an `int`"
+                            .to_string()
+                    )
+                ),
+                (
+                    "missing",
+                    Err("`count` is missing an argument for `eggs`".to_string())
+                ),
+                ("not_a_function", Err("1 is not a function".to_string())),
+                (
+                    "forever",
+                    Err(format!(
+                        "the program went more than {} calls deep",
+                        interpreter::MAX_DEPTH
+                    ))
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_intrinsics() {
+        let input = r#"
+        egg max(eggs) {
+            eggs
+        }
+        egg math() {
+            abs(0 - 3) + min(2, 7) + pow(2, 10) + sqrt(17) + max(1)
+        }
+        egg shadowed(abs) {
+            let min = |eggs| eggs;
+            abs(1) + min(2)
+        }
+        egg wrong() {
+            abs(1, 2) + pow("two", 2)
+        }
+        egg overflow() {
+            pow(2, 64)
+        }
+        egg negative() {
+            sqrt(0 - 4)
+        }
+        "#;
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+
+        let intrinsics = |name| {
+            let func = program
+                .functions
+                .values()
+                .find(|func| func.definition.name == name)
+                .unwrap();
+            let mut calls = Vec::new();
+            let mut expressions = vec![&*func.body];
+            while let Some(expr) = expressions.pop() {
+                match expr {
+                    Expression::Block(_, Some(tail), _) => expressions.push(tail),
+                    Expression::Op(lhs, _, rhs, _) => expressions.extend([&**rhs, &**lhs]),
+                    Expression::FunctionCall(call) => calls.push(match call {
+                        FunctionCall::Intrinsic { intrinsic, .. } => Some(*intrinsic),
+                        _ => None,
+                    }),
+                    _ => {}
+                }
+            }
+            calls
+        };
+        // functions of the program and local bindings hide intrinsics of the same name
+        assert_eq!(
+            intrinsics("math"),
+            [
+                Some(Intrinsic::Abs),
+                Some(Intrinsic::Min),
+                Some(Intrinsic::Pow),
+                Some(Intrinsic::Sqrt),
+                None
+            ]
+        );
+        assert_eq!(intrinsics("shadowed"), [None, None]);
+
+        assert!(names::check(&program).is_empty());
+        let diagnostics = arity::check(&program)
+            .into_iter()
+            .chain(typecheck::check(&program))
+            .map(|diagnostic| (diagnostic.message, &input[diagnostic.span]))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            diagnostics,
+            [
+                (
+                    "`abs` takes 1 argument, but 2 were given".to_string(),
+                    "abs(1, 2)"
+                ),
+                (
+                    "`pow` expects an `int`, but got a `string`".to_string(),
+                    r#"pow("two", 2)"#
+                ),
+            ]
+        );
+
+        let compiled = bytecode::compile(&program);
+        let run = |name| {
+            let (id, func) = program
+                .functions
+                .iter()
+                .find(|(_, func)| func.definition.name == name)
+                .unwrap();
+            let span = &func.definition.span;
+            let interpreted = interpreter::Interpreter::new(&program)
+                .call(id, vec![], span)
+                .map(|value| value.to_string());
+            let run = vm::Vm::new(&compiled)
+                .call(id, vec![], span)
+                .map(|value| value.to_string());
+            assert_eq!(interpreted, run);
+            run.map_err(|failure| failure.message)
+        };
+        assert_eq!(run("math"), Ok("1034".to_string()));
+        assert_eq!(
+            run("wrong"),
+            Err("`abs` takes 1 argument, but got 2".to_string())
+        );
+        assert_eq!(
+            run("overflow"),
+            Err("`pow(2, 64)` overflows, the result doesn't fit in an `int`".to_string())
+        );
+        assert_eq!(
+            run("negative"),
+            Err("`sqrt` of the negative number -4 isn't an `int`".to_string())
+        );
+    }
+
+    #[test]
+    fn test_string_intrinsics() {
+        let input = r#"
+        egg strings() {
+            let song = concat("cluck ", "🐔🐔");
+            let chorus = slice(song, 3, 7);
+            chorus + " " + concat(len(song) == 8 ? "eight" : "not eight", "")
+        }
+        egg found() {
+            contains("cluck cluck", "k c") == contains("", "")
+        }
+        egg out_of_bounds() {
+            slice("hen", 2, 4)
+        }
+        egg wrong_types() {
+            len(some(1))
+        }
+        egg checked() {
+            contains(len("hen"), "h")
+        }
+        "#;
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+
+        let diagnostics = typecheck::check(&program)
+            .into_iter()
+            .map(|diagnostic| (diagnostic.message, &input[diagnostic.span]))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            diagnostics,
+            [(
+                "`contains` expects a `string`, but got an `int`".to_string(),
+                r#"contains(len("hen"), "h")"#
+            )]
+        );
+
+        let compiled = bytecode::compile(&program);
+        let run = |name| {
+            let (id, func) = program
+                .functions
+                .iter()
+                .find(|(_, func)| func.definition.name == name)
+                .unwrap();
+            let span = &func.definition.span;
+            let interpreted = interpreter::Interpreter::new(&program)
+                .call(id, vec![], span)
+                .map(|value| value.to_string());
+            let run = vm::Vm::new(&compiled)
+                .call(id, vec![], span)
+                .map(|value| value.to_string());
+            assert_eq!(interpreted, run);
+            run.map_err(|failure| failure.message)
+        };
+        assert_eq!(run("strings"), Ok("\"ck 🐔 eight\"".to_string()));
+        assert_eq!(run("found"), Ok("true".to_string()));
+        assert_eq!(
+            run("out_of_bounds"),
+            Err("`slice(\"hen\", 2, 4)` is out of bounds, the string has 3 characters".to_string())
+        );
+        assert_eq!(
+            run("wrong_types"),
+            Err("`len` expects a `string` or a list, but got some(1)".to_string())
+        );
+        assert_eq!(
+            run("checked"),
+            Err("`contains` expects a `string` and a `string`, but got 3, \"h\"".to_string())
+        );
+    }
+
+    #[test]
+    fn test_io_intrinsics() {
+        let path = std::env::temp_dir().join("wryneck_test_io_intrinsics.txt");
+        let input = format!(
+            r#"
+        egg nest() {{
+            "{}"
+        }}
+        egg store(eggs) {{
+            write_file(nest(), eggs);
+            read_file(nest())
+        }}
+        [
+            "cluck" = "cluck",
+        ]
+        egg missing() {{
+            read_file(concat(nest(), ".missing"))
+        }}
+        "#,
+            path.display()
+        );
+        let (program, errors) = parse(&input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+        let function = |name| {
+            program
+                .functions
+                .iter()
+                .find(|(_, func)| func.definition.name == name)
+                .unwrap()
+        };
+
+        // reading and writing are effects
+        assert_eq!(function("nest").1.purity, Purity::Pure);
+        assert_eq!(function("store").1.purity, Purity::Effectful);
+
+        let compiled = bytecode::compile(&program);
+        for io in [false, true] {
+            let (id, func) = function("store");
+            let span = &func.definition.span;
+            let arg = "cluck".to_string();
+            let interpreted = interpreter::Interpreter::new(&program)
+                .with_io(io)
+                .call(id, vec![interpreter::Value::String(arg.clone())], span)
+                .map(|value| value.to_string());
+            let run = vm::Vm::new(&compiled)
+                .with_io(io)
+                .call(id, vec![vm::Value::String(arg)], span)
+                .map(|value| value.to_string());
+            assert_eq!(interpreted, run);
+            if io {
+                assert_eq!(run, Ok("\"cluck\"".to_string()));
+            } else {
+                assert_eq!(
+                    run.unwrap_err().message,
+                    "`write_file` reads or writes outside of the program, which it isn't allowed to"
+                );
+            }
+        }
+        let (id, func) = function("missing");
+        let failure = interpreter::Interpreter::new(&program)
+            .with_io(true)
+            .call(id, vec![], &func.definition.span)
+            .unwrap_err();
+        assert!(failure.message.starts_with(&format!(
+            "`read_file(\"{}.missing\")` failed: ",
+            path.display()
+        )));
+        std::fs::remove_file(&path).unwrap();
+
+        // tests run without I/O
+        let results = test_runner::run(&program, test_runner::LIMITS, None);
+        assert!(matches!(
+            results[0].tests[0].outcome,
+            test_runner::Outcome::Error(_)
+        ));
+    }
+
+    #[test]
+    fn test_hatch_args() {
+        let input = r#"
+        egg hatch(args) {
+            len(args) == 2 ? len(get(args, 1)) : get(args, 2)
+        }
+        "#;
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+        assert!(typecheck::check(&program).is_empty());
+
+        let compiled = bytecode::compile(&program);
+        let hatch = program.hatch().unwrap();
+        let span = &program.functions[hatch].definition.span;
+        let run = |args: &[&str]| {
+            let list = args.iter().map(|arg| arg.to_string());
+            let interpreted = interpreter::Interpreter::new(&program)
+                .call(
+                    hatch,
+                    vec![interpreter::Value::List(
+                        list.clone().map(interpreter::Value::String).collect(),
+                    )],
+                    span,
+                )
+                .map(|value| value.to_string());
+            let run = vm::Vm::new(&compiled)
+                .call(
+                    hatch,
+                    vec![vm::Value::List(list.map(vm::Value::String).collect())],
+                    span,
+                )
+                .map(|value| value.to_string());
+            assert_eq!(interpreted, run);
+            run.map_err(|failure| failure.message)
+        };
+        assert_eq!(run(&["nest", "cluck"]), Ok("5".to_string()));
+        assert_eq!(
+            run(&["nest"]),
+            Err("`get([\"nest\"], 2)` is out of bounds, the list has 1 element".to_string())
+        );
+    }
+
+    #[test]
+    fn test_interpreter_limits() {
+        let input = r#"
+        egg fib(n) {
+            n == 0 ? 0 : n == 1 ? 1 : fib(n - 1) + fib(n - 2)
+        }
+        [
+            18 = 2584,
+        ]
+        egg grow(song, times = 20) {
+            times == 0 ? len(song) : grow(song + song, times - 1)
+        }
+        [
+            "cluck" = 5242880,
+        ]
+        egg deep(eggs) {
+            eggs == 0 ? 0 : deep(eggs - 1)
+        }
+        [
+            10 = 0,
+            50 = 0,
+        ]
+        "#;
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+
+        let limits = interpreter::Limits {
+            steps: Some(10_000),
+            depth: 20,
+            memory: Some(1024),
+        };
+        let results = test_runner::run(&program, limits, None)
+            .into_iter()
+            .flat_map(|function| function.tests)
+            .map(|test| match test.outcome {
+                test_runner::Outcome::Error(failure) => {
+                    Err((&input[failure.span], failure.message))
+                }
+                outcome => Ok(outcome),
+            })
+            .collect::<Vec<_>>();
+        let body = |name| {
+            let (_, func) = program
+                .functions
+                .iter()
+                .find(|(_, func)| func.definition.name == name)
+                .unwrap();
+            &input[func.body.span()]
+        };
+        assert_eq!(
+            results,
+            [
+                Err((
+                    body("fib"),
+                    "the program took more than 10000 steps".to_string()
+                )),
+                Err((
+                    "song + song",
+                    "the program built a value of 1280 bytes, more than the 1024 it may"
+                        .to_string()
+                )),
+                Ok(test_runner::Outcome::Passed),
+                Err((
+                    body("deep"),
+                    "the program went more than 20 calls deep".to_string()
+                )),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_debugger() {
+        let input = r#"
+        egg count(eggs) {
+            let mut total = 0;
+            if eggs == 0 {
+                *)> total;
+            };
+            total += eggs + count(eggs - 1);
+            total
+        }
+        egg hatch() {
+            let eggs = 2;
+            try {
+                count(eggs)
+            } rescue error {
+                0
+            }
+        }
+        "#;
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+        let hatch = program.hatch().unwrap();
+        let span = &program.functions[hatch].definition.span;
+        let debug = |commands: &str| {
+            let mut output = Vec::new();
+            let debugger =
+                debugger::Debugger::new(&program, input, commands.as_bytes(), &mut output);
+            let mut interpreter =
+                interpreter::Interpreter::new(&program).with_observer(Box::new(debugger));
+            let result = interpreter
+                .call(hatch, vec![], span)
+                .map(|value| value.to_string())
+                .map_err(|failure| failure.message);
+            let stopped = interpreter.stopped();
+            drop(interpreter);
+            (result, stopped, String::from_utf8(output).unwrap())
+        };
+
+        let (result, stopped, output) =
+            debug("b count\nc\nn\nlocals\nb 8\nd count\nc\np total\nc\nc\n");
+        assert_eq!(result, Ok("3".to_string()));
+        assert!(!stopped);
+        assert_eq!(
+            output.split("(wryneck) ").collect::<Vec<_>>(),
+            [
+                "called `hatch`, line 10: egg hatch() {\n",
+                "",
+                "called `count`, line 2: egg count(eggs) {\n",
+                "at `count`, line 3: let mut total = 0;\n",
+                "eggs = 2\n",
+                "",
+                "",
+                "at `count`, line 8: total\n",
+                "total = 1\n",
+                "at `count`, line 8: total\n",
+                "",
+            ]
+        );
+
+        // the debugger stops the program, a `try` doesn't rescue it
+        let (result, stopped, output) = debug("s\ns\ns\nq\n");
+        assert_eq!(result, Err("the program was stopped".to_string()));
+        assert!(stopped);
+        assert!(output.ends_with("at `hatch`, line 13: count(eggs)\n(wryneck) "));
+    }
+
+    #[test]
+    fn test_runtime_values() {
+        let values: Vec<interpreter::Value> = vec![
+            runtime::Value::Some(Box::new(runtime::Value::String("cluck".to_string()))),
+            runtime::Value::Enum {
+                name: "Nest",
+                variant: "Full",
+                fields: vec![
+                    ("eggs", runtime::Value::Int(2)),
+                    ("warm", runtime::Value::Bool(true)),
+                ],
+            },
+            runtime::Value::Range {
+                start: 0,
+                end: 21,
+                inclusive: true,
+            },
+            runtime::Value::List(vec![runtime::Value::Unit, runtime::Value::None]),
+        ];
+        let printed = values
+            .iter()
+            .map(|value| value.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            printed,
+            [
+                "some(\"cluck\")",
+                "Nest::Full(2, true)",
+                "0..=21",
+                "[(), none]",
+            ]
+        );
+
+        // values go to intrinsics and back unchanged, unless intrinsics can't take them
+        let list = runtime::Value::List(vec![
+            runtime::Value::String("hen".to_string()),
+            runtime::Value::Int(3),
+        ]);
+        let operand = list.operand().unwrap();
+        assert_eq!(
+            vm::Value::from(operand.clone()).to_string(),
+            list.to_string()
+        );
+        assert_eq!(interpreter::Value::from(operand), list);
+        assert_eq!(values[0].operand(), None);
+    }
+
+    #[test]
+    fn test_test_report() {
+        let input = r#"
+        egg double(eggs) {
+            eggs * 2
+        }
+        [
+            1 = 2,
+            2 = 5,
+            1 / 0 = 1,
+        ]
+        egg nest() {
+            "<nest>"
+        }
+        [
+            () = "<nest>",
+        ]
+        "#;
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+        let results = test_runner::run(&program, test_runner::LIMITS, None);
+
+        let json: serde_json::Value =
+            serde_json::from_str(&test_report::json(&program, "coop.wy", input, &results)).unwrap();
+        assert_eq!(json["file"], "coop.wy");
+        assert_eq!((&json["passed"], &json["failed"]), (&2.into(), &2.into()));
+        let tests = json["tests"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|test| {
+                assert!(test["duration"].as_f64().unwrap() >= 0.0);
+                (
+                    test["function"].as_str().unwrap(),
+                    test["index"].as_u64().unwrap(),
+                    test["line"].as_u64().unwrap(),
+                    test["outcome"].as_str().unwrap(),
+                    test["expected"].as_str(),
+                    test["actual"].as_str(),
+                    test["error"].as_str(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tests,
+            [
+                ("double", 0, 6, "passed", None, None, None),
+                ("double", 1, 7, "failed", Some("5"), Some("4"), None),
+                (
+                    "double",
+                    2,
+                    8,
+                    "error",
+                    None,
+                    None,
+                    Some("`1 / 0` divides by zero")
+                ),
+                ("nest", 0, 14, "passed", None, None, None),
+            ]
+        );
+
+        let junit = test_report::junit(&program, "coop.wy", input, &results);
+        assert!(junit.starts_with("<?xml"));
+        assert!(junit.contains(r#"<testsuites name="coop.wy" tests="4" failures="1" errors="1""#));
+        assert!(junit.contains(
+            r#"<testsuite name="double" file="coop.wy" tests="3" failures="1" errors="1""#
+        ));
+        assert!(junit.contains(r#"<failure message="expected 5, got 4"/>"#));
+        assert!(junit.contains(r#"<testcase name="nest[0]: () = &quot;&lt;nest&gt;&quot;" classname="nest" file="coop.wy" line="14""#));
+
+        let mut junit = test_report::Junit::new("wryneck");
+        junit.add(&program, "coop.wy", input, &results);
+        junit.add(&program, "barn.wy", input, &results);
+        let junit = junit.to_xml();
+        assert!(junit.contains(r#"<testsuites name="wryneck" tests="8" failures="2" errors="2""#));
+        assert!(junit.contains(r#"<testsuite name="double" file="barn.wy""#));
+        assert_eq!(junit.matches("</testsuites>").count(), 1);
+    }
+
+    #[test]
+    fn test_test_names() {
+        let input = r#"egg count(eggs) {
+    eggs
+}
+[
+    "small flock": count(2) = 2,
+    "empty nest": count(0) = 0,
+    5 = 5,
+]
+
+egg nest() {
+    "nest"
+}
+[
+    () = "nest",
+]
+"#;
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        assert!(formatter::format(&program).contains("    \"small flock\": count(2) = 2,\n"));
+        let program: resolved_ast::Program = program.into();
+        let (_, count) = program.functions.iter().next().unwrap();
+        let names = count
+            .tests
+            .iter()
+            .map(|test| test.name.as_ref().map(|name| name.value))
+            .collect::<Vec<_>>();
+        assert_eq!(names, [Some("small flock"), Some("empty nest"), None]);
+
+        let run = |filter| {
+            test_runner::run(&program, test_runner::LIMITS, filter)
+                .into_iter()
+                .map(|function| {
+                    let name = program.functions[function.function].definition.name;
+                    let tests = function.tests.iter().map(|test| test.index).collect();
+                    (name, tests)
+                })
+                .collect::<Vec<(_, Vec<_>)>>()
+        };
+        assert_eq!(run(None), [("count", vec![0, 1, 2]), ("nest", vec![0])]);
+        // the filter matches the names of tests and of functions
+        assert_eq!(run(Some("nest")), [("count", vec![1]), ("nest", vec![0])]);
+        assert_eq!(run(Some("flock")), [("count", vec![0])]);
+        assert_eq!(run(Some("hen")), []);
+    }
+
+    #[test]
+    fn test_expected_failures() {
+        let input = r#"
+        egg count(eggs) {
+            assert eggs != 0 - 1;
+            eggs == 0 ? 0 : eggs + count(eggs - 1)
+        }
+        [
+            "negative": 0 - 1 = error,
+            count(0 - 1) = error,
+            "valid": 2 = error,
+            "not a number": none = error,
+            "too deep": 0 - 2 = error,
+            "unexpected": 0 - 1 = 0,
+        ]
+        "#;
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+
+        let outcomes = test_runner::run(&program, test_runner::LIMITS, None)
+            .into_iter()
+            .flat_map(|function| function.tests)
+            .map(|test| match test.outcome {
+                test_runner::Outcome::Error(failure) => Err(failure.message),
+                outcome => Ok(outcome),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            outcomes,
+            [
+                Ok(test_runner::Outcome::Passed),
+                Ok(test_runner::Outcome::Passed),
+                Ok(test_runner::Outcome::Failed {
+                    expected: "error".to_string(),
+                    got: "3".to_string()
+                }),
+                Ok(test_runner::Outcome::Passed),
+                // going past the limits isn't the error the test expects
+                Err("the program went more than 1000 calls deep".to_string()),
+                Err("assertion `eggs != 0 - 1` failed".to_string()),
+            ]
+        );
+
+        // arithmetic that can't be done is what a test expecting an error may be about
+        let input = r#"
+        egg halve(eggs) { *)> eggs / 2; }
+        [
+            "broken egg": 1 / 0 = error,
+            "unexpected": 2 / 0 = 1,
+        ]
+        "#;
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let mut program: resolved_ast::Program = program.into();
+        let spans = |diagnostics: Vec<Diagnostic>| {
+            diagnostics
+                .into_iter()
+                .map(|diagnostic| &input[diagnostic.span])
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(spans(fold::check(&program)), ["2 / 0"]);
+        assert_eq!(spans(fold::fold(&mut program)), ["2 / 0"]);
+    }
+
+    #[test]
+    fn test_property_tests() {
+        let input = r#"
+        egg double(eggs) {
+            eggs * 2 + grown(eggs)
+        }
+        [
+            "small": forall eggs in 0..7: eggs = eggs * 2,
+            "large": forall eggs in 0..500: eggs = eggs * 2,
+            "pairs": forall eggs in 0..3, chicks in 1..=3: eggs + chicks = (eggs + chicks) * 2,
+            "empty": forall eggs in 3..3: eggs = 0,
+            "no range": forall eggs in 3: eggs = 0,
+        ]
+
+        egg grown(eggs) {
+            eggs == 7 ? 1 : eggs == 0 ? 0 : grown(eggs - 1)
+        }
+        "#;
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+        assert!(formatter::format(&program)
+            .contains("\"pairs\": forall eggs in 0..3, chicks in 1..=3: "));
+
+        let outcomes = test_runner::run(&program, test_runner::LIMITS, None)
+            .into_iter()
+            .flat_map(|function| function.tests)
+            .map(|test| {
+                let outcome = match test.outcome {
+                    test_runner::Outcome::Error(failure) => Err(failure.message),
+                    outcome => Ok(outcome),
+                };
+                (outcome, test.case)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            outcomes,
+            [
+                (Ok(test_runner::Outcome::Passed), None),
+                // the smallest case that fails
+                (
+                    Ok(test_runner::Outcome::Failed {
+                        expected: "14".to_string(),
+                        got: "15".to_string()
+                    }),
+                    Some("eggs = 7".to_string())
+                ),
+                (Ok(test_runner::Outcome::Passed), None),
+                (
+                    Err("`eggs` has no values, its range is empty".to_string()),
+                    None
+                ),
+                (
+                    Err("`eggs` takes its values from a range of ints, not from 3".to_string()),
+                    None
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_test_coverage() {
+        use test_coverage::Line::{Executed, Missed, Other};
+
+        let input = r#"egg count(eggs) {
+    if eggs == 0 {
+        *)> 0;
+    };
+    count(eggs - 1) + 1
+}
+[0 = 0]
+egg unused(eggs) {
+    eggs
+}
+"#;
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+
+        let (results, executed) =
+            test_runner::run_with_coverage(&program, test_runner::LIMITS, None);
+        assert_eq!(results[0].passed(), 1);
+        let coverage = test_coverage::coverage(&program, &executed);
+        assert_eq!(
+            test_coverage::summary(&program, &coverage),
+            "count: 2 of 3 statements\n\
+             unused: never called\n\
+             2 of 4 statements (50%), 1 of 2 functions called\n"
+        );
+        let lines = test_coverage::lines(&program, input, &coverage)
+            .into_iter()
+            .map(|(line, _)| line)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            [Executed, Executed, Executed, Other, Missed, Other, Other, Missed, Missed, Other]
+        );
+    }
+
+    #[test]
+    fn test_benchmarks() {
+        let input = r#"
+        egg count(eggs) {
+            eggs == 0 ? 0 : 1 + count(eggs - 1)
+        }
+        [3 = 3]
+        <[10, none]>
+        egg hatch() {
+            count(1)
+        }
+        <[()]>
+        "#;
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+        assert!(formatter::format(&program).contains("]<[\n    10,\n    none,\n]>"));
+
+        for backend in [runtime::Backend::Interpreter, runtime::Backend::Vm] {
+            let results = bench::run(&program, backend, 3)
+                .into_iter()
+                .map(|function| {
+                    let timings = function
+                        .benchmarks
+                        .into_iter()
+                        .map(|benchmark| {
+                            benchmark
+                                .timings
+                                .map(|timings| timings.runs)
+                                .map_err(|failure| failure.message)
+                        })
+                        .collect::<Vec<_>>();
+                    (
+                        program.functions[function.function].definition.name,
+                        timings,
+                    )
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(
+                results,
+                [
+                    (
+                        "count",
+                        vec![Ok(3), Err("cannot use `-` with none and 1".to_string())]
+                    ),
+                    ("hatch", vec![Ok(3)]),
+                ],
+                "{:?}",
+                backend
+            );
+        }
+    }
+
+    #[test]
+    fn test_format_snapshots() {
+        let dir = std::env::temp_dir().join("wryneck_test_format_snapshots");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("nest")).unwrap();
+        std::fs::write(dir.join("lay.wy"), "egg lay() { 1 }").unwrap();
+        std::fs::write(dir.join("nest/hatch.wy"), "egg hatch() { 2 }").unwrap();
+        std::fs::write(dir.join("nest/broken.wy"), "egg (").unwrap();
+        let config = formatter::FormatConfig::default();
+        let outcomes = |update| {
+            snapshot::check(&dir, config, update)
+                .unwrap()
+                .into_iter()
+                .map(|snapshot| {
+                    let path = snapshot.path.strip_prefix(&dir).unwrap().to_path_buf();
+                    (path.display().to_string(), snapshot.outcome)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            outcomes(false),
+            [
+                ("lay.wy".to_string(), snapshot::Outcome::Missing),
+                ("nest/broken.wy".to_string(), snapshot::Outcome::Unparsable),
+                ("nest/hatch.wy".to_string(), snapshot::Outcome::Missing),
+            ]
+        );
+        assert_eq!(
+            outcomes(true),
+            [
+                ("lay.wy".to_string(), snapshot::Outcome::Created),
+                ("nest/broken.wy".to_string(), snapshot::Outcome::Unparsable),
+                ("nest/hatch.wy".to_string(), snapshot::Outcome::Created),
+            ]
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.join("lay.expected")).unwrap(),
+            "🥚 lay() {\n    1\n}\n\n"
+        );
+
+        std::fs::write(dir.join("lay.wy"), "egg lay() { 3 }").unwrap();
+        assert_eq!(
+            outcomes(false)[0].1,
+            snapshot::Outcome::Differs {
+                expected: "🥚 lay() {\n    1\n}\n\n".to_string(),
+                formatted: "🥚 lay() {\n    3\n}\n\n".to_string(),
+            }
+        );
+        assert_eq!(outcomes(true)[0].1, snapshot::Outcome::Updated);
+        assert_eq!(outcomes(false)[0].1, snapshot::Outcome::Matches);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_format_config() {
+        let config = formatter::FormatConfig::default()
+            .with_toml(
+                r#"
+                [format]
+                indent_style = "tabs"
+                max_blank_lines = 0
+                trailing_commas = "never"
+
+                [lints]
+                shadowing = "deny"
+                "#,
+            )
+            .unwrap();
+        assert_eq!(
+            config,
+            formatter::FormatConfig {
+                trailing_commas: formatter::TrailingCommas::Never,
+                indent_style: formatter::IndentStyle::Tabs,
+                max_blank_lines: 0,
+                ..Default::default()
+            }
+        );
+        let (program, errors) = parse("egg lay() { 1 } [1 = 1] enum Nest { Empty }").unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+        assert_eq!(
+            formatter::format_with(&program, config),
+            "🥚 lay() {\n\t1\n}[\n\t1 = 1\n]\nenum Nest {\n\tEmpty\n}\n"
+        );
+
+        let config = formatter::FormatConfig::default()
+            .with_toml("[format]\nindent_width = 2")
+            .unwrap();
+        assert_eq!(
+            formatter::format_with(&program, config),
+            "🥚 lay() {\n  1\n}[\n  1 = 1,\n]\n\nenum Nest {\n  Empty,\n}\n\n"
+        );
+        let config = formatter::FormatConfig::default()
+            .with_toml("[format]\nindent = { style = \"space\", width = 2 }")
+            .unwrap();
+        assert_eq!(
+            formatter::format_with(&program, config),
+            "🥚 lay() {\n  1\n}[\n  1 = 1,\n]\n\nenum Nest {\n  Empty,\n}\n\n"
+        );
+        assert_eq!(
+            formatter::FormatConfig::default().with_toml("[format]\nindent.style = \"tab\""),
+            Ok(formatter::FormatConfig {
+                indent_style: formatter::IndentStyle::Tabs,
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            formatter::FormatConfig::default().with_toml("[format]\nindent_style = \"tabstops\""),
+            Err("unknown indent style `tabstops`, expected spaces or tabs".to_string())
+        );
+        assert_eq!(
+            formatter::FormatConfig::default()
+                .with_toml("[format]\nindent_width = 2\nindent = { width = 4 }"),
+            Err("`indent.width` and `indent_width` are both set".to_string())
+        );
+    }
+
+    #[test]
+    fn test_max_blank_lines() {
+        let input = "import \"birds.wy\";\n\n\n\n// the nest\n\n\negg lay() { 1 }\n\n\n\n\
+            egg sit() { 2 }\negg hatch() { 3 } // done\n";
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+        let format = |max_blank_lines| {
+            let config = formatter::FormatConfig {
+                max_blank_lines,
+                ..Default::default()
+            };
+            formatter::format_source(&program, input, config)
+        };
+        // up to that many of the blank lines of the source are kept, and never fewer than one
+        // between functions unless it is 0
+        assert_eq!(
+            format(3),
+            "import \"birds.wy\";\n\n\n\n// the nest\n\n\n🥚 lay() {\n    1\n}\n\n\n\n\
+             🥚 sit() {\n    2\n}\n\n🥚 🐣() {\n    3\n} // done\n\n"
+        );
+        assert_eq!(
+            format(2),
+            "import \"birds.wy\";\n\n\n// the nest\n\n\n🥚 lay() {\n    1\n}\n\n\n\
+             🥚 sit() {\n    2\n}\n\n🥚 🐣() {\n    3\n} // done\n\n"
+        );
+        assert_eq!(
+            format(1),
+            "import \"birds.wy\";\n\n// the nest\n\n🥚 lay() {\n    1\n}\n\n\
+             🥚 sit() {\n    2\n}\n\n🥚 🐣() {\n    3\n} // done\n\n"
+        );
+        assert_eq!(
+            format(0),
+            "import \"birds.wy\";\n// the nest\n🥚 lay() {\n    1\n}\n\
+             🥚 sit() {\n    2\n}\n🥚 🐣() {\n    3\n} // done\n"
+        );
+        // formatting again keeps them
+        let formatted = format(3);
+        let (again, _) = parse(&formatted).unwrap();
+        let again: resolved_ast::Program = again.into();
+        let config = formatter::FormatConfig {
+            max_blank_lines: 3,
+            ..Default::default()
+        };
+        assert_eq!(
+            formatter::format_source(&again, &formatted, config),
+            formatted
+        );
+    }
+
+    #[test]
+    fn test_format_width() {
+        let (program, errors) =
+            parse("egg lay(eggs) { nest(add(eggs, 1000), add(eggs, 2000)) + eggs |> nest(eggs) }")
+                .unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+        let format = |max_width| {
+            let config = formatter::FormatConfig {
+                max_width,
+                ..Default::default()
+            };
+            formatter::format_with(&program, config)
+        };
+        assert_eq!(
+            format(100),
+            "🥚 lay(eggs) {\n    (nest(add(eggs, 1000), add(eggs, 2000)) + eggs) |> nest(eggs)\n}\n\n"
+        );
+        assert_eq!(
+            format(60),
+            "🥚 lay(eggs) {\n    (nest(add(eggs, 1000), add(eggs, 2000)) + eggs)\n        |> nest(eggs)\n}\n\n"
+        );
+        assert_eq!(
+            format(40),
+            "🥚 lay(eggs) {\n    (nest(\n        add(eggs, 1000),\n        add(eggs, 2000),\n    ) +\n        eggs)\n        |> nest(eggs)\n}\n\n"
+        );
+        // what fits doesn't change
+        let config = formatter::FormatConfig {
+            max_width: 20,
+            ..Default::default()
+        };
+        let formatted = formatter::format_with(&program, config);
+        let (reparsed, errors) = parse(&formatted).unwrap();
+        assert!(errors.is_empty());
+        assert_eq!(
+            formatter::format_with(&resolved_ast::Program::from(reparsed), config),
+            formatted
+        );
+    }
+
+    #[test]
+    fn test_format_parens() {
+        let input = "egg lay(a, b, c) { let x = (a - (b - c)) * (a + b) + a * b - (c); (a + b).len() + (1..3 |> len) == (a == b) }";
+        let format = |input: &str, parens| {
+            let (program, errors) = parse(input).unwrap();
+            assert!(errors.is_empty());
+            let config = formatter::FormatConfig {
+                parens,
+                ..Default::default()
+            };
+            formatter::format_with(&resolved_ast::Program::from(program), config)
+        };
+        let full = format(input, formatter::Parens::Full);
+        assert_eq!(
+            full,
+            "🥚 lay(a, b, c) {\n    let x = ((((a - (b - c)) * (a + b)) + (a * b)) - c);\n    (((a + b).len() + (1..3 |> len)) == (a == b))\n}\n\n"
+        );
+        let minimal = format(input, formatter::Parens::Minimal);
+        assert_eq!(
+            minimal,
+            "🥚 lay(a, b, c) {\n    let x = (a - (b - c)) * (a + b) + a * b - c;\n    (a + b).len() + (1..3 |> len) == (a == b)\n}\n\n"
+        );
+        assert_eq!(
+            format(input, formatter::Parens::Preserve),
+            "🥚 lay(a, b, c) {\n    let x = (a - (b - c)) * (a + b) + a * b - (c);\n    (a + b).len() + (1..3 |> len) == (a == b)\n}\n\n"
+        );
+        // leaving out parentheses doesn't change what the program means
+        assert_eq!(format(&minimal, formatter::Parens::Full), full);
+        assert_eq!(
+            formatter::FormatConfig::default()
+                .with_toml("[format]\nparens = \"minimal\"")
+                .map(|config| config.parens),
+            Ok(formatter::Parens::Minimal)
+        );
+    }
+
+    #[test]
+    fn test_trailing_comments() {
+        let input = "egg lay(eggs) { // counts\n    // the first\n    let chicks = eggs;   // all of them\n    chicks\n} // end\n";
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+        assert_eq!(
+            formatter::format(&program),
+            "🥚 lay(eggs) { // counts\n    // the first\n    let chicks = eggs; // all of them\n    chicks\n} // end\n\n"
+        );
+        let config = formatter::FormatConfig::default()
+            .with_toml("[format]\ncomment_column = 24")
+            .unwrap();
+        assert_eq!(
+            formatter::format_with(&program, config),
+            "🥚 lay(eggs) {           // counts\n    // the first\n    let chicks = eggs;  // all of them\n    chicks\n}                       // end\n\n"
+        );
+    }
+
+    #[test]
+    fn test_format_diff() {
+        let old = "🥚 lay(a) {\n    a+1\n}\n\n🥚 two() {\n    2\n}\n\n🥚 x() {\n    let y = 1;\n    let z = 2;\n    let w = 3;\n    let v = 4;\n    y+z\n}\n";
+        let (program, errors) = parse(old).unwrap();
+        assert!(errors.is_empty());
+        let new = formatter::format(&resolved_ast::Program::from(program));
+        assert_eq!(
+            diff::unified(old, &new, "old.wy", "new.wy"),
+            "--- old.wy\n+++ new.wy\n@@ -1,5 +1,5 @@\n 🥚 lay(a) {\n-    a+1\n+    (a + 1)\n }\n \n 🥚 two() {\n@@ -11,5 +11,6 @@\n     let z = 2;\n     let w = 3;\n     let v = 4;\n-    y+z\n+    (y + z)\n }\n+\n"
+        );
+        assert_eq!(diff::unified(&new, &new, "old.wy", "new.wy"), "");
+        assert_eq!(
+            diff::unified("", "egg\n", "old.wy", "new.wy"),
+            "--- old.wy\n+++ new.wy\n@@ -0,0 +1,1 @@\n+egg\n"
+        );
+    }
+
+    #[test]
+    fn test_find_sources() {
+        let dir = std::env::temp_dir().join("wryneck_test_find_sources");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("nest")).unwrap();
+        std::fs::create_dir_all(dir.join("vendor")).unwrap();
+        for file in [
+            "lay.wy",
+            "lay.txt",
+            "nest/hatch.wy",
+            "nest/hatch_old.wy",
+            "vendor/egg.wy",
+        ] {
+            std::fs::write(dir.join(file), "").unwrap();
+        }
+        let sources = |paths: Vec<PathBuf>, exclude: &[&str]| {
+            let exclude = exclude.iter().map(|pattern| pattern.to_string());
+            find_sources(&paths, &exclude.collect::<Vec<_>>())
+                .unwrap()
+                .into_iter()
+                .map(|path| path.strip_prefix(&dir).unwrap().display().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            sources(vec![dir.clone()], &[]),
+            [
+                "lay.wy",
+                "nest/hatch.wy",
+                "nest/hatch_old.wy",
+                "vendor/egg.wy"
+            ]
+        );
+        assert_eq!(
+            sources(vec![dir.clone()], &["vend?r", "*_old.wy"]),
+            ["lay.wy", "nest/hatch.wy"]
+        );
+        // files that are given are formatted whatever they are called
+        assert_eq!(
+            sources(vec![dir.join("lay.txt"), dir.join("nest")], &["hatch*"]),
+            ["lay.txt"]
+        );
+        assert!(matches_pattern("*", ""));
+        assert!(matches_pattern("*.wy", "🥚.wy"));
+        assert!(!matches_pattern("?", ""));
+
+        // patterns the shell left alone, where `**` is any number of directories
+        std::fs::create_dir_all(dir.join(".git")).unwrap();
+        std::fs::write(dir.join(".git/hook.wy"), "").unwrap();
+        assert_eq!(
+            sources(vec![dir.join("**/*.wy")], &["*_old.wy"]),
+            ["lay.wy", "nest/hatch.wy", "vendor/egg.wy"]
+        );
+        assert_eq!(
+            sources(vec![dir.join("ne?t/*"), dir.join("*.txt")], &[]),
+            ["nest/hatch.wy", "nest/hatch_old.wy", "lay.txt"]
+        );
+        assert_eq!(
+            sources(vec![dir.join("*")], &["vendor", "*.txt"]),
+            ["lay.wy", "nest/hatch.wy", "nest/hatch_old.wy"]
+        );
+        // a pattern that matches nothing is kept, so reading it says what is wrong
+        assert_eq!(sources(vec![dir.join("*.md")], &[]), ["*.md"]);
+    }
+
+    #[test]
+    fn test_format_off() {
+        let input = "// wryneck-fmt: off\negg   table() {\n    let a  =  1;  // as it is\n    a+2\n}\n// wryneck-fmt: on\negg lay(a) {\n    let b = a+1;\n    // wryneck-fmt: off\n    let c  =  b*2;\n\n    // wryneck-fmt: on\n    c+1\n}\n\negg rest(a) {\n    // wryneck-fmt: off\n    let b  =  a;\n    b  +  1 }\n";
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program = resolved_ast::Program::from(program);
+        let config = formatter::FormatConfig::default();
+        assert_eq!(
+            formatter::format_source(&program, input, config),
+            "// wryneck-fmt: off\negg   table() {\n    let a  =  1;  // as it is\n    a+2\n}\n// wryneck-fmt: on\n🥚 lay(a) {\n    let b = (a + 1);\n    // wryneck-fmt: off\n    let c  =  b*2;\n\n    // wryneck-fmt: on\n    (c + 1)\n}\n\n🥚 rest(a) {\n    // wryneck-fmt: off\n    let b  =  a;\n    b  +  1\n}\n\n"
+        );
+        // without the source, the comments are only comments
+        assert!(formatter::format(&program).contains("    let c = (b * 2);\n"));
+    }
+
+    #[test]
+    fn test_format_color() {
+        let (program, _) = parse("egg hatch() { *)> 1 + 99999999999999999999; }").unwrap();
+        let program = resolved_ast::Program::from(program);
+        // even where colored would color, the formatter only does if it is asked to
+        colored::control::set_override(true);
+        let plain = formatter::format(&program);
+        let config = formatter::FormatConfig {
+            color: true,
+            ..Default::default()
+        };
+        let colored = formatter::format_with(&program, config);
+        colored::control::unset_override();
+        assert!(plain.contains("(1 + error)") && !plain.contains('\x1b'));
+        assert!(colored.contains("(1 + \x1b[31merror\x1b[0m)"));
+    }
+
+    #[test]
+    fn test_format_to() {
+        /// Keeps every write apart.
+        struct Writes(Vec<String>);
+        impl std::io::Write for Writes {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.push(String::from_utf8(buf.to_vec()).unwrap());
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+        let input = "egg lay(a) { a+1 }\negg hatch() { lay(2) } // twice\n// done\n";
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program = resolved_ast::Program::from(program);
+        let mut writes = Writes(Vec::new());
+        let config = formatter::FormatConfig::default();
+        formatter::format_to(&program, &mut writes, &config).unwrap();
+        assert_eq!(writes.0.concat(), formatter::format(&program));
+        // the last line of each item waits for a comment that may follow it
+        assert_eq!(
+            writes.0,
+            [
+                "🥚 lay(a) {\n    (a + 1)\n",
+                "}\n\n🥚 🐣() {\n    lay(2)\n",
+                "} // twice\n\n",
+                "// done\n",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_format_verify() {
+        let input = "egg a(x) {\n    if x { 1 } else if x { 3 } else { 2 }\n}\n";
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let config = formatter::FormatConfig::default();
+        let formatted =
+            formatter::format_source(&resolved_ast::Program::from(program), input, config);
+        // an `if` after `else` can't be `x ? 3 : 2`
+        assert!(formatted.contains("} else if x {\n"));
+        assert!(verify_format(&formatted, "a.wy", config));
+        assert!(!verify_format(
+            "egg a(x) {\n    1\n} else x ? 3 : 2\n",
+            "a.wy",
+            config
+        ));
+        // formatted again, the operation gets its parentheses
+        assert!(!verify_format("egg a(x) {\n    x + 1\n}\n", "a.wy", config));
+    }
+
+    #[test]
+    fn test_format_align_tests() {
+        let input = "egg count(eggs) {\n    eggs\n}\n[\n    \"small flock\": count(2) = 2,\n    5 = 5,\n    forall x in 1..3: x = x,\n]\n";
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program = resolved_ast::Program::from(program);
+        let config = formatter::FormatConfig::default()
+            .with_toml("[format]\nalign_tests = true\nmax_width = 20")
+            .unwrap();
+        // the calls would be broken up to fit in 20 columns if the tests weren't a table
+        assert_eq!(
+            formatter::format_with(&program, config),
+            "🥚 count(eggs) {\n    eggs\n}[\n    \"small flock\": count(2) = 2,\n    5                       = 5,\n    forall x in 1..3: x     = x,\n]\n\n"
+        );
+    }
+
+    #[test]
+    fn test_format_newlines() {
+        let input = "egg lay(a) { // eggs\r\n    a\r\n}\r\n";
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program = resolved_ast::Program::from(program);
+        let config = formatter::FormatConfig::default();
+        assert_eq!(
+            formatter::format_with(&program, config),
+            "🥚 lay(a) { // eggs\n    a\n}\n\n"
+        );
+        let config = config
+            .with_toml("[format]\nnewline = \"crlf\"\nfinal_newline = false")
+            .unwrap();
+        assert_eq!(
+            formatter::format_with(&program, config),
+            "🥚 lay(a) { // eggs\r\n    a\r\n}"
+        );
+        assert!(config.with_toml("[format]\nnewline = \"cr\"").is_err());
+    }
+
+    #[test]
+    fn test_format_sort_functions() {
+        let input = "// zeta\negg zeta() { 1 } // last\nenum Nest { Empty }\negg alpha() { 2 }\n[1 = 1]\negg hatch() { zeta() }\n// end\n";
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program = resolved_ast::Program::from(program);
+        let config = formatter::FormatConfig::default()
+            .with_toml("[format]\nsort_functions = true")
+            .unwrap();
+        assert_eq!(
+            formatter::format_with(&program, config),
+            "🥚 🐣() {\n    zeta()\n}\n\nenum Nest {\n    Empty,\n}\n\n🥚 alpha() {\n    2\n}[\n    1 = 1,\n]\n\n// zeta\n🥚 zeta() {\n    1\n} // last\n\n// end\n"
+        );
+        // the source that is kept as it is written can't be reordered
+        let input = "egg b() { 1 }\n// wryneck-fmt: off\negg a() { 2 }\n";
+        let (program, _) = parse(input).unwrap();
+        let program = resolved_ast::Program::from(program);
+        assert_eq!(
+            formatter::format_source(&program, input, config),
+            "🥚 b() {\n    1\n}\n\n// wryneck-fmt: off\negg a() { 2 }\n"
+        );
+    }
+
+    #[test]
+    fn test_format_wrap_comments() {
+        let input = "// Counts the eggs\n// in the nest, one by one, which takes a while.\n//\n// - first\n// - second\n// let eggs = 1;\n🥚 lay(a) {\n    // a short\n    // paragraph\n    a\n}\n";
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program = resolved_ast::Program::from(program);
+        let config = formatter::FormatConfig::default()
+            .with_toml("[format]\nwrap_comments = true\nmax_width = 30")
+            .unwrap();
+        assert_eq!(
+            formatter::format_with(&program, config),
+            "// Counts the eggs in the\n// nest, one by one, which\n// takes a while.\n// \n// - first\n// - second\n// let eggs = 1;\n🥚 lay(a) {\n    // a short paragraph\n    a\n}\n\n"
+        );
+        assert_eq!(
+            formatter::wrap_comments(&["an egg", "1. a", "2. b", "nest()"], 3),
+            ["an", "egg", "1.", "a", "2.", "b", "nest()"]
+        );
+    }
+
+    #[test]
+    fn test_line_index() {
+        let text = "egg lay() {\r\n    🥚 + 1\n}";
+        let lines = LineIndex::new(text);
+        assert_eq!(lines.position(0), (1, 1));
+        assert_eq!(lines.position(4), (1, 5));
+        assert_eq!(lines.position(13), (2, 1));
+        // the egg is one character of four bytes
+        assert_eq!(lines.position(17), (2, 5));
+        assert_eq!(lines.position(21), (2, 6));
+        assert_eq!(lines.position(19), (2, 5));
+        assert_eq!(lines.position(text.len()), (3, 2));
+        assert_eq!(lines.position(100), (3, 2));
+        assert_eq!(lines.line(1), "egg lay() {");
+        assert_eq!(lines.line(2), "    🥚 + 1");
+        assert_eq!(lines.line(3), "}");
+    }
+
+    #[test]
+    fn test_render_diagnostics() {
+        let text = "egg lay(eggs) {\n    let hens = 1;\n\n\n\n    eggs +\n\thens\n}";
+        let lines = LineIndex::new(text);
+        let hens = text.find("hens").unwrap();
+        let sum = text.rfind("eggs").unwrap()..text.rfind("hens").unwrap() + 4;
+        let diagnostic = Diagnostic::warning("eggs and hens", sum)
+            .with_note("declared here", hens..hens + 4)
+            .with_note("eggs", 8..12);
+        assert_eq!(
+            diagnostics::render("lay.wy", &lines, &diagnostic.labels(), false),
+            " --> lay.wy:6:5
+  |
+1 | egg lay(eggs) {
+  |         ---- eggs
+2 |     let hens = 1;
+  |         ---- declared here
+...
+6 |     eggs +
+  |     ^^^^^^
+7 |     hens
+  | ^^^^^^^^
+"
+        );
+        // at the end of the file
+        assert_eq!(
+            diagnostics::render(
+                "lay.wy",
+                &lines,
+                &[Label::primary(text.len()..text.len(), "here")],
+                false
+            ),
+            " --> lay.wy:8:2\n  |\n8 | }\n  |  ^ here\n"
+        );
+    }
+
+    #[test]
+    fn test_diagnostic_json() {
+        let text = "egg lay(eggs) {\n    eggs +\n}";
+        let lines = LineIndex::new(text);
+        let error = match parse(text) {
+            Ok((_, errors)) => errors[0].error.clone(),
+            Err(failure) => failure.error,
+        };
+        let diagnostic = Diagnostic::from_parse_error(&error, text);
+        assert_eq!(diagnostic.message, "unrecognized token `}`");
+        assert_eq!(diagnostic.span, text.len() - 1..text.len());
+        assert!(diagnostic.help[0].starts_with("expected one of "));
+        let rendered = diagnostic.render("lay.wy", &lines, false);
+        assert!(rendered.starts_with("error[E003]: unrecognized token `}`\n --> lay.wy:3:1\n"));
+        assert!(rendered.contains("\n  = help: expected one of "));
+
+        let eggs = text.find("eggs").unwrap();
+        let mut diagnostic = Diagnostic::warning("`eggs` is never read", eggs..eggs + 4)
+            .with_note("used here", 20..24)
+            .with_help("remove it");
+        diagnostic.code = Some("W010");
+        let json: serde_json::Value =
+            serde_json::from_str(&diagnostic.json("lay.wy", &lines)).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "file": "lay.wy",
+                "severity": "warning",
+                "code": "W010",
+                "message": "`eggs` is never read",
+                "spans": [
+                    {
+                        "start": 8,
+                        "end": 12,
+                        "line": 1,
+                        "column": 9,
+                        "end_line": 1,
+                        "end_column": 13,
+                        "label": "",
+                        "primary": true
+                    },
+                    {
+                        "start": 20,
+                        "end": 24,
+                        "line": 2,
+                        "column": 5,
+                        "end_line": 2,
+                        "end_column": 9,
+                        "label": "used here",
+                        "primary": false
+                    }
+                ],
+                "help": ["remove it"],
+                "fix": null
+            })
+        );
+        assert!(!diagnostic.json("lay.wy", &lines).contains('\n'));
+    }
+
+    #[test]
+    fn test_diagnostic_codes() {
+        let codes = diagnostics::EXPLANATIONS
+            .iter()
+            .map(|explanation| explanation.code)
+            .collect::<Vec<_>>();
+        let mut sorted = codes.clone();
+        sorted.sort_unstable();
+        sorted.dedup();
+        assert_eq!(codes, sorted);
+        assert_eq!(diagnostics::explain("e010").unwrap().code, "E010");
+        assert!(diagnostics::explain("E999").is_none());
+
+        let input = "
+        egg lay(eggs) {
+            let hens = 1;
+            eggs = chicks;
+            lay()
+        }
+        egg hatch() { lya(1) }
+        ";
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+        let mut found = assignments::check(&program)
+            .into_iter()
+            .chain(names::check(&program))
+            .chain(arity::check(&program))
+            .chain(lints::Registry::new().check(&program))
+            .map(|diagnostic| (diagnostic.code.unwrap(), &input[diagnostic.span]))
+            .collect::<Vec<_>>();
+        found.sort_unstable();
+        assert_eq!(
+            found,
+            [
+                ("E010", "chicks"),
+                ("E011", "lya"),
+                ("E012", "eggs = chicks"),
+                ("E020", "lay()"),
+                ("W010", "let hens = 1"),
+                ("W011", "egg lay(eggs)"),
+            ]
+        );
+        let error = parse("egg lay(eggs").unwrap_err().error;
+        assert_eq!(
+            Diagnostic::from_parse_error(&error, "egg lay(eggs").code,
+            Some("E002")
+        );
+    }
+
+    #[test]
+    fn test_parse_recovery() {
+        let input = "egg lay(eggs) {\n    let hens = eggs\n    let chicks = hens;\n    chicks\n}\n";
+        let (program, errors) = parse(input).unwrap();
+        let diagnostics = errors
+            .iter()
+            .map(|error| Diagnostic::from_parse_error(&error.error, input))
+            .collect::<Vec<_>>();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].message, "expected `;` after statement");
+        assert_eq!(diagnostics[0].code, Some("E008"));
+        assert_eq!(diagnostics[0].span, 35..35);
+        // the next statement still declares `chicks`
+        let program: resolved_ast::Program = program.into();
+        assert!(names::check(&program).is_empty());
+
+        let input =
+            "egg lay(eggs) {\n    if eggs == 0 {\n        \"{\" // {\n\negg hatch() { lay(2) }\n";
+        let error = parse(input).unwrap_err().error;
+        let diagnostic = Diagnostic::from_parse_error(&error, input);
+        assert_eq!(diagnostic.code, Some("E009"));
+        assert_eq!(
+            diagnostic.notes,
+            [("unclosed block started here".to_string(), 33..34)]
+        );
+
+        // the errors before the one the parser stops at are kept, and the `}` indented like the
+        // function closes it rather than the `if`
+        let input = "egg lay(eggs) {\n    let hens = eggs\n    let chicks = hens\n    chicks\n}\n\
+            egg brood(eggs) {\n    if eggs {\n        eggs\n}\n\negg hatch() { lay(2) }\n";
+        let failure = parse(input).unwrap_err();
+        let codes = failure
+            .recovered
+            .iter()
+            .map(|error| Diagnostic::from_parse_error(&error.error, input).code)
+            .collect::<Vec<_>>();
+        assert_eq!(codes, [Some("E008"), Some("E008"), Some("E008")]);
+        let diagnostic = Diagnostic::from_parse_error(&failure.error, input);
+        assert_eq!(diagnostic.code, Some("E009"));
+        let (_, brace) = &diagnostic.notes[0];
+        assert_eq!(&input[brace.end - 9..brace.end], "if eggs {");
+    }
+
+    #[test]
+    fn test_only() {
+        let input = "egg count_pigeons(n) { *)> twice(n) + 1; }\n\
+            egg twice(n) { egg add(a, b) { *)> a + b; } *)> add(n, n); }\n\
+            egg other() { *)> count_pigeons(1); }\n";
+        let (program, _) = parse(input).unwrap();
+        let program: resolved_ast::Program = program.into();
+        let name = |id: resolved_ast::FunctionId| program.functions[id].definition.name;
+        let count = program.top_level_function("count_pigeons").unwrap();
+        let other = program.top_level_function("other").unwrap();
+        let callees = program.callees(count);
+        assert_eq!(
+            callees.iter().map(|&id| name(id)).collect::<Vec<_>>(),
+            ["count_pigeons", "twice", "add"]
+        );
+        assert_eq!(program.callees(other).len(), 4);
+        assert_eq!(program.top_level_function("add"), None);
+
+        let compiled = bytecode::compile_only(&program, &callees);
+        let span = &program.functions[count].definition.span;
+        let result = vm::Vm::new(&compiled)
+            .call(count, vec![runtime::Value::Int(5)], span)
+            .map(|value| value.to_string());
+        assert_eq!(result, Ok("11".to_string()));
+        let left_out = vm::Vm::new(&compiled)
+            .call(other, vec![], span)
+            .unwrap_err();
+        assert_eq!(left_out.message, "`other` was left out of the program");
+        let left_out = interpreter::Interpreter::new(&program)
+            .with_only(&callees)
+            .call(other, vec![], span)
+            .unwrap_err();
+        assert_eq!(left_out.message, "`other` was left out of the program");
+        let args = vec!["--only", "twice", "--args", "4", "a.wy"];
+        let cli = Cli::from_iter_safe(["wryneck", "run"].into_iter().chain(args)).unwrap();
+        assert!(matches!(
+            cli.command,
+            Opt::Run { only_args, input, .. } if only_args == ["4"] && input.as_os_str() == "a.wy"
+        ));
+        // the arguments of `hatch` have no place in a run of a single function
+        assert!(Cli::from_iter_safe(["wryneck", "run", "a.wy", "--only", "twice", "4"]).is_err());
+
+        let args = Args::Json(vec!["[1, true]".into(), "hen".into(), "1.5".into()]);
+        let values: Vec<interpreter::Value> = args.values();
+        assert_eq!(
+            values
+                .iter()
+                .map(|value| value.to_string())
+                .collect::<Vec<_>>(),
+            ["[1, true]", "\"hen\"", "\"1.5\""]
+        );
+    }
+
+    #[test]
+    fn test_serve() {
+        let input = "egg count_pigeons(n, extra = 1) { *)> n * 2 + extra; }\n\
+            egg boom(x) { *)> 1 / x; }\n";
+        let (program, _) = parse(input).unwrap();
+        let program: resolved_ast::Program = program.into();
+        let settings = serve::Settings {
+            io: false,
+            limits: test_runner::LIMITS,
+        };
+        let request = |request: &str| {
+            let mut response = Vec::new();
+            serve::handle(&program, request.as_bytes(), &mut response, settings).unwrap();
+            String::from_utf8(response).unwrap()
+        };
+        let response = request("POST /count_pigeons HTTP/1.1\r\nContent-Length: 3\r\n\r\n[5]");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("\r\n\r\n11"));
+
+        let respond = |method: &str, path: &str, body: &str| {
+            let request = serve::Request {
+                method: method.to_string(),
+                path: path.to_string(),
+                body: body.as_bytes().to_vec(),
+            };
+            let response = serve::respond(&program, &request, settings);
+            (response.status, response.body.to_string())
+        };
+        assert_eq!(
+            respond("POST", "/count_pigeons", "[5, 0]"),
+            (200, "10".into())
+        );
+        assert_eq!(
+            respond("POST", "/boom", "[0]"),
+            (422, r#"{"error":"`1 / 0` divides by zero"}"#.into())
+        );
+        assert_eq!(respond("POST", "/nope", "[]").0, 404);
+        assert_eq!(respond("GET", "/boom", "").0, 405);
+        assert_eq!(respond("POST", "/boom", "{}").0, 400);
+        assert_eq!(respond("POST", "/boom", "[1.5]").0, 400);
+
+        // a client that never sends its request doesn't keep the others waiting
+        use std::io::{Read, Write};
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || serve::serve(&program, &listener, settings));
+        let _idle = std::net::TcpStream::connect(address).unwrap();
+        let mut client = std::net::TcpStream::connect(address).unwrap();
+        client
+            .write_all(b"POST /count_pigeons HTTP/1.1\r\nContent-Length: 3\r\n\r\n[5]")
+            .unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        assert!(response.ends_with("\r\n\r\n11"));
+    }
+
+    #[test]
+    fn test_value_json() {
+        let json = serde_json::json!([1, "egg", true, null, [2]]);
+        let value = interpreter::Value::from_json(&json).unwrap();
+        assert_eq!(value.to_string(), "[1, \"egg\", true, none, [2]]");
+        assert_eq!(value.to_json(), json);
+        let some = interpreter::Value::Some(Box::new(interpreter::Value::Unit));
+        assert_eq!(some.to_json(), serde_json::Value::Null);
+        assert!(interpreter::Value::from_json(&serde_json::json!(1.5)).is_err());
+        assert!(interpreter::Value::from_json(&serde_json::json!({})).is_err());
+    }
+
+    #[cfg(feature = "spellcheck")]
+    #[test]
+    fn test_spellcheck() {
+        use wryneck::spellcheck::{self, Dictionary};
+
+        let input = "// the good eggz\negg lay() {\n    *)> \"a «nestt»\";\n}\n\
+            egg cluck() { *)> r#\"chickn\"#; } // keep_going foo.wy\n";
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+        let mut dictionary = Dictionary::bundled();
+        let misspellings = |dictionary: &Dictionary| {
+            spellcheck::check(&program, dictionary)
+                .into_iter()
+                .map(|misspelling| (misspelling.word, &input[misspelling.span]))
+                .collect::<Vec<_>>()
+        };
+        // the spans point at the words in the input, whatever comes before them
+        assert_eq!(
+            misspellings(&dictionary),
+            [("eggz", "eggz"), ("nestt", "nestt"), ("chickn", "chickn")]
+        );
+        dictionary.extend_from_str("# birds\nnestt\n");
+        assert_eq!(
+            misspellings(&dictionary),
+            [("eggz", "eggz"), ("chickn", "chickn")]
+        );
+        assert!(dictionary.suggestions("eggz").contains(&"eggs".to_string()));
+    }
+
+    #[test]
+    fn test_fixes() {
+        use diagnostics::{apply_fixes, Edit, Fix};
+
+        let fix = |message: &str, edits| Fix {
+            message: message.to_string(),
+            edits,
+        };
+        let fixes = [
+            fix("hens", vec![Edit::replace(4..8, "hens")]),
+            // overlaps the first one
+            fix("chicks", vec![Edit::replace(6..10, "chicks")]),
+            fix("semicolon", vec![Edit::insert(12, ";")]),
+            // inserts where another fix does
+            fix("again", vec![Edit::insert(12, ";")]),
+        ];
+        assert_eq!(
+            apply_fixes("let eggs = 1", &fixes),
+            ("let hens = 1;".to_string(), 2)
+        );
+
+        let fixed = |input: &str| {
+            let error = match parse(input) {
+                Ok((_, errors)) => errors[0].error.clone(),
+                Err(failure) => failure.error,
+            };
+            let diagnostic = Diagnostic::from_parse_error(&error, input);
+            diagnostic.fix.map(|fix| apply_fixes(input, [&fix]).0)
+        };
+        assert_eq!(
+            fixed("egg lay() {\n    let eggs = 1\n    eggs\n}").as_deref(),
+            Some("egg lay() {\n    let eggs = 1;\n    eggs\n}")
+        );
+        assert_eq!(
+            fixed("egg lay() {\n    let eggs = 1 // one\n    eggs\n}").as_deref(),
+            Some("egg lay() {\n    let eggs = 1; // one\n    eggs\n}")
+        );
+        // on one line, it isn't clear what is missing
+        assert_eq!(fixed("egg lay() {\n    let eggs = 1 eggs\n}"), None);
+
+        let dir = std::env::temp_dir().join("wryneck_test_fixes");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("lay.wy");
+        std::fs::write(
+            &path,
+            "egg lay(eggs) {\n    let hens = eggs\n    hens\n}\negg hatch() { lya(1) }\n",
+        )
+        .unwrap();
+        let check = || {
+            let files = load_files(&path, MessageFormat::Human).unwrap();
+            let diagnostics = {
+                let program = read_linked_program(&files, MessageFormat::Human).unwrap();
+                names::check(&program)
+            };
+            fix_files(&files, &diagnostics);
+            std::fs::read_to_string(&path).unwrap()
+        };
+        // the call is only fixed once the file parses
+        assert_eq!(
+            check(),
+            "egg lay(eggs) {\n    let hens = eggs;\n    hens\n}\negg hatch() { lya(1) }\n"
+        );
+        assert_eq!(
+            check(),
+            "egg lay(eggs) {\n    let hens = eggs;\n    hens\n}\negg hatch() { lay(1) }\n"
+        );
+
+        // a fix is only applied to the file it points into
+        let main = dir.join("main.wy");
+        let lib = dir.join("lib.wy");
+        std::fs::write(
+            &main,
+            "import \"lib.wy\";\negg hatch() { *)> 1000000000; }\n",
+        )
+        .unwrap();
+        std::fs::write(&lib, "egg helper(x) { *)> helpr(x); }\n").unwrap();
+        let files = load_files(&main, MessageFormat::Human).unwrap();
+        let diagnostic = Diagnostic::error("there is no function named `helpr`", 20..25)
+            .with_fix("rename to `helper`", vec![Edit::replace(20..25, "helper")])
+            .with_file(1);
+        fix_files(&files, &[diagnostic]);
+        assert_eq!(
+            std::fs::read_to_string(&main).unwrap(),
+            "import \"lib.wy\";\negg hatch() { *)> 1000000000; }\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(&lib).unwrap(),
+            "egg helper(x) { *)> helper(x); }\n"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_unreadable_input() {
+        let dir = std::env::temp_dir().join("wryneck_test_unreadable_input");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("bad1.wy"), "egg lay() { *)> eggs; }\n").unwrap();
+        std::fs::write(dir.join("bad2.wy"), "egg brood() { *)> chicks; }\n").unwrap();
+        let path = |name: &str| dir.join(name).display().to_string();
+
+        // the files after one that can't be read are checked all the same
+        let cli = Cli::from_iter_safe([
+            "wryneck".to_string(),
+            "check".to_string(),
+            "--message-format=sarif".to_string(),
+            path("bad1.wy"),
+            path("missing.wy"),
+            path("bad2.wy"),
+        ])
+        .unwrap();
+        execute(cli.command, ColorChoice::Never);
+        let log = SARIF.lock().unwrap().take().unwrap().to_json();
+        let log = serde_json::from_str::<serde_json::Value>(&log).unwrap();
+        let results = log["runs"][0]["results"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|result| {
+                let uri = result["locations"][0]["physicalLocation"]["artifactLocation"]["uri"]
+                    .as_str()
+                    .unwrap();
+                let file = uri.rsplit('/').next().unwrap().to_string();
+                (result["ruleId"].as_str().unwrap().to_string(), file)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            results,
+            [
+                ("E010".to_string(), "bad1.wy".to_string()),
+                ("E015".to_string(), "missing.wy".to_string()),
+                ("E010".to_string(), "bad2.wy".to_string()),
+            ]
+        );
+        assert!(UNREADABLE.load(Ordering::Relaxed));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_color_choice() {
+        for (flag, args) in [
+            (
+                "never",
+                &["wryneck", "--color", "never", "check", "lay.wy"][..],
+            ),
+            ("always", &["wryneck", "check", "--color=always", "lay.wy"]),
+            ("auto", &["wryneck", "check", "lay.wy"]),
+        ] {
+            let cli = Cli::from_iter_safe(args).unwrap();
+            assert_eq!(cli.color, flag.parse().unwrap());
+        }
+        assert!(
+            Cli::from_iter_safe(["wryneck", "--color", "sometimes", "check", "lay.wy"]).is_err()
+        );
+        assert!(matches!(
+            Cli::from_iter_safe(["wryneck", "ast", "lay.wy"])
+                .unwrap()
+                .command,
+            Opt::Ast { .. }
+        ));
+
+        assert!(ColorChoice::Always.enabled(false));
+        assert!(!ColorChoice::Never.enabled(true));
+        assert!(!ColorChoice::Auto.enabled(false));
+        std::env::set_var("NO_COLOR", "1");
+        assert!(!ColorChoice::Auto.enabled(true));
+        assert!(ColorChoice::Always.enabled(true));
+        std::env::set_var("NO_COLOR", "");
+        assert!(ColorChoice::Auto.enabled(true));
+        std::env::remove_var("NO_COLOR");
+        assert!(ColorChoice::Auto.enabled(true));
+    }
+
+    #[test]
+    fn test_summary() {
+        assert_eq!(summary(0, 0, 0, 20), None);
+        assert_eq!(
+            summary(3, 2, 0, 20).as_deref(),
+            Some("3 errors, 2 warnings emitted")
+        );
+        assert_eq!(summary(1, 0, 0, 20).as_deref(), Some("1 error emitted"));
+        assert_eq!(summary(0, 1, 0, 0).as_deref(), Some("1 warning emitted"));
+        assert_eq!(
+            summary(20, 1, 7, 20).as_deref(),
+            Some("20 errors, 1 warning emitted, 7 more left out after the first 20 errors")
+        );
+    }
+
+    #[test]
+    fn test_allow_comments() {
+        let input = "
+        // wryneck-allow(dead_function)
+        egg lay() { 1 }
+        egg hatch() {
+            // wryneck-allow(unused-variable, shadowing)
+            // a comment in between
+            let hens = 1;
+            let chicks = 2;
+            // wryneck-allow(unused-variable)
+            egg nest() { let eggs = 3; }
+            let ducks = 4; // wryneck-allow(unused-variable)
+            let geese = 5;
+        }
+        ";
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+        let found = lints::Registry::new()
+            .check(&program)
+            .into_iter()
+            .map(|diagnostic| &input[diagnostic.span])
+            .collect::<Vec<_>>();
+        // only comments on a line of their own allow anything, and only in what follows
+        assert_eq!(
+            found,
+            [
+                "let chicks = 2",
+                "let ducks = 4",
+                "let geese = 5",
+                "egg nest()"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sarif() {
+        let text = "egg lay(eggs) {\n    eggs + hen\n}";
+        let lines = LineIndex::new(text);
+        let hen = text.find("hen").unwrap();
+        let diagnostic = Diagnostic::error("`hen` is not declared in this scope", hen..hen + 3)
+            .with_code("E010")
+            .with_note("did you mean this?", 8..12)
+            .with_help("declare it with `let`")
+            .with_fix(
+                "use `eggs`",
+                vec![diagnostics::Edit::replace(hen..hen + 3, "eggs")],
+            );
+        let mut log = sarif::Log::new();
+        log.add(&diagnostic, "coop\\lay.wy", &lines);
+        let json: serde_json::Value = serde_json::from_str(&log.to_json()).unwrap();
+        assert_eq!(json["version"], "2.1.0");
+        let run = &json["runs"][0];
+        let rules = run["tool"]["driver"]["rules"].as_array().unwrap();
+        assert_eq!(rules.len(), diagnostics::EXPLANATIONS.len());
+        let result = &run["results"][0];
+        assert_eq!(
+            rules[result["ruleIndex"].as_u64().unwrap() as usize]["id"],
+            "E010"
+        );
+        let region = serde_json::json!({
+            "startLine": 2,
+            "startColumn": 12,
+            "endLine": 2,
+            "endColumn": 15,
+            "byteOffset": 27,
+            "byteLength": 3
+        });
+        assert_eq!(
+            result,
+            &serde_json::json!({
+                "ruleId": "E010",
+                "ruleIndex": 9,
+                "level": "error",
+                "message": {
+                    "text": "`hen` is not declared in this scope\nhelp: declare it with `let`"
+                },
+                "locations": [{
+                    "physicalLocation": {
+                        "artifactLocation": { "uri": "coop/lay.wy" },
+                        "region": region
+                    }
+                }],
+                "relatedLocations": [{
+                    "id": 0,
+                    "physicalLocation": {
+                        "artifactLocation": { "uri": "coop/lay.wy" },
+                        "region": {
+                            "startLine": 1,
+                            "startColumn": 9,
+                            "endLine": 1,
+                            "endColumn": 13,
+                            "byteOffset": 8,
+                            "byteLength": 4
+                        }
+                    },
+                    "message": { "text": "did you mean this?" }
+                }],
+                "fixes": [{
+                    "description": { "text": "use `eggs`" },
+                    "artifactChanges": [{
+                        "artifactLocation": { "uri": "coop/lay.wy" },
+                        "replacements": [{
+                            "deletedRegion": region,
+                            "insertedContent": { "text": "eggs" }
+                        }]
+                    }]
+                }]
+            })
+        );
+        // without diagnostics, a log still has its run
+        let json: serde_json::Value = serde_json::from_str(&sarif::Log::new().to_json()).unwrap();
+        assert_eq!(json["runs"][0]["results"], serde_json::json!([]));
+    }
+
+    #[test]
+    fn test_related_notes() {
+        let input = "
+        egg lay(eggs, eggs) {
+            let hens = 1;
+            hens = 2;
+            hens
+        }
+        egg count(n: int) { n }
+        egg lay() { count(\"two\") }
+        ";
+        let (program, errors) = parse(input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+        let diagnostics = names::check(&program)
+            .into_iter()
+            .chain(assignments::check(&program))
+            .chain(typecheck::check(&program))
+            .collect::<Vec<_>>();
+        let found = diagnostics
+            .iter()
+            .map(|diagnostic| {
+                let (note, span) = &diagnostic.notes[0];
+                (
+                    diagnostic.code.unwrap(),
+                    note.as_str(),
+                    &input[span.clone()],
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            [
+                ("E014", "the previous definition is here", "eggs"),
+                (
+                    "E014",
+                    "the previous definition is here",
+                    "egg lay(eggs, eggs)"
+                ),
+                ("E012", "`hens` is declared here", "let hens = 1"),
+                ("E035", "`n` is declared here", "n: int"),
+            ]
+        );
+        // a note left of the primary span goes below the underlines
+        let lines = LineIndex::new(input);
+        let rendered = diagnostics[0].render("lay.wy", &lines, false);
+        assert!(rendered.ends_with(
+            "  |                 ----  ^^^^\n  |                 the previous definition is here\n"
+        ));
+    }
+
+    #[test]
+    fn test_timings() {
+        use tracing_subscriber::prelude::*;
+        let timings = timings::Timings::new();
+        let subscriber = tracing_subscriber::registry().with(timings.clone());
+        tracing::subscriber::with_default(subscriber, || {
+            let text = "egg lay(eggs) {\n    eggs + 1\n}\nlay(2);";
+            let (ast, errors) = parse(text).unwrap();
+            assert!(errors.is_empty());
+            let program = resolved_ast::Program::from(ast);
+            formatter::format_source(&program, text, Default::default());
+        });
+        let phases = timings.phases();
+        let names = phases.iter().map(|phase| phase.name).collect::<Vec<_>>();
+        assert_eq!(names, ["parse", "resolve", "format"]);
+        assert!(phases.iter().all(|phase| phase.count == 1));
+        let report = timings.report();
+        assert_eq!(report.lines().count(), 3);
+        assert!(report.starts_with("parse  "));
+    }
+
+    #[test]
+    fn test_ast_json() {
+        let text = "egg lay(eggs) {\n    eggs + 1\n}\n";
+        let (ast, errors) = parse(text).unwrap();
+        assert!(errors.is_empty());
+        let base = serde_json::to_value(&ast).unwrap();
+        let function = &base["things"][0]["Function"];
+        assert_eq!(function["definition"]["name"], "lay");
+        assert_eq!(function["definition"]["params"][0]["name"], "eggs");
+        assert_eq!(function["body"]["Block"][1]["Op"][1], "Add");
+
+        let program = resolved_ast::Program::from(ast);
+        let resolved = serde_json::to_value(&program).unwrap();
+        assert_eq!(resolved["things"][0]["Function"], 0);
+        let function = &resolved["functions"][0];
+        assert_eq!(function["definition"]["params"][0]["id"], 0);
+        assert_eq!(
+            function["body"]["Block"][1]["Op"][0]["Variable"]["span"],
+            serde_json::json!({"start": 20, "end": 24})
+        );
+
+        let cli = Cli::from_iter_safe([
+            "wryneck", "ast", "--format", "json", "--stage", "base", "lay.wy",
+        ]);
+        assert!(matches!(
+            cli.unwrap().command,
+            Opt::Ast {
+                format: AstFormat::Json,
+                stage: Stage::Base,
+                ..
+            }
+        ));
+        assert!(Cli::from_iter_safe(["wryneck", "ast", "--stage", "parsed", "lay.wy"]).is_err());
+    }
+
+    #[test]
+    fn test_keyword_spellings() {
+        let input = r#"
+        import "birds.wy";
+        enum Nest { Empty }
+        egg lay(eggs) {
+            let mut chicks = ();
+            let laid = some(none);
+            let sure = true != false;
+            assert eggs != 0;
+            if eggs { *)> 1; } else { chicks; };
+            try { eggs; } rescue error { error; };
+        }
+        [forall eggs in 0..3: eggs = 1]
+        egg hatch() { lay(1) }
+        "#;
+        let format = |input, keywords| {
+            let (program, errors) = parse(input).unwrap();
+            assert!(errors.is_empty());
+            let program: resolved_ast::Program = program.into();
+            let config = formatter::FormatConfig {
+                keywords,
+                ..Default::default()
+            };
+            formatter::format_with(&program, config)
+        };
+
+        let ascii = format(input, formatter::KeywordStyle::Preserve);
+        let emoji = format(&ascii, formatter::KeywordStyle::Emoji);
+        assert_eq!(
+            emoji,
+            r#"📥 "birds.wy";
+
+🧺 Nest {
+    Empty,
+}
+
+🥚 lay(eggs) {
+    📦 🔓 chicks = 🫥;
+    📦 laid = 🪺(🪹);
+    📦 sure = (✅ != ❌);
+    🔍 (eggs != 0);
+    ❓ eggs {
+        🐔 1;
+    } ❗ {
+        chicks;
+    };
+    🤞 {
+        eggs;
+    } 🛟 error {
+        error;
+    };
+}[
+    🎲 eggs 👉 0..3: eggs = 1,
+]
+
+🥚 🐣() {
+    lay(1)
+}
+
+"#
+        );
+        assert_eq!(format(&emoji, formatter::KeywordStyle::Preserve), emoji);
+        assert_eq!(format(&emoji, formatter::KeywordStyle::Ascii), ascii);
+        // the name of the entry point keeps its spelling, whatever the keyword in front of it
+        assert_eq!(
+            format("egg 🐣() { 1 }", formatter::KeywordStyle::Preserve),
+            "egg 🐣() {\n    1\n}\n\n"
+        );
+        for (keyword, ascii_spelling, emoji_spelling) in wryneck::keywords::KEYWORDS {
+            assert!(ascii.contains(ascii_spelling), "{:?}", keyword);
+            assert!(emoji.contains(emoji_spelling), "{:?}", keyword);
+        }
+    }
+
+    #[test]
+    fn test_hello_world() {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/hello_world.wy");
+        let input = std::fs::read_to_string(path).unwrap();
+        let (program, errors) = parse(&input).unwrap();
+        assert!(errors.is_empty());
+        let program: resolved_ast::Program = program.into();
+
+        // `true` and `false` are declared, only `test` is used where it is out of scope
+        let errors = names::check(&program)
+            .into_iter()
+            .chain(arity::check(&program))
+            .chain(units::check(&program))
+            .chain(typecheck::check(&program))
+            .chain(control_flow::check(&program))
+            .filter(|diagnostic| diagnostic.severity == Severity::Error)
+            .map(|diagnostic| diagnostic.message)
+            .collect::<Vec<_>>();
+        assert_eq!(errors, ["`test` is not declared in this scope"]);
+
+        // both backends get past the conditions to fail on `test`
+        let hatch = program.hatch().unwrap();
+        let span = &program.functions[hatch].definition.span;
+        let interpreted = interpreter::Interpreter::new(&program)
+            .call(hatch, vec![], span)
+            .unwrap_err();
+        assert_eq!(interpreted.message, "`test` is not defined");
+        let compiled = bytecode::compile(&program);
+        let run = vm::Vm::new(&compiled)
+            .call(hatch, vec![], span)
+            .unwrap_err();
+        assert_eq!(run.message, "`test` is not defined");
+    }
+
+    #[test]
+    fn test_grammar_coverage() {
+        let mut coverage = coverage::Coverage::new();
+        for path in find_sources(
+            &[PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests")],
+            &[],
+        )
+        .unwrap()
+        {
+            let input = std::fs::read_to_string(&path).unwrap();
+            if let Ok((program, errors)) = parse(&input) {
+                if errors.is_empty() {
+                    coverage.record(&program.into());
+                }
+            }
+        }
+        assert_eq!(coverage.missing(), Vec::<&str>::new());
+    }
 }
@@ -0,0 +1,148 @@
+//! Checks that every name the program uses refers to something, and that no name is defined twice
+//! where only one of the definitions can be used.
+use id_collections::Id;
+
+use crate::diagnostics::{Diagnostic, Edit};
+use crate::resolved_ast::*;
+use crate::suggest;
+use crate::visit::{walk_expression, walk_function, Visitor};
+use crate::Span;
+
+/// Check the names used in every function of a linked program, whose variables are resolved,
+/// except in imported files, whose spans point into those files.
+pub fn check(program: &Program) -> Vec<Diagnostic> {
+    let mut checker = Checker {
+        program,
+        function: FunctionId::from_index(0),
+        diagnostics: Vec::new(),
+    };
+    for (id, func) in &program.functions {
+        if program.is_imported(id) {
+            continue;
+        }
+        checker.function = id;
+        walk_function(&mut checker, func);
+    }
+    checker.diagnostics.extend(duplicates(program));
+    checker.diagnostics
+}
+
+/// Functions defined in the same scope with the same name as an earlier one, and parameters of
+/// the same name as an earlier one of their function, except in imported files.
+fn duplicates(program: &Program) -> Vec<Diagnostic> {
+    let defined_twice = |name: &str, span: Span, earlier: Span| {
+        Diagnostic::error(format!("`{}` is defined more than once", name), span)
+            .with_code("E014")
+            .with_note("the previous definition is here", earlier)
+    };
+    let mut diagnostics = Vec::new();
+    let functions = program
+        .functions
+        .iter()
+        .filter(|(id, _)| Some(*id) != program.script && !program.is_imported(*id))
+        .collect::<Vec<_>>();
+    for (i, (_, func)) in functions.iter().enumerate() {
+        let definition = &func.definition;
+        // functions come in the order they are defined in
+        if let Some((_, earlier)) = functions[..i].iter().find(|(_, earlier)| {
+            earlier.parent == func.parent && earlier.definition.name == definition.name
+        }) {
+            diagnostics.push(defined_twice(
+                definition.name,
+                definition.span.clone(),
+                earlier.definition.span.clone(),
+            ));
+        }
+        for (j, param) in definition.params.iter().enumerate() {
+            if let Some(earlier) = definition.params[..j]
+                .iter()
+                .find(|earlier| earlier.name == param.name)
+            {
+                diagnostics.push(defined_twice(
+                    param.name,
+                    param.span.clone(),
+                    earlier.span.clone(),
+                ));
+            }
+        }
+    }
+    diagnostics
+}
+
+struct Checker<'a, 'input> {
+    program: &'a Program<'input>,
+    /// The function being checked, which decides the nested functions that are visible.
+    function: FunctionId,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Checker<'_, '_> {
+    /// The names of the functions that can be called from the function being checked.
+    fn function_names(&self) -> Vec<&str> {
+        let mut names = Intrinsic::ALL
+            .into_iter()
+            .map(Intrinsic::name)
+            .collect::<Vec<_>>();
+        for (id, func) in &self.program.functions {
+            let name = func.definition.name;
+            if self.program.function_named(self.function, name) == Some(id) {
+                names.push(name);
+            }
+        }
+        names
+    }
+}
+
+impl<'input> Visitor<'input> for Checker<'_, 'input> {
+    fn visit_expression(&mut self, expr: &Expression<'input>) {
+        match expr {
+            // a function can be used as a value, like `let f = brood;`
+            Expression::Variable(var)
+                if var.id.is_none()
+                    && self
+                        .program
+                        .function_named(self.function, var.name)
+                        .is_none() =>
+            {
+                self.diagnostics.push(
+                    Diagnostic::error(
+                        format!("`{}` is not declared in this scope", var.name),
+                        var.span.clone(),
+                    )
+                    .with_code("E010"),
+                );
+            }
+            Expression::FunctionCall(FunctionCall::Indirect { callee, args, .. }) => {
+                match &**callee {
+                    Expression::Variable(var) => {
+                        // calls naming a function are direct, this one is meant to call a function
+                        // unless the name is bound
+                        if var.id.is_none() {
+                            let mut message = format!("there is no function named `{}`", var.name);
+                            let suggestion = suggest::closest(var.name, self.function_names())
+                                .first()
+                                .map(|suggestion| suggestion.to_string());
+                            if let Some(suggestion) = &suggestion {
+                                message += &format!(", did you mean `{}`?", suggestion);
+                            }
+                            let mut diagnostic =
+                                Diagnostic::error(message, var.span.clone()).with_code("E011");
+                            if let Some(suggestion) = suggestion {
+                                diagnostic = diagnostic.with_fix(
+                                    format!("rename to `{}`", suggestion),
+                                    vec![Edit::replace(var.span.clone(), suggestion)],
+                                );
+                            }
+                            self.diagnostics.push(diagnostic);
+                        }
+                        for arg in args {
+                            self.visit_expression(arg);
+                        }
+                    }
+                    _ => walk_expression(self, expr),
+                }
+            }
+            _ => walk_expression(self, expr),
+        }
+    }
+}
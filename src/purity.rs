@@ -0,0 +1,116 @@
+//! Which functions are pure, see [`Purity`]. Functions are effectful when they assert, which can
+//! stop the program, call a function value, which could be anything, call a name that isn't a
+//! function of the program or an intrinsic, call an intrinsic that does I/O, or call an effectful
+//! function.
+use crate::diagnostics::Diagnostic;
+use crate::resolved_ast::*;
+use crate::visit::{walk_expression, walk_function, walk_statement, Visitor};
+
+/// Set [`Function::purity`] for every function of a linked program.
+pub fn classify(program: &mut Program) {
+    let mut effectful = program.functions.map_refs(|_, func| {
+        let mut effects = Effects { found: false };
+        walk_function(&mut effects, func);
+        effects.found
+    });
+    // effects spread to the callers, until every caller of an effectful function is effectful
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (id, callees) in &program.calls {
+            if !effectful[id] && callees.iter().any(|callee| effectful[*callee]) {
+                effectful[id] = true;
+                changed = true;
+            }
+        }
+    }
+    for (id, func) in program.functions.iter_mut() {
+        func.purity = if effectful[id] {
+            Purity::Effectful
+        } else {
+            Purity::Pure
+        };
+    }
+}
+
+/// Report expression statements that call a pure function, whose value is all the call is good
+/// for. Imported functions are skipped.
+pub fn check(program: &Program) -> Vec<Diagnostic> {
+    let mut checker = Checker {
+        program,
+        diagnostics: Vec::new(),
+    };
+    for (id, func) in &program.functions {
+        if !program.is_imported(id) {
+            walk_function(&mut checker, func);
+        }
+    }
+    checker.diagnostics
+}
+
+/// Finds whether a function has effects of its own, without looking into the functions it calls.
+struct Effects {
+    found: bool,
+}
+
+impl<'input> Visitor<'input> for Effects {
+    fn visit_statement(&mut self, stmt: &Statement<'input>) {
+        self.found |= matches!(stmt, Statement::Assert(_));
+        walk_statement(self, stmt);
+    }
+
+    fn visit_expression(&mut self, expr: &Expression<'input>) {
+        self.found |= match expr {
+            Expression::FunctionCall(FunctionCall::Indirect { .. }) => true,
+            Expression::FunctionCall(FunctionCall::Intrinsic { intrinsic, .. }) => {
+                intrinsic.does_io()
+            }
+            _ => false,
+        };
+        walk_expression(self, expr);
+    }
+}
+
+struct Checker<'a, 'input> {
+    program: &'a Program<'input>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'input> Visitor<'input> for Checker<'_, 'input> {
+    fn visit_statement(&mut self, stmt: &Statement<'input>) {
+        if let Statement::Expression(expr) = stmt {
+            let mut expr = &**expr;
+            while let Expression::Expression(inner)
+            | Expression::Paren(inner, _)
+            | Expression::ExpressionComment((inner, _)) = expr
+            {
+                expr = inner;
+            }
+            let pure = match expr {
+                Expression::FunctionCall(FunctionCall::Direct { function, span, .. })
+                    if self.program.functions[*function].purity == Purity::Pure =>
+                {
+                    Some((self.program.functions[*function].definition.name, span))
+                }
+                Expression::FunctionCall(FunctionCall::Intrinsic {
+                    intrinsic, span, ..
+                }) if !intrinsic.does_io() => Some((intrinsic.name(), span)),
+                _ => None,
+            };
+            if let Some((name, span)) = pure {
+                self.diagnostics.push(
+                    Diagnostic::warning(
+                        format!(
+                            "the value of this call is discarded, and calling `{}` has no other \
+                         effect",
+                            name
+                        ),
+                        span.clone(),
+                    )
+                    .with_code("W013"),
+                );
+            }
+        }
+        walk_statement(self, stmt);
+    }
+}
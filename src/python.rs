@@ -0,0 +1,145 @@
+//! Python bindings, enabled with the `pyo3` feature.
+//!
+//! ```python
+//! import wryneck
+//! print(wryneck.format(open("hello_world.wy").read()))
+//! print(wryneck.run(open("pigeons.wy").read(), "count_pigeons", [5]))
+//! ```
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyTypeError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBool, PyList};
+use pyo3::IntoPyObjectExt;
+
+use crate::line_index::LineIndex;
+use crate::{formatter, interpreter, resolved_ast};
+
+create_exception!(wryneck, WryneckError, PyException);
+create_exception!(wryneck, ParseError, WryneckError);
+create_exception!(wryneck, RunError, WryneckError);
+
+/// Parse the input, turning any parse errors (recovered or not) into a `ParseError`.
+fn parse_program(input: &str) -> PyResult<resolved_ast::Program<'_>> {
+    let messages = |errors: &[crate::RecoveredError]| {
+        errors
+            .iter()
+            .map(|err| err.error.to_string())
+            .collect::<Vec<_>>()
+    };
+    let (program, errors) = crate::parse(input).map_err(|failure| {
+        let mut messages = messages(&failure.recovered);
+        messages.push(failure.error.to_string());
+        ParseError::new_err(messages.join("\n"))
+    })?;
+    if !errors.is_empty() {
+        return Err(ParseError::new_err(messages(&errors).join("\n")));
+    }
+    Ok(program.into())
+}
+
+/// Parse the source and return a dump of its AST.
+#[pyfunction]
+fn parse(source: &str) -> PyResult<String> {
+    Ok(format!("{:#?}", parse_program(source)?))
+}
+
+/// Parse the source and return it formatted.
+#[pyfunction]
+fn format(source: &str) -> PyResult<String> {
+    Ok(formatter::format_source(
+        &parse_program(source)?,
+        source,
+        Default::default(),
+    ))
+}
+
+/// Run a top level function of the source, `hatch` by default, and return what it comes to.
+/// Failures are raised as `RunError`, with the line and column they happened at.
+#[pyfunction]
+#[pyo3(signature = (source, function = "hatch", args = Vec::new(), allow_io = false))]
+fn run(
+    py: Python<'_>,
+    source: &str,
+    function: &str,
+    args: Vec<Bound<'_, PyAny>>,
+    allow_io: bool,
+) -> PyResult<PyObject> {
+    let program = parse_program(source)?;
+    let id = match function {
+        "hatch" => program.hatch(),
+        _ => program.top_level_function(function),
+    }
+    .ok_or_else(|| RunError::new_err(format!("there is no function named `{}`", function)))?;
+    let args = args.iter().map(to_json).collect::<PyResult<Vec<_>>>()?;
+    let span = program.functions[id].definition.span.clone();
+    // values can't leave the thread the interpreter runs on, so they come and go as JSON
+    let result = py.allow_threads(|| {
+        interpreter::with_large_stack(|| {
+            let args = args
+                .iter()
+                .map(interpreter::Value::from_json)
+                .collect::<Result<_, _>>()
+                .map_err(|message| crate::runtime::Failure::new(message, &span))?;
+            interpreter::Interpreter::new(&program)
+                .with_io(allow_io)
+                .call(id, args, &span)
+                .map(|value| value.to_json())
+        })
+    });
+    match result {
+        Ok(value) => from_json(py, &value),
+        Err(failure) => {
+            let (line, column) = LineIndex::new(source).position(failure.span.start);
+            Err(RunError::new_err(format!(
+                "{}:{}: {}",
+                line, column, failure.message
+            )))
+        }
+    }
+}
+
+/// The JSON for a Python argument: ints, strings, bools, `None` and lists of them.
+fn to_json(arg: &Bound<'_, PyAny>) -> PyResult<serde_json::Value> {
+    if let Ok(value) = arg.downcast::<PyBool>() {
+        Ok(value.is_true().into())
+    } else if let Ok(value) = arg.extract::<i64>() {
+        Ok(value.into())
+    } else if let Ok(value) = arg.extract::<String>() {
+        Ok(value.into())
+    } else if arg.is_none() {
+        Ok(serde_json::Value::Null)
+    } else if let Ok(values) = arg.downcast::<PyList>() {
+        values.iter().map(|value| to_json(&value)).collect()
+    } else {
+        Err(PyTypeError::new_err(format!(
+            "cannot pass a {} to a wryneck function",
+            arg.get_type().name()?
+        )))
+    }
+}
+
+/// The Python object for a result, which only holds what [`interpreter::Value::to_json`] makes.
+fn from_json(py: Python<'_>, json: &serde_json::Value) -> PyResult<PyObject> {
+    match json {
+        serde_json::Value::Bool(value) => value.into_py_any(py),
+        serde_json::Value::Number(number) => number.as_i64().into_py_any(py),
+        serde_json::Value::String(value) => value.into_py_any(py),
+        serde_json::Value::Array(values) => values
+            .iter()
+            .map(|value| from_json(py, value))
+            .collect::<PyResult<Vec<_>>>()?
+            .into_py_any(py),
+        _ => Ok(py.None()),
+    }
+}
+
+#[pymodule]
+fn wryneck(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("WryneckError", m.py().get_type::<WryneckError>())?;
+    m.add("ParseError", m.py().get_type::<ParseError>())?;
+    m.add("RunError", m.py().get_type::<RunError>())?;
+    m.add_function(wrap_pyfunction!(parse, m)?)?;
+    m.add_function(wrap_pyfunction!(format, m)?)?;
+    m.add_function(wrap_pyfunction!(run, m)?)?;
+    Ok(())
+}
@@ -1,102 +1,838 @@
 use colored::Colorize;
 use id_collections::id_type;
-use id_collections::IdVec;
+use id_collections::{Id, IdVec};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::fmt::{Debug, Display, Error};
+use std::io;
 
-use crate::formatter::{Format, Formatter};
+use crate::formatter::{self, Format, FormatConfig, Formatter, Parens, FORMAT_OFF, FORMAT_ON};
+use crate::keywords::{Keyword, Spelling};
+use crate::visit::{
+    walk_expression, walk_expression_mut, walk_function, walk_function_mut, walk_program_mut,
+    walk_statement, walk_statement_mut, Visitor, VisitorMut,
+};
+use crate::Span;
 
-#[id_type]
+#[id_type(serde = true)]
 pub struct FunctionId(usize);
 
-#[derive(Debug)]
+#[id_type(serde = true)]
+pub struct EnumId(usize);
+
+/// A variable bound by a parameter, a `let` or a `rescue`, see [`Program::variables`].
+#[id_type(serde = true)]
+pub struct VariableId(usize);
+
+impl VariableId {
+    /// What a binding refers to until [`Program::link`] has resolved the variables.
+    fn unresolved() -> Self {
+        Self::from_index(usize::MAX)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(bound(deserialize = "'de: 'input"))]
 pub struct Program<'input> {
     pub things: Vec<TopLevel<'input>>,
     pub functions: IdVec<FunctionId, Function<'input>>,
+    pub enums: IdVec<EnumId, Enum<'input>>,
+    /// The implicit `hatch` the top level statements make up, if there are any.
+    pub script: Option<FunctionId>,
+    /// Every variable bound in any function, each binding is a variable of its own.
+    pub variables: IdVec<VariableId, Binding<'input>>,
+    /// The functions each function calls or uses as a value, in order of first use.
+    pub calls: IdVec<FunctionId, Vec<FunctionId>>,
 }
 
 impl Format for Program<'_> {
     fn format(&self, fmt: &mut Formatter) {
-        for func in &self.things {
+        let mut out = Vec::new();
+        self.format_to(&mut out, fmt.config, fmt.source)
+            .expect("writing to a Vec doesn't fail");
+        fmt.push_string(String::from_utf8(out).expect("the formatter only writes strings"));
+    }
+}
+
+impl Program<'_> {
+    /// Format the program to `out` one top level item at a time, so only the item being
+    /// formatted is held in memory.
+    pub(crate) fn format_to(
+        &self,
+        out: &mut impl io::Write,
+        config: FormatConfig,
+        source: Option<&str>,
+    ) -> io::Result<()> {
+        let _span = tracing::info_span!("format").entered();
+        let mut fmt_program = Formatter::new(config);
+        fmt_program.program = Some(self);
+        fmt_program.source = source;
+        let fmt = &mut fmt_program;
+        let things = if config.sort_functions {
+            self.sorted_things()
+        } else {
+            self.things.iter().collect()
+        };
+        let mut i = 0;
+        while i < things.len() {
+            let comments = things[i..].iter().map(|thing| match thing {
+                TopLevel::Comment(comment) => Some(comment),
+                _ => None,
+            });
+            if let Some(wrapped) = format_paragraphs(fmt, comments) {
+                i += wrapped;
+                self.keep_blank_lines(fmt, things.get(i).copied());
+                continue;
+            }
+            let func = things[i];
             match func {
                 TopLevel::Function(func) => self.functions[*func].format(fmt),
+                TopLevel::Enum(enum_) => self.enums[*enum_].format(fmt),
                 TopLevel::Comment(comment) => comment.format(fmt),
+                TopLevel::Import(import) => import.format(fmt),
+                TopLevel::Statement(index) => self.script_statements()[*index].format(fmt),
+            }
+            // separate the imports and the top level statements from the rest of the file
+            let next = things.get(i + 1).copied();
+            let separate = match func {
+                TopLevel::Import(_) => !matches!(next, Some(TopLevel::Import(_)) | None),
+                TopLevel::Statement(..) => !matches!(next, Some(TopLevel::Statement(..)) | None),
+                _ => false,
+            };
+            if separate && fmt.config.max_blank_lines > 0 {
+                fmt.push_str("\n");
+            }
+            let rest = things[i + 1..].iter().map(|thing| match thing {
+                TopLevel::Comment(comment) => Some(comment),
+                _ => None,
+            });
+            let skipped = match func {
+                TopLevel::Comment(off) => format_off(fmt, off, rest, usize::MAX),
+                _ => None,
+            };
+            i += 1 + skipped.unwrap_or(0);
+            self.keep_blank_lines(fmt, things.get(i).copied());
+            fmt.flush(out)?;
+        }
+        fmt_program.finish(out)
+    }
+
+    /// Keep up to [`FormatConfig::max_blank_lines`] of the blank lines the source has before the
+    /// next top level item, if the formatter put fewer. Sorted items are no longer next to what
+    /// came before them in the source, so they keep none.
+    fn keep_blank_lines(&self, fmt: &mut Formatter, next: Option<&TopLevel>) {
+        let source = match fmt.source {
+            Some(source) if !fmt.config.sort_functions => source,
+            _ => return,
+        };
+        let start = match next {
+            Some(TopLevel::Function(func)) => self.functions[*func].definition.span.start,
+            Some(TopLevel::Enum(enum_)) => self.enums[*enum_].span.start,
+            Some(TopLevel::Comment(comment)) if !comment.trailing => comment.span.start,
+            Some(TopLevel::Import(import)) => import.span.start,
+            Some(TopLevel::Statement(index)) => self.script_statements()[*index].span(self).start,
+            Some(TopLevel::Comment(_)) | None => return,
+        };
+        let before = &source[..start];
+        let line = before.rfind('\n').map_or(0, |i| i + 1);
+        if !before[line..].trim().is_empty() {
+            return;
+        }
+        let blank = before[..line]
+            .lines()
+            .rev()
+            .take_while(|line| line.trim().is_empty())
+            .count();
+        let newlines = fmt.string.len() - fmt.string.trim_end_matches('\n').len();
+        let put = newlines.saturating_sub(1);
+        for _ in put..blank.min(fmt.config.max_blank_lines) {
+            fmt.push_str("\n");
+        }
+    }
+
+    /// The top level items with the functions sorted by name, `hatch` first, each together with
+    /// the comments before it and the comment after it on its line. The other items stay where
+    /// they are, and so does everything if the formatter is turned off outside of functions,
+    /// which keeps the source as it is written.
+    fn sorted_things(&self) -> Vec<&TopLevel<'_>> {
+        let things = &self.things;
+        let comment = |i: usize, text: &str| matches!(&things[i], TopLevel::Comment(comment) if comment.text.trim_end() == text);
+        if (0..things.len()).any(|i| comment(i, FORMAT_OFF)) {
+            return things.iter().collect();
+        }
+        // every item with its comments, and the comments at the end of the file
+        let mut units = Vec::new();
+        let mut start = 0;
+        for (i, thing) in things.iter().enumerate() {
+            if i < start || matches!(thing, TopLevel::Comment(_)) {
+                continue;
             }
+            let mut end = i + 1;
+            while matches!(things.get(end), Some(TopLevel::Comment(comment)) if comment.trailing) {
+                end += 1;
+            }
+            units.push(start..end);
+            start = end;
+        }
+        if start < things.len() {
+            units.push(start..things.len());
         }
+        let function = |unit: &std::ops::Range<usize>| {
+            unit.clone().find_map(|i| match things[i] {
+                TopLevel::Function(id) => Some(self.functions[id].definition.name),
+                _ => None,
+            })
+        };
+        let mut functions = units
+            .iter()
+            .filter(|unit| function(unit).is_some())
+            .cloned()
+            .collect::<Vec<_>>();
+        functions.sort_by_key(|unit| {
+            let name = function(unit);
+            (name != Some("hatch"), name)
+        });
+        let mut functions = functions.into_iter();
+        units
+            .into_iter()
+            .flat_map(|unit| match function(&unit) {
+                Some(_) => functions.next().unwrap(),
+                None => unit,
+            })
+            .map(|i| &things[i])
+            .collect()
     }
 }
 
 // convert from base_ast::Program to Program
 impl<'input> From<crate::base_ast::Program<'input>> for Program<'input> {
     fn from(ast: crate::base_ast::Program<'input>) -> Self {
-        let mut functions = IdVec::new();
-        Program {
-            things: ast
-                .things
-                .into_iter()
-                .map(|thing| match thing {
+        Self::link(ast, Vec::new())
+    }
+}
+
+impl<'input> Program<'input> {
+    /// Combine a program with the programs it imports.
+    ///
+    /// Only the top level items of `main` are kept, the functions and enums of the imports are
+    /// added after its own. Calls resolve to the first function of that name. The top level
+    /// statements of `main` make up [`Program::script`], the ones of the imports are dropped.
+    pub fn link(
+        main: crate::base_ast::Program<'input>,
+        imports: Vec<crate::base_ast::Program<'input>>,
+    ) -> Self {
+        let _span = tracing::info_span!("resolve").entered();
+        let mut lifter = Lifter {
+            functions: IdVec::new(),
+        };
+        let mut enums = IdVec::new();
+        let mut things = Vec::new();
+        let mut statements = Vec::new();
+        let mut script_span: Option<Span> = None;
+        for (i, ast) in std::iter::once(main).chain(imports).enumerate() {
+            for thing in ast.things {
+                let thing = match thing {
                     crate::base_ast::TopLevel::Function(func) => {
-                        let id = functions.push(Function::from(func));
-                        TopLevel::Function(id)
+                        TopLevel::Function(lifter.add(func, None))
+                    }
+                    crate::base_ast::TopLevel::Enum(enum_) => {
+                        TopLevel::Enum(enums.push(Enum::from(enum_)))
                     }
                     crate::base_ast::TopLevel::Comment(comment) => {
                         TopLevel::Comment(comment.into())
                     }
-                })
-                .collect::<Vec<_>>(),
-            functions,
+                    crate::base_ast::TopLevel::Import(import) => TopLevel::Import(import.into()),
+                    crate::base_ast::TopLevel::Statement(_, _) if i != 0 => continue,
+                    crate::base_ast::TopLevel::Statement(stmt, span) => {
+                        script_span.get_or_insert(span.clone()).end = span.end;
+                        statements.push(stmt);
+                        TopLevel::Statement(statements.len() - 1)
+                    }
+                };
+                if i == 0 {
+                    things.push(thing);
+                }
+            }
+        }
+        // after the other functions, so an explicit `hatch` takes precedence in calls
+        let script = script_span.map(|span| {
+            let definition = crate::base_ast::FunctionDefinition {
+                keyword: Spelling::default(),
+                name: "hatch",
+                name_keyword: Spelling::default(),
+                params: Vec::new(),
+                return_type: None,
+                span: span.clone(),
+            };
+            let body = crate::base_ast::Expression::Block(statements, None, span.clone());
+            lifter.add(
+                crate::base_ast::Function {
+                    definition,
+                    body: Box::new(body),
+                    tests: Vec::new(),
+                    benchmarks: Vec::new(),
+                },
+                None,
+            )
+        });
+        let mut program = Program {
+            things,
+            functions: lifter.functions,
+            enums,
+            script,
+            variables: IdVec::new(),
+            calls: IdVec::new(),
+        };
+
+        let mut variables = ResolveVariables {
+            variables: IdVec::new(),
+            function: FunctionId::from_index(0),
+            scopes: Vec::new(),
+        };
+        for id in program.functions.count() {
+            variables.function = id;
+            variables.visit_function_mut(&mut program.functions[id]);
+        }
+        program.variables = variables.variables;
+
+        let mut resolver = ResolveCalls {
+            functions: HashMap::new(),
+            nested: Vec::new(),
+            scopes: Vec::new(),
+        };
+        for (id, func) in program.functions.iter().rev() {
+            // the first definition of a name wins
+            if func.parent.is_none() {
+                resolver.functions.insert(func.definition.name, id);
+            }
+        }
+        for id in program.functions.count() {
+            // the functions nested in this one and in the functions around it, innermost last
+            let mut nested = Vec::new();
+            let mut parent = Some(id);
+            while let Some(outer) = parent {
+                nested.extend(
+                    program
+                        .functions
+                        .iter()
+                        .filter(|(_, func)| func.parent == Some(outer))
+                        .map(|(id, func)| (func.definition.name, id))
+                        .rev(),
+                );
+                parent = program.functions[outer].parent;
+            }
+            nested.reverse();
+            resolver.nested = nested;
+            resolver.visit_function_mut(&mut program.functions[id]);
+        }
+
+        // only now that the calls in the defaults are resolved can they be copied into calls
+        let mut filler = FillDefaults {
+            defaults: program.functions.map_refs(|_, func| {
+                func.definition
+                    .params
+                    .iter()
+                    .map(|param| param.default.clone())
+                    .collect()
+            }),
+        };
+        walk_program_mut(&mut filler, &mut program);
+        let mut operators = ResolveOperators {
+            strings: HashSet::new(),
+        };
+        walk_program_mut(&mut operators, &mut program);
+
+        // the calls in the defaults count for the function they are defaults of, which evaluates
+        // them
+        let calls = program.functions.map_refs(|id, func| {
+            let mut callees = Callees {
+                program: &program,
+                function: id,
+                callees: Vec::new(),
+            };
+            walk_function(&mut callees, func);
+            callees.callees
+        });
+        program.calls = calls;
+        crate::tail_calls::mark(&mut program);
+        crate::purity::classify(&mut program);
+        tracing::debug!(
+            functions = program.functions.len(),
+            variables = program.variables.len(),
+            "resolved"
+        );
+        program
+    }
+
+    /// The statements of [`Program::script`], in the order they appear at the top level.
+    pub fn script_statements(&self) -> &[Statement<'input>] {
+        match self.script.map(|script| &*self.functions[script].body) {
+            Some(Expression::Block(block, _, _)) => block,
+            _ => &[],
+        }
+    }
+
+    /// The functions defined directly inside the given one.
+    pub fn nested_functions(&self, parent: FunctionId) -> impl Iterator<Item = FunctionId> + '_ {
+        self.functions
+            .iter()
+            .filter(move |(_, func)| func.parent == Some(parent))
+            .map(|(id, _)| id)
+    }
+
+    /// The entry point of the program: the top level statements, or else the `hatch` function.
+    pub fn hatch(&self) -> Option<FunctionId> {
+        self.script.or_else(|| self.top_level_function("hatch"))
+    }
+
+    /// The first function of that name defined at the top level of the main file.
+    pub fn top_level_function(&self, name: &str) -> Option<FunctionId> {
+        self.things.iter().find_map(|thing| match thing {
+            TopLevel::Function(id) if self.functions[*id].definition.name == name => Some(*id),
+            _ => None,
+        })
+    }
+
+    /// The function and every function it calls, directly or through the functions it calls,
+    /// in the order they are found.
+    pub fn callees(&self, function: FunctionId) -> Vec<FunctionId> {
+        let mut found = vec![function];
+        let mut next = 0;
+        while let Some(&caller) = found.get(next) {
+            for callee in &self.calls[caller] {
+                if !found.contains(callee) {
+                    found.push(*callee);
+                }
+            }
+            next += 1;
+        }
+        found
+    }
+
+    /// Whether the function comes from an imported file, so its spans point into that file.
+    pub fn is_imported(&self, func: FunctionId) -> bool {
+        let mut outermost = func;
+        while let Some(parent) = self.functions[outermost].parent {
+            outermost = parent;
+        }
+        Some(outermost) != self.script
+            && !self
+                .things
+                .iter()
+                .any(|thing| matches!(thing, TopLevel::Function(id) if *id == outermost))
+    }
+
+    /// The function a name refers to inside the given one, if no local variable shadows it: the
+    /// innermost function of that name nested in it or the functions around it, or else the first
+    /// top level function of that name.
+    pub fn function_named(&self, inside: FunctionId, name: &str) -> Option<FunctionId> {
+        let mut parent = Some(inside);
+        while let Some(outer) = parent {
+            let nested = self
+                .nested_functions(outer)
+                .filter(|nested| self.functions[*nested].definition.name == name)
+                .last();
+            if nested.is_some() {
+                return nested;
+            }
+            parent = self.functions[outer].parent;
+        }
+        self.functions
+            .iter()
+            .find(|(_, func)| func.parent.is_none() && func.definition.name == name)
+            .map(|(id, _)| id)
+    }
+}
+
+/// Moves nested functions out of their parents into the program's functions.
+struct Lifter<'input> {
+    functions: IdVec<FunctionId, Function<'input>>,
+}
+
+impl<'input> Lifter<'input> {
+    fn add(
+        &mut self,
+        mut ast: crate::base_ast::Function<'input>,
+        parent: Option<FunctionId>,
+    ) -> FunctionId {
+        // reserve the id, so nested functions come after their parent
+        let id = self.functions.push(Function::comment(ast.definition.name));
+        for param in &mut ast.definition.params {
+            if let Some(default) = &mut param.default {
+                self.lift_expression(default, id);
+            }
+        }
+        self.lift_expression(&mut ast.body, id);
+        for test in &mut ast.tests {
+            for generator in test
+                .forall
+                .iter_mut()
+                .flat_map(|forall| &mut forall.generators)
+            {
+                self.lift_expression(&mut generator.range, id);
+            }
+            self.lift_expression(&mut test.input, id);
+            self.lift_expression(&mut test.output, id);
+        }
+        for benchmark in &mut ast.benchmarks {
+            self.lift_expression(&mut benchmark.input, id);
+        }
+        self.functions[id] = Function {
+            parent,
+            ..Function::from(ast)
+        };
+        id
+    }
+
+    fn lift_statement(
+        &mut self,
+        stmt: &mut crate::base_ast::Statement<'input>,
+        parent: FunctionId,
+    ) {
+        use crate::base_ast::{NestedFunction, Statement};
+        match stmt {
+            Statement::Let(let_) => self.lift_expression(&mut let_.value, parent),
+            Statement::Assign(assign) => self.lift_expression(&mut assign.value, parent),
+            Statement::Assert(assert) => self.lift_expression(&mut assert.condition, parent),
+            Statement::Expression(expr) | Statement::Return(expr, ..) => {
+                self.lift_expression(expr, parent)
+            }
+            Statement::Function(nested) => {
+                let lifted = NestedFunction::Lifted(self.functions.len());
+                if let NestedFunction::Definition(func) = std::mem::replace(nested, lifted) {
+                    self.add(*func, Some(parent));
+                }
+            }
+            Statement::Comment(_) => {}
+        }
+    }
+
+    fn lift_expression(
+        &mut self,
+        expr: &mut crate::base_ast::Expression<'input>,
+        parent: FunctionId,
+    ) {
+        use crate::base_ast::{Else, Expression};
+        match expr {
+            Expression::Expression(expr) | Expression::Paren(expr, _) => {
+                self.lift_expression(expr, parent)
+            }
+            Expression::Block(block, tail, _) => {
+                for stmt in block {
+                    self.lift_statement(stmt, parent);
+                }
+                if let Some(tail) = tail {
+                    self.lift_expression(tail, parent);
+                }
+            }
+            Expression::FunctionCall(call) => {
+                for arg in &mut call.args {
+                    self.lift_expression(arg, parent);
+                }
+            }
+            Expression::Pipe(lhs, call, _) => {
+                self.lift_expression(lhs, parent);
+                for arg in &mut call.args {
+                    self.lift_expression(arg, parent);
+                }
+            }
+            Expression::EnumConstructor(constructor) => {
+                for arg in &mut constructor.args {
+                    self.lift_expression(arg, parent);
+                }
+            }
+            Expression::If(if_) => {
+                let mut if_ = if_;
+                loop {
+                    self.lift_expression(&mut if_.condition, parent);
+                    self.lift_expression(&mut if_.body, parent);
+                    match &mut if_.else_body {
+                        Some(Else::Block(block)) => break self.lift_expression(block, parent),
+                        Some(Else::If(else_if)) => if_ = else_if,
+                        None => break,
+                    }
+                }
+            }
+            Expression::Lambda(lambda) => self.lift_expression(&mut lambda.body, parent),
+            Expression::Try(try_) => {
+                self.lift_expression(&mut try_.body, parent);
+                self.lift_expression(&mut try_.rescue, parent);
+            }
+            Expression::Range(range) => {
+                self.lift_expression(&mut range.start, parent);
+                self.lift_expression(&mut range.end, parent);
+            }
+            Expression::Field(field) => self.lift_expression(&mut field.object, parent),
+            Expression::MethodCall(call) => {
+                self.lift_expression(&mut call.object, parent);
+                for arg in &mut call.args {
+                    self.lift_expression(arg, parent);
+                }
+            }
+            Expression::Op(lhs, _, rhs, _) => {
+                self.lift_expression(lhs, parent);
+                self.lift_expression(rhs, parent);
+            }
+            Expression::ExpressionComment((expr, _)) => self.lift_expression(expr, parent),
+            Expression::Some(value, _, _) => self.lift_expression(value, parent),
+            Expression::Propagate(expr, _) => self.lift_expression(expr, parent),
+            Expression::Variable(_)
+            | Expression::Number(_)
+            | Expression::String(_)
+            | Expression::Unit(_, _)
+            | Expression::Bool(_, _, _)
+            | Expression::None(_, _)
+            | Expression::Error(_) => {}
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(bound(deserialize = "'de: 'input"))]
 pub enum TopLevel<'input> {
     Function(FunctionId),
+    Enum(EnumId),
     Comment(Comment<'input>),
+    Import(Import<'input>),
+    /// The statement at this index of the body of [`Program::script`].
+    Statement(usize),
 }
 
-#[derive(Debug)]
-pub struct Comment<'input> {
-    pub text: &'input str,
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(bound(deserialize = "'de: 'input"))]
+pub struct Import<'input> {
+    pub keyword: Spelling,
+    /// The imported file, relative to the importing one.
+    pub path: &'input str,
+    pub span: Span,
+}
+
+impl Format for Import<'_> {
+    fn format(&self, fmt: &mut Formatter) {
+        fmt.push_keyword(Keyword::Import, self.keyword);
+        fmt.push_string(format!(" \"{}\";\n", self.path));
+    }
 }
 
-impl<'input> Comment<'input> {
-    pub fn new(text: &'input str) -> Self {
-        Self { text }
+impl<'input> From<crate::base_ast::Import<'input>> for Import<'input> {
+    fn from(ast: crate::base_ast::Import<'input>) -> Self {
+        Self {
+            keyword: ast.keyword,
+            path: ast.path,
+            span: ast.span,
+        }
     }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound(deserialize = "'de: 'input"))]
+pub struct Comment<'input> {
+    pub text: &'input str,
+    pub span: Span,
+    /// Whether the comment follows code on its line, where the formatter keeps it.
+    pub trailing: bool,
+}
+
 impl Format for Comment<'_> {
     fn format(&self, fmt: &mut Formatter) {
+        if self.trailing {
+            fmt.push_trailing_comment(self.text);
+            return;
+        }
         fmt.push_str_indented("// ");
         fmt.push_str(self.text);
         fmt.push_str("\n");
     }
 }
 
+/// With [`FormatConfig::wrap_comments`], format the comments on lines of their own in a row at the
+/// start of `comments`, the rest of the comments of a block or file like for [`format_off`],
+/// re-flowed to [`FormatConfig::max_width`]. Returns how many it formatted, unless the first
+/// isn't one of them. The comments that turn the formatter off and on are left to [`format_off`].
+fn format_paragraphs<'c>(
+    fmt: &mut Formatter,
+    comments: impl Iterator<Item = Option<&'c Comment<'c>>>,
+) -> Option<usize> {
+    if !fmt.config.wrap_comments {
+        return None;
+    }
+    let lines = comments
+        .map_while(|comment| {
+            comment.filter(|comment| {
+                !comment.trailing && ![FORMAT_OFF, FORMAT_ON].contains(&comment.text.trim_end())
+            })
+        })
+        .map(|comment| comment.text)
+        .collect::<Vec<_>>();
+    if lines.is_empty() {
+        return None;
+    }
+    let indent = fmt.indent_level * fmt.config.indent_width + "// ".len();
+    let width = fmt.config.max_width.saturating_sub(indent);
+    for line in formatter::wrap_comments(&lines, width) {
+        fmt.push_str_indented("// ");
+        fmt.push_str(&line);
+        fmt.push_str("\n");
+    }
+    Some(lines.len())
+}
+
+/// Turn the formatter off after `off` if that is a [`FORMAT_OFF`] comment and the formatter has
+/// the source: push the source as it is written up to the [`FORMAT_ON`] comment among the `rest`
+/// of the comments of a block or file, in which `None` stands for the items that aren't comments,
+/// or else up to `end`. Returns how many of the rest that skips, not counting the comment that
+/// turns the formatter on again.
+fn format_off<'c>(
+    fmt: &mut Formatter,
+    off: &Comment,
+    rest: impl Iterator<Item = Option<&'c Comment<'c>>>,
+    end: usize,
+) -> Option<usize> {
+    let source = fmt.source?;
+    if off.text.trim_end() != FORMAT_OFF {
+        return None;
+    }
+    let mut skipped = 0;
+    let mut end = end.min(source.len());
+    for comment in rest {
+        if let Some(on) = comment.filter(|comment| comment.text.trim_end() == FORMAT_ON) {
+            // up to the line of the comment
+            end = source[..on.span.start].rfind('\n').map_or(0, |i| i + 1);
+            break;
+        }
+        skipped += 1;
+    }
+    fmt.push_unformatted(source, off.span.end, end);
+    Some(skipped)
+}
+
 impl<'input> From<crate::base_ast::Comment<'input>> for Comment<'input> {
     fn from(ast: crate::base_ast::Comment<'input>) -> Self {
-        Self::new(ast.text)
+        Self {
+            text: ast.text,
+            span: ast.span,
+            trailing: ast.trailing,
+        }
     }
 }
 
 // function -------------------------------------------------------------------
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(bound(deserialize = "'de: 'input"))]
 pub struct Function<'input> {
     pub definition: FunctionDefinition<'input>,
     pub body: Box<Expression<'input>>,
     pub tests: Vec<Test<'input>>,
+    pub benchmarks: Vec<Benchmark<'input>>,
+    /// The function this one is defined in, if it is nested.
+    pub parent: Option<FunctionId>,
+    pub purity: Purity,
+}
+
+/// Whether calling a function can do anything apart from computing its value, see
+/// [`crate::purity`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Purity {
+    /// Its value only depends on its arguments, and calling it has no other effect.
+    Pure,
+    /// It may have effects, or calls something that may.
+    Effectful,
+}
+
+/// The functions built into the language, see [`crate::intrinsics`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Intrinsic {
+    Abs,
+    Min,
+    Max,
+    Pow,
+    Sqrt,
+    Len,
+    Get,
+    Concat,
+    Slice,
+    Contains,
+    ReadLine,
+    Print,
+    ReadFile,
+    WriteFile,
+}
+
+impl Intrinsic {
+    pub const ALL: [Intrinsic; 14] = [
+        Intrinsic::Abs,
+        Intrinsic::Min,
+        Intrinsic::Max,
+        Intrinsic::Pow,
+        Intrinsic::Sqrt,
+        Intrinsic::Len,
+        Intrinsic::Get,
+        Intrinsic::Concat,
+        Intrinsic::Slice,
+        Intrinsic::Contains,
+        Intrinsic::ReadLine,
+        Intrinsic::Print,
+        Intrinsic::ReadFile,
+        Intrinsic::WriteFile,
+    ];
+
+    /// The name programs call it by.
+    pub fn name(self) -> &'static str {
+        match self {
+            Intrinsic::Abs => "abs",
+            Intrinsic::Min => "min",
+            Intrinsic::Max => "max",
+            Intrinsic::Pow => "pow",
+            Intrinsic::Sqrt => "sqrt",
+            Intrinsic::Len => "len",
+            Intrinsic::Get => "get",
+            Intrinsic::Concat => "concat",
+            Intrinsic::Slice => "slice",
+            Intrinsic::Contains => "contains",
+            Intrinsic::ReadLine => "read_line",
+            Intrinsic::Print => "print",
+            Intrinsic::ReadFile => "read_file",
+            Intrinsic::WriteFile => "write_file",
+        }
+    }
+
+    pub fn named(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|intrinsic| intrinsic.name() == name)
+    }
+
+    /// Whether it reads or writes anything, which programs may only do if they are allowed to.
+    pub fn does_io(self) -> bool {
+        matches!(
+            self,
+            Intrinsic::ReadLine | Intrinsic::Print | Intrinsic::ReadFile | Intrinsic::WriteFile
+        )
+    }
+
+    /// The number of arguments it takes.
+    pub fn arity(self) -> usize {
+        crate::intrinsics::signature(self).0.len()
+    }
 }
 
 impl<'input> Function<'input> {
     pub fn comment(content: &'input str) -> Self {
         Self {
             definition: FunctionDefinition {
+                keyword: Spelling::default(),
                 name: content,
+                name_keyword: Spelling::default(),
                 params: Vec::new(),
+                return_type: None,
+                span: 0..0,
             },
-            body: Box::new(Expression::Error),
+            body: Box::new(Expression::Error(0..0)),
             tests: Vec::new(),
+            benchmarks: Vec::new(),
+            parent: None,
+            purity: Purity::Effectful,
         }
     }
 }
@@ -108,15 +844,58 @@ impl Format for Function<'_> {
         if !self.tests.is_empty() {
             fmt.push_str_indented("[\n");
             fmt.indent();
-            for test in &self.tests {
+            // the inputs and what they come to, one line each, with the `=` lined up after the
+            // last line of the longest input
+            let width = |input: &str| input.lines().last().map_or(0, |line| line.chars().count());
+            let table = fmt.config.align_tests.then(|| {
+                let rows = self
+                    .tests
+                    .iter()
+                    .map(|test| {
+                        let input = fmt.one_line(|fmt| test.format_input(fmt));
+                        let output = fmt.one_line(|fmt| test.format_output(fmt));
+                        (input, output)
+                    })
+                    .collect::<Vec<_>>();
+                let column = rows.iter().map(|(input, _)| width(input)).max();
+                (rows, column.unwrap_or(0))
+            });
+            for (i, test) in self.tests.iter().enumerate() {
                 fmt.push_str_indented("");
-                test.format(fmt);
-                fmt.push_str(",\n");
+                match &table {
+                    Some((rows, column)) => {
+                        let (input, output) = &rows[i];
+                        fmt.push_str(input);
+                        fmt.push_string(" ".repeat(column - width(input)));
+                        fmt.push_str(" = ");
+                        fmt.push_str(output);
+                    }
+                    None => test.format(fmt),
+                }
+                fmt.push_list_separator(i, self.tests.len(), true);
+                fmt.push_str("\n");
             }
             fmt.unindent();
             fmt.push_str_indented("]");
         }
-        fmt.push_str("\n\n");
+        if !self.benchmarks.is_empty() {
+            fmt.push_str_indented("<[\n");
+            fmt.indent();
+            for (i, benchmark) in self.benchmarks.iter().enumerate() {
+                fmt.push_str_indented("");
+                benchmark.input.format(fmt);
+                fmt.push_list_separator(i, self.benchmarks.len(), true);
+                fmt.push_str("\n");
+            }
+            fmt.unindent();
+            fmt.push_str_indented("]>");
+        }
+        // nested functions are statements and don't need to be separated as much
+        if self.parent.is_some() {
+            fmt.push_str("\n");
+        } else {
+            fmt.push_blank_line();
+        }
     }
 }
 
@@ -130,56 +909,102 @@ impl<'input> From<crate::base_ast::Function<'input>> for Function<'input> {
                 .into_iter()
                 .map(|test| test.into())
                 .collect::<Vec<_>>(),
+            benchmarks: ast.benchmarks.into_iter().map(Benchmark::from).collect(),
+            parent: None,
+            // until [`Program::link`] knows better
+            purity: Purity::Effectful,
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(bound(deserialize = "'de: 'input"))]
 pub struct FunctionDefinition<'input> {
+    pub keyword: Spelling,
     pub name: &'input str,
+    /// How the name was spelled, if it is `hatch`, which has an emoji spelling like a keyword.
+    pub name_keyword: Spelling,
     pub params: Vec<Parameter<'input>>,
+    pub return_type: Option<Type<'input>>,
+    /// From the keyword to the return type.
+    pub span: Span,
 }
 
 impl Format for FunctionDefinition<'_> {
     fn format(&self, fmt: &mut Formatter) {
+        fmt.push_indent();
+        fmt.push_keyword(Keyword::Function, self.keyword);
+        fmt.push_str(" ");
         if self.name == "hatch" {
-            fmt.push_str_indented("🥚 🐣(");
+            fmt.push_keyword(Keyword::Hatch, self.name_keyword);
         } else {
-            fmt.push_str_indented("🥚 ");
             fmt.push_str(self.name);
-            fmt.push_str("(");
         }
-        fmt.push_string(
-            self.params
-                .iter()
-                .map(|param| format!("{}", param))
-                .collect::<Vec<_>>()
-                .join(", "),
-        );
+        fmt.push_str("(");
+        for (i, param) in self.params.iter().enumerate() {
+            fmt.push_string(param.to_string());
+            if let Some(default) = &param.default {
+                fmt.push_str(" = ");
+                default.format(fmt);
+            }
+            fmt.push_list_separator(i, self.params.len(), false);
+        }
 
         fmt.push_str(") ");
+        if let Some(return_type) = &self.return_type {
+            fmt.push_string(format!("-> {} ", return_type));
+        }
     }
 }
 
 impl<'input> From<crate::base_ast::FunctionDefinition<'input>> for FunctionDefinition<'input> {
     fn from(ast: crate::base_ast::FunctionDefinition<'input>) -> Self {
         Self {
+            keyword: ast.keyword,
             name: ast.name,
+            name_keyword: ast.name_keyword,
             params: ast
                 .params
                 .into_iter()
                 .map(|param| param.into())
                 .collect::<Vec<_>>(),
+            return_type: ast.return_type.map(Type::from),
+            span: ast.span,
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound(deserialize = "'de: 'input"))]
 pub struct Parameter<'input> {
     pub name: &'input str,
+    /// The variable the parameter binds, the fields of enum variants bind none.
+    pub id: Option<VariableId>,
+    pub type_: Option<Type<'input>>,
+    /// The value of the parameter when a call leaves it out, only functions have these.
+    pub default: Option<Box<Expression<'input>>>,
+    pub span: Span,
 }
 
 impl Display for Parameter<'_> {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> Result<(), Error> {
+        write!(fmt, "{}", self.name)?;
+        if let Some(type_) = &self.type_ {
+            write!(fmt, ": {}", type_)?;
+        }
+        Ok(())
+    }
+}
+
+/// A type annotation. Types are not checked yet, only parsed and formatted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound(deserialize = "'de: 'input"))]
+pub struct Type<'input> {
+    pub name: &'input str,
+    pub span: Span,
+}
+
+impl Display for Type<'_> {
     fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> Result<(), Error> {
         write!(fmt, "{}", self.name)
     }
@@ -187,181 +1012,1208 @@ impl Display for Parameter<'_> {
 
 impl<'input> From<crate::base_ast::Parameter<'input>> for Parameter<'input> {
     fn from(ast: crate::base_ast::Parameter<'input>) -> Self {
-        Self { name: ast.name }
+        Self {
+            name: ast.name,
+            id: None,
+            type_: ast.type_.map(Type::from),
+            default: ast.default.map(|default| Box::new((*default).into())),
+            span: ast.span,
+        }
+    }
+}
+
+impl<'input> From<crate::base_ast::Type<'input>> for Type<'input> {
+    fn from(ast: crate::base_ast::Type<'input>) -> Self {
+        Self {
+            name: ast.name,
+            span: ast.span,
+        }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(bound(deserialize = "'de: 'input"))]
 pub struct Test<'input> {
+    pub name: Option<ASTString<'input>>,
+    pub forall: Option<Forall<'input>>,
     pub input: Box<Expression<'input>>,
-    pub output: Box<Expression<'input>>,
+    pub output: Expected<'input>,
+    pub span: Span,
+}
+
+/// What the input of a test has to come to.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(bound(deserialize = "'de: 'input"))]
+pub enum Expected<'input> {
+    Value(Box<Expression<'input>>),
+    /// `input = error`, evaluating the input has to fail.
+    Failure(Span),
 }
 
 impl Format for Test<'_> {
     fn format(&self, fmt: &mut Formatter) {
-        self.input.format(fmt);
+        self.format_input(fmt);
         fmt.push_str(" = ");
-        self.output.format(fmt);
+        self.format_output(fmt);
+    }
+}
+
+impl Test<'_> {
+    /// Everything before the `=`: the name, the `forall` and the input.
+    fn format_input(&self, fmt: &mut Formatter) {
+        if let Some(name) = &self.name {
+            fmt.push_string(name.to_string());
+            fmt.push_str(": ");
+        }
+        if let Some(forall) = &self.forall {
+            forall.format(fmt);
+        }
+        self.input.format(fmt);
+    }
+
+    fn format_output(&self, fmt: &mut Formatter) {
+        match &self.output {
+            Expected::Value(output) => output.format(fmt),
+            Expected::Failure(_) => fmt.push_str("error"),
+        }
     }
 }
 
 impl<'input> From<crate::base_ast::Test<'input>> for Test<'input> {
     fn from(ast: crate::base_ast::Test<'input>) -> Self {
+        let output = match *ast.output {
+            // the parser doesn't know `error` from a variable
+            crate::base_ast::Expression::Variable(var) if var.name == "error" => {
+                Expected::Failure(var.span)
+            }
+            output => Expected::Value(Box::new(output.into())),
+        };
         Self {
+            name: ast.name.map(ASTString::from),
+            forall: ast.forall.map(Forall::from),
             input: Box::new(ast.input.into()),
-            output: Box::new(ast.output.into()),
+            output,
+            span: ast.span,
         }
     }
 }
 
-// statements -----------------------------------------------------------------
+/// A benchmark after a function, `<[10000]>`, the first argument of the calls that are timed.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(bound(deserialize = "'de: 'input"))]
+pub struct Benchmark<'input> {
+    pub input: Box<Expression<'input>>,
+    pub span: Span,
+}
 
-#[derive(Debug)]
-pub enum Statement<'input> {
-    Let(Let<'input>),
-    Expression(Box<Expression<'input>>),
-    Return(Box<Expression<'input>>),
-    Comment(Comment<'input>),
-    Error,
+impl<'input> From<crate::base_ast::Benchmark<'input>> for Benchmark<'input> {
+    fn from(ast: crate::base_ast::Benchmark<'input>) -> Self {
+        Self {
+            input: Box::new(ast.input.into()),
+            span: ast.span,
+        }
+    }
 }
 
-impl Format for Statement<'_> {
+/// `forall n in 0..10:` before a test, which makes it a test for the values of `n`.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(bound(deserialize = "'de: 'input"))]
+pub struct Forall<'input> {
+    pub keyword: Spelling,
+    pub generators: Vec<Generator<'input>>,
+    /// Without the colon.
+    pub span: Span,
+}
+
+impl Format for Forall<'_> {
     fn format(&self, fmt: &mut Formatter) {
-        match self {
-            Statement::Let(let_) => let_.format(fmt),
-            Statement::Expression(expr) => {
-                fmt.push_str_indented("");
-                expr.format(fmt);
-                fmt.push_str(";\n");
-            }
-            Statement::Return(expr) => {
-                fmt.push_str_indented("🐔 ");
-                expr.format(fmt);
-                fmt.push_str(";\n");
+        fmt.push_keyword(Keyword::Forall, self.keyword);
+        fmt.push_str(" ");
+        for (i, generator) in self.generators.iter().enumerate() {
+            if i > 0 {
+                fmt.push_str(", ");
             }
-            Statement::Comment(comment) => comment.format(fmt),
-            Statement::Error => fmt.push_string_indented("error!\n".red().to_string()),
+            generator.format(fmt);
         }
+        fmt.push_str(": ");
     }
 }
 
-impl<'input> From<crate::base_ast::Statement<'input>> for Statement<'input> {
-    fn from(ast: crate::base_ast::Statement<'input>) -> Self {
-        match ast {
-            crate::base_ast::Statement::Let(let_) => Self::Let(let_.into()),
-            crate::base_ast::Statement::Expression(expr) => Self::Expression(Box::new(expr.into())),
-            crate::base_ast::Statement::Return(expr) => Self::Return(Box::new(expr.into())),
-            crate::base_ast::Statement::Comment(comment) => Self::Comment(comment.into()),
-            crate::base_ast::Statement::Error => Self::Error,
+impl<'input> From<crate::base_ast::Forall<'input>> for Forall<'input> {
+    fn from(ast: crate::base_ast::Forall<'input>) -> Self {
+        Self {
+            keyword: ast.keyword,
+            generators: ast.generators.into_iter().map(Generator::from).collect(),
+            span: ast.span,
         }
     }
 }
 
-#[derive(Debug)]
-pub struct Let<'input> {
+/// `n in 0..10`, a variable of a [`Forall`] and the range it takes its values from.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(bound(deserialize = "'de: 'input"))]
+pub struct Generator<'input> {
     pub name: &'input str,
-    pub value: Box<Expression<'input>>,
+    pub id: VariableId,
+    /// The spelling of `in`.
+    pub keyword: Spelling,
+    pub range: Box<Expression<'input>>,
+    pub span: Span,
 }
 
-impl Format for Let<'_> {
+impl Format for Generator<'_> {
     fn format(&self, fmt: &mut Formatter) {
-        fmt.push_str_indented("let ");
         fmt.push_str(self.name);
-        fmt.push_str(" = ");
-        self.value.format(fmt);
-        fmt.push_str(";\n");
+        fmt.push_str(" ");
+        fmt.push_keyword(Keyword::In, self.keyword);
+        fmt.push_str(" ");
+        self.range.format(fmt);
     }
 }
 
-impl<'input> From<crate::base_ast::Let<'input>> for Let<'input> {
-    fn from(ast: crate::base_ast::Let<'input>) -> Self {
+impl<'input> From<crate::base_ast::Generator<'input>> for Generator<'input> {
+    fn from(ast: crate::base_ast::Generator<'input>) -> Self {
         Self {
             name: ast.name,
-            value: Box::new(ast.value.into()),
+            id: VariableId::unresolved(),
+            keyword: ast.keyword,
+            range: Box::new((*ast.range).into()),
+            span: ast.span,
         }
     }
 }
 
-#[derive(Debug)]
-pub struct FunctionCall<'input> {
+// enums ----------------------------------------------------------------------
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(bound(deserialize = "'de: 'input"))]
+pub struct Enum<'input> {
+    pub keyword: Spelling,
     pub name: &'input str,
-    pub args: Vec<Box<Expression<'input>>>,
+    pub variants: Vec<Variant<'input>>,
+    pub span: Span,
 }
 
-impl Format for FunctionCall<'_> {
+impl Format for Enum<'_> {
     fn format(&self, fmt: &mut Formatter) {
-        fmt.push_str(&format!("{}(", self.name));
-        for (i, arg) in self.args.iter().enumerate() {
-            if i > 0 {
-                fmt.push_str(", ");
-            }
-            arg.format(fmt);
+        fmt.push_indent();
+        fmt.push_keyword(Keyword::Enum, self.keyword);
+        fmt.push_str(" ");
+        fmt.push_str(self.name);
+        fmt.push_str(" {\n");
+        fmt.indent();
+        for (i, variant) in self.variants.iter().enumerate() {
+            fmt.push_str_indented("");
+            variant.format(fmt);
+            fmt.push_list_separator(i, self.variants.len(), true);
+            fmt.push_str("\n");
         }
-        fmt.push_str(")");
+        fmt.unindent();
+        fmt.push_str_indented("}");
+        fmt.push_blank_line();
     }
 }
 
-impl<'input> From<crate::base_ast::FunctionCall<'input>> for FunctionCall<'input> {
-    fn from(ast: crate::base_ast::FunctionCall<'input>) -> Self {
+impl<'input> From<crate::base_ast::Enum<'input>> for Enum<'input> {
+    fn from(ast: crate::base_ast::Enum<'input>) -> Self {
         Self {
+            keyword: ast.keyword,
             name: ast.name,
-            args: ast
-                .args
+            variants: ast
+                .variants
                 .into_iter()
-                .map(|arg| Box::new(arg.into()))
+                .map(|variant| variant.into())
                 .collect::<Vec<_>>(),
+            span: ast.span,
         }
     }
 }
 
-// expressions ----------------------------------------------------------------
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(bound(deserialize = "'de: 'input"))]
+pub struct Variant<'input> {
+    pub name: &'input str,
+    pub fields: Vec<Parameter<'input>>,
+    pub span: Span,
+}
 
-#[derive(Debug)]
+impl Format for Variant<'_> {
+    fn format(&self, fmt: &mut Formatter) {
+        fmt.push_str(self.name);
+        if !self.fields.is_empty() {
+            fmt.push_str("(");
+            for (i, field) in self.fields.iter().enumerate() {
+                fmt.push_string(field.to_string());
+                fmt.push_list_separator(i, self.fields.len(), false);
+            }
+            fmt.push_str(")");
+        }
+    }
+}
+
+impl<'input> From<crate::base_ast::Variant<'input>> for Variant<'input> {
+    fn from(ast: crate::base_ast::Variant<'input>) -> Self {
+        Self {
+            name: ast.name,
+            fields: ast
+                .fields
+                .into_iter()
+                .map(|field| field.into())
+                .collect::<Vec<_>>(),
+            span: ast.span,
+        }
+    }
+}
+
+// statements -----------------------------------------------------------------
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound(deserialize = "'de: 'input"))]
+pub enum Statement<'input> {
+    Let(Let<'input>),
+    Assign(Assign<'input>),
+    Expression(Box<Expression<'input>>),
+    /// The span covers the whole statement, without the semicolon.
+    Return(Box<Expression<'input>>, Spelling, Span),
+    Assert(Assert<'input>),
+    Comment(Comment<'input>),
+    /// A function defined inside another one, the function itself is in [`Program::functions`].
+    Function(FunctionId),
+}
+
+impl Format for Statement<'_> {
+    fn format(&self, fmt: &mut Formatter) {
+        match self {
+            Statement::Let(let_) => let_.format(fmt),
+            Statement::Assign(assign) => assign.format(fmt),
+            Statement::Assert(assert) => assert.format(fmt),
+            Statement::Expression(expr) => {
+                fmt.push_str_indented("");
+                expr.format(fmt);
+                fmt.push_str(";\n");
+            }
+            Statement::Return(expr, keyword, _) => {
+                fmt.push_indent();
+                fmt.push_keyword(Keyword::Return, *keyword);
+                fmt.push_str(" ");
+                expr.format(fmt);
+                fmt.push_str(";\n");
+            }
+            Statement::Comment(comment) => comment.format(fmt),
+            Statement::Function(func) => match fmt.program {
+                Some(program) => program.functions[*func].format(fmt),
+                None => fmt.push_string_indented(format!("{:?}\n", func)),
+            },
+        }
+    }
+}
+
+impl Statement<'_> {
+    /// The span of the whole statement, without the semicolon. Nested functions are looked up in
+    /// the program they were lifted into.
+    pub fn span(&self, program: &Program) -> Span {
+        match self {
+            Statement::Let(let_) => let_.span.clone(),
+            Statement::Assign(assign) => assign.span.clone(),
+            Statement::Expression(expr) => expr.span(),
+            Statement::Return(_, _, span) => span.clone(),
+            Statement::Assert(assert) => assert.span.clone(),
+            Statement::Comment(comment) => comment.span.clone(),
+            Statement::Function(func) => program.functions[*func].definition.span.clone(),
+        }
+    }
+}
+
+impl<'input> From<crate::base_ast::Statement<'input>> for Statement<'input> {
+    fn from(ast: crate::base_ast::Statement<'input>) -> Self {
+        match ast {
+            crate::base_ast::Statement::Let(let_) => Self::Let(let_.into()),
+            crate::base_ast::Statement::Assign(assign) => Self::Assign(assign.into()),
+            crate::base_ast::Statement::Expression(expr) => Self::Expression(Box::new(expr.into())),
+            crate::base_ast::Statement::Return(expr, keyword, span) => {
+                Self::Return(Box::new(expr.into()), keyword, span)
+            }
+            crate::base_ast::Statement::Assert(assert) => Self::Assert(assert.into()),
+            crate::base_ast::Statement::Comment(comment) => Self::Comment(comment.into()),
+            crate::base_ast::Statement::Function(nested) => match nested {
+                crate::base_ast::NestedFunction::Lifted(index) => {
+                    Self::Function(FunctionId::from_index(index))
+                }
+                crate::base_ast::NestedFunction::Definition(_) => {
+                    unreachable!("nested functions are lifted by Program::link")
+                }
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound(deserialize = "'de: 'input"))]
+pub struct Let<'input> {
+    pub keyword: Spelling,
+    pub name: &'input str,
+    pub id: VariableId,
+    /// The spelling of `mut`, if the binding is mutable.
+    pub mutable: Option<Spelling>,
+    pub value: Box<Expression<'input>>,
+    /// The whole binding, without the semicolon.
+    pub span: Span,
+}
+
+impl Format for Let<'_> {
+    fn format(&self, fmt: &mut Formatter) {
+        fmt.push_indent();
+        fmt.push_keyword(Keyword::Let, self.keyword);
+        fmt.push_str(" ");
+        if let Some(mutable) = self.mutable {
+            fmt.push_keyword(Keyword::Mut, mutable);
+            fmt.push_str(" ");
+        }
+        fmt.push_str(self.name);
+        fmt.push_str(" = ");
+        self.value.format(fmt);
+        fmt.push_str(";\n");
+    }
+}
+
+impl<'input> From<crate::base_ast::Let<'input>> for Let<'input> {
+    fn from(ast: crate::base_ast::Let<'input>) -> Self {
+        Self {
+            keyword: ast.keyword,
+            name: ast.name,
+            id: VariableId::unresolved(),
+            mutable: ast.mutable,
+            value: Box::new(ast.value.into()),
+            span: ast.span,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound(deserialize = "'de: 'input"))]
+pub struct Assert<'input> {
+    pub keyword: Spelling,
+    pub condition: Box<Expression<'input>>,
+    /// The condition as written, to report a failing assertion.
+    pub text: &'input str,
+    /// The whole assertion, without the semicolon.
+    pub span: Span,
+}
+
+impl Format for Assert<'_> {
+    fn format(&self, fmt: &mut Formatter) {
+        fmt.push_indent();
+        fmt.push_keyword(Keyword::Assert, self.keyword);
+        fmt.push_str(" ");
+        self.condition.format(fmt);
+        fmt.push_str(";\n");
+    }
+}
+
+impl<'input> From<crate::base_ast::Assert<'input>> for Assert<'input> {
+    fn from(ast: crate::base_ast::Assert<'input>) -> Self {
+        Self {
+            keyword: ast.keyword,
+            condition: Box::new(ast.condition.into()),
+            text: ast.text,
+            span: ast.span,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound(deserialize = "'de: 'input"))]
+pub struct Assign<'input> {
+    pub target: &'input str,
+    /// The variable assigned to, if the target is bound at all.
+    pub variable: Option<VariableId>,
+    /// The operator of a compound assignment like `x += 1`.
+    pub op: Option<Opcode>,
+    pub value: Box<Expression<'input>>,
+    pub span: Span,
+}
+
+impl Format for Assign<'_> {
+    fn format(&self, fmt: &mut Formatter) {
+        fmt.push_str_indented(self.target);
+        fmt.push_str(" ");
+        if let Some(op) = self.op {
+            fmt.push_string(op.to_string());
+        }
+        fmt.push_str("= ");
+        self.value.format(fmt);
+        fmt.push_str(";\n");
+    }
+}
+
+impl<'input> From<crate::base_ast::Assign<'input>> for Assign<'input> {
+    fn from(ast: crate::base_ast::Assign<'input>) -> Self {
+        Self {
+            target: ast.target,
+            variable: None,
+            op: ast.op.map(|op| op.into()),
+            value: Box::new(ast.value.into()),
+            span: ast.span,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound(deserialize = "'de: 'input"))]
+pub enum FunctionCall<'input> {
+    /// A call to a function of the program by name.
+    Direct {
+        function: FunctionId,
+        name: &'input str,
+        args: Vec<Box<Expression<'input>>>,
+        /// How many of the arguments are defaults filled in for omitted ones, at the end of `args`.
+        defaulted: usize,
+        /// Written as `x |> f`, with `x` as the first argument.
+        piped: bool,
+        /// Its value is what the function or lambda around it returns, see [`crate::tail_calls`].
+        tail: bool,
+        span: Span,
+    },
+    /// A call to a function built into the language, when no function of the program has its name.
+    Intrinsic {
+        intrinsic: Intrinsic,
+        args: Vec<Box<Expression<'input>>>,
+        /// Written as `x |> f`, with `x` as the first argument.
+        piped: bool,
+        /// Its value is what the function or lambda around it returns, see [`crate::tail_calls`].
+        tail: bool,
+        span: Span,
+    },
+    /// A call to the function value the callee evaluates to, like a lambda bound to a variable.
+    Indirect {
+        callee: Box<Expression<'input>>,
+        args: Vec<Box<Expression<'input>>>,
+        /// Written as `x |> f`, with `x` as the first argument.
+        piped: bool,
+        /// Its value is what the function or lambda around it returns, see [`crate::tail_calls`].
+        tail: bool,
+        span: Span,
+    },
+}
+
+impl<'input> FunctionCall<'input> {
+    /// A call written as `lhs |> call`, which passes `lhs` as the first argument. The span is the
+    /// one of the whole pipe.
+    fn pipe(
+        lhs: Expression<'input>,
+        call: crate::base_ast::FunctionCall<'input>,
+        span: Span,
+    ) -> Self {
+        let callee = Variable::callee(&call);
+        let mut args = vec![Box::new(lhs)];
+        args.extend(call.args.into_iter().map(|arg| Box::new(arg.into())));
+        Self::Indirect {
+            callee: Box::new(Expression::Variable(callee)),
+            args,
+            piped: true,
+            tail: false,
+            span,
+        }
+    }
+
+    pub fn piped(&self) -> bool {
+        match self {
+            FunctionCall::Direct { piped, .. }
+            | FunctionCall::Intrinsic { piped, .. }
+            | FunctionCall::Indirect { piped, .. } => *piped,
+        }
+    }
+
+    pub fn tail(&self) -> bool {
+        match self {
+            FunctionCall::Direct { tail, .. }
+            | FunctionCall::Intrinsic { tail, .. }
+            | FunctionCall::Indirect { tail, .. } => *tail,
+        }
+    }
+
+    /// The call in the source, without the piped argument.
+    pub fn span(&self) -> &Span {
+        match self {
+            FunctionCall::Direct { span, .. }
+            | FunctionCall::Intrinsic { span, .. }
+            | FunctionCall::Indirect { span, .. } => span,
+        }
+    }
+
+    /// The arguments written in the call, without the defaults filled in for omitted ones, which
+    /// belong to the callee.
+    pub fn args(&self) -> &[Box<Expression<'input>>] {
+        match self {
+            FunctionCall::Direct {
+                args, defaulted, ..
+            } => &args[..args.len() - defaulted],
+            FunctionCall::Intrinsic { args, .. } | FunctionCall::Indirect { args, .. } => args,
+        }
+    }
+
+    /// The arguments written in the call, like [`FunctionCall::args`].
+    pub fn args_mut(&mut self) -> &mut [Box<Expression<'input>>] {
+        match self {
+            FunctionCall::Direct {
+                args, defaulted, ..
+            } => {
+                let given = args.len() - *defaulted;
+                &mut args[..given]
+            }
+            FunctionCall::Intrinsic { args, .. } | FunctionCall::Indirect { args, .. } => args,
+        }
+    }
+}
+
+impl FunctionCall<'_> {
+    /// Format what is called and the arguments that aren't piped into the call.
+    fn format_callee(&self, args: &[Box<Expression>], fmt: &mut Formatter) {
+        match self {
+            FunctionCall::Direct { name, .. } => fmt.push_str(name),
+            FunctionCall::Intrinsic { intrinsic, .. } => fmt.push_str(intrinsic.name()),
+            FunctionCall::Indirect { callee, .. } => callee.format(fmt),
+        }
+        if !self.piped() || !args.is_empty() {
+            fmt.push_arguments(args, |arg, fmt| arg.format(fmt));
+        }
+    }
+}
+
+impl Format for FunctionCall<'_> {
+    fn format(&self, fmt: &mut Formatter) {
+        let args = self.args();
+        if self.piped() {
+            // a lambda would take the rest of the pipeline as part of its body, and a conditional
+            // its last branch
+            let (piped, args) = (&args[0], &args[1..]);
+            // a pipeline that doesn't fit continues on the next line
+            fmt.group(|fmt| {
+                format_operand(piped, Precedence::Pipeline, fmt);
+                fmt.nest(|fmt| {
+                    fmt.push_line();
+                    fmt.push_str("|> ");
+                    self.format_callee(args, fmt);
+                });
+            });
+            return;
+        }
+        self.format_callee(args, fmt);
+    }
+}
+
+/// Calls start out as indirect calls through a variable, [`Program`] turns the ones naming a
+/// function into direct calls once all functions are known.
+impl<'input> From<crate::base_ast::FunctionCall<'input>> for FunctionCall<'input> {
+    fn from(ast: crate::base_ast::FunctionCall<'input>) -> Self {
+        Self::Indirect {
+            callee: Box::new(Expression::Variable(Variable::callee(&ast))),
+            args: ast
+                .args
+                .into_iter()
+                .map(|arg| Box::new(arg.into()))
+                .collect::<Vec<_>>(),
+            piped: false,
+            tail: false,
+            span: ast.span,
+        }
+    }
+}
+
+/// Gives every binding its own [`VariableId`] and points the variables and assignments at the
+/// binding their name refers to.
+struct ResolveVariables<'input> {
+    variables: IdVec<VariableId, Binding<'input>>,
+    /// The function being resolved.
+    function: FunctionId,
+    /// The variables bound in each enclosing scope, innermost last.
+    scopes: Vec<Vec<(&'input str, VariableId)>>,
+}
+
+impl<'input> ResolveVariables<'input> {
+    fn bind(&mut self, name: &'input str, mutable: bool) -> VariableId {
+        let id = self.variables.push(Binding {
+            name,
+            mutable,
+            function: self.function,
+        });
+        self.scopes.last_mut().unwrap().push((name, id));
+        id
+    }
+
+    /// The innermost binding of the name.
+    fn lookup(&self, name: &str) -> Option<VariableId> {
+        self.scopes
+            .iter()
+            .flatten()
+            .rev()
+            .find(|(bound, _)| *bound == name)
+            .map(|(_, id)| *id)
+    }
+}
+
+impl<'input> VisitorMut<'input> for ResolveVariables<'input> {
+    fn visit_function_mut(&mut self, func: &mut Function<'input>) {
+        self.scopes = vec![Vec::new()];
+        for param in &mut func.definition.params {
+            param.id = Some(self.bind(param.name, false));
+        }
+        walk_function_mut(self, func);
+    }
+
+    fn visit_test_mut(&mut self, test: &mut Test<'input>) {
+        self.scopes.push(Vec::new());
+        if let Some(forall) = &mut test.forall {
+            // the ranges can't refer to the variables, they don't depend on each other
+            for generator in &mut forall.generators {
+                self.visit_expression_mut(&mut generator.range);
+            }
+            for generator in &mut forall.generators {
+                generator.id = self.bind(generator.name, false);
+            }
+        }
+        self.visit_expression_mut(&mut test.input);
+        if let Expected::Value(output) = &mut test.output {
+            self.visit_expression_mut(output);
+        }
+        self.scopes.pop();
+    }
+
+    fn visit_statement_mut(&mut self, stmt: &mut Statement<'input>) {
+        match stmt {
+            Statement::Let(let_) => {
+                // the value can't refer to the variable it is bound to
+                self.visit_expression_mut(&mut let_.value);
+                let_.id = self.bind(let_.name, let_.mutable.is_some());
+            }
+            Statement::Assign(assign) => {
+                assign.variable = self.lookup(assign.target);
+                self.visit_expression_mut(&mut assign.value);
+            }
+            _ => walk_statement_mut(self, stmt),
+        }
+    }
+
+    fn visit_expression_mut(&mut self, expr: &mut Expression<'input>) {
+        match expr {
+            Expression::Variable(var) => var.id = self.lookup(var.name),
+            Expression::Block(..) => {
+                self.scopes.push(Vec::new());
+                walk_expression_mut(self, expr);
+                self.scopes.pop();
+            }
+            Expression::Lambda(lambda) => {
+                self.scopes.push(Vec::new());
+                for param in &mut lambda.params {
+                    param.id = Some(self.bind(param.name, false));
+                }
+                self.visit_expression_mut(&mut lambda.body);
+                self.scopes.pop();
+            }
+            Expression::Try(try_) => {
+                self.visit_expression_mut(&mut try_.body);
+                self.scopes.push(Vec::new());
+                try_.error_id = self.bind(try_.error, false);
+                self.visit_expression_mut(&mut try_.rescue);
+                self.scopes.pop();
+            }
+            _ => walk_expression_mut(self, expr),
+        }
+    }
+}
+
+/// Collects the functions a function calls or uses as a value, see [`Program::calls`].
+struct Callees<'a, 'input> {
+    program: &'a Program<'input>,
+    function: FunctionId,
+    callees: Vec<FunctionId>,
+}
+
+impl<'input> Visitor<'input> for Callees<'_, 'input> {
+    fn visit_expression(&mut self, expr: &Expression<'input>) {
+        let callee = match expr {
+            Expression::FunctionCall(FunctionCall::Direct { function, .. }) => Some(*function),
+            Expression::Variable(var) if var.id.is_none() => {
+                self.program.function_named(self.function, var.name)
+            }
+            _ => None,
+        };
+        if let Some(callee) = callee.filter(|callee| !self.callees.contains(callee)) {
+            self.callees.push(callee);
+        }
+        walk_expression(self, expr);
+    }
+}
+
+/// Turns calls through a name that isn't bound locally but names a function into direct calls.
+struct ResolveCalls<'input> {
+    /// The top level functions, which are visible everywhere.
+    functions: HashMap<&'input str, FunctionId>,
+    /// The functions nested in the current function or the functions around it, innermost last.
+    nested: Vec<(&'input str, FunctionId)>,
+    /// The names bound in each enclosing scope, innermost last.
+    scopes: Vec<Vec<&'input str>>,
+}
+
+impl<'input> ResolveCalls<'input> {
+    fn is_bound(&self, name: &str) -> bool {
+        self.scopes.iter().flatten().any(|bound| *bound == name)
+    }
+
+    /// The function a name refers to, unless it is shadowed by a local binding.
+    fn function(&self, name: &str) -> Option<FunctionId> {
+        if self.is_bound(name) {
+            return None;
+        }
+        self.nested
+            .iter()
+            .rev()
+            .find(|(nested, _)| *nested == name)
+            .map(|(_, id)| *id)
+            .or_else(|| self.functions.get(name).copied())
+    }
+
+    /// The intrinsic a name refers to, unless a local binding or a function of the program has it.
+    fn intrinsic(&self, name: &str) -> Option<Intrinsic> {
+        if self.is_bound(name) || self.function(name).is_some() {
+            return None;
+        }
+        Intrinsic::named(name)
+    }
+
+    fn resolve(&self, call: &mut FunctionCall<'input>) {
+        if let FunctionCall::Indirect {
+            callee,
+            args,
+            piped,
+            tail,
+            span,
+        } = call
+        {
+            if let Expression::Variable(Variable { name, .. }) = **callee {
+                if let Some(function) = self.function(name) {
+                    let args = std::mem::take(args);
+                    *call = FunctionCall::Direct {
+                        function,
+                        name,
+                        args,
+                        defaulted: 0,
+                        piped: *piped,
+                        tail: *tail,
+                        span: span.clone(),
+                    };
+                } else if let Some(intrinsic) = self.intrinsic(name) {
+                    let args = std::mem::take(args);
+                    *call = FunctionCall::Intrinsic {
+                        intrinsic,
+                        args,
+                        piped: *piped,
+                        tail: *tail,
+                        span: span.clone(),
+                    };
+                }
+            }
+        }
+    }
+}
+
+/// Adds the default values of the parameters a direct call leaves out.
+struct FillDefaults<'input> {
+    /// The default of each parameter of each function.
+    defaults: IdVec<FunctionId, Vec<Option<Box<Expression<'input>>>>>,
+}
+
+impl<'input> VisitorMut<'input> for FillDefaults<'input> {
+    fn visit_expression_mut(&mut self, expr: &mut Expression<'input>) {
+        // fill in after walking, so a default calling its own function isn't expanded forever
+        walk_expression_mut(self, expr);
+        if let Expression::FunctionCall(FunctionCall::Direct {
+            function,
+            args,
+            defaulted,
+            ..
+        }) = expr
+        {
+            let given = args.len();
+            let omitted = self.defaults[*function].iter().skip(given);
+            // a parameter without a default ends the arguments that can be filled in
+            args.extend(omitted.map_while(|default| default.clone()));
+            *defaulted = args.len() - given;
+        }
+    }
+}
+
+/// Turns `+` with a string operand into [`Opcode::Concat`], as far as the operands are known to be
+/// strings without running the program.
+struct ResolveOperators {
+    /// The variables bound to a string.
+    strings: HashSet<VariableId>,
+}
+
+impl ResolveOperators {
+    fn is_string(&self, expr: &Expression) -> bool {
+        match expr {
+            Expression::String(_) | Expression::Op(_, Opcode::Concat, _, _) => true,
+            Expression::Expression(expr) | Expression::Paren(expr, _) => self.is_string(expr),
+            Expression::Block(_, Some(tail), _) => self.is_string(tail),
+            Expression::Variable(var) => self.is_string_variable(var.id),
+            _ => false,
+        }
+    }
+
+    fn is_string_variable(&self, id: Option<VariableId>) -> bool {
+        id.is_some_and(|id| self.strings.contains(&id))
+    }
+}
+
+impl<'input> VisitorMut<'input> for ResolveOperators {
+    fn visit_statement_mut(&mut self, stmt: &mut Statement<'input>) {
+        walk_statement_mut(self, stmt);
+        match stmt {
+            Statement::Let(let_) if self.is_string(&let_.value) => {
+                self.strings.insert(let_.id);
+            }
+            Statement::Assign(assign)
+                if matches!(assign.op, Some(Opcode::Add))
+                    && (self.is_string_variable(assign.variable)
+                        || self.is_string(&assign.value)) =>
+            {
+                assign.op = Some(Opcode::Concat);
+            }
+            _ => {}
+        }
+    }
+
+    fn visit_expression_mut(&mut self, expr: &mut Expression<'input>) {
+        match expr {
+            Expression::Op(..) => {
+                walk_expression_mut(self, expr);
+                if let Expression::Op(lhs, op @ Opcode::Add, rhs, _) = expr {
+                    if self.is_string(lhs) || self.is_string(rhs) {
+                        *op = Opcode::Concat;
+                    }
+                }
+            }
+            _ => walk_expression_mut(self, expr),
+        }
+    }
+}
+
+impl<'input> VisitorMut<'input> for ResolveCalls<'input> {
+    fn visit_function_mut(&mut self, func: &mut Function<'input>) {
+        self.scopes = vec![func.definition.params.iter().map(|p| p.name).collect()];
+        walk_function_mut(self, func);
+    }
+
+    fn visit_statement_mut(&mut self, stmt: &mut Statement<'input>) {
+        walk_statement_mut(self, stmt);
+        if let Statement::Let(let_) = stmt {
+            self.scopes.last_mut().unwrap().push(let_.name);
+        }
+    }
+
+    fn visit_expression_mut(&mut self, expr: &mut Expression<'input>) {
+        match expr {
+            Expression::Block(..) => {
+                self.scopes.push(Vec::new());
+                walk_expression_mut(self, expr);
+                self.scopes.pop();
+            }
+            Expression::Lambda(lambda) => {
+                // functions are global, a lambda doesn't need to capture them
+                lambda
+                    .captures
+                    .retain(|name| self.function(name).is_none() && self.intrinsic(name).is_none());
+                self.scopes
+                    .push(lambda.params.iter().map(|param| param.name).collect());
+                walk_expression_mut(self, expr);
+                self.scopes.pop();
+            }
+            Expression::Try(try_) => {
+                self.visit_expression_mut(&mut try_.body);
+                self.scopes.push(vec![try_.error]);
+                self.visit_expression_mut(&mut try_.rescue);
+                self.scopes.pop();
+            }
+            Expression::FunctionCall(_) => {
+                walk_expression_mut(self, expr);
+                if let Expression::FunctionCall(call) = expr {
+                    self.resolve(call);
+                }
+            }
+            _ => walk_expression_mut(self, expr),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound(deserialize = "'de: 'input"))]
+pub struct EnumConstructor<'input> {
+    pub enum_name: &'input str,
+    pub variant: &'input str,
+    pub args: Vec<Box<Expression<'input>>>,
+    pub span: Span,
+}
+
+impl Format for EnumConstructor<'_> {
+    fn format(&self, fmt: &mut Formatter) {
+        fmt.push_str(&format!("{}::{}", self.enum_name, self.variant));
+        if !self.args.is_empty() {
+            fmt.push_arguments(&self.args, |arg, fmt| arg.format(fmt));
+        }
+    }
+}
+
+impl<'input> From<crate::base_ast::EnumConstructor<'input>> for EnumConstructor<'input> {
+    fn from(ast: crate::base_ast::EnumConstructor<'input>) -> Self {
+        Self {
+            enum_name: ast.enum_name,
+            variant: ast.variant,
+            args: ast
+                .args
+                .into_iter()
+                .map(|arg| Box::new(arg.into()))
+                .collect::<Vec<_>>(),
+            span: ast.span,
+        }
+    }
+}
+
+// expressions ----------------------------------------------------------------
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound(deserialize = "'de: 'input"))]
 pub enum Expression<'input> {
     Expression(Box<Expression<'input>>),
-    Block(Vec<Statement<'input>>),
+    /// An expression in parentheses, with the span of them. Only the formatter tells it apart
+    /// from the expression, to keep the parentheses that were written.
+    Paren(Box<Expression<'input>>, Span),
+    /// Statements, optionally followed by an expression without a semicolon that is the value of
+    /// the block, with the span of the braces and everything between them.
+    Block(
+        Vec<Statement<'input>>,
+        Option<Box<Expression<'input>>>,
+        Span,
+    ),
     FunctionCall(FunctionCall<'input>),
+    EnumConstructor(EnumConstructor<'input>),
     Variable(Variable<'input>),
-    Number(i32),
+    Number(Number<'input>),
     String(ASTString<'input>),
+    /// `()`, the value of expressions that have no meaningful value.
+    Unit(Spelling, Span),
+    /// `true` or `false`.
+    Bool(bool, Spelling, Span),
+    /// `none`, a missing value.
+    None(Spelling, Span),
+    /// `some(value)`, a value that is present. Its field `value` is the value inside.
+    Some(Box<Expression<'input>>, Spelling, Span),
     If(If<'input>),
-    Op(Box<Expression<'input>>, Opcode, Box<Expression<'input>>),
+    Lambda(Lambda<'input>),
+    /// `object.name`
+    Field(Field<'input>),
+    /// `object.method(args)`
+    MethodCall(MethodCall<'input>),
+    /// `try { ... } rescue error { ... }`
+    Try(Try<'input>),
+    Range(Range<'input>),
+    /// `lhs op rhs`, with the span of the whole operation.
+    Op(
+        Box<Expression<'input>>,
+        Opcode,
+        Box<Expression<'input>>,
+        Span,
+    ),
     ExpressionComment((Box<Expression<'input>>, Comment<'input>)),
-    Error,
+    /// Where the parser recovered from a syntax error.
+    Error(Span),
+}
+
+/// The variable `expr?` binds `expr` to. It can't be written in the source, so it doesn't clash
+/// with anything, and the formatter can tell the desugared block apart from one written out.
+const PROPAGATED: &str = "?";
+
+impl<'input> Expression<'input> {
+    /// The span of the whole expression.
+    pub fn span(&self) -> Span {
+        match self {
+            Expression::Expression(expr) | Expression::ExpressionComment((expr, _)) => expr.span(),
+            Expression::Block(_, _, span)
+            | Expression::Paren(_, span)
+            | Expression::Unit(_, span)
+            | Expression::Bool(_, _, span)
+            | Expression::None(_, span)
+            | Expression::Some(_, _, span)
+            | Expression::Op(_, _, _, span)
+            | Expression::Error(span) => span.clone(),
+            Expression::FunctionCall(call) => call.span().clone(),
+            Expression::EnumConstructor(constructor) => constructor.span.clone(),
+            Expression::Variable(var) => var.span.clone(),
+            Expression::Number(number) => number.span.clone(),
+            Expression::String(string) => string.span.clone(),
+            Expression::If(if_) => if_.span.clone(),
+            Expression::Lambda(lambda) => lambda.span.clone(),
+            Expression::Field(field) => field.span.clone(),
+            Expression::MethodCall(call) => call.span.clone(),
+            Expression::Try(try_) => try_.span.clone(),
+            Expression::Range(range) => range.span.clone(),
+        }
+    }
+
+    /// `expr?` as
+    /// `{ let ? = expr; if ? == none { 🐔 none; } else { ?.value } }`, where everything has the
+    /// span of `expr?`.
+    fn propagate(expr: Expression<'input>, span: Span) -> Self {
+        let var = || {
+            Box::new(Expression::Variable(Variable::new(
+                PROPAGATED,
+                span.clone(),
+            )))
+        };
+        let none = || Box::new(Expression::None(Spelling::default(), span.clone()));
+        let bind = Statement::Let(Let {
+            keyword: Spelling::default(),
+            name: PROPAGATED,
+            id: VariableId::unresolved(),
+            mutable: None,
+            value: Box::new(expr),
+            span: span.clone(),
+        });
+        let check = If {
+            keyword: Spelling::default(),
+            condition: Box::new(Expression::Op(var(), Opcode::Eq, none(), span.clone())),
+            body: Box::new(Expression::Block(
+                vec![Statement::Return(none(), Spelling::default(), span.clone())],
+                None,
+                span.clone(),
+            )),
+            else_keyword: Spelling::default(),
+            else_body: Some(Else::Block(Box::new(Expression::Block(
+                vec![],
+                Some(Box::new(Expression::Field(Field {
+                    object: var(),
+                    name: "value",
+                    span: span.clone(),
+                }))),
+                span.clone(),
+            )))),
+            span: span.clone(),
+        };
+        Expression::Block(vec![bind], Some(Box::new(Expression::If(check))), span)
+    }
+
+    /// The operand of `expr?`, if this is what it desugars to.
+    pub fn propagated(&self) -> Option<&Expression<'input>> {
+        match self {
+            Expression::Block(block, Some(_), _) => match block.as_slice() {
+                [Statement::Let(let_)] if let_.name == PROPAGATED => Some(&let_.value),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
 }
 
 impl Format for Expression<'_> {
     fn format(&self, fmt: &mut Formatter) {
+        if let Some(expr) = self.propagated() {
+            format_operand(expr, Precedence::Postfix, fmt);
+            fmt.push_str("?");
+            return;
+        }
         match self {
             Expression::Expression(expr) => expr.format(fmt),
+            Expression::Paren(expr, _) if fmt.config.parens == Parens::Preserve => {
+                fmt.push_str("(");
+                expr.format(fmt);
+                fmt.push_str(")");
+            }
+            Expression::Paren(expr, _) => expr.format(fmt),
             Expression::FunctionCall(func) => func.format(fmt),
-            Expression::Block(block) => {
+            Expression::EnumConstructor(constructor) => constructor.format(fmt),
+            Expression::Block(block, None, _) if block.is_empty() => fmt.push_str("{}"),
+            Expression::Block(block, tail, span) => {
                 fmt.push_str("{\n");
-                fmt.indent();
-                for stmt in block {
-                    stmt.format(fmt);
-                }
-                fmt.unindent();
+                fmt.lines(|fmt| {
+                    fmt.indent();
+                    let mut i = 0;
+                    while i < block.len() {
+                        let comments = block[i..].iter().map(|stmt| match stmt {
+                            Statement::Comment(comment) => Some(comment),
+                            _ => None,
+                        });
+                        if let Some(wrapped) = format_paragraphs(fmt, comments) {
+                            i += wrapped;
+                            continue;
+                        }
+                        block[i].format(fmt);
+                        let rest = block[i + 1..].iter().map(|stmt| match stmt {
+                            Statement::Comment(comment) => Some(comment),
+                            _ => None,
+                        });
+                        let skipped = match &block[i] {
+                            // up to the closing brace, if the formatter doesn't turn on again
+                            Statement::Comment(off) => format_off(fmt, off, rest, span.end - 1),
+                            _ => None,
+                        };
+                        i += 1 + skipped.unwrap_or(0);
+                        if skipped.is_some() && i == block.len() {
+                            // the tail is part of what isn't formatted
+                            fmt.unindent();
+                            return;
+                        }
+                    }
+                    if let Some(tail) = tail {
+                        fmt.push_indent();
+                        tail.format(fmt);
+                        fmt.push_str("\n");
+                    }
+                    fmt.unindent();
+                });
                 fmt.push_str_indented("}");
             }
             Expression::Variable(var) => fmt.push_string(var.to_string()),
             Expression::Number(num) => fmt.push_string(num.to_string()),
             Expression::String(str) => fmt.push_string(str.to_string()),
-            Expression::Op(lhs, op, rhs) => {
+            Expression::Unit(keyword, _) => fmt.push_keyword(Keyword::Unit, *keyword),
+            Expression::Bool(true, keyword, _) => fmt.push_keyword(Keyword::True, *keyword),
+            Expression::Bool(false, keyword, _) => fmt.push_keyword(Keyword::False, *keyword),
+            Expression::None(keyword, _) => fmt.push_keyword(Keyword::None, *keyword),
+            Expression::Some(value, keyword, _) => {
+                fmt.push_keyword(Keyword::Some, *keyword);
                 fmt.push_str("(");
-                lhs.format(fmt);
-                fmt.push_str(" ");
-                fmt.push_string(op.to_string());
-                fmt.push_str(" ");
-                rhs.format(fmt);
+                value.format(fmt);
                 fmt.push_str(")");
             }
+            // an operation that doesn't fit continues after the operator on the next line
+            Expression::Op(lhs, op, rhs, _) => fmt.group(|fmt| {
+                let full = fmt.config.parens == Parens::Full;
+                if full {
+                    fmt.push_str("(");
+                }
+                // operators are left associative, `a - b - c` is `(a - b) - c`
+                format_operand(lhs, op.precedence(), fmt);
+                fmt.push_str(" ");
+                fmt.push_string(op.to_string());
+                fmt.nest(|fmt| {
+                    fmt.push_line();
+                    format_operand(rhs, op.precedence().tighter(), fmt);
+                });
+                if full {
+                    fmt.push_str(")");
+                }
+            }),
+            Expression::Range(range) => range.format(fmt),
             Expression::If(if_) => if_.format(fmt),
+            Expression::Lambda(lambda) => lambda.format(fmt),
+            Expression::Field(field) => field.format(fmt),
+            Expression::MethodCall(call) => call.format(fmt),
+            Expression::Try(try_) => try_.format(fmt),
             Expression::ExpressionComment((expr, comment)) => {
                 expr.format(fmt);
                 comment.format(fmt);
             }
-            Expression::Error => fmt.push_string("error".red().to_string()),
+            Expression::Error(_) => fmt.push_error("error"),
         }
     }
 }
@@ -372,24 +2224,51 @@ impl<'input> From<crate::base_ast::Expression<'input>> for Expression<'input> {
             crate::base_ast::Expression::Expression(expr) => {
                 Self::Expression(Box::new(expr.into()))
             }
-            crate::base_ast::Expression::Block(block) => Self::Block(
+            crate::base_ast::Expression::Paren(expr, span) => {
+                Self::Paren(Box::new((*expr).into()), span)
+            }
+            crate::base_ast::Expression::Block(block, tail, span) => Self::Block(
                 block
                     .into_iter()
                     .map(|stmt| stmt.into())
                     .collect::<Vec<_>>(),
+                tail.map(|tail| Box::new((*tail).into())),
+                span,
             ),
             crate::base_ast::Expression::FunctionCall(func) => Self::FunctionCall(func.into()),
+            crate::base_ast::Expression::Pipe(lhs, call, span) => {
+                Self::FunctionCall(FunctionCall::pipe(lhs.into(), call, span))
+            }
+            crate::base_ast::Expression::EnumConstructor(constructor) => {
+                Self::EnumConstructor(constructor.into())
+            }
             crate::base_ast::Expression::Variable(var) => Self::Variable(var.into()),
-            crate::base_ast::Expression::Number(num) => Self::Number(num),
+            crate::base_ast::Expression::Number(num) => Self::Number(num.into()),
             crate::base_ast::Expression::String(str) => Self::String(str.into()),
             crate::base_ast::Expression::If(if_) => Self::If(if_.into()),
-            crate::base_ast::Expression::Op(lhs, op, rhs) => {
-                Self::Op(Box::new(lhs.into()), op.into(), Box::new(rhs.into()))
+            crate::base_ast::Expression::Lambda(lambda) => Self::Lambda(lambda.into()),
+            crate::base_ast::Expression::Try(try_) => Self::Try(try_.into()),
+            crate::base_ast::Expression::Range(range) => Self::Range(range.into()),
+            crate::base_ast::Expression::Field(field) => Self::Field(field.into()),
+            crate::base_ast::Expression::MethodCall(call) => Self::MethodCall(call.into()),
+            crate::base_ast::Expression::Op(lhs, op, rhs, span) => {
+                Self::Op(Box::new(lhs.into()), op.into(), Box::new(rhs.into()), span)
             }
             crate::base_ast::Expression::ExpressionComment((expr, comment)) => {
                 Self::ExpressionComment((Box::new(expr.into()), comment.into()))
             }
-            crate::base_ast::Expression::Error => Self::Error,
+            crate::base_ast::Expression::Unit(keyword, span) => Self::Unit(keyword, span),
+            crate::base_ast::Expression::Bool(value, keyword, span) => {
+                Self::Bool(value, keyword, span)
+            }
+            crate::base_ast::Expression::None(keyword, span) => Self::None(keyword, span),
+            crate::base_ast::Expression::Some(value, keyword, span) => {
+                Self::Some(Box::new(value.into()), keyword, span)
+            }
+            crate::base_ast::Expression::Propagate(expr, span) => {
+                Self::propagate(expr.into(), span)
+            }
+            crate::base_ast::Expression::Error(span) => Self::Error(span),
         }
     }
 }
@@ -400,9 +2279,40 @@ impl<'input> From<Box<crate::base_ast::Expression<'input>>> for Expression<'inpu
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound(deserialize = "'de: 'input"))]
 pub struct Variable<'input> {
     pub name: &'input str,
+    /// The binding the name refers to, if it is bound in the enclosing function at all.
+    pub id: Option<VariableId>,
+    pub span: Span,
+}
+
+impl<'input> Variable<'input> {
+    pub fn new(name: &'input str, span: Span) -> Self {
+        Self {
+            name,
+            id: None,
+            span,
+        }
+    }
+
+    /// The name a call is made through, with the span of just the name.
+    fn callee(call: &crate::base_ast::FunctionCall<'input>) -> Self {
+        let start = call.span.start;
+        Self::new(call.name, start..start + call.name.len())
+    }
+}
+
+/// A variable of [`Program::variables`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound(deserialize = "'de: 'input"))]
+pub struct Binding<'input> {
+    pub name: &'input str,
+    /// Whether it is bound by `let mut` and can be assigned to.
+    pub mutable: bool,
+    /// The function the variable is local to.
+    pub function: FunctionId,
 }
 
 impl Display for Variable<'_> {
@@ -413,41 +2323,198 @@ impl Display for Variable<'_> {
 
 impl<'input> From<crate::base_ast::Variable<'input>> for Variable<'input> {
     fn from(ast: crate::base_ast::Variable<'input>) -> Self {
-        Self { name: ast.name }
+        Self::new(ast.name, ast.span)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound(deserialize = "'de: 'input"))]
+pub struct Number<'input> {
+    pub value: i64,
+    pub radix: Radix,
+    /// The literal as written, including its prefix and digit separators. Numbers computed by
+    /// [`crate::fold`] have none.
+    pub text: Option<&'input str>,
+    /// The literal, or the arithmetic it was folded from.
+    pub span: Span,
+}
+
+impl Display for Number<'_> {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> Result<(), Error> {
+        match self.text {
+            Some(text) => write!(fmt, "{}", text),
+            // there are no negative literals
+            None if self.value < 0 => write!(fmt, "(0 - {})", self.value.unsigned_abs()),
+            None => write!(fmt, "{}", self.value),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Radix {
+    Binary,
+    Octal,
+    Decimal,
+    Hexadecimal,
+}
+
+impl From<crate::base_ast::Radix> for Radix {
+    fn from(ast: crate::base_ast::Radix) -> Self {
+        match ast {
+            crate::base_ast::Radix::Binary => Self::Binary,
+            crate::base_ast::Radix::Octal => Self::Octal,
+            crate::base_ast::Radix::Decimal => Self::Decimal,
+            crate::base_ast::Radix::Hexadecimal => Self::Hexadecimal,
+        }
+    }
+}
+
+impl<'input> From<crate::base_ast::Number<'input>> for Number<'input> {
+    fn from(ast: crate::base_ast::Number<'input>) -> Self {
+        Self {
+            value: ast.value,
+            radix: ast.radix.into(),
+            text: Some(ast.text),
+            span: ast.span,
+        }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound(deserialize = "'de: 'input"))]
 pub struct ASTString<'input> {
+    /// The contents between the delimiters, exactly as written.
     pub value: &'input str,
+    pub kind: StringKind,
+    /// The whole literal, including the delimiters.
+    pub span: Span,
 }
 
 impl<'input> Display for ASTString<'input> {
     fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> Result<(), Error> {
-        write!(fmt, "{}", self.value)
+        let (open, close) = self.kind.delimiters();
+        write!(fmt, "{}{}{}", open, self.value, close)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StringKind {
+    /// `"..."`
+    Plain,
+    /// `"""..."""`, which can contain single quotes.
+    Multiline,
+    /// `r"..."`, or `r#"..."#` with one hash, which can contain quotes.
+    Raw { hashes: usize },
+}
+
+impl StringKind {
+    pub fn delimiters(&self) -> (String, String) {
+        match self {
+            StringKind::Plain => ("\"".to_string(), "\"".to_string()),
+            StringKind::Multiline => ("\"\"\"".to_string(), "\"\"\"".to_string()),
+            StringKind::Raw { hashes } => (
+                format!("r{}\"", "#".repeat(*hashes)),
+                format!("\"{}", "#".repeat(*hashes)),
+            ),
+        }
     }
 }
 
 impl<'input> From<crate::base_ast::ASTString<'input>> for ASTString<'input> {
     fn from(ast: crate::base_ast::ASTString<'input>) -> Self {
-        Self { value: ast.value }
+        Self {
+            value: ast.value,
+            kind: ast.kind.into(),
+            span: ast.span,
+        }
+    }
+}
+
+impl From<crate::base_ast::StringKind> for StringKind {
+    fn from(ast: crate::base_ast::StringKind) -> Self {
+        match ast {
+            crate::base_ast::StringKind::Plain => Self::Plain,
+            crate::base_ast::StringKind::Multiline => Self::Multiline,
+            crate::base_ast::StringKind::Raw { hashes } => Self::Raw { hashes },
+        }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound(deserialize = "'de: 'input"))]
 pub struct If<'input> {
+    pub keyword: Spelling,
     pub condition: Box<Expression<'input>>,
     pub body: Box<Expression<'input>>,
-    pub else_body: Option<Box<Expression<'input>>>,
+    /// The spelling of `else`, if there is an else branch.
+    pub else_keyword: Spelling,
+    pub else_body: Option<Else<'input>>,
+    /// The whole `if`, including the else branches.
+    pub span: Span,
+}
+
+impl<'input> If<'input> {
+    /// The values of both branches, if they are simple enough to write as `condition ? a : b`.
+    pub fn conditional_branches(&self) -> Option<(&Expression<'input>, &Expression<'input>)> {
+        let then = block_value(&self.body)?;
+        let else_ = match &self.else_body {
+            Some(Else::Block(block)) => block_value(block)?,
+            _ => return None,
+        };
+        let simple = |expr: &Expression| {
+            !matches!(
+                expr,
+                Expression::Block(..)
+                    | Expression::If(_)
+                    | Expression::Lambda(_)
+                    | Expression::ExpressionComment(_)
+            ) || expr.propagated().is_some()
+        };
+        // `a ? b : c ? d : e` nests to the right
+        (simple(then) && (simple(else_) || is_conditional(else_))).then_some((then, else_))
+    }
+}
+
+/// The value of a block that consists of nothing but its final expression.
+fn block_value<'a, 'input>(expr: &'a Expression<'input>) -> Option<&'a Expression<'input>> {
+    match expr {
+        Expression::Block(block, Some(tail), _) if block.is_empty() => Some(tail),
+        _ => None,
+    }
+}
+
+/// Whether the expression is formatted as `condition ? a : b`.
+fn is_conditional(expr: &Expression) -> bool {
+    matches!(expr, Expression::If(if_) if if_.conditional_branches().is_some())
 }
 
 impl Format for If<'_> {
     fn format(&self, fmt: &mut Formatter) {
-        fmt.push_str("if ");
+        if let Some((then, else_)) = self.conditional_branches() {
+            format_operand(&self.condition, Precedence::Pipeline, fmt);
+            fmt.push_str(" ? ");
+            then.format(fmt);
+            fmt.push_str(" : ");
+            else_.format(fmt);
+            return;
+        }
+        self.format_keywords(fmt);
+    }
+}
+
+impl If<'_> {
+    /// Format the `if` with its keywords, even if it could be `condition ? a : b`, which can't
+    /// follow an `else`.
+    fn format_keywords(&self, fmt: &mut Formatter) {
+        fmt.push_keyword(Keyword::If, self.keyword);
+        fmt.push_str(" ");
         self.condition.format(fmt);
         fmt.push_str(" ");
         self.body.format(fmt);
         if let Some(else_) = &self.else_body {
+            fmt.push_str(" ");
+            fmt.push_keyword(Keyword::Else, self.else_keyword);
+            fmt.push_str(" ");
             else_.format(fmt);
         }
     }
@@ -456,9 +2523,329 @@ impl Format for If<'_> {
 impl<'input> From<crate::base_ast::If<'input>> for If<'input> {
     fn from(ast: crate::base_ast::If<'input>) -> Self {
         Self {
+            keyword: ast.keyword,
             condition: Box::new(ast.condition.into()),
             body: Box::new(ast.body.into()),
-            else_body: ast.else_body.map(|else_| Box::new(else_.into())),
+            else_keyword: ast.else_keyword,
+            else_body: ast.else_body.map(|else_| else_.into()),
+            span: ast.span,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound(deserialize = "'de: 'input"))]
+pub enum Else<'input> {
+    Block(Box<Expression<'input>>),
+    If(Box<If<'input>>),
+}
+
+impl Format for Else<'_> {
+    fn format(&self, fmt: &mut Formatter) {
+        match self {
+            Else::Block(block) => block.format(fmt),
+            Else::If(if_) => if_.format_keywords(fmt),
+        }
+    }
+}
+
+impl<'input> From<crate::base_ast::Else<'input>> for Else<'input> {
+    fn from(ast: crate::base_ast::Else<'input>) -> Self {
+        match ast {
+            crate::base_ast::Else::Block(block) => Self::Block(Box::new(block.into())),
+            crate::base_ast::Else::If(if_) => Self::If(Box::new((*if_).into())),
+        }
+    }
+}
+
+/// `start..end`, or `start..=end` if the end is included.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound(deserialize = "'de: 'input"))]
+pub struct Range<'input> {
+    pub start: Box<Expression<'input>>,
+    pub end: Box<Expression<'input>>,
+    pub inclusive: bool,
+    pub span: Span,
+}
+
+impl Format for Range<'_> {
+    fn format(&self, fmt: &mut Formatter) {
+        format_operand(&self.start, Precedence::Comparison, fmt);
+        fmt.push_str(if self.inclusive { "..=" } else { ".." });
+        format_operand(&self.end, Precedence::Comparison, fmt);
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound(deserialize = "'de: 'input"))]
+pub struct Field<'input> {
+    pub object: Box<Expression<'input>>,
+    pub name: &'input str,
+    pub span: Span,
+}
+
+impl Format for Field<'_> {
+    fn format(&self, fmt: &mut Formatter) {
+        format_operand(&self.object, Precedence::Postfix, fmt);
+        fmt.push_str(".");
+        fmt.push_str(self.name);
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound(deserialize = "'de: 'input"))]
+pub struct MethodCall<'input> {
+    pub object: Box<Expression<'input>>,
+    pub method: &'input str,
+    pub args: Vec<Box<Expression<'input>>>,
+    pub span: Span,
+}
+
+impl Format for MethodCall<'_> {
+    fn format(&self, fmt: &mut Formatter) {
+        format_operand(&self.object, Precedence::Postfix, fmt);
+        fmt.push_str(".");
+        fmt.push_str(self.method);
+        fmt.push_arguments(&self.args, |arg, fmt| arg.format(fmt));
+    }
+}
+
+impl<'input> From<crate::base_ast::Field<'input>> for Field<'input> {
+    fn from(ast: crate::base_ast::Field<'input>) -> Self {
+        Self {
+            object: Box::new((*ast.object).into()),
+            name: ast.name,
+            span: ast.span,
+        }
+    }
+}
+
+impl<'input> From<crate::base_ast::MethodCall<'input>> for MethodCall<'input> {
+    fn from(ast: crate::base_ast::MethodCall<'input>) -> Self {
+        Self {
+            object: Box::new((*ast.object).into()),
+            method: ast.method,
+            args: ast
+                .args
+                .into_iter()
+                .map(|arg| Box::new(arg.into()))
+                .collect::<Vec<_>>(),
+            span: ast.span,
+        }
+    }
+}
+
+/// How tightly expressions bind, the tiers of the grammar from the loosest to the tightest, to
+/// know where the formatter needs parentheses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Precedence {
+    /// Lambdas and conditionals.
+    Conditional,
+    Pipeline,
+    Range,
+    Comparison,
+    Sum,
+    Product,
+    /// Field accesses, method calls, `?` and everything that isn't made of operators.
+    Postfix,
+}
+
+impl Precedence {
+    fn tighter(self) -> Self {
+        match self {
+            Precedence::Conditional => Precedence::Pipeline,
+            Precedence::Pipeline => Precedence::Range,
+            Precedence::Range => Precedence::Comparison,
+            Precedence::Comparison => Precedence::Sum,
+            Precedence::Sum => Precedence::Product,
+            Precedence::Product | Precedence::Postfix => Precedence::Postfix,
+        }
+    }
+}
+
+impl Expression<'_> {
+    /// How tightly the expression binds as the formatter prints it. With [`Parens::Full`]
+    /// operations are always in parentheses, and with [`Parens::Preserve`] so is what was written
+    /// in them.
+    fn precedence(&self, parens: Parens) -> Precedence {
+        if self.propagated().is_some() {
+            return Precedence::Postfix;
+        }
+        match self {
+            Expression::Expression(expr) | Expression::ExpressionComment((expr, _)) => {
+                expr.precedence(parens)
+            }
+            Expression::Paren(expr, _) if parens != Parens::Preserve => expr.precedence(parens),
+            Expression::Lambda(_) => Precedence::Conditional,
+            expr if is_conditional(expr) => Precedence::Conditional,
+            Expression::FunctionCall(call) if call.piped() => Precedence::Pipeline,
+            Expression::Range(_) => Precedence::Range,
+            Expression::Op(_, op, _, _) if parens != Parens::Full => op.precedence(),
+            _ => Precedence::Postfix,
+        }
+    }
+}
+
+/// Format an operand of an operator, pipeline or conditional, or the object of a field access or
+/// method call, adding parentheses if it binds less tightly than `tightest` there.
+fn format_operand(expr: &Expression, tightest: Precedence, fmt: &mut Formatter) {
+    if expr.precedence(fmt.config.parens) < tightest {
+        fmt.push_str("(");
+        expr.format(fmt);
+        fmt.push_str(")");
+    } else {
+        expr.format(fmt);
+    }
+}
+
+impl<'input> From<crate::base_ast::Range<'input>> for Range<'input> {
+    fn from(ast: crate::base_ast::Range<'input>) -> Self {
+        Self {
+            start: Box::new(ast.start.into()),
+            end: Box::new(ast.end.into()),
+            inclusive: ast.inclusive,
+            span: ast.span,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound(deserialize = "'de: 'input"))]
+pub struct Lambda<'input> {
+    pub params: Vec<Parameter<'input>>,
+    pub body: Box<Expression<'input>>,
+    /// The variables of enclosing scopes the body refers to, in order of first use.
+    pub captures: Vec<&'input str>,
+    pub span: Span,
+}
+
+impl Format for Lambda<'_> {
+    fn format(&self, fmt: &mut Formatter) {
+        fmt.push_str("|");
+        for (i, param) in self.params.iter().enumerate() {
+            fmt.push_string(param.to_string());
+            fmt.push_list_separator(i, self.params.len(), false);
+        }
+        fmt.push_str("| ");
+        self.body.format(fmt);
+    }
+}
+
+impl<'input> From<crate::base_ast::Lambda<'input>> for Lambda<'input> {
+    fn from(ast: crate::base_ast::Lambda<'input>) -> Self {
+        let params = ast
+            .params
+            .into_iter()
+            .map(|param| param.into())
+            .collect::<Vec<Parameter>>();
+        let body = Box::new(Expression::from(ast.body));
+        let mut captures = FreeVariables {
+            scopes: vec![params.iter().map(|param| param.name).collect()],
+            free: Vec::new(),
+        };
+        captures.visit_expression(&body);
+        Self {
+            params,
+            body,
+            captures: captures.free,
+            span: ast.span,
+        }
+    }
+}
+
+/// Collects the variables an expression uses without binding them itself.
+struct FreeVariables<'input> {
+    /// The names bound in each scope of the expression, innermost last.
+    scopes: Vec<Vec<&'input str>>,
+    free: Vec<&'input str>,
+}
+
+impl<'input> FreeVariables<'input> {
+    fn use_variable(&mut self, name: &'input str) {
+        if !self.scopes.iter().flatten().any(|bound| *bound == name) && !self.free.contains(&name) {
+            self.free.push(name);
+        }
+    }
+}
+
+impl<'input> Visitor<'input> for FreeVariables<'input> {
+    fn visit_statement(&mut self, stmt: &Statement<'input>) {
+        match stmt {
+            Statement::Let(let_) => {
+                self.visit_expression(&let_.value);
+                self.scopes.last_mut().unwrap().push(let_.name);
+            }
+            Statement::Assign(assign) => {
+                self.use_variable(assign.target);
+                self.visit_expression(&assign.value);
+            }
+            _ => walk_statement(self, stmt),
+        }
+    }
+
+    fn visit_expression(&mut self, expr: &Expression<'input>) {
+        match expr {
+            Expression::Block(..) => {
+                self.scopes.push(Vec::new());
+                walk_expression(self, expr);
+                self.scopes.pop();
+            }
+            Expression::Variable(var) => self.use_variable(var.name),
+            Expression::Try(try_) => {
+                self.visit_expression(&try_.body);
+                self.scopes.push(vec![try_.error]);
+                self.visit_expression(&try_.rescue);
+                self.scopes.pop();
+            }
+            // the captures of a nested lambda have already been computed
+            Expression::Lambda(lambda) => {
+                for name in &lambda.captures {
+                    self.use_variable(name);
+                }
+            }
+            _ => walk_expression(self, expr),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound(deserialize = "'de: 'input"))]
+pub struct Try<'input> {
+    pub keyword: Spelling,
+    pub body: Box<Expression<'input>>,
+    /// The name the rescue block binds the error to.
+    pub error: &'input str,
+    pub error_id: VariableId,
+    pub rescue_keyword: Spelling,
+    /// Evaluated instead of the rest of the body when the body fails.
+    pub rescue: Box<Expression<'input>>,
+    pub span: Span,
+}
+
+impl Format for Try<'_> {
+    fn format(&self, fmt: &mut Formatter) {
+        fmt.push_keyword(Keyword::Try, self.keyword);
+        fmt.push_str(" ");
+        self.body.format(fmt);
+        fmt.push_str(" ");
+        fmt.push_keyword(Keyword::Rescue, self.rescue_keyword);
+        fmt.push_str(" ");
+        fmt.push_str(self.error);
+        fmt.push_str(" ");
+        self.rescue.format(fmt);
+    }
+}
+
+impl<'input> From<crate::base_ast::Try<'input>> for Try<'input> {
+    fn from(ast: crate::base_ast::Try<'input>) -> Self {
+        Self {
+            keyword: ast.keyword,
+            body: Box::new((*ast.body).into()),
+            error: ast.error,
+            error_id: VariableId::unresolved(),
+            rescue_keyword: ast.rescue_keyword,
+            rescue: Box::new((*ast.rescue).into()),
+            span: ast.span,
         }
     }
 }
@@ -505,12 +2892,16 @@ impl<'input> From<crate::base_ast::ExprSymbol<'input>> for ExprSymbol<'input> {
     }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Serialize, Deserialize)]
 pub enum Opcode {
     Mul,
     Div,
     Add,
     Sub,
+    /// `+` with a string operand, told apart from [`Opcode::Add`] when the program is linked.
+    Concat,
+    Eq,
+    NotEq,
 }
 
 impl Debug for Opcode {
@@ -519,8 +2910,20 @@ impl Debug for Opcode {
         match *self {
             Mul => write!(fmt, "*"),
             Div => write!(fmt, "/"),
-            Add => write!(fmt, "+"),
+            Add | Concat => write!(fmt, "+"),
             Sub => write!(fmt, "-"),
+            Eq => write!(fmt, "=="),
+            NotEq => write!(fmt, "!="),
+        }
+    }
+}
+
+impl Opcode {
+    fn precedence(self) -> Precedence {
+        match self {
+            Opcode::Mul | Opcode::Div => Precedence::Product,
+            Opcode::Add | Opcode::Sub | Opcode::Concat => Precedence::Sum,
+            Opcode::Eq | Opcode::NotEq => Precedence::Comparison,
         }
     }
 }
@@ -531,19 +2934,23 @@ impl Display for Opcode {
         match *self {
             Mul => write!(fmt, "*"),
             Div => write!(fmt, "/"),
-            Add => write!(fmt, "+"),
+            Add | Concat => write!(fmt, "+"),
             Sub => write!(fmt, "-"),
+            Eq => write!(fmt, "=="),
+            NotEq => write!(fmt, "!="),
         }
     }
 }
 
-impl<'input> From<crate::base_ast::Opcode> for Opcode {
+impl From<crate::base_ast::Opcode> for Opcode {
     fn from(ast: crate::base_ast::Opcode) -> Self {
         match ast {
             crate::base_ast::Opcode::Mul => Self::Mul,
             crate::base_ast::Opcode::Div => Self::Div,
             crate::base_ast::Opcode::Add => Self::Add,
             crate::base_ast::Opcode::Sub => Self::Sub,
+            crate::base_ast::Opcode::Eq => Self::Eq,
+            crate::base_ast::Opcode::NotEq => Self::NotEq,
         }
     }
 }
@@ -0,0 +1,341 @@
+//! What programs compute with when they run, the same for every backend, so their results and
+//! failures read the same. Only lambdas differ, every backend keeps what it needs to call them.
+use std::fmt::{Display, Error};
+use std::rc::Rc;
+use std::str::FromStr;
+
+use crate::fold;
+use crate::intrinsics::{self, Operand};
+use crate::resolved_ast::{FunctionId, Intrinsic, Opcode};
+use crate::Span;
+
+/// What runs a program: [`crate::interpreter`] walks the syntax tree, [`crate::vm`] runs it
+/// compiled to bytecode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    Interpreter,
+    Vm,
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "interpreter" => Ok(Self::Interpreter),
+            "vm" => Ok(Self::Vm),
+            _ => Err(format!(
+                "unknown backend `{}`, expected interpreter or vm",
+                s
+            )),
+        }
+    }
+}
+
+/// What a backend keeps of a lambda to call it later.
+pub trait Closure {
+    /// The names of the parameters of the lambda.
+    fn params(&self) -> Vec<&str>;
+}
+
+/// What evaluating an expression results in. There are no floats, the only numbers are ints.
+#[derive(Debug, Clone)]
+pub enum Value<'a, C> {
+    Int(i64),
+    String(String),
+    /// What comparisons result in, there are no literals for them.
+    Bool(bool),
+    Unit,
+    None,
+    Some(Box<Value<'a, C>>),
+    /// There are no literals for lists, the arguments of the program are one.
+    List(Vec<Value<'a, C>>),
+    Enum {
+        name: &'a str,
+        variant: &'a str,
+        fields: Vec<(&'a str, Value<'a, C>)>,
+    },
+    Range {
+        start: i64,
+        end: i64,
+        inclusive: bool,
+    },
+    Function(FunctionId, &'a str),
+    Closure(Rc<C>),
+}
+
+impl<C> PartialEq for Value<'_, C> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Int(a), Value::Int(b)) => a == b,
+            (Value::String(a), Value::String(b)) => a == b,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Unit, Value::Unit) | (Value::None, Value::None) => true,
+            (Value::Some(a), Value::Some(b)) => a == b,
+            (Value::List(a), Value::List(b)) => a == b,
+            (
+                Value::Enum {
+                    name,
+                    variant,
+                    fields,
+                },
+                Value::Enum {
+                    name: other_name,
+                    variant: other_variant,
+                    fields: other_fields,
+                },
+            ) => name == other_name && variant == other_variant && fields == other_fields,
+            (
+                Value::Range {
+                    start,
+                    end,
+                    inclusive,
+                },
+                Value::Range {
+                    start: other_start,
+                    end: other_end,
+                    inclusive: other_inclusive,
+                },
+            ) => start == other_start && end == other_end && inclusive == other_inclusive,
+            (Value::Function(a, _), Value::Function(b, _)) => a == b,
+            // the same lambda evaluated at different times can see different variables
+            (Value::Closure(a), Value::Closure(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+impl<C: Closure> Display for Value<'_, C> {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> Result<(), Error> {
+        match self {
+            Value::Int(value) => write!(fmt, "{}", value),
+            Value::String(value) => write!(fmt, "\"{}\"", value),
+            Value::Bool(value) => write!(fmt, "{}", value),
+            Value::Unit => write!(fmt, "()"),
+            Value::None => write!(fmt, "none"),
+            Value::Some(value) => write!(fmt, "some({})", value),
+            Value::List(values) => {
+                let values = values.iter().map(Value::to_string).collect::<Vec<_>>();
+                write!(fmt, "[{}]", values.join(", "))
+            }
+            Value::Enum {
+                name,
+                variant,
+                fields,
+            } => {
+                write!(fmt, "{}::{}", name, variant)?;
+                if !fields.is_empty() {
+                    let fields = fields
+                        .iter()
+                        .map(|(_, value)| value.to_string())
+                        .collect::<Vec<_>>();
+                    write!(fmt, "({})", fields.join(", "))?;
+                }
+                Ok(())
+            }
+            Value::Range {
+                start,
+                end,
+                inclusive,
+            } => write!(
+                fmt,
+                "{}{}{}",
+                start,
+                if *inclusive { "..=" } else { ".." },
+                end
+            ),
+            Value::Function(_, name) => write!(fmt, "egg {}", name),
+            Value::Closure(closure) => write!(fmt, "|{}| ...", closure.params().join(", ")),
+        }
+    }
+}
+
+impl<C: Closure> Value<'_, C> {
+    /// The value of an argument given as JSON: numbers, strings, bools, arrays for lists and
+    /// `null` for `none`.
+    pub fn from_json(json: &serde_json::Value) -> Result<Self, String> {
+        Ok(match json {
+            serde_json::Value::Null => Value::None,
+            serde_json::Value::Bool(value) => Value::Bool(*value),
+            serde_json::Value::Number(number) => match number.as_i64() {
+                Some(value) => Value::Int(value),
+                None => return Err(format!("{} is not an int", number)),
+            },
+            serde_json::Value::String(value) => Value::String(value.clone()),
+            serde_json::Value::Array(values) => Value::List(
+                values
+                    .iter()
+                    .map(Value::from_json)
+                    .collect::<Result<_, _>>()?,
+            ),
+            serde_json::Value::Object(_) => return Err("objects can't be passed".to_string()),
+        })
+    }
+
+    /// The value as JSON: `()` and `none` are `null`, `some` is what it holds, and what JSON has
+    /// nothing for, like enums and functions, is the text it prints as.
+    pub fn to_json(&self) -> serde_json::Value {
+        match self {
+            Value::Int(value) => (*value).into(),
+            Value::String(value) => value.as_str().into(),
+            Value::Bool(value) => (*value).into(),
+            Value::Unit | Value::None => serde_json::Value::Null,
+            Value::Some(value) => value.to_json(),
+            Value::List(values) => values.iter().map(Value::to_json).collect(),
+            value => value.to_string().into(),
+        }
+    }
+}
+
+impl<C> From<Operand> for Value<'_, C> {
+    fn from(operand: Operand) -> Self {
+        match operand {
+            Operand::Int(value) => Value::Int(value),
+            Operand::String(value) => Value::String(value),
+            Operand::Bool(value) => Value::Bool(value),
+            Operand::Unit => Value::Unit,
+            Operand::List(values) => Value::List(values.into_iter().map(Value::from).collect()),
+        }
+    }
+}
+
+impl<'a, C> Value<'a, C> {
+    /// The value as an intrinsic takes it, if it can take it at all.
+    pub fn operand(&self) -> Option<Operand> {
+        match self {
+            Value::Int(value) => Some(Operand::Int(*value)),
+            Value::String(value) => Some(Operand::String(value.clone())),
+            Value::Bool(value) => Some(Operand::Bool(*value)),
+            Value::Unit => Some(Operand::Unit),
+            Value::List(values) => values
+                .iter()
+                .map(Value::operand)
+                .collect::<Option<_>>()
+                .map(Operand::List),
+            _ => None,
+        }
+    }
+
+    /// The same value for another backend, unless there is a lambda in it, which only the
+    /// backend that made it can call.
+    pub fn for_backend<D>(&self) -> Option<Value<'a, D>> {
+        Some(match self {
+            Value::Int(value) => Value::Int(*value),
+            Value::String(value) => Value::String(value.clone()),
+            Value::Bool(value) => Value::Bool(*value),
+            Value::Unit => Value::Unit,
+            Value::None => Value::None,
+            Value::Some(value) => Value::Some(Box::new(value.for_backend()?)),
+            Value::List(values) => Value::List(
+                values
+                    .iter()
+                    .map(Value::for_backend)
+                    .collect::<Option<_>>()?,
+            ),
+            Value::Enum {
+                name,
+                variant,
+                fields,
+            } => Value::Enum {
+                name,
+                variant,
+                fields: fields
+                    .iter()
+                    .map(|(field, value)| Some((*field, value.for_backend()?)))
+                    .collect::<Option<_>>()?,
+            },
+            Value::Range {
+                start,
+                end,
+                inclusive,
+            } => Value::Range {
+                start: *start,
+                end: *end,
+                inclusive: *inclusive,
+            },
+            Value::Function(function, name) => Value::Function(*function, name),
+            Value::Closure(_) => return None,
+        })
+    }
+
+    /// Roughly how much memory the value takes, counting only what grows: strings and lists.
+    pub fn bytes(&self) -> usize {
+        match self {
+            Value::String(value) => value.len(),
+            Value::List(values) => values.iter().map(Value::bytes).sum(),
+            Value::Some(value) => value.bytes(),
+            Value::Enum { fields, .. } => fields.iter().map(|(_, value)| value.bytes()).sum(),
+            _ => 0,
+        }
+    }
+}
+
+/// Stops the program, unless a `try` around it rescues it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Failure {
+    pub message: String,
+    /// What failed, which is in an imported file if an imported function failed.
+    pub span: Span,
+}
+
+impl Failure {
+    pub(crate) fn new(message: impl Into<String>, span: &Span) -> Self {
+        Self {
+            message: message.into(),
+            span: span.clone(),
+        }
+    }
+}
+
+/// Apply a binary operator to two values.
+pub(crate) fn operation<'a, C: Closure>(
+    lhs: Value<'a, C>,
+    op: Opcode,
+    rhs: Value<'a, C>,
+    span: &Span,
+) -> Result<Value<'a, C>, Failure> {
+    match (lhs, op, rhs) {
+        (lhs, Opcode::Eq, rhs) => Ok(Value::Bool(lhs == rhs)),
+        (lhs, Opcode::NotEq, rhs) => Ok(Value::Bool(lhs != rhs)),
+        (Value::String(lhs), Opcode::Add | Opcode::Concat, Value::String(rhs)) => {
+            Ok(Value::String(lhs + &rhs))
+        }
+        (Value::Int(lhs), op, Value::Int(rhs)) => {
+            // which `+` concatenates strings is only a guess until the program runs
+            let op = if matches!(op, Opcode::Concat) {
+                Opcode::Add
+            } else {
+                op
+            };
+            match fold::evaluate(lhs, op, rhs) {
+                Ok(Some(value)) => Ok(Value::Int(value)),
+                Ok(None) => unreachable!(),
+                Err(message) => Err(Failure::new(message, span)),
+            }
+        }
+        (lhs, op, rhs) => Err(Failure::new(
+            format!("cannot use `{}` with {} and {}", op, lhs, rhs),
+            span,
+        )),
+    }
+}
+
+/// Call an intrinsic with values it can take.
+pub(crate) fn intrinsic<'a, C: Closure>(
+    intrinsic: Intrinsic,
+    args: Vec<Value<'a, C>>,
+    io: bool,
+    span: &Span,
+) -> Result<Value<'a, C>, Failure> {
+    let operands = args
+        .iter()
+        .map(Value::operand)
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| {
+            let args = args.iter().map(Value::to_string).collect::<Vec<_>>();
+            Failure::new(intrinsics::wrong_arguments(intrinsic, &args), span)
+        })?;
+    intrinsics::evaluate(intrinsic, &operands, io)
+        .map(Value::from)
+        .map_err(|message| Failure::new(message, span))
+}
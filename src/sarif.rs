@@ -0,0 +1,240 @@
+//! SARIF 2.1.0 logs of diagnostics, the format code scanning dashboards take results in. Unlike
+//! [`Diagnostic::json`], which is a line per diagnostic, a log is one document, so the
+//! diagnostics are collected in a [`Log`] and written once they are all there.
+use serde::Serialize;
+
+use crate::diagnostics::{Diagnostic, Severity, EXPLANATIONS};
+use crate::line_index::LineIndex;
+use crate::Span;
+
+#[derive(Debug, Clone, Serialize)]
+struct Message {
+    text: String,
+}
+
+impl Message {
+    fn new(text: impl Into<String>) -> Self {
+        Self { text: text.into() }
+    }
+}
+
+/// Where a span is, with lines and columns counted from 1 and columns in characters, see
+/// [`LineIndex::position`].
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Region {
+    start_line: usize,
+    start_column: usize,
+    end_line: usize,
+    end_column: usize,
+    byte_offset: usize,
+    byte_length: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct ArtifactLocation {
+    uri: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PhysicalLocation {
+    artifact_location: ArtifactLocation,
+    region: Region,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Location {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<usize>,
+    physical_location: PhysicalLocation,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<Message>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Replacement {
+    deleted_region: Region,
+    inserted_content: Message,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ArtifactChange {
+    artifact_location: ArtifactLocation,
+    replacements: Vec<Replacement>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Fix {
+    description: Message,
+    artifact_changes: Vec<ArtifactChange>,
+}
+
+/// A diagnostic as SARIF has it, a result of the run.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SarifResult {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rule_id: Option<&'static str>,
+    /// Where the rule is in the rules of the log, which are [`EXPLANATIONS`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rule_index: Option<usize>,
+    level: &'static str,
+    message: Message,
+    locations: Vec<Location>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    related_locations: Vec<Location>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    fixes: Vec<Fix>,
+}
+
+/// The diagnostics of a run, in the order they were added.
+#[derive(Debug, Clone, Default)]
+pub struct Log {
+    results: Vec<SarifResult>,
+}
+
+impl Log {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a diagnostic in the file `name`. Its help goes at the end of its message, and its
+    /// notes are its related locations.
+    pub fn add(&mut self, diagnostic: &Diagnostic, name: &str, lines: &LineIndex) {
+        let location = |span: &Span| PhysicalLocation {
+            artifact_location: ArtifactLocation { uri: uri(name) },
+            region: region(span, lines),
+        };
+        let mut message = diagnostic.message.clone();
+        for help in &diagnostic.help {
+            message += &format!("\nhelp: {}", help);
+        }
+        let fixes = diagnostic.fix.iter().map(|fix| Fix {
+            description: Message::new(&fix.message),
+            artifact_changes: vec![ArtifactChange {
+                artifact_location: ArtifactLocation { uri: uri(name) },
+                replacements: fix
+                    .edits
+                    .iter()
+                    .map(|edit| Replacement {
+                        deleted_region: region(&edit.span, lines),
+                        inserted_content: Message::new(&edit.replacement),
+                    })
+                    .collect(),
+            }],
+        });
+        self.results.push(SarifResult {
+            rule_id: diagnostic.code,
+            rule_index: diagnostic.code.and_then(|code| {
+                EXPLANATIONS
+                    .iter()
+                    .position(|explanation| explanation.code == code)
+            }),
+            level: match diagnostic.severity {
+                Severity::Error => "error",
+                Severity::Warning => "warning",
+            },
+            message: Message::new(message),
+            locations: vec![Location {
+                id: None,
+                physical_location: location(&diagnostic.span),
+                message: None,
+            }],
+            related_locations: diagnostic
+                .notes
+                .iter()
+                .enumerate()
+                .map(|(id, (note, span))| Location {
+                    id: Some(id),
+                    physical_location: location(span),
+                    message: Some(Message::new(note)),
+                })
+                .collect(),
+            fixes: fixes.collect(),
+        });
+    }
+
+    /// The log as a SARIF document, with every code [`crate::diagnostics::explain`] knows as a
+    /// rule.
+    pub fn to_json(&self) -> String {
+        #[derive(Serialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Rule {
+            id: &'static str,
+            short_description: Message,
+            full_description: Message,
+        }
+        #[derive(Serialize)]
+        struct Driver {
+            name: &'static str,
+            version: &'static str,
+            rules: Vec<Rule>,
+        }
+        #[derive(Serialize)]
+        struct Tool {
+            driver: Driver,
+        }
+        #[derive(Serialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Run<'a> {
+            tool: Tool,
+            column_kind: &'static str,
+            results: &'a [SarifResult],
+        }
+        #[derive(Serialize)]
+        struct Sarif<'a> {
+            #[serde(rename = "$schema")]
+            schema: &'static str,
+            version: &'static str,
+            runs: [Run<'a>; 1],
+        }
+        let rules = EXPLANATIONS
+            .iter()
+            .map(|explanation| Rule {
+                id: explanation.code,
+                short_description: Message::new(explanation.title),
+                full_description: Message::new(explanation.description),
+            })
+            .collect();
+        let sarif = Sarif {
+            schema: "https://json.schemastore.org/sarif-2.1.0.json",
+            version: "2.1.0",
+            runs: [Run {
+                tool: Tool {
+                    driver: Driver {
+                        name: "wryneck",
+                        version: env!("CARGO_PKG_VERSION"),
+                        rules,
+                    },
+                },
+                // the columns of `LineIndex` count characters, not UTF-16 code units
+                column_kind: "unicodeCodePoints",
+                results: &self.results,
+            }],
+        };
+        serde_json::to_string_pretty(&sarif).unwrap()
+    }
+}
+
+/// A file name as a relative or absolute URI reference, which separates directories with `/`.
+fn uri(name: &str) -> String {
+    name.replace('\\', "/")
+}
+
+fn region(span: &Span, lines: &LineIndex) -> Region {
+    let (start_line, start_column) = lines.position(span.start);
+    let (end_line, end_column) = lines.position(span.end);
+    Region {
+        start_line,
+        start_column,
+        end_line,
+        end_column,
+        byte_offset: span.start,
+        byte_length: span.len(),
+    }
+}
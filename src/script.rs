@@ -0,0 +1,27 @@
+//! Checks that a program with top level statements doesn't also define its own `hatch`.
+use crate::diagnostics::Diagnostic;
+use crate::resolved_ast::*;
+
+/// The top level statements make up an implicit `hatch`, see [`Program::script`], so a second
+/// one at the top level would never be called.
+pub fn check(program: &Program) -> Vec<Diagnostic> {
+    if program.script.is_none() {
+        return Vec::new();
+    }
+    program
+        .things
+        .iter()
+        .filter_map(|thing| match thing {
+            TopLevel::Function(func) => Some(&program.functions[*func].definition),
+            _ => None,
+        })
+        .filter(|definition| definition.name == "hatch")
+        .map(|definition| {
+            Diagnostic::error(
+                "`hatch` is defined here, but the top level statements already make up the entry point",
+                definition.span.clone(),
+            )
+            .with_code("E050")
+        })
+        .collect()
+}
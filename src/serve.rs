@@ -0,0 +1,186 @@
+//! Serves the top level functions of a program over HTTP: `POST /count_pigeons` with the body
+//! `[5]` calls `count_pigeons(5)` and answers with what it comes to as JSON. Every request runs
+//! on an interpreter of its own, within the limits it is given and without I/O unless allowed.
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::time::Duration;
+
+use crate::interpreter::{self, Interpreter, Limits, Value};
+use crate::resolved_ast::Program;
+
+/// How many bytes the body of a request may take.
+const MAX_BODY: usize = 1024 * 1024;
+
+/// How many connections are answered at the same time.
+const WORKERS: usize = 4;
+
+/// How long reading a request or writing a response may wait for the client, so one that stops
+/// sending or reading doesn't hold a worker forever.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A request to call a function: the path names it and the body holds its arguments.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub body: Vec<u8>,
+}
+
+/// What a request comes to: a status and a JSON body.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    pub status: u16,
+    pub body: serde_json::Value,
+}
+
+impl Response {
+    fn ok(body: serde_json::Value) -> Self {
+        Self { status: 200, body }
+    }
+
+    fn error(status: u16, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            body: serde_json::json!({ "error": message.into() }),
+        }
+    }
+
+    fn reason(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            413 => "Payload Too Large",
+            422 => "Unprocessable Entity",
+            _ => "Internal Server Error",
+        }
+    }
+}
+
+/// The settings every request runs with.
+#[derive(Debug, Clone, Copy)]
+pub struct Settings {
+    /// Whether the functions may read and write stdin, stdout and files.
+    pub io: bool,
+    pub limits: Limits,
+}
+
+/// Answer the requests of every connection to the listener, [`WORKERS`] at a time. Connections
+/// that break or time out are left, the others are still answered.
+pub fn serve(program: &Program, listener: &TcpListener, settings: Settings) {
+    std::thread::scope(|scope| {
+        for _ in 0..WORKERS {
+            scope.spawn(|| interpreter::with_large_stack(|| work(program, listener, settings)));
+        }
+    });
+}
+
+/// Take connections from the listener and answer them, one after the other.
+fn work(program: &Program, listener: &TcpListener, settings: Settings) {
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(_) => continue,
+        };
+        if stream.set_read_timeout(Some(TIMEOUT)).is_err()
+            || stream.set_write_timeout(Some(TIMEOUT)).is_err()
+        {
+            continue;
+        }
+        let reader = match stream.try_clone() {
+            Ok(reader) => BufReader::new(reader),
+            Err(_) => continue,
+        };
+        let _ = handle(program, reader, stream, settings);
+    }
+}
+
+/// Read one request and write the response to it.
+pub fn handle(
+    program: &Program,
+    mut reader: impl BufRead,
+    mut writer: impl Write,
+    settings: Settings,
+) -> std::io::Result<()> {
+    let response = match read_request(&mut reader)? {
+        Ok(request) => respond(program, &request, settings),
+        Err(response) => response,
+    };
+    let body = response.body.to_string();
+    write!(
+        writer,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n{}",
+        response.status,
+        response.reason(),
+        body.len(),
+        body
+    )?;
+    writer.flush()
+}
+
+/// Read a request, or the response to a request that can't be read.
+fn read_request(reader: &mut impl BufRead) -> std::io::Result<Result<Request, Response>> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (method, path) = match (parts.next(), parts.next()) {
+        (Some(method), Some(path)) => (method.to_string(), path.to_string()),
+        _ => return Ok(Err(Response::error(400, "malformed request line"))),
+    };
+    let mut length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                length = match value.trim().parse() {
+                    Ok(length) => length,
+                    Err(_) => return Ok(Err(Response::error(400, "malformed Content-Length"))),
+                };
+            }
+        }
+    }
+    if length > MAX_BODY {
+        return Ok(Err(Response::error(
+            413,
+            format!("the body may take at most {} bytes", MAX_BODY),
+        )));
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    Ok(Ok(Request { method, path, body }))
+}
+
+/// Call the function the path names with the arguments in the body.
+pub fn respond(program: &Program, request: &Request, settings: Settings) -> Response {
+    let name = request.path.trim_start_matches('/');
+    let function = match program.top_level_function(name) {
+        Some(function) => function,
+        None => return Response::error(404, format!("there is no function named `{}`", name)),
+    };
+    if request.method != "POST" {
+        return Response::error(405, format!("call `{}` with POST", name));
+    }
+    let args = match serde_json::from_slice(&request.body) {
+        Ok(serde_json::Value::Array(args)) => args,
+        Ok(_) => return Response::error(400, "the body must be a JSON array of arguments"),
+        Err(err) => return Response::error(400, format!("the body is not JSON: {}", err)),
+    };
+    let args = match args.iter().map(Value::from_json).collect() {
+        Ok(args) => args,
+        Err(message) => return Response::error(400, message),
+    };
+    let span = program.functions[function].definition.span.clone();
+    let result = Interpreter::new(program)
+        .with_io(settings.io)
+        .with_limits(settings.limits)
+        .call(function, args, &span);
+    match result {
+        Ok(value) => Response::ok(value.to_json()),
+        Err(failure) => Response::error(422, failure.message),
+    }
+}
@@ -0,0 +1,75 @@
+//! Warns about `let` bindings that hide a parameter or an earlier binding of the same function.
+use std::collections::HashSet;
+
+use crate::diagnostics::Diagnostic;
+use crate::resolved_ast::*;
+use crate::symbols::{Symbol, SymbolTable};
+use crate::visit::{walk_expression, walk_function, walk_statement, Visitor};
+
+/// Check the bindings of every function of a linked program, except the imported ones.
+pub fn check(program: &Program) -> Vec<Diagnostic> {
+    let mut checker = Checker {
+        program,
+        symbols: SymbolTable::new(program),
+        params: HashSet::new(),
+        diagnostics: Vec::new(),
+    };
+    for (id, func) in &program.functions {
+        if !program.is_imported(id) {
+            checker
+                .params
+                .extend(func.definition.params.iter().filter_map(|param| param.id));
+            walk_function(&mut checker, func);
+        }
+    }
+    checker.diagnostics
+}
+
+struct Checker<'a, 'input> {
+    program: &'a Program<'input>,
+    symbols: SymbolTable,
+    /// The variables bound by parameters of functions and lambdas.
+    params: HashSet<VariableId>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'input> Visitor<'input> for Checker<'_, 'input> {
+    fn visit_statement(&mut self, stmt: &Statement<'input>) {
+        walk_statement(self, stmt);
+        let let_ = match stmt {
+            Statement::Let(let_) => let_,
+            _ => return,
+        };
+        let shadowed = self
+            .symbols
+            .visible(let_.span.start)
+            .into_iter()
+            .find(|symbol| {
+                matches!(symbol, Symbol::Variable(_)) && symbol.name(self.program) == let_.name
+            });
+        if let Some(Symbol::Variable(id)) = shadowed {
+            let what = if self.params.contains(&id) {
+                "parameter"
+            } else {
+                "binding"
+            };
+            let earlier = self.symbols.definitions[&Symbol::Variable(id)].clone();
+            self.diagnostics.push(
+                Diagnostic::warning(
+                    format!("`{}` shadows the {} of the same name", let_.name, what),
+                    let_.span.clone(),
+                )
+                .with_code("W012")
+                .with_note(format!("the shadowed {} is here", what), earlier),
+            );
+        }
+    }
+
+    fn visit_expression(&mut self, expr: &Expression<'input>) {
+        if let Expression::Lambda(lambda) = expr {
+            self.params
+                .extend(lambda.params.iter().filter_map(|param| param.id));
+        }
+        walk_expression(self, expr);
+    }
+}
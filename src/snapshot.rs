@@ -0,0 +1,89 @@
+//! Snapshots of what the formatter makes of files: next to every `.wy` file in a directory, a
+//! `.expected` file holds what it formats to, so a change to the formatter shows up as the
+//! snapshots it changes, and once they are updated it can be reviewed in their diff.
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::formatter::{self, FormatConfig};
+use crate::resolved_ast::Program;
+use crate::{find_sources, parse};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// The file formats to its snapshot.
+    Matches,
+    /// The file has no snapshot, and `update` wasn't asked for.
+    Missing,
+    /// The file formats to something else than its snapshot, and `update` wasn't asked for.
+    Differs { expected: String, formatted: String },
+    /// The file had no snapshot, and now has one.
+    Created,
+    /// The snapshot was different, and now it is what the file formats to.
+    Updated,
+    /// The file doesn't parse without errors, so it has no snapshot.
+    Unparsable,
+}
+
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    /// The formatted file.
+    pub path: PathBuf,
+    pub outcome: Outcome,
+}
+
+impl Snapshot {
+    /// Whether the snapshot is missing or isn't what the file formats to. Files that don't
+    /// parse are skipped rather than failed.
+    pub fn failed(&self) -> bool {
+        matches!(self.outcome, Outcome::Missing | Outcome::Differs { .. })
+    }
+}
+
+/// Where the snapshot of a file is, next to it.
+pub fn expected_path(path: &Path) -> PathBuf {
+    path.with_extension("expected")
+}
+
+/// Format every `.wy` file in `dir` and the directories in it, and compare what it formats to with
+/// its snapshot. With `update`, missing snapshots are created and differing ones replaced.
+pub fn check(dir: &Path, config: FormatConfig, update: bool) -> io::Result<Vec<Snapshot>> {
+    let mut snapshots = Vec::new();
+    for path in find_sources(&[dir.to_path_buf()], &[])? {
+        let text = std::fs::read_to_string(&path)?;
+        let formatted = match parse(&text) {
+            Ok((ast, errors)) if errors.is_empty() => {
+                formatter::format_source(&Program::from(ast), &text, config)
+            }
+            _ => {
+                snapshots.push(Snapshot {
+                    path,
+                    outcome: Outcome::Unparsable,
+                });
+                continue;
+            }
+        };
+        let expected_path = expected_path(&path);
+        let expected = match std::fs::read_to_string(&expected_path) {
+            Ok(expected) => Some(expected),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => None,
+            Err(error) => return Err(error),
+        };
+        let outcome = match expected {
+            Some(expected) if expected == formatted => Outcome::Matches,
+            None if !update => Outcome::Missing,
+            Some(expected) if !update => Outcome::Differs {
+                expected,
+                formatted,
+            },
+            expected => {
+                std::fs::write(&expected_path, formatted)?;
+                match expected {
+                    Some(_) => Outcome::Updated,
+                    None => Outcome::Created,
+                }
+            }
+        };
+        snapshots.push(Snapshot { path, outcome });
+    }
+    Ok(snapshots)
+}
@@ -0,0 +1,148 @@
+//! Spell checking for comments and string literals, enabled with the `spellcheck` feature.
+//!
+//! Words are checked against a small bundled English word list, which can be extended with a
+//! per-project dictionary file containing one word per line.
+use std::collections::HashSet;
+use std::ops::Range;
+
+use crate::resolved_ast::*;
+use crate::suggest;
+use crate::visit::{walk_expression, walk_program, Visitor};
+
+/// The name of the per-project dictionary file.
+pub const DICTIONARY_FILE: &str = "wryneck.dic";
+
+const BUNDLED_WORDS: &str = include_str!("words.txt");
+
+pub struct Dictionary {
+    words: HashSet<String>,
+}
+
+impl Dictionary {
+    /// The bundled word list.
+    pub fn bundled() -> Self {
+        let mut dictionary = Self {
+            words: HashSet::new(),
+        };
+        dictionary.extend_from_str(BUNDLED_WORDS);
+        dictionary
+    }
+
+    /// Add the words of a dictionary file, one per line. Lines starting with `#` are ignored.
+    pub fn extend_from_str(&mut self, words: &str) {
+        self.words.extend(
+            words
+                .lines()
+                .map(str::trim)
+                .filter(|word| !word.is_empty() && !word.starts_with('#'))
+                .map(str::to_lowercase),
+        );
+    }
+
+    pub fn contains(&self, word: &str) -> bool {
+        let word = word.to_lowercase();
+        self.words.contains(&word)
+            || word
+                .strip_suffix("'s")
+                .or_else(|| word.strip_suffix('s'))
+                .is_some_and(|stem| self.words.contains(stem))
+    }
+
+    /// Words of the dictionary close to the given one, best match first.
+    pub fn suggestions(&self, word: &str) -> Vec<String> {
+        let word = word.to_lowercase();
+        suggest::closest(&word, self.words.iter().map(String::as_str))
+            .into_iter()
+            .take(3)
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+pub struct Misspelling<'input> {
+    pub word: &'input str,
+    /// The byte range of the word in the input.
+    pub span: Range<usize>,
+    pub suggestions: Vec<String>,
+}
+
+/// Check all comments and string literals of the program, with the spans of the words in the
+/// file the program was parsed from.
+pub fn check<'input>(
+    program: &Program<'input>,
+    dictionary: &Dictionary,
+) -> Vec<Misspelling<'input>> {
+    let mut checker = Checker {
+        dictionary,
+        misspellings: Vec::new(),
+    };
+    walk_program(&mut checker, program);
+    checker.misspellings
+}
+
+struct Checker<'a, 'input> {
+    dictionary: &'a Dictionary,
+    misspellings: Vec<Misspelling<'input>>,
+}
+
+impl<'input> Visitor<'input> for Checker<'_, 'input> {
+    fn visit_comment(&mut self, comment: &Comment<'input>) {
+        // the text is what ends the comment, after the `//`
+        self.text(comment.text, comment.span.end - comment.text.len());
+    }
+
+    fn visit_expression(&mut self, expr: &Expression<'input>) {
+        if let Expression::String(string) = expr {
+            let (_, close) = string.kind.delimiters();
+            let start = string.span.end - close.len() - string.value.len();
+            self.text(string.value, start);
+        }
+        walk_expression(self, expr);
+    }
+}
+
+impl<'input> Checker<'_, 'input> {
+    /// Check the words of a piece of prose that starts at `start` in the input, skipping anything
+    /// that looks like code.
+    fn text(&mut self, text: &'input str, start: usize) {
+        for (offset, chunk) in chunks(text) {
+            if looks_like_code(chunk) {
+                continue;
+            }
+            let word = chunk.trim_matches(|c: char| !c.is_alphabetic());
+            if word.chars().count() < 3 || self.dictionary.contains(word) {
+                continue;
+            }
+            let before = chunk.len() - chunk.trim_start_matches(|c: char| !c.is_alphabetic()).len();
+            let start = start + offset + before;
+            self.misspellings.push(Misspelling {
+                word,
+                span: start..start + word.len(),
+                suggestions: self.dictionary.suggestions(word),
+            });
+        }
+    }
+}
+
+/// The parts of a text between whitespace, with where each starts in it.
+fn chunks(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.split(char::is_whitespace)
+        .scan(0, |offset, chunk| {
+            let start = *offset;
+            *offset += chunk.len();
+            // the whitespace after the chunk, if there is any
+            *offset += text[*offset..].chars().next().map_or(0, char::len_utf8);
+            Some((start, chunk))
+        })
+        .filter(|(_, chunk)| !chunk.is_empty())
+}
+
+/// Identifiers, paths, numbers and the like are not prose and should not be checked.
+fn looks_like_code(chunk: &str) -> bool {
+    let word = chunk.trim_matches(|c: char| !c.is_alphanumeric());
+    word.chars()
+        .any(|c| c.is_ascii_digit() || c == '_' || c == '.' || c == '/' || c == ':')
+        || word.chars().skip(1).any(char::is_uppercase)
+        || chunk.contains(|c| "(){}[]=<>*".contains(c))
+}
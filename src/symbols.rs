@@ -0,0 +1,272 @@
+//! The scopes of a linked program and where each variable and function is defined and used, for
+//! tools that need to know what a name refers to without resolving it themselves.
+use std::collections::HashMap;
+
+use id_collections::{id_type, Id, IdVec};
+
+use crate::resolved_ast::*;
+use crate::visit::{walk_expression, walk_function, walk_statement, Visitor};
+use crate::Span;
+
+#[id_type]
+pub struct ScopeId(usize);
+
+/// Something a name can refer to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Symbol {
+    Variable(VariableId),
+    Function(FunctionId),
+}
+
+impl Symbol {
+    pub fn name<'input>(self, program: &Program<'input>) -> &'input str {
+        match self {
+            Symbol::Variable(id) => program.variables[id].name,
+            Symbol::Function(id) => program.functions[id].definition.name,
+        }
+    }
+}
+
+/// A function, block, lambda or `rescue` and the symbols defined directly in it.
+#[derive(Debug)]
+pub struct Scope {
+    /// The scope this one is in, only the top level scope has none. The scope of a nested
+    /// function is in the block that defines it, but can't use the variables around it.
+    pub parent: Option<ScopeId>,
+    /// The function the scope belongs to, none for the top level scope.
+    pub function: Option<FunctionId>,
+    pub span: Span,
+    /// In order of definition.
+    pub symbols: Vec<Symbol>,
+}
+
+/// The scope tree of a program with the definition and the references of every symbol in it.
+/// Imported functions are left out, their spans point into other files, and so is the definition
+/// of the implicit `hatch`, which isn't written anywhere.
+#[derive(Debug)]
+pub struct SymbolTable {
+    pub scopes: IdVec<ScopeId, Scope>,
+    /// The span of what defines the symbol: a function's signature, a parameter, a `let`, or the
+    /// `try` that binds the error of its `rescue`.
+    pub definitions: HashMap<Symbol, Span>,
+    /// Where the symbol is used, in order: variables that are read or assigned to and calls.
+    pub references: HashMap<Symbol, Vec<Span>>,
+}
+
+impl SymbolTable {
+    /// The symbol table of a linked program, whose names are resolved.
+    pub fn new(program: &Program) -> Self {
+        let mut builder = Builder {
+            program,
+            table: SymbolTable {
+                scopes: IdVec::new(),
+                definitions: HashMap::new(),
+                references: HashMap::new(),
+            },
+            function: None,
+            scope: None,
+        };
+        let root = builder.enter(0..usize::MAX);
+        for (id, func) in &program.functions {
+            if func.parent.is_none() && !program.is_imported(id) {
+                if Some(id) != program.script {
+                    builder.define(Symbol::Function(id), func.definition.span.clone());
+                }
+                builder.function(id);
+            }
+        }
+        builder.leave(root);
+        builder.table
+    }
+
+    /// The top level scope, which contains the top level functions.
+    pub fn root(&self) -> ScopeId {
+        ScopeId::from_index(0)
+    }
+
+    pub fn references(&self, symbol: Symbol) -> &[Span] {
+        self.references.get(&symbol).map_or(&[], Vec::as_slice)
+    }
+
+    /// The symbol defined or used at the byte offset, if there is one. Where spans nest, like a
+    /// variable used in the value of a `let`, the innermost one wins.
+    pub fn symbol_at(&self, offset: usize) -> Option<Symbol> {
+        let references = self
+            .references
+            .iter()
+            .flat_map(|(symbol, spans)| spans.iter().map(move |span| (symbol, span)));
+        self.definitions
+            .iter()
+            .chain(references)
+            .filter(|(_, span)| span.contains(&offset))
+            .min_by_key(|(_, span)| span.len())
+            .map(|(symbol, _)| *symbol)
+    }
+
+    /// The innermost scope around the byte offset.
+    pub fn scope_at(&self, offset: usize) -> ScopeId {
+        let mut scope = self.root();
+        // children are pushed after their parents
+        for (id, inner) in &self.scopes {
+            if inner.span.contains(&offset) && inner.parent.is_some_and(|parent| parent == scope) {
+                scope = id;
+            }
+        }
+        scope
+    }
+
+    /// The symbols that can be used at the byte offset, innermost first. Functions are visible
+    /// from the functions nested in them, variables only after their definition in the function
+    /// that defines them.
+    pub fn visible(&self, offset: usize) -> Vec<Symbol> {
+        let scope = self.scope_at(offset);
+        let function = self.scopes[scope].function;
+        let mut visible = Vec::new();
+        let mut parent = Some(scope);
+        while let Some(outer) = parent {
+            let outer = &self.scopes[outer];
+            visible.extend(outer.symbols.iter().rev().filter(|symbol| match symbol {
+                Symbol::Function(_) => true,
+                Symbol::Variable(_) => {
+                    outer.function == function && self.definitions[symbol].end <= offset
+                }
+            }));
+            parent = outer.parent;
+        }
+        visible
+    }
+}
+
+struct Builder<'a, 'input> {
+    program: &'a Program<'input>,
+    table: SymbolTable,
+    /// The function being visited, which decides the nested functions that are visible.
+    function: Option<FunctionId>,
+    scope: Option<ScopeId>,
+}
+
+impl Builder<'_, '_> {
+    fn enter(&mut self, span: Span) -> Option<ScopeId> {
+        let outer = self.scope;
+        self.scope = Some(self.table.scopes.push(Scope {
+            parent: outer,
+            function: self.function,
+            span,
+            symbols: Vec::new(),
+        }));
+        outer
+    }
+
+    fn leave(&mut self, outer: Option<ScopeId>) {
+        self.scope = outer;
+    }
+
+    fn define(&mut self, symbol: Symbol, span: Span) {
+        let scope = self.scope.unwrap();
+        self.table.scopes[scope].symbols.push(symbol);
+        self.table.definitions.insert(symbol, span);
+    }
+
+    fn refer(&mut self, symbol: Symbol, span: Span) {
+        self.table.references.entry(symbol).or_default().push(span);
+    }
+
+    fn function(&mut self, id: FunctionId) {
+        let func = &self.program.functions[id];
+        let outer_function = self.function.replace(id);
+        let span = func.definition.span.start..func.body.span().end;
+        let outer = self.enter(span);
+        for param in &func.definition.params {
+            if let Some(param_id) = param.id {
+                self.define(Symbol::Variable(param_id), param.span.clone());
+            }
+        }
+        walk_function(self, func);
+        self.leave(outer);
+        self.function = outer_function;
+    }
+}
+
+impl<'input> Visitor<'input> for Builder<'_, 'input> {
+    fn visit_test(&mut self, test: &Test<'input>) {
+        let outer = self.enter(test.span.clone());
+        let generators = test.forall.iter().flat_map(|forall| &forall.generators);
+        for generator in generators.clone() {
+            self.visit_expression(&generator.range);
+        }
+        for generator in generators {
+            self.define(Symbol::Variable(generator.id), generator.span.clone());
+        }
+        self.visit_expression(&test.input);
+        if let Expected::Value(output) = &test.output {
+            self.visit_expression(output);
+        }
+        self.leave(outer);
+    }
+
+    fn visit_statement(&mut self, stmt: &Statement<'input>) {
+        match stmt {
+            Statement::Let(let_) => {
+                // the value can't refer to the variable it is bound to
+                self.visit_expression(&let_.value);
+                self.define(Symbol::Variable(let_.id), let_.span.clone());
+            }
+            Statement::Assign(assign) => {
+                if let Some(id) = assign.variable {
+                    self.refer(Symbol::Variable(id), assign.span.clone());
+                }
+                self.visit_expression(&assign.value);
+            }
+            Statement::Function(id) => {
+                let definition = self.program.functions[*id].definition.span.clone();
+                self.define(Symbol::Function(*id), definition);
+                self.function(*id);
+            }
+            _ => walk_statement(self, stmt),
+        }
+    }
+
+    fn visit_expression(&mut self, expr: &Expression<'input>) {
+        match expr {
+            Expression::Variable(var) => {
+                let symbol = match var.id {
+                    Some(id) => Some(Symbol::Variable(id)),
+                    None => self
+                        .program
+                        .function_named(self.function.unwrap(), var.name)
+                        .map(Symbol::Function),
+                };
+                if let Some(symbol) = symbol {
+                    self.refer(symbol, var.span.clone());
+                }
+            }
+            Expression::FunctionCall(FunctionCall::Direct { function, span, .. }) => {
+                self.refer(Symbol::Function(*function), span.clone());
+                walk_expression(self, expr);
+            }
+            Expression::Block(_, _, span) => {
+                let outer = self.enter(span.clone());
+                walk_expression(self, expr);
+                self.leave(outer);
+            }
+            Expression::Lambda(lambda) => {
+                let outer = self.enter(lambda.span.clone());
+                for param in &lambda.params {
+                    if let Some(id) = param.id {
+                        self.define(Symbol::Variable(id), param.span.clone());
+                    }
+                }
+                self.visit_expression(&lambda.body);
+                self.leave(outer);
+            }
+            Expression::Try(try_) => {
+                self.visit_expression(&try_.body);
+                let outer = self.enter(try_.rescue.span());
+                self.define(Symbol::Variable(try_.error_id), try_.span.clone());
+                self.visit_expression(&try_.rescue);
+                self.leave(outer);
+            }
+            _ => walk_expression(self, expr),
+        }
+    }
+}
@@ -0,0 +1,148 @@
+//! Calls in tail position, whose value is what the function or lambda around them returns, so
+//! nothing of the caller is needed anymore once they are made. [`Program::link`] marks them, and
+//! the `non-tail-recursion` lint reports recursive calls that aren't.
+use std::collections::HashSet;
+
+use crate::diagnostics::Diagnostic;
+use crate::resolved_ast::*;
+use crate::visit::{
+    walk_expression, walk_expression_mut, walk_function, walk_function_mut, walk_program_mut,
+    walk_statement_mut, Visitor, VisitorMut,
+};
+use crate::Span;
+
+/// Set [`FunctionCall::tail`] on the calls in tail position of every function.
+pub fn mark(program: &mut Program) {
+    walk_program_mut(&mut Marker, program);
+}
+
+/// The spans of the calls in tail position in a function, without the functions nested in it.
+pub fn spans(func: &Function) -> Vec<Span> {
+    let mut calls = Calls { calls: Vec::new() };
+    walk_function(&mut calls, func);
+    calls
+        .calls
+        .into_iter()
+        .filter(|(_, tail, _)| *tail)
+        .map(|(_, _, span)| span)
+        .collect()
+}
+
+/// Report the calls that are not in tail position but may lead back to the calling function,
+/// every level of such recursion takes up stack space. Imported functions are skipped.
+pub fn check(program: &Program) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    for (id, func) in &program.functions {
+        if program.is_imported(id) {
+            continue;
+        }
+        let mut calls = Calls { calls: Vec::new() };
+        walk_function(&mut calls, func);
+        for (callee, _, span) in calls.calls.into_iter().filter(|(_, tail, _)| !tail) {
+            let message = match callee {
+                Some(callee) if callee == id => format!(
+                    "`{}` calls itself outside of a tail position, deep recursion can overflow \
+                     the stack",
+                    func.definition.name
+                ),
+                Some(callee) if reaches(program, callee, id) => format!(
+                    "`{}` calls `{}`, which calls back into it, outside of a tail position, deep \
+                     recursion can overflow the stack",
+                    func.definition.name, program.functions[callee].definition.name
+                ),
+                _ => continue,
+            };
+            diagnostics.push(Diagnostic::warning(message, span).with_code("W014"));
+        }
+    }
+    diagnostics
+}
+
+/// Whether calls starting at `from` can lead to `to`.
+fn reaches(program: &Program, from: FunctionId, to: FunctionId) -> bool {
+    let mut visited = HashSet::from([from]);
+    let mut unvisited = vec![from];
+    while let Some(func) = unvisited.pop() {
+        for callee in &program.calls[func] {
+            if *callee == to {
+                return true;
+            }
+            if visited.insert(*callee) {
+                unvisited.push(*callee);
+            }
+        }
+    }
+    false
+}
+
+struct Marker;
+
+impl Marker {
+    /// Mark the calls whose value is the value of the expression.
+    fn mark(expr: &mut Expression) {
+        match expr {
+            Expression::FunctionCall(
+                FunctionCall::Direct { tail, .. }
+                | FunctionCall::Intrinsic { tail, .. }
+                | FunctionCall::Indirect { tail, .. },
+            ) => *tail = true,
+            Expression::Expression(inner)
+            | Expression::Paren(inner, _)
+            | Expression::ExpressionComment((inner, _)) => Self::mark(inner),
+            Expression::Block(_, Some(value), _) => Self::mark(value),
+            Expression::If(if_) => Self::mark_if(if_),
+            // the rescue has to stay ready while the body runs, but not while the rescue does
+            Expression::Try(try_) => Self::mark(&mut try_.rescue),
+            _ => {}
+        }
+    }
+
+    fn mark_if(if_: &mut If) {
+        Self::mark(&mut if_.body);
+        match &mut if_.else_body {
+            Some(Else::Block(block)) => Self::mark(block),
+            Some(Else::If(else_if)) => Self::mark_if(else_if),
+            None => {}
+        }
+    }
+}
+
+impl<'input> VisitorMut<'input> for Marker {
+    fn visit_function_mut(&mut self, func: &mut Function<'input>) {
+        Self::mark(&mut func.body);
+        walk_function_mut(self, func);
+    }
+
+    fn visit_statement_mut(&mut self, stmt: &mut Statement<'input>) {
+        if let Statement::Return(value, ..) = stmt {
+            Self::mark(value);
+        }
+        walk_statement_mut(self, stmt);
+    }
+
+    fn visit_expression_mut(&mut self, expr: &mut Expression<'input>) {
+        if let Expression::Lambda(lambda) = expr {
+            Self::mark(&mut lambda.body);
+        }
+        walk_expression_mut(self, expr);
+    }
+}
+
+/// Collects the calls of a function: the function called directly, if any, whether the call is
+/// in tail position, and its span.
+struct Calls {
+    calls: Vec<(Option<FunctionId>, bool, Span)>,
+}
+
+impl<'input> Visitor<'input> for Calls {
+    fn visit_expression(&mut self, expr: &Expression<'input>) {
+        if let Expression::FunctionCall(call) = expr {
+            let callee = match call {
+                FunctionCall::Direct { function, .. } => Some(*function),
+                FunctionCall::Intrinsic { .. } | FunctionCall::Indirect { .. } => None,
+            };
+            self.calls.push((callee, call.tail(), call.span().clone()));
+        }
+        walk_expression(self, expr);
+    }
+}
@@ -0,0 +1,178 @@
+//! Which functions and statements of a program its tests ran, for `wryneck test --coverage`.
+//! [`crate::coverage`] is about the grammar instead, which constructs a set of files uses.
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use crate::interpreter::{Event, Observer, Value};
+use crate::resolved_ast::*;
+use crate::visit::{walk_expression, Visitor};
+use crate::Span;
+
+/// What running the tests reached, filled in by a [`Recorder`] for every interpreter they ran on.
+#[derive(Debug, Default, Clone)]
+pub struct Executed {
+    functions: HashSet<FunctionId>,
+    /// The statements and block tails, with the function they belong to.
+    statements: HashSet<(FunctionId, Span)>,
+}
+
+/// Records what an interpreter runs into [`Executed`], which the recorders of all the
+/// interpreters share.
+pub(crate) struct Recorder(pub(crate) Rc<RefCell<Executed>>);
+
+impl<'a> Observer<'a> for Recorder {
+    fn observe(
+        &mut self,
+        event: Event,
+        function: FunctionId,
+        span: &Span,
+        _depth: usize,
+        _variables: &HashMap<VariableId, Value<'a>>,
+    ) -> bool {
+        let mut executed = self.0.borrow_mut();
+        match event {
+            Event::Call => executed.functions.insert(function),
+            Event::Evaluate => executed.statements.insert((function, span.clone())),
+        };
+        true
+    }
+}
+
+/// What the tests covered of a function.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionCoverage {
+    pub function: FunctionId,
+    pub called: bool,
+    /// The statements and block tails of the function, nested blocks and lambdas included, in
+    /// the order they are written, each with whether it ran.
+    pub statements: Vec<(Span, bool)>,
+}
+
+impl FunctionCoverage {
+    pub fn executed(&self) -> usize {
+        self.statements.iter().filter(|(_, ran)| *ran).count()
+    }
+}
+
+/// Collects the statements of a function the way the interpreter tells its observer about them.
+struct Statements<'a, 'input> {
+    program: &'a Program<'input>,
+    spans: Vec<Span>,
+}
+
+impl<'input> Visitor<'input> for Statements<'_, 'input> {
+    fn visit_expression(&mut self, expr: &Expression<'input>) {
+        if let Expression::Block(block, tail, _) = expr {
+            self.spans
+                .extend(block.iter().map(|stmt| stmt.span(self.program)));
+            self.spans.extend(tail.iter().map(|tail| tail.span()));
+        }
+        walk_expression(self, expr);
+    }
+}
+
+/// The coverage of every function that isn't imported, in the order of the program.
+pub fn coverage(program: &Program, executed: &Executed) -> Vec<FunctionCoverage> {
+    program
+        .functions
+        .iter()
+        .filter(|(id, _)| !program.is_imported(*id))
+        .map(|(id, func)| {
+            let mut statements = Statements {
+                program,
+                spans: Vec::new(),
+            };
+            statements.visit_expression(&func.body);
+            let mut spans = statements.spans;
+            spans.sort_by_key(|span| span.start);
+            FunctionCoverage {
+                function: id,
+                called: executed.functions.contains(&id),
+                statements: spans
+                    .into_iter()
+                    .map(|span| {
+                        let ran = executed.statements.contains(&(id, span.clone()));
+                        (span, ran)
+                    })
+                    .collect(),
+            }
+        })
+        .collect()
+}
+
+/// How many statements of each function ran, and of all of them together.
+pub fn summary(program: &Program, coverage: &[FunctionCoverage]) -> String {
+    let mut summary = String::new();
+    for function in coverage {
+        let name = program.functions[function.function].definition.name;
+        summary += &if function.called {
+            format!(
+                "{}: {} of {} statements\n",
+                name,
+                function.executed(),
+                function.statements.len()
+            )
+        } else {
+            format!("{}: never called\n", name)
+        };
+    }
+    let statements = coverage
+        .iter()
+        .map(|function| function.statements.len())
+        .sum::<usize>();
+    let executed = coverage
+        .iter()
+        .map(FunctionCoverage::executed)
+        .sum::<usize>();
+    let called = coverage.iter().filter(|function| function.called).count();
+    summary += &format!(
+        "{} of {} statements ({}%), {} of {} functions called\n",
+        executed,
+        statements,
+        (executed * 100).checked_div(statements).unwrap_or(100),
+        called,
+        coverage.len()
+    );
+    summary
+}
+
+/// Whether a line of the source ran, by the statements that start on it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Line {
+    /// No statement starts on the line.
+    Other,
+    Executed,
+    /// A statement on the line never ran, or it defines a function that was never called.
+    Missed,
+}
+
+/// Every line of `text`, the file the functions are in, with whether it ran.
+pub fn lines<'t>(
+    program: &Program,
+    text: &'t str,
+    coverage: &[FunctionCoverage],
+) -> Vec<(Line, &'t str)> {
+    let mut lines = text
+        .lines()
+        .map(|line| (Line::Other, line))
+        .collect::<Vec<_>>();
+    let mut mark = |offset: usize, ran: bool| {
+        let line = text[..offset].matches('\n').count();
+        if let Some((line, _)) = lines.get_mut(line) {
+            // a line is only executed if everything that starts on it ran
+            *line = match (*line, ran) {
+                (Line::Missed, _) | (_, false) => Line::Missed,
+                _ => Line::Executed,
+            };
+        }
+    };
+    for function in coverage {
+        let definition = &program.functions[function.function].definition.span;
+        mark(definition.start, function.called);
+        for (span, ran) in &function.statements {
+            mark(span.start, *ran);
+        }
+    }
+    lines
+}
@@ -0,0 +1,226 @@
+//! Export of the results of [`crate::test_runner`] for CI systems and editors.
+use serde::Serialize;
+
+use crate::resolved_ast::Program;
+use crate::test_runner::{FunctionResults, Outcome, TestResult};
+
+/// A test as it is reported, with where to find it and what it came to.
+#[derive(Debug, Serialize)]
+struct Report<'a> {
+    function: &'a str,
+    name: Option<&'a str>,
+    /// The position of the test after its function, counting from 0.
+    index: usize,
+    /// The line of the test, counting from 1.
+    line: usize,
+    /// The text of the test, `input = output`.
+    test: &'a str,
+    outcome: &'static str,
+    expected: Option<&'a str>,
+    actual: Option<&'a str>,
+    error: Option<&'a str>,
+    /// The values of the variables of `forall` the test failed for.
+    case: Option<&'a str>,
+    /// In seconds.
+    duration: f64,
+}
+
+fn reports<'a>(
+    program: &'a Program,
+    text: &'a str,
+    results: &'a [FunctionResults],
+) -> impl Iterator<Item = Report<'a>> {
+    results.iter().flat_map(move |function| {
+        function
+            .tests
+            .iter()
+            .map(move |test| report(program, function, text, test))
+    })
+}
+
+fn report<'a>(
+    program: &'a Program,
+    function: &FunctionResults,
+    text: &'a str,
+    test: &'a TestResult,
+) -> Report<'a> {
+    let func = &program.functions[function.function];
+    let (outcome, expected, actual, error) = match &test.outcome {
+        Outcome::Passed => ("passed", None, None, None),
+        Outcome::Failed { expected, got } => {
+            ("failed", Some(expected.as_str()), Some(got.as_str()), None)
+        }
+        Outcome::Error(failure) => ("error", None, None, Some(failure.message.as_str())),
+    };
+    Report {
+        function: func.definition.name,
+        name: func.tests[test.index].name.as_ref().map(|name| name.value),
+        index: test.index,
+        line: text[..test.span.start].matches('\n').count() + 1,
+        test: &text[test.span.clone()],
+        outcome,
+        expected,
+        actual,
+        error,
+        case: test.case.as_deref(),
+        duration: test.duration.as_secs_f64(),
+    }
+}
+
+/// The results of the tests of `file`, whose text is `text`, as a JSON object with the file, the
+/// number of tests that passed and failed, and every test with its function, name, index, line,
+/// text, outcome, expected and actual value or error, the case of `forall` it failed for, and
+/// duration in seconds.
+pub fn json(program: &Program, file: &str, text: &str, results: &[FunctionResults]) -> String {
+    #[derive(Serialize)]
+    struct Tests<'a> {
+        file: &'a str,
+        passed: usize,
+        failed: usize,
+        tests: Vec<Report<'a>>,
+    }
+    let tests = Tests {
+        file,
+        passed: results.iter().map(FunctionResults::passed).sum(),
+        failed: results.iter().map(FunctionResults::failed).sum(),
+        tests: reports(program, text, results).collect(),
+    };
+    serde_json::to_string_pretty(&tests).unwrap()
+}
+
+/// The results of the tests of `file`, whose text is `text`, as JUnit XML, with a test suite for
+/// every function.
+pub fn junit(program: &Program, file: &str, text: &str, results: &[FunctionResults]) -> String {
+    let mut junit = Junit::new(file);
+    junit.add(program, file, text, results);
+    junit.to_xml()
+}
+
+/// The results of the tests of any number of files as one JUnit XML document, which they are
+/// added to a file at a time, so the programs they are of needn't all be kept until the end.
+#[derive(Debug, Clone, Default)]
+pub struct Junit {
+    name: String,
+    tests: usize,
+    failures: usize,
+    errors: usize,
+    /// In seconds.
+    time: f64,
+    /// The test suites of the files added so far.
+    suites: String,
+}
+
+impl Junit {
+    /// A document without results, whose test suites are called `name`.
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            ..Self::default()
+        }
+    }
+
+    /// Add the results of the tests of `file`, whose text is `text`, with a test suite for every
+    /// function.
+    pub fn add(&mut self, program: &Program, file: &str, text: &str, results: &[FunctionResults]) {
+        let count = |outcome: &str| {
+            reports(program, text, results)
+                .filter(|report| report.outcome == outcome)
+                .count()
+        };
+        self.tests += reports(program, text, results).count();
+        self.failures += count("failed");
+        self.errors += count("error");
+        self.time += results
+            .iter()
+            .map(|function| time(&function.tests))
+            .sum::<f64>();
+        self.suites += &suites(program, file, text, results);
+    }
+
+    pub fn to_xml(&self) -> String {
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        xml += &format!(
+            "<testsuites name=\"{}\" tests=\"{}\" failures=\"{}\" errors=\"{}\" time=\"{:.6}\">\n",
+            escape(&self.name),
+            self.tests,
+            self.failures,
+            self.errors,
+            self.time
+        );
+        xml += &self.suites;
+        xml += "</testsuites>\n";
+        xml
+    }
+}
+
+/// How long the tests took altogether, in seconds.
+fn time(tests: &[TestResult]) -> f64 {
+    tests.iter().map(|test| test.duration.as_secs_f64()).sum()
+}
+
+/// The test suites of the functions of a file.
+fn suites(program: &Program, file: &str, text: &str, results: &[FunctionResults]) -> String {
+    let mut xml = String::new();
+    for function in results {
+        let name = program.functions[function.function].definition.name;
+        let reports = function
+            .tests
+            .iter()
+            .map(|test| report(program, function, text, test))
+            .collect::<Vec<_>>();
+        xml += &format!(
+            "  <testsuite name=\"{}\" file=\"{}\" tests=\"{}\" failures=\"{}\" errors=\"{}\" time=\"{:.6}\">\n",
+            escape(name),
+            escape(file),
+            reports.len(),
+            reports.iter().filter(|report| report.outcome == "failed").count(),
+            reports.iter().filter(|report| report.outcome == "error").count(),
+            time(&function.tests)
+        );
+        for report in reports {
+            xml += &format!(
+                "    <testcase name=\"{}[{}]: {}\" classname=\"{}\" file=\"{}\" line=\"{}\" time=\"{:.6}\"",
+                escape(name),
+                report.index,
+                escape(report.name.unwrap_or(report.test)),
+                escape(name),
+                escape(file),
+                report.line,
+                report.duration
+            );
+            let case = report
+                .case
+                .map(|case| format!(", for {}", case))
+                .unwrap_or_default();
+            match (report.expected, report.actual, report.error) {
+                (Some(expected), Some(actual), _) => {
+                    xml += &format!(
+                        ">\n      <failure message=\"expected {}, got {}{}\"/>\n    </testcase>\n",
+                        escape(expected),
+                        escape(actual),
+                        escape(&case)
+                    )
+                }
+                (_, _, Some(error)) => {
+                    xml += &format!(
+                        ">\n      <error message=\"{}{}\"/>\n    </testcase>\n",
+                        escape(error),
+                        escape(&case)
+                    )
+                }
+                _ => xml += "/>\n",
+            }
+        }
+        xml += "  </testsuite>\n";
+    }
+    xml
+}
+
+/// Escape text for XML attributes.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\n', "&#10;")
+}
@@ -0,0 +1,365 @@
+//! Runs the tests attached to functions, like `[2 = 4]` after a function that doubles. The input
+//! of a test is the first argument, the other parameters get their defaults, and the result of
+//! the call has to equal the output. A test after `forall n in 0..10:` runs for the values of
+//! `n`, and if it fails, the runner looks for the smallest value it still fails for.
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crate::interpreter::{self, Interpreter, Limits, Value, MAX_DEPTH};
+use crate::resolved_ast::*;
+use crate::runtime::Failure;
+use crate::test_coverage::{Executed, Recorder};
+use crate::Span;
+
+/// The limits a test runs with unless it is given others, so a test that doesn't finish fails
+/// instead of hanging. They are far above what tests that do finish need.
+pub const LIMITS: Limits = Limits {
+    steps: Some(10_000_000),
+    depth: MAX_DEPTH,
+    memory: Some(64 * 1024 * 1024),
+};
+
+/// How many cases of a `forall` test run at most. A test with more cases runs a sample of them.
+pub const CASES: usize = 100;
+
+/// How many smaller cases a failing `forall` test tries at most.
+const SHRINKS: usize = 1000;
+
+/// What running a test came to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Passed,
+    /// The function returned something else than the output, both as written by
+    /// [`Value`]'s `Display`, or returned at all when the output is `error`.
+    Failed {
+        expected: String,
+        got: String,
+    },
+    /// Evaluating the input, the call or the output failed, without the output being `error`,
+    /// or the program went past its limits.
+    Error(Failure),
+}
+
+#[derive(Debug, Clone)]
+pub struct TestResult {
+    /// The position of the test in [`Function::tests`], which the filter may have skipped some of.
+    pub index: usize,
+    /// The test, `input = output`.
+    pub span: Span,
+    pub outcome: Outcome,
+    /// The values of the variables of `forall` the test failed for, like `n = 3`.
+    pub case: Option<String>,
+    /// How long evaluating the test took, with all of its cases.
+    pub duration: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub struct FunctionResults {
+    pub function: FunctionId,
+    /// In the order of the tests.
+    pub tests: Vec<TestResult>,
+}
+
+impl FunctionResults {
+    pub fn passed(&self) -> usize {
+        self.tests
+            .iter()
+            .filter(|test| test.outcome == Outcome::Passed)
+            .count()
+    }
+
+    pub fn failed(&self) -> usize {
+        self.tests.len() - self.passed()
+    }
+}
+
+/// Whether a test runs with a filter, which has to be part of the name of the test or of its
+/// function.
+fn matches(filter: &str, function: &Function, test: &Test) -> bool {
+    function.definition.name.contains(filter)
+        || test
+            .name
+            .as_ref()
+            .is_some_and(|name| name.value.contains(filter))
+}
+
+/// Run the tests of every function that has some, except the imported ones, and only those that
+/// [`matches`] the filter if there is one. Every test gets the whole `limits` for itself.
+pub fn run(program: &Program, limits: Limits, filter: Option<&str>) -> Vec<FunctionResults> {
+    let _span = tracing::info_span!("execute").entered();
+    interpreter::with_large_stack(|| {
+        run_tests(program, filter, &|| {
+            Interpreter::new(program).with_limits(limits)
+        })
+    })
+}
+
+/// Run the tests like [`run`], and record which functions and statements they ran.
+pub fn run_with_coverage(
+    program: &Program,
+    limits: Limits,
+    filter: Option<&str>,
+) -> (Vec<FunctionResults>, Executed) {
+    let _span = tracing::info_span!("execute").entered();
+    interpreter::with_large_stack(|| {
+        let executed = Rc::new(RefCell::new(Executed::default()));
+        let results = run_tests(program, filter, &|| {
+            Interpreter::new(program)
+                .with_limits(limits)
+                .with_observer(Box::new(Recorder(executed.clone())))
+        });
+        (results, executed.take())
+    })
+}
+
+/// Run the tests, every test, case and range of `forall` on an interpreter of its own.
+fn run_tests<'a>(
+    program: &'a Program<'a>,
+    filter: Option<&str>,
+    interpreter: &dyn Fn() -> Interpreter<'a>,
+) -> Vec<FunctionResults> {
+    program
+        .functions
+        .iter()
+        .filter(|(id, _)| !program.is_imported(*id))
+        .map(|(id, func)| FunctionResults {
+            function: id,
+            tests: func
+                .tests
+                .iter()
+                .enumerate()
+                .filter(|(_, test)| filter.is_none_or(|filter| matches(filter, func, test)))
+                .map(|(index, test)| {
+                    let start = Instant::now();
+                    let (outcome, case) = run_cases(program, interpreter, id, test);
+                    TestResult {
+                        index,
+                        span: test.span.clone(),
+                        outcome,
+                        case,
+                        duration: start.elapsed(),
+                    }
+                })
+                .collect(),
+        })
+        .filter(|results| !results.tests.is_empty())
+        .collect()
+}
+
+/// Run a test, for the cases of its `forall` if it has one, with the case it failed for.
+fn run_cases<'a>(
+    program: &'a Program<'a>,
+    interpreter: &dyn Fn() -> Interpreter<'a>,
+    function: FunctionId,
+    test: &'a Test<'a>,
+) -> (Outcome, Option<String>) {
+    let run = |variables| run_test(&mut interpreter(), program, function, test, variables);
+    let Some(forall) = &test.forall else {
+        return (run(HashMap::new()), None);
+    };
+    let ranges = match forall
+        .generators
+        .iter()
+        .map(|generator| range(&mut interpreter(), function, generator))
+        .collect::<Result<Vec<_>, _>>()
+    {
+        Ok(ranges) => ranges,
+        Err(failure) => return (Outcome::Error(failure), None),
+    };
+    let run_case = |case: &[i64]| {
+        run(forall
+            .generators
+            .iter()
+            .zip(case)
+            .map(|(generator, value)| (generator.id, Value::Int(*value)))
+            .collect())
+    };
+    let failed = cases(&ranges)
+        .into_iter()
+        .map(|case| (run_case(&case), case))
+        .find(|(outcome, _)| *outcome != Outcome::Passed);
+    let Some((outcome, case)) = failed else {
+        return (Outcome::Passed, None);
+    };
+    let (outcome, case) = shrink(&ranges, outcome, case, run_case);
+    let case = forall
+        .generators
+        .iter()
+        .zip(case)
+        .map(|(generator, value)| format!("{} = {}", generator.name, value))
+        .collect::<Vec<_>>();
+    (outcome, Some(case.join(", ")))
+}
+
+/// The first and the last value of the range of a variable of `forall`.
+fn range<'a>(
+    interpreter: &mut Interpreter<'a>,
+    function: FunctionId,
+    generator: &'a Generator<'a>,
+) -> Result<(i64, i64), Failure> {
+    let span = generator.range.span();
+    match interpreter.evaluate(function, HashMap::new(), &generator.range)? {
+        Value::Range {
+            start,
+            end,
+            inclusive,
+        } => {
+            let last = if inclusive {
+                Some(end)
+            } else {
+                end.checked_sub(1)
+            };
+            match last.filter(|last| *last >= start) {
+                Some(last) => Ok((start, last)),
+                None => Err(Failure::new(
+                    format!("`{}` has no values, its range is empty", generator.name),
+                    &span,
+                )),
+            }
+        }
+        value => Err(Failure::new(
+            format!(
+                "`{}` takes its values from a range of ints, not from {}",
+                generator.name, value
+            ),
+            &span,
+        )),
+    }
+}
+
+/// The cases of a `forall` whose variables have the ranges, each its first and last value. They
+/// are all of them if there are at most [`CASES`], otherwise the first values, the last values,
+/// and others picked at random, though the same every run, so a failure can be run again.
+fn cases(ranges: &[(i64, i64)]) -> Vec<Vec<i64>> {
+    let sizes = ranges
+        .iter()
+        .map(|(first, last)| (*last as i128 - *first as i128) as u128 + 1)
+        .collect::<Vec<_>>();
+    let count = sizes
+        .iter()
+        .try_fold(1u128, |count, size| count.checked_mul(*size));
+    if let Some(count) = count.filter(|count| *count <= CASES as u128) {
+        return (0..count)
+            .map(|mut index| {
+                // the first variable changes the slowest
+                let mut case = ranges
+                    .iter()
+                    .zip(&sizes)
+                    .rev()
+                    .map(|((first, _), size)| {
+                        let value = *first as i128 + (index % size) as i128;
+                        index /= size;
+                        value as i64
+                    })
+                    .collect::<Vec<_>>();
+                case.reverse();
+                case
+            })
+            .collect();
+    }
+    let mut seed = 0x5EED_u64;
+    let mut random = move || {
+        // splitmix64
+        seed = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = seed;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    };
+    let mut cases = vec![
+        ranges.iter().map(|(first, _)| *first).collect(),
+        ranges.iter().map(|(_, last)| *last).collect(),
+    ];
+    while cases.len() < CASES {
+        cases.push(
+            ranges
+                .iter()
+                .zip(&sizes)
+                .map(|((first, _), size)| {
+                    (*first as i128 + (random() as u128 % size) as i128) as i64
+                })
+                .collect(),
+        );
+    }
+    cases
+}
+
+/// Move the values of a failing case towards 0, as far as their ranges let them, for as long as
+/// the case keeps failing, so the case reported is about the smallest that fails.
+fn shrink(
+    ranges: &[(i64, i64)],
+    mut outcome: Outcome,
+    mut case: Vec<i64>,
+    run: impl Fn(&[i64]) -> Outcome,
+) -> (Outcome, Vec<i64>) {
+    let mut shrinks = 0;
+    'shrink: while shrinks < SHRINKS {
+        for (i, (first, last)) in ranges.iter().enumerate() {
+            // try 0 first, then halfway between it and the value, and so on closer to the value
+            let target = 0.clamp(*first, *last) as i128;
+            let mut distance = case[i] as i128 - target;
+            while distance != 0 && shrinks < SHRINKS {
+                let mut smaller = case.clone();
+                smaller[i] = (case[i] as i128 - distance) as i64;
+                shrinks += 1;
+                let smaller_outcome = run(&smaller);
+                if smaller_outcome != Outcome::Passed {
+                    outcome = smaller_outcome;
+                    case = smaller;
+                    continue 'shrink;
+                }
+                distance /= 2;
+            }
+        }
+        break;
+    }
+    (outcome, case)
+}
+
+fn run_test<'a>(
+    interpreter: &mut Interpreter<'a>,
+    program: &'a Program<'a>,
+    function: FunctionId,
+    test: &'a Test<'a>,
+    variables: HashMap<VariableId, Value<'a>>,
+) -> Outcome {
+    let got = call(interpreter, program, function, test, variables.clone());
+    match (&test.output, got) {
+        // going past the limits isn't the failure the test expects
+        (Expected::Failure(_), Err(failure)) if !interpreter.exceeded(&failure) => Outcome::Passed,
+        (Expected::Failure(_), Ok(got)) => Outcome::Failed {
+            expected: "error".to_string(),
+            got: got.to_string(),
+        },
+        (_, Err(failure)) => Outcome::Error(failure),
+        (Expected::Value(output), Ok(got)) => {
+            match interpreter.evaluate(function, variables, output) {
+                Ok(expected) if expected == got => Outcome::Passed,
+                Ok(expected) => Outcome::Failed {
+                    expected: expected.to_string(),
+                    got: got.to_string(),
+                },
+                Err(failure) => Outcome::Error(failure),
+            }
+        }
+    }
+}
+
+/// What the function returned for the input of the test.
+fn call<'a>(
+    interpreter: &mut Interpreter<'a>,
+    program: &'a Program<'a>,
+    function: FunctionId,
+    test: &'a Test<'a>,
+    variables: HashMap<VariableId, Value<'a>>,
+) -> Result<Value<'a>, Failure> {
+    let input = interpreter.evaluate(function, variables, &test.input)?;
+    // `() = output` tests functions without parameters
+    let args = match input {
+        Value::Unit if program.functions[function].definition.params.is_empty() => vec![],
+        input => vec![input],
+    };
+    interpreter.call(function, args, &test.span)
+}
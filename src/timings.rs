@@ -0,0 +1,89 @@
+//! How long the phases of a command took, like parsing, resolving, formatting and executing, as
+//! measured by the `tracing` spans of the same names around them, for `--timings`.
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tracing::span::{Attributes, Id};
+use tracing::Subscriber;
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+/// The time spent in the spans of one name, added up over every time one was open.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Phase {
+    pub name: &'static str,
+    pub total: Duration,
+    /// How many spans of the name there were, a file that imports another is parsed twice.
+    pub count: usize,
+}
+
+/// A layer that times the spans, and a handle to read the times with, as clones share them.
+#[derive(Debug, Clone, Default)]
+pub struct Timings {
+    /// In the order the first span of each name closed.
+    phases: Arc<Mutex<Vec<Phase>>>,
+}
+
+/// When a span was created, kept with it until it closes.
+struct Opened(Instant);
+
+impl Timings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn phases(&self) -> Vec<Phase> {
+        self.phases.lock().unwrap().clone()
+    }
+
+    fn record(&self, name: &'static str, elapsed: Duration) {
+        let mut phases = self.phases.lock().unwrap();
+        match phases.iter_mut().find(|phase| phase.name == name) {
+            Some(phase) => {
+                phase.total += elapsed;
+                phase.count += 1;
+            }
+            None => phases.push(Phase {
+                name,
+                total: elapsed,
+                count: 1,
+            }),
+        }
+    }
+
+    /// The phases one to a line, with how long they took and how often they ran if more than once.
+    pub fn report(&self) -> String {
+        let phases = self.phases();
+        let width = phases
+            .iter()
+            .map(|phase| phase.name.len())
+            .max()
+            .unwrap_or(0);
+        let mut report = String::new();
+        for phase in phases {
+            let total = format!("{:.3?}", phase.total);
+            report += &format!("{:width$}  {:>10}", phase.name, total);
+            if phase.count > 1 {
+                report += &format!(" ({} times)", phase.count);
+            }
+            report += "\n";
+        }
+        report
+    }
+}
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for Timings {
+    fn on_new_span(&self, _attributes: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(Opened(Instant::now()));
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(&id) {
+            if let Some(Opened(opened)) = span.extensions().get::<Opened>() {
+                self.record(span.name(), opened.elapsed());
+            }
+        }
+    }
+}
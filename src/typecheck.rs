@@ -0,0 +1,352 @@
+//! Checks that operators, conditions and annotated parameters get values of the right type.
+//!
+//! Only `int`, `string`, `bool` and unit are known types. The type of everything else, like
+//! lambdas or enum values, is unknown and accepted anywhere. Calls use the signatures
+//! [`crate::infer`] infers, so unannotated functions are checked as well.
+use std::collections::HashMap;
+use std::fmt::{Display, Error};
+use std::ops;
+
+use id_collections::IdVec;
+
+use crate::diagnostics::Diagnostic;
+use crate::infer::{self, Signature};
+use crate::intrinsics;
+use crate::resolved_ast::*;
+use crate::visit::{walk_program, Visitor};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Type {
+    Int,
+    String,
+    /// The result of `==` and `!=`, there are no literals for it.
+    Bool,
+    Unit,
+    Unknown,
+}
+
+impl Type {
+    /// The type an annotation names, unknown for the names of enums.
+    pub fn from_annotation(annotation: &crate::resolved_ast::Type) -> Self {
+        match annotation.name {
+            "int" => Type::Int,
+            "string" => Type::String,
+            "bool" => Type::Bool,
+            "unit" => Type::Unit,
+            _ => Type::Unknown,
+        }
+    }
+
+    /// Whether both types are known and differ.
+    fn conflicts(self, other: Type) -> bool {
+        self != Type::Unknown && other != Type::Unknown && self != other
+    }
+}
+
+impl Display for Type {
+    /// The type with an article, as in "expected an `int`".
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> Result<(), Error> {
+        match self {
+            Type::Int => write!(fmt, "an `int`"),
+            Type::String => write!(fmt, "a `string`"),
+            Type::Bool => write!(fmt, "a `bool`"),
+            Type::Unit => write!(fmt, "unit"),
+            Type::Unknown => write!(fmt, "a value"),
+        }
+    }
+}
+
+pub fn check(program: &Program) -> Vec<Diagnostic> {
+    let mut checker = Checker {
+        program,
+        signatures: infer::signatures(program),
+        variables: HashMap::new(),
+        diagnostics: Vec::new(),
+    };
+    walk_program(&mut checker, program);
+    checker.diagnostics
+}
+
+struct Checker<'a, 'input> {
+    program: &'a Program<'input>,
+    signatures: IdVec<FunctionId, Signature>,
+    /// The types of the variables whose type is known.
+    variables: HashMap<VariableId, Type>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Checker<'_, '_> {
+    fn error(&mut self, code: &'static str, message: String, span: ops::Range<usize>) {
+        self.diagnostics
+            .push(Diagnostic::error(message, span).with_code(code));
+    }
+
+    fn check_condition(&mut self, condition: &Expression, span: &ops::Range<usize>) {
+        let type_ = self.type_of(condition);
+        if type_.conflicts(Type::Bool) {
+            self.error(
+                "E030",
+                format!("the condition has to be a `bool`, but it is {}", type_),
+                span.clone(),
+            );
+        }
+    }
+
+    /// The type of `lhs op rhs`, reporting operands of the wrong type.
+    fn operation(&mut self, lhs: Type, op: Opcode, rhs: Type, span: &ops::Range<usize>) -> Type {
+        match op {
+            Opcode::Eq | Opcode::NotEq => {
+                // unit operands are reported by `units`
+                if lhs.conflicts(rhs) && lhs != Type::Unit && rhs != Type::Unit {
+                    self.error(
+                        "E031",
+                        format!("cannot compare {} with {}", lhs, rhs),
+                        span.clone(),
+                    );
+                }
+                Type::Bool
+            }
+            Opcode::Add | Opcode::Sub | Opcode::Mul | Opcode::Div | Opcode::Concat => {
+                let expected = match op {
+                    Opcode::Concat => Type::String,
+                    _ => Type::Int,
+                };
+                for operand in [lhs, rhs] {
+                    if operand.conflicts(expected) && operand != Type::Unit {
+                        self.error(
+                            "E032",
+                            format!("cannot use {} with `{}`", operand, op),
+                            span.clone(),
+                        );
+                        // once is enough for `"a" * "b"`
+                        break;
+                    }
+                }
+                expected
+            }
+        }
+    }
+
+    fn check_statement(&mut self, stmt: &Statement) {
+        match stmt {
+            Statement::Let(let_) => {
+                let type_ = self.type_of(&let_.value);
+                self.variables.insert(let_.id, type_);
+            }
+            Statement::Assign(assign) => {
+                let mut type_ = self.type_of(&assign.value);
+                let variable = assign
+                    .variable
+                    .and_then(|id| self.variables.get(&id).copied())
+                    .unwrap_or(Type::Unknown);
+                if let Some(op) = assign.op {
+                    type_ = self.operation(variable, op, type_, &assign.span);
+                }
+                if type_.conflicts(variable) {
+                    self.error(
+                        "E034",
+                        format!(
+                            "cannot assign {} to `{}`, which holds {}",
+                            type_, assign.target, variable
+                        ),
+                        assign.span.clone(),
+                    );
+                }
+            }
+            Statement::Assert(assert) => self.check_condition(&assert.condition, &assert.span),
+            Statement::Expression(expr) | Statement::Return(expr, ..) => {
+                self.type_of(expr);
+            }
+            // nested functions are checked on their own
+            Statement::Comment(_) | Statement::Function(_) => {}
+        }
+    }
+
+    /// The type of the expression, checking everything in it along the way.
+    fn type_of(&mut self, expr: &Expression) -> Type {
+        match expr {
+            Expression::Expression(expr) | Expression::Paren(expr, _) => self.type_of(expr),
+            Expression::ExpressionComment((expr, _)) => self.type_of(expr),
+            Expression::Block(block, tail, _) => {
+                for stmt in block {
+                    self.check_statement(stmt);
+                }
+                match tail {
+                    Some(tail) => self.type_of(tail),
+                    None => Type::Unit,
+                }
+            }
+            Expression::Number(_) => Type::Int,
+            Expression::String(_) => Type::String,
+            Expression::Unit(_, _) => Type::Unit,
+            Expression::Bool(..) => Type::Bool,
+            Expression::None(_, _) => Type::Unknown,
+            Expression::Variable(var) => var
+                .id
+                .and_then(|id| self.variables.get(&id).copied())
+                .unwrap_or(Type::Unknown),
+            Expression::Op(lhs, op, rhs, span) => {
+                let lhs = self.type_of(lhs);
+                let rhs = self.type_of(rhs);
+                self.operation(lhs, *op, rhs, span)
+            }
+            Expression::If(if_) => self.type_of_if(if_),
+            Expression::FunctionCall(call) => {
+                let args = call
+                    .args()
+                    .iter()
+                    .map(|arg| self.type_of(arg))
+                    .collect::<Vec<_>>();
+                match call {
+                    FunctionCall::Direct {
+                        function,
+                        name,
+                        span,
+                        ..
+                    } => {
+                        let definition = &self.program.functions[*function].definition;
+                        let signature = &self.signatures[*function];
+                        for ((arg, param), expected) in
+                            args.iter().zip(&definition.params).zip(&signature.params)
+                        {
+                            let expected = expected.known();
+                            if arg.conflicts(expected) {
+                                let mut diagnostic = Diagnostic::error(
+                                    format!(
+                                        "`{}` expects {} for `{}`, but got {}",
+                                        name, expected, param.name, arg
+                                    ),
+                                    span.clone(),
+                                )
+                                .with_code("E035");
+                                // the spans of an imported function point into another file
+                                if !self.program.is_imported(*function) {
+                                    diagnostic = diagnostic.with_note(
+                                        format!("`{}` is declared here", param.name),
+                                        param.span.clone(),
+                                    );
+                                }
+                                self.diagnostics.push(diagnostic);
+                            }
+                        }
+                        signature.ret.known()
+                    }
+                    FunctionCall::Intrinsic {
+                        intrinsic, span, ..
+                    } => {
+                        let (params, ret) = intrinsics::signature(*intrinsic);
+                        for (arg, expected) in args.iter().zip(params) {
+                            if arg.conflicts(*expected) {
+                                self.diagnostics.push(
+                                    Diagnostic::error(
+                                        format!(
+                                            "`{}` expects {}, but got {}",
+                                            intrinsic.name(),
+                                            expected,
+                                            arg
+                                        ),
+                                        span.clone(),
+                                    )
+                                    .with_code("E035"),
+                                );
+                            }
+                        }
+                        ret
+                    }
+                    FunctionCall::Indirect { callee, .. } => {
+                        self.type_of(callee);
+                        Type::Unknown
+                    }
+                }
+            }
+            Expression::EnumConstructor(constructor) => {
+                for arg in &constructor.args {
+                    self.type_of(arg);
+                }
+                Type::Unknown
+            }
+            Expression::Some(value, _, _) => {
+                self.type_of(value);
+                Type::Unknown
+            }
+            Expression::Lambda(lambda) => {
+                self.type_of(&lambda.body);
+                Type::Unknown
+            }
+            Expression::Field(field) => {
+                self.type_of(&field.object);
+                Type::Unknown
+            }
+            Expression::MethodCall(call) => {
+                self.type_of(&call.object);
+                for arg in &call.args {
+                    self.type_of(arg);
+                }
+                Type::Unknown
+            }
+            Expression::Try(try_) => {
+                let body = self.type_of(&try_.body);
+                let rescue = self.type_of(&try_.rescue);
+                if body == rescue {
+                    body
+                } else {
+                    Type::Unknown
+                }
+            }
+            Expression::Range(range) => {
+                self.type_of(&range.start);
+                self.type_of(&range.end);
+                Type::Unknown
+            }
+            Expression::Error(_) => Type::Unknown,
+        }
+    }
+
+    fn type_of_if(&mut self, if_: &If) -> Type {
+        self.check_condition(&if_.condition, &if_.span);
+        let body = self.type_of(&if_.body);
+        let else_ = match &if_.else_body {
+            Some(Else::Block(block)) => self.type_of(block),
+            Some(Else::If(else_if)) => self.type_of_if(else_if),
+            None => Type::Unit,
+        };
+        if body == else_ {
+            body
+        } else {
+            Type::Unknown
+        }
+    }
+}
+
+impl<'input> Visitor<'input> for Checker<'_, 'input> {
+    fn visit_function(&mut self, func: &Function<'input>) {
+        for param in &func.definition.params {
+            if let (Some(id), Some(type_)) = (param.id, &param.type_) {
+                self.variables.insert(id, Type::from_annotation(type_));
+            }
+        }
+        for default in func
+            .definition
+            .params
+            .iter()
+            .filter_map(|param| param.default.as_ref())
+        {
+            self.type_of(default);
+        }
+        self.type_of(&func.body);
+        for test in &func.tests {
+            for generator in test.forall.iter().flat_map(|forall| &forall.generators) {
+                self.type_of(&generator.range);
+                // ranges are of ints
+                self.variables.insert(generator.id, Type::Int);
+            }
+            self.type_of(&test.input);
+            if let Expected::Value(output) = &test.output {
+                self.type_of(output);
+            }
+        }
+        for benchmark in &func.benchmarks {
+            self.type_of(&benchmark.input);
+        }
+    }
+}
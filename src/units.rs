@@ -0,0 +1,67 @@
+//! Checks that unit values, which have no meaningful value, are not used in arithmetic.
+use std::collections::HashSet;
+
+use crate::diagnostics::Diagnostic;
+use crate::resolved_ast::*;
+use crate::visit::{walk_expression, walk_program, walk_statement, Visitor};
+
+pub fn check(program: &Program) -> Vec<Diagnostic> {
+    let mut checker = Checker {
+        units: HashSet::new(),
+        diagnostics: Vec::new(),
+    };
+    walk_program(&mut checker, program);
+    checker.diagnostics
+}
+
+struct Checker {
+    /// The variables bound to unit.
+    units: HashSet<VariableId>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Checker {
+    /// Whether the expression evaluates to unit, as far as can be told without running it.
+    fn is_unit(&self, expr: &Expression) -> bool {
+        match expr {
+            Expression::Unit(_, _) => true,
+            Expression::Expression(expr) | Expression::Paren(expr, _) => self.is_unit(expr),
+            // a block that returns at its end never comes to a value
+            Expression::Block(block, None, _) => {
+                !matches!(block.last(), Some(Statement::Return(..)))
+            }
+            Expression::Block(_, Some(tail), _) => self.is_unit(tail),
+            Expression::Variable(var) => var.id.is_some_and(|id| self.units.contains(&id)),
+            _ => false,
+        }
+    }
+}
+
+impl<'input> Visitor<'input> for Checker {
+    fn visit_statement(&mut self, stmt: &Statement<'input>) {
+        walk_statement(self, stmt);
+        if let Statement::Let(let_) = stmt {
+            if self.is_unit(&let_.value) {
+                self.units.insert(let_.id);
+            }
+        }
+    }
+
+    fn visit_expression(&mut self, expr: &Expression<'input>) {
+        match expr {
+            Expression::Op(lhs, op, rhs, span) => {
+                if self.is_unit(lhs) || self.is_unit(rhs) {
+                    self.diagnostics.push(
+                        Diagnostic::error(
+                            format!("cannot use a unit value with `{}`", op),
+                            span.clone(),
+                        )
+                        .with_code("E033"),
+                    );
+                }
+                walk_expression(self, expr);
+            }
+            _ => walk_expression(self, expr),
+        }
+    }
+}
@@ -0,0 +1,49 @@
+//! Warns about `let` bindings whose value is never read.
+use std::collections::HashSet;
+
+use crate::diagnostics::Diagnostic;
+use crate::resolved_ast::*;
+use crate::visit::{walk_expression, walk_function, walk_statement, Visitor};
+use crate::Span;
+
+/// Check the bindings of every function of a linked program, except the imported ones.
+pub fn check(program: &Program) -> Vec<Diagnostic> {
+    let mut uses = Uses {
+        lets: Vec::new(),
+        read: HashSet::new(),
+    };
+    for (id, func) in &program.functions {
+        if !program.is_imported(id) {
+            walk_function(&mut uses, func);
+        }
+    }
+    uses.lets
+        .into_iter()
+        .filter(|(_, id, _)| !uses.read.contains(id))
+        .map(|(name, _, span)| {
+            Diagnostic::warning(format!("`{}` is never read", name), span).with_code("W010")
+        })
+        .collect()
+}
+
+struct Uses<'input> {
+    /// The name, variable and span of every `let`, in order.
+    lets: Vec<(&'input str, VariableId, Span)>,
+    read: HashSet<VariableId>,
+}
+
+impl<'input> Visitor<'input> for Uses<'input> {
+    fn visit_statement(&mut self, stmt: &Statement<'input>) {
+        if let Statement::Let(let_) = stmt {
+            self.lets.push((let_.name, let_.id, let_.span.clone()));
+        }
+        walk_statement(self, stmt);
+    }
+
+    fn visit_expression(&mut self, expr: &Expression<'input>) {
+        if let Expression::Variable(Variable { id: Some(id), .. }) = expr {
+            self.read.insert(*id);
+        }
+        walk_expression(self, expr);
+    }
+}
@@ -0,0 +1,359 @@
+//! Traversal of the resolved AST.
+//!
+//! Implementors of [`Visitor`] override the methods for the nodes they are interested in and call
+//! the matching `walk_*` function to continue into the node's children.
+use crate::resolved_ast::*;
+
+pub trait Visitor<'input> {
+    fn visit_function(&mut self, func: &Function<'input>) {
+        walk_function(self, func);
+    }
+
+    /// The implicit `hatch` made up of the top level statements, see [`Program::script`].
+    fn visit_script(&mut self, func: &Function<'input>) {
+        self.visit_function(func);
+    }
+
+    fn visit_enum(&mut self, _enum: &Enum<'input>) {}
+
+    fn visit_comment(&mut self, _comment: &Comment<'input>) {}
+
+    fn visit_import(&mut self, _import: &Import<'input>) {}
+
+    fn visit_test(&mut self, test: &Test<'input>) {
+        walk_test(self, test);
+    }
+
+    fn visit_statement(&mut self, stmt: &Statement<'input>) {
+        walk_statement(self, stmt);
+    }
+
+    fn visit_expression(&mut self, expr: &Expression<'input>) {
+        walk_expression(self, expr);
+    }
+
+    fn visit_if(&mut self, if_: &If<'input>) {
+        walk_if(self, if_);
+    }
+}
+
+/// Visit everything at the top level of the program, in source order. Each function is followed
+/// by the functions nested in it. The top level statements are visited together as the script, at
+/// the place of the first one.
+pub fn walk_program<'input, V: Visitor<'input> + ?Sized>(
+    visitor: &mut V,
+    program: &Program<'input>,
+) {
+    fn walk_nested<'input, V: Visitor<'input> + ?Sized>(
+        visitor: &mut V,
+        program: &Program<'input>,
+        func: FunctionId,
+    ) {
+        visitor.visit_function(&program.functions[func]);
+        walk_nested_functions(visitor, program, func);
+    }
+
+    fn walk_nested_functions<'input, V: Visitor<'input> + ?Sized>(
+        visitor: &mut V,
+        program: &Program<'input>,
+        func: FunctionId,
+    ) {
+        for nested in program.nested_functions(func) {
+            walk_nested(visitor, program, nested);
+        }
+    }
+
+    for thing in &program.things {
+        match thing {
+            TopLevel::Function(func) => walk_nested(visitor, program, *func),
+            TopLevel::Enum(enum_) => visitor.visit_enum(&program.enums[*enum_]),
+            TopLevel::Comment(comment) => visitor.visit_comment(comment),
+            TopLevel::Import(import) => visitor.visit_import(import),
+            TopLevel::Statement(0) => {
+                if let Some(script) = program.script {
+                    visitor.visit_script(&program.functions[script]);
+                    walk_nested_functions(visitor, program, script);
+                }
+            }
+            TopLevel::Statement(_) => {}
+        }
+    }
+}
+
+pub fn walk_function<'input, V: Visitor<'input> + ?Sized>(
+    visitor: &mut V,
+    func: &Function<'input>,
+) {
+    for default in func
+        .definition
+        .params
+        .iter()
+        .filter_map(|param| param.default.as_ref())
+    {
+        visitor.visit_expression(default);
+    }
+    visitor.visit_expression(&func.body);
+    for test in &func.tests {
+        visitor.visit_test(test);
+    }
+    for benchmark in &func.benchmarks {
+        visitor.visit_expression(&benchmark.input);
+    }
+}
+
+pub fn walk_test<'input, V: Visitor<'input> + ?Sized>(visitor: &mut V, test: &Test<'input>) {
+    for generator in test.forall.iter().flat_map(|forall| &forall.generators) {
+        visitor.visit_expression(&generator.range);
+    }
+    visitor.visit_expression(&test.input);
+    if let Expected::Value(output) = &test.output {
+        visitor.visit_expression(output);
+    }
+}
+
+pub fn walk_statement<'input, V: Visitor<'input> + ?Sized>(
+    visitor: &mut V,
+    stmt: &Statement<'input>,
+) {
+    match stmt {
+        Statement::Let(let_) => visitor.visit_expression(&let_.value),
+        Statement::Assign(assign) => visitor.visit_expression(&assign.value),
+        Statement::Assert(assert) => visitor.visit_expression(&assert.condition),
+        Statement::Expression(expr) | Statement::Return(expr, ..) => visitor.visit_expression(expr),
+        Statement::Comment(comment) => visitor.visit_comment(comment),
+        // nested functions are visited by `walk_program`
+        Statement::Function(_) => {}
+    }
+}
+
+pub fn walk_expression<'input, V: Visitor<'input> + ?Sized>(
+    visitor: &mut V,
+    expr: &Expression<'input>,
+) {
+    match expr {
+        Expression::Expression(expr) | Expression::Paren(expr, _) => visitor.visit_expression(expr),
+        Expression::Block(block, tail, _) => {
+            for stmt in block {
+                visitor.visit_statement(stmt);
+            }
+            if let Some(tail) = tail {
+                visitor.visit_expression(tail);
+            }
+        }
+        Expression::FunctionCall(call) => {
+            if let FunctionCall::Indirect { callee, .. } = call {
+                visitor.visit_expression(callee);
+            }
+            for arg in call.args() {
+                visitor.visit_expression(arg);
+            }
+        }
+        Expression::EnumConstructor(constructor) => {
+            for arg in &constructor.args {
+                visitor.visit_expression(arg);
+            }
+        }
+        Expression::If(if_) => visitor.visit_if(if_),
+        Expression::Lambda(lambda) => visitor.visit_expression(&lambda.body),
+        Expression::Try(try_) => {
+            visitor.visit_expression(&try_.body);
+            visitor.visit_expression(&try_.rescue);
+        }
+        Expression::Range(range) => {
+            visitor.visit_expression(&range.start);
+            visitor.visit_expression(&range.end);
+        }
+        Expression::Some(value, _, _) => visitor.visit_expression(value),
+        Expression::Field(field) => visitor.visit_expression(&field.object),
+        Expression::MethodCall(call) => {
+            visitor.visit_expression(&call.object);
+            for arg in &call.args {
+                visitor.visit_expression(arg);
+            }
+        }
+        Expression::Op(lhs, _, rhs, _) => {
+            visitor.visit_expression(lhs);
+            visitor.visit_expression(rhs);
+        }
+        Expression::ExpressionComment((expr, comment)) => {
+            visitor.visit_expression(expr);
+            visitor.visit_comment(comment);
+        }
+        Expression::Variable(_)
+        | Expression::Number(_)
+        | Expression::String(_)
+        | Expression::Unit(_, _)
+        | Expression::Bool(_, _, _)
+        | Expression::None(_, _)
+        | Expression::Error(_) => {}
+    }
+}
+
+pub fn walk_if<'input, V: Visitor<'input> + ?Sized>(visitor: &mut V, if_: &If<'input>) {
+    visitor.visit_expression(&if_.condition);
+    visitor.visit_expression(&if_.body);
+    match &if_.else_body {
+        Some(Else::Block(block)) => visitor.visit_expression(block),
+        Some(Else::If(if_)) => visitor.visit_if(if_),
+        None => {}
+    }
+}
+
+/// Like [`Visitor`], but able to modify the nodes it visits.
+pub trait VisitorMut<'input> {
+    fn visit_function_mut(&mut self, func: &mut Function<'input>) {
+        walk_function_mut(self, func);
+    }
+
+    fn visit_test_mut(&mut self, test: &mut Test<'input>) {
+        walk_test_mut(self, test);
+    }
+
+    fn visit_statement_mut(&mut self, stmt: &mut Statement<'input>) {
+        walk_statement_mut(self, stmt);
+    }
+
+    fn visit_expression_mut(&mut self, expr: &mut Expression<'input>) {
+        walk_expression_mut(self, expr);
+    }
+
+    fn visit_if_mut(&mut self, if_: &mut If<'input>) {
+        walk_if_mut(self, if_);
+    }
+}
+
+/// Visit all functions of the program, including imported ones. Enums, comments and imports
+/// contain no code and are skipped.
+pub fn walk_program_mut<'input, V: VisitorMut<'input> + ?Sized>(
+    visitor: &mut V,
+    program: &mut Program<'input>,
+) {
+    for func in program.functions.values_mut() {
+        visitor.visit_function_mut(func);
+    }
+}
+
+pub fn walk_function_mut<'input, V: VisitorMut<'input> + ?Sized>(
+    visitor: &mut V,
+    func: &mut Function<'input>,
+) {
+    for default in func
+        .definition
+        .params
+        .iter_mut()
+        .filter_map(|param| param.default.as_mut())
+    {
+        visitor.visit_expression_mut(default);
+    }
+    visitor.visit_expression_mut(&mut func.body);
+    for test in &mut func.tests {
+        visitor.visit_test_mut(test);
+    }
+    for benchmark in &mut func.benchmarks {
+        visitor.visit_expression_mut(&mut benchmark.input);
+    }
+}
+
+pub fn walk_test_mut<'input, V: VisitorMut<'input> + ?Sized>(
+    visitor: &mut V,
+    test: &mut Test<'input>,
+) {
+    for generator in test
+        .forall
+        .iter_mut()
+        .flat_map(|forall| &mut forall.generators)
+    {
+        visitor.visit_expression_mut(&mut generator.range);
+    }
+    visitor.visit_expression_mut(&mut test.input);
+    if let Expected::Value(output) = &mut test.output {
+        visitor.visit_expression_mut(output);
+    }
+}
+
+pub fn walk_statement_mut<'input, V: VisitorMut<'input> + ?Sized>(
+    visitor: &mut V,
+    stmt: &mut Statement<'input>,
+) {
+    match stmt {
+        Statement::Let(let_) => visitor.visit_expression_mut(&mut let_.value),
+        Statement::Assign(assign) => visitor.visit_expression_mut(&mut assign.value),
+        Statement::Assert(assert) => visitor.visit_expression_mut(&mut assert.condition),
+        Statement::Expression(expr) | Statement::Return(expr, ..) => {
+            visitor.visit_expression_mut(expr)
+        }
+        Statement::Comment(_) | Statement::Function(_) => {}
+    }
+}
+
+pub fn walk_expression_mut<'input, V: VisitorMut<'input> + ?Sized>(
+    visitor: &mut V,
+    expr: &mut Expression<'input>,
+) {
+    match expr {
+        Expression::Expression(expr) | Expression::Paren(expr, _) => {
+            visitor.visit_expression_mut(expr)
+        }
+        Expression::Block(block, tail, _) => {
+            for stmt in block {
+                visitor.visit_statement_mut(stmt);
+            }
+            if let Some(tail) = tail {
+                visitor.visit_expression_mut(tail);
+            }
+        }
+        Expression::FunctionCall(call) => {
+            if let FunctionCall::Indirect { callee, .. } = call {
+                visitor.visit_expression_mut(callee);
+            }
+            for arg in call.args_mut() {
+                visitor.visit_expression_mut(arg);
+            }
+        }
+        Expression::EnumConstructor(constructor) => {
+            for arg in &mut constructor.args {
+                visitor.visit_expression_mut(arg);
+            }
+        }
+        Expression::If(if_) => visitor.visit_if_mut(if_),
+        Expression::Lambda(lambda) => visitor.visit_expression_mut(&mut lambda.body),
+        Expression::Try(try_) => {
+            visitor.visit_expression_mut(&mut try_.body);
+            visitor.visit_expression_mut(&mut try_.rescue);
+        }
+        Expression::Range(range) => {
+            visitor.visit_expression_mut(&mut range.start);
+            visitor.visit_expression_mut(&mut range.end);
+        }
+        Expression::Some(value, _, _) => visitor.visit_expression_mut(value),
+        Expression::Field(field) => visitor.visit_expression_mut(&mut field.object),
+        Expression::MethodCall(call) => {
+            visitor.visit_expression_mut(&mut call.object);
+            for arg in &mut call.args {
+                visitor.visit_expression_mut(arg);
+            }
+        }
+        Expression::Op(lhs, _, rhs, _) => {
+            visitor.visit_expression_mut(lhs);
+            visitor.visit_expression_mut(rhs);
+        }
+        Expression::ExpressionComment((expr, _)) => visitor.visit_expression_mut(expr),
+        Expression::Variable(_)
+        | Expression::Number(_)
+        | Expression::String(_)
+        | Expression::Unit(_, _)
+        | Expression::Bool(_, _, _)
+        | Expression::None(_, _)
+        | Expression::Error(_) => {}
+    }
+}
+
+pub fn walk_if_mut<'input, V: VisitorMut<'input> + ?Sized>(visitor: &mut V, if_: &mut If<'input>) {
+    visitor.visit_expression_mut(&mut if_.condition);
+    visitor.visit_expression_mut(&mut if_.body);
+    match &mut if_.else_body {
+        Some(Else::Block(block)) => visitor.visit_expression_mut(block),
+        Some(Else::If(if_)) => visitor.visit_if_mut(if_),
+        None => {}
+    }
+}
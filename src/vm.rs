@@ -0,0 +1,379 @@
+//! Runs programs compiled by [`crate::bytecode`] on a stack machine, which is faster than walking
+//! the resolved AST like [`crate::interpreter`] does. The results and failures are the same.
+use std::rc::Rc;
+
+use crate::bytecode::{Chunk, Compiled, Instruction};
+use crate::interpreter::MAX_DEPTH;
+use crate::resolved_ast::FunctionId;
+use crate::runtime::{self, Failure};
+use crate::Span;
+
+/// What the stack machine computes with.
+pub type Value<'a> = runtime::Value<'a, Closure<'a>>;
+
+/// A lambda with the slots of the function it was created in.
+#[derive(Debug, Clone)]
+pub struct Closure<'a> {
+    /// The index in [`Compiled::lambdas`].
+    pub lambda: usize,
+    pub params: Vec<&'a str>,
+    pub slots: Vec<Option<Value<'a>>>,
+}
+
+impl runtime::Closure for Closure<'_> {
+    fn params(&self) -> Vec<&str> {
+        self.params.clone()
+    }
+}
+
+/// Where to go when an instruction inside a `try` fails.
+struct Handler {
+    rescue: usize,
+    slot: usize,
+    /// The height of the stack when the `try` started.
+    stack: usize,
+}
+
+/// A call being run.
+struct Frame<'c, 'a> {
+    chunk: &'c Chunk<'a>,
+    /// The next instruction.
+    ip: usize,
+    slots: Vec<Option<Value<'a>>>,
+    /// The `try`s the next instruction is in, innermost last.
+    handlers: Vec<Handler>,
+    /// The height of the stack when the call started.
+    stack: usize,
+}
+
+pub struct Vm<'c, 'a> {
+    compiled: &'c Compiled<'a>,
+    stack: Vec<Value<'a>>,
+    frames: Vec<Frame<'c, 'a>>,
+    /// Whether the program may read and write stdin, stdout and files.
+    io: bool,
+}
+
+impl<'c, 'a> Vm<'c, 'a> {
+    /// A machine for programs that don't do I/O.
+    pub fn new(compiled: &'c Compiled<'a>) -> Self {
+        Self {
+            compiled,
+            stack: Vec::new(),
+            frames: Vec::new(),
+            io: false,
+        }
+    }
+
+    /// Allow or forbid the program to read and write stdin, stdout and files.
+    pub fn with_io(self, io: bool) -> Self {
+        Self { io, ..self }
+    }
+
+    /// Call a function with the given arguments, the parameters left out get their defaults.
+    pub fn call(
+        &mut self,
+        function: FunctionId,
+        args: Vec<Value<'a>>,
+        span: &Span,
+    ) -> Result<Value<'a>, Failure> {
+        let chunk = &self.compiled.functions[function];
+        let base = self.frames.len();
+        let slots = vec![None; chunk.slots.len()];
+        self.enter(chunk, slots, args, span)?;
+        self.run(base)
+    }
+
+    /// Start running a chunk, with the slots it starts out with.
+    fn enter(
+        &mut self,
+        chunk: &'c Chunk<'a>,
+        mut slots: Vec<Option<Value<'a>>>,
+        args: Vec<Value<'a>>,
+        span: &Span,
+    ) -> Result<(), Failure> {
+        let params = &chunk.params;
+        let wrong_count = match chunk.name {
+            Some(_) => args.len() > params.len(),
+            None => args.len() != params.len(),
+        };
+        if wrong_count {
+            let callee = match chunk.name {
+                Some(name) => format!("`{}`", name),
+                None => "the lambda".to_string(),
+            };
+            return Err(Failure::new(
+                format!(
+                    "{} takes {} arguments, but got {}",
+                    callee,
+                    params.len(),
+                    args.len()
+                ),
+                span,
+            ));
+        }
+        if let Some(missing) = params[args.len()..].iter().find(|param| !param.default) {
+            return Err(Failure::new(
+                format!(
+                    "`{}` is missing an argument for `{}`",
+                    chunk.name.unwrap_or_default(),
+                    missing.name
+                ),
+                span,
+            ));
+        }
+        if self.frames.len() == MAX_DEPTH {
+            return Err(Failure::new(
+                format!("the program went more than {} calls deep", MAX_DEPTH),
+                &chunk.span,
+            ));
+        }
+        for (param, value) in params.iter().zip(args) {
+            if let Some(slot) = param.slot {
+                slots[slot] = Some(value);
+            }
+        }
+        self.frames.push(Frame {
+            chunk,
+            ip: 0,
+            slots,
+            handlers: Vec::new(),
+            stack: self.stack.len(),
+        });
+        Ok(())
+    }
+
+    /// Run instructions until the frame above `base` returns.
+    fn run(&mut self, base: usize) -> Result<Value<'a>, Failure> {
+        loop {
+            let frame = self.frames.last_mut().unwrap();
+            let chunk = frame.chunk;
+            let ip = frame.ip;
+            frame.ip += 1;
+            match self.execute(&chunk.code[ip], &chunk.spans[ip], base) {
+                Ok(Some(value)) => return Ok(value),
+                Ok(None) => {}
+                Err(failure) => self.unwind(failure, base)?,
+            }
+        }
+    }
+
+    /// Continue at the innermost `try` that is still running, failing if there is none.
+    fn unwind(&mut self, failure: Failure, base: usize) -> Result<(), Failure> {
+        while self.frames.len() > base {
+            let frame = self.frames.last_mut().unwrap();
+            if let Some(handler) = frame.handlers.pop() {
+                self.stack.truncate(handler.stack);
+                frame.slots[handler.slot] = Some(Value::String(failure.message));
+                frame.ip = handler.rescue;
+                return Ok(());
+            }
+            let frame = self.frames.pop().unwrap();
+            self.stack.truncate(frame.stack);
+        }
+        Err(failure)
+    }
+
+    fn pop(&mut self) -> Value<'a> {
+        self.stack.pop().unwrap()
+    }
+
+    fn pop_many(&mut self, count: usize) -> Vec<Value<'a>> {
+        self.stack.split_off(self.stack.len() - count)
+    }
+
+    fn jump_if(&mut self, expected: bool, to: usize, span: &Span) -> Result<(), Failure> {
+        match self.pop() {
+            Value::Bool(condition) => {
+                if condition == expected {
+                    self.frames.last_mut().unwrap().ip = to;
+                }
+                Ok(())
+            }
+            value => Err(Failure::new(
+                format!("the condition has to be a comparison, but is {}", value),
+                span,
+            )),
+        }
+    }
+
+    /// Run an instruction, returning the value of the call if the frame above `base` returned.
+    fn execute(
+        &mut self,
+        instruction: &'c Instruction<'a>,
+        span: &Span,
+        base: usize,
+    ) -> Result<Option<Value<'a>>, Failure> {
+        let value = match instruction {
+            Instruction::Int(value) => Value::Int(*value),
+            Instruction::String(value) => Value::String(value.to_string()),
+            Instruction::Unit => Value::Unit,
+            Instruction::Bool(value) => Value::Bool(*value),
+            Instruction::None => Value::None,
+            Instruction::Some => Value::Some(Box::new(self.pop())),
+            Instruction::Function(function) => Value::Function(
+                *function,
+                self.compiled.functions[*function].name.unwrap_or_default(),
+            ),
+            Instruction::Closure(lambda) => {
+                let frame = self.frames.last().unwrap();
+                Value::Closure(Rc::new(Closure {
+                    lambda: *lambda,
+                    params: self.compiled.lambdas[*lambda]
+                        .params
+                        .iter()
+                        .map(|param| param.name)
+                        .collect(),
+                    slots: frame.slots.clone(),
+                }))
+            }
+            Instruction::Load(slot) => {
+                let frame = self.frames.last().unwrap();
+                frame.slots[*slot].clone().ok_or_else(|| {
+                    Failure::new(
+                        format!("`{}` has no value here", frame.chunk.slots[*slot]),
+                        span,
+                    )
+                })?
+            }
+            Instruction::Store(slot) => {
+                let value = self.pop();
+                self.frames.last_mut().unwrap().slots[*slot] = Some(value);
+                return Ok(None);
+            }
+            Instruction::SkipDefault { slot, to } => {
+                let frame = self.frames.last_mut().unwrap();
+                if frame.slots[*slot].is_some() {
+                    frame.ip = *to;
+                }
+                return Ok(None);
+            }
+            Instruction::Pop => {
+                self.pop();
+                return Ok(None);
+            }
+            Instruction::Op(op) => {
+                let rhs = self.pop();
+                let lhs = self.pop();
+                runtime::operation(lhs, *op, rhs, span)?
+            }
+            Instruction::Range { inclusive } => {
+                let end = self.pop();
+                let start = self.pop();
+                match (start, end) {
+                    (Value::Int(start), Value::Int(end)) => Value::Range {
+                        start,
+                        end,
+                        inclusive: *inclusive,
+                    },
+                    (start, end) => {
+                        return Err(Failure::new(
+                            format!("a range has to be between ints, not {} and {}", start, end),
+                            span,
+                        ))
+                    }
+                }
+            }
+            Instruction::Enum {
+                name,
+                variant,
+                fields,
+            } => {
+                let values = self.pop_many(fields.len());
+                Value::Enum {
+                    name,
+                    variant,
+                    fields: fields.iter().copied().zip(values).collect(),
+                }
+            }
+            Instruction::Field(name) => {
+                let object = self.pop();
+                let value = match (&object, *name) {
+                    (Value::Enum { fields, .. }, name) => fields
+                        .iter()
+                        .find(|(field, _)| *field == name)
+                        .map(|(_, value)| value.clone()),
+                    (Value::Some(value), "value") => Some((**value).clone()),
+                    (Value::Range { start, .. }, "start") => Some(Value::Int(*start)),
+                    (Value::Range { end, .. }, "end") => Some(Value::Int(*end)),
+                    _ => None,
+                };
+                value.ok_or_else(|| {
+                    Failure::new(format!("{} has no field `{}`", object, name), span)
+                })?
+            }
+            Instruction::Method { name, args } => {
+                self.pop_many(*args);
+                let object = self.pop();
+                return Err(Failure::new(
+                    format!("{} has no method `{}`", object, name),
+                    span,
+                ));
+            }
+            Instruction::Call { function, args } => {
+                let args = self.pop_many(*args);
+                let chunk = &self.compiled.functions[*function];
+                self.enter(chunk, vec![None; chunk.slots.len()], args, span)?;
+                return Ok(None);
+            }
+            Instruction::Intrinsic { intrinsic, args } => {
+                let args = self.pop_many(*args);
+                runtime::intrinsic(*intrinsic, args, self.io, span)?
+            }
+            Instruction::CallValue { args } => {
+                let args = self.pop_many(*args);
+                match self.pop() {
+                    Value::Function(function, _) => {
+                        let chunk = &self.compiled.functions[function];
+                        self.enter(chunk, vec![None; chunk.slots.len()], args, span)?;
+                    }
+                    Value::Closure(closure) => {
+                        let chunk = &self.compiled.lambdas[closure.lambda];
+                        self.enter(chunk, closure.slots.clone(), args, span)?;
+                    }
+                    callee => {
+                        return Err(Failure::new(format!("{} is not a function", callee), span))
+                    }
+                }
+                return Ok(None);
+            }
+            Instruction::Return => {
+                let value = self.pop();
+                let frame = self.frames.pop().unwrap();
+                self.stack.truncate(frame.stack);
+                if self.frames.len() == base {
+                    return Ok(Some(value));
+                }
+                value
+            }
+            Instruction::Jump(to) => {
+                self.frames.last_mut().unwrap().ip = *to;
+                return Ok(None);
+            }
+            Instruction::JumpIf(to) => {
+                self.jump_if(true, *to, span)?;
+                return Ok(None);
+            }
+            Instruction::JumpUnless(to) => {
+                self.jump_if(false, *to, span)?;
+                return Ok(None);
+            }
+            Instruction::Try { rescue, slot } => {
+                let stack = self.stack.len();
+                self.frames.last_mut().unwrap().handlers.push(Handler {
+                    rescue: *rescue,
+                    slot: *slot,
+                    stack,
+                });
+                return Ok(None);
+            }
+            Instruction::EndTry => {
+                self.frames.last_mut().unwrap().handlers.pop();
+                return Ok(None);
+            }
+            Instruction::Fail(message) => return Err(Failure::new(message.clone(), span)),
+        };
+        self.stack.push(value);
+        Ok(None)
+    }
+}
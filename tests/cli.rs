@@ -0,0 +1,56 @@
+use std::process::Command;
+
+fn wryneck(args: &[&str]) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_wryneck"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_recovered_error_is_not_run() {
+    let dir = std::env::temp_dir().join("wryneck_test_recovered_error");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("missing_semicolon.wy");
+    std::fs::write(&path, "egg hatch() {\n    let hens = 1\n    \"ran\"\n}\n").unwrap();
+    let path = path.to_str().unwrap();
+    for args in [
+        &["run", path][..],
+        &["run", "--backend", "vm", path],
+        &["debug", path],
+    ] {
+        let output = wryneck(args);
+        assert_eq!(output.status.code(), Some(1), "{:?}", args);
+        assert!(output.stdout.is_empty(), "{:?}", args);
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(
+            stderr.contains("expected `;` after statement"),
+            "{}",
+            stderr
+        );
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_decompile_to_full_stdout() {
+    let dir = std::env::temp_dir().join("wryneck_test_decompile_to_full_stdout");
+    std::fs::create_dir_all(&dir).unwrap();
+    let source = dir.join("lay.wy");
+    let compiled = dir.join("lay.wknb");
+    std::fs::write(&source, "egg lay() { 1 }\n").unwrap();
+    let output = wryneck(&["compile", source.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(0));
+
+    // a stdout that can't be written to is reported, not a panic
+    let output = Command::new(env!("CARGO_BIN_EXE_wryneck"))
+        .args(["decompile", compiled.to_str().unwrap()])
+        .stdout(std::fs::File::create("/dev/full").unwrap())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("cannot write to stdout"), "{}", stderr);
+    std::fs::remove_dir_all(&dir).unwrap();
+}